@@ -3,6 +3,7 @@ mod utils;
 
 use crate::tests::lifecycle::{ContainerCreate, ContainerLifecycle};
 use crate::tests::linux_ns_itype::get_ns_itype_tests;
+use crate::tests::mount_propagation::get_propagation_tests;
 use crate::tests::pidfile::get_pidfile_test;
 use crate::tests::seccomp_notify::get_seccomp_notify_test;
 use crate::tests::tlb::get_tlb_test;
@@ -63,6 +64,7 @@ fn main() -> Result<()> {
     let huge_tlb = get_tlb_test();
     let pidfile = get_pidfile_test();
     let ns_itype = get_ns_itype_tests();
+    let mount_propagation = get_propagation_tests();
     let cgroup_v1_pids = cgroups::pids::get_test_group();
     let seccomp_notify = get_seccomp_notify_test();
 
@@ -71,6 +73,7 @@ fn main() -> Result<()> {
     tm.add_test_group(&huge_tlb);
     tm.add_test_group(&pidfile);
     tm.add_test_group(&ns_itype);
+    tm.add_test_group(&mount_propagation);
     tm.add_test_group(&cgroup_v1_pids);
 
     tm.add_cleanup(Box::new(cgroups::cleanup));
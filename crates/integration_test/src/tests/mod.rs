@@ -1,6 +1,7 @@
 pub mod cgroups;
 pub mod lifecycle;
 pub mod linux_ns_itype;
+pub mod mount_propagation;
 pub mod pidfile;
 pub mod seccomp_notify;
 pub mod tlb;
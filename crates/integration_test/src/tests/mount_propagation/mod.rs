@@ -0,0 +1,2 @@
+mod propagation_test;
+pub use propagation_test::get_propagation_tests;
@@ -0,0 +1,146 @@
+use crate::utils::test_outside_container;
+use crate::utils::test_utils::check_container_created;
+use crate::utils::{create_temp_dir, generate_uuid};
+use anyhow::{anyhow, bail, Result};
+use oci_spec::runtime::{MountBuilder, Spec, SpecBuilder};
+use procfs::process::{MountInfo, MountOptFields, Process};
+use std::path::Path;
+use test_framework::{Test, TestGroup, TestResult};
+
+// destination of the volume in the container
+const VOLUME: &str = "/propagation";
+
+fn get_spec(rootfs_propagation: Option<&str>, volume: Option<(&Path, &str)>) -> Spec {
+    let mut spec = SpecBuilder::default()
+        .build()
+        .expect("could not build spec");
+
+    let mut linux = spec.linux().clone().expect("no linux in default spec");
+    linux.set_rootfs_propagation(rootfs_propagation.map(|p| p.to_string()));
+    spec.set_linux(Some(linux));
+
+    if let Some((source, propagation)) = volume {
+        let mut mounts = spec.mounts().clone().unwrap_or_default();
+        mounts.push(
+            MountBuilder::default()
+                .destination(VOLUME)
+                .typ("bind")
+                .source(source)
+                .options(vec!["rbind".to_string(), propagation.to_string()])
+                .build()
+                .expect("could not build mount"),
+        );
+        spec.set_mounts(Some(mounts));
+    }
+
+    spec
+}
+
+// returns the entry of the mount point in the mountinfo of the container
+// process, the last entry is the one which is visible in the container
+fn container_mount(pid: i32, mount_point: &str) -> Result<MountInfo> {
+    Process::new(pid)?
+        .mountinfo()?
+        .into_iter()
+        .filter(|mi| mi.mount_point == Path::new(mount_point))
+        .last()
+        .ok_or_else(|| anyhow!("{} is not mounted in the container", mount_point))
+}
+
+fn check_propagation(mount: &MountInfo, propagation: &str) -> Result<()> {
+    let shared = mount
+        .opt_fields
+        .iter()
+        .any(|f| matches!(f, MountOptFields::Shared(_)));
+    let slave = mount
+        .opt_fields
+        .iter()
+        .any(|f| matches!(f, MountOptFields::Master(_)));
+    let unbindable = mount
+        .opt_fields
+        .iter()
+        .any(|f| matches!(f, MountOptFields::Unbindable));
+
+    let ok = match propagation {
+        "shared" | "rshared" => shared,
+        "slave" | "rslave" => slave && !shared,
+        "private" | "rprivate" => !shared && !slave && !unbindable,
+        "unbindable" | "runbindable" => unbindable,
+        _ => bail!("unknown propagation {}", propagation),
+    };
+    if !ok {
+        bail!(
+            "expected {:?} to be {}, but optional fields are {:?}",
+            mount.mount_point,
+            propagation,
+            mount.opt_fields
+        );
+    }
+
+    Ok(())
+}
+
+fn test_rootfs_propagation(propagation: &'static str) -> TestResult {
+    let spec = get_spec(Some(propagation), None);
+    test_outside_container(spec, &|data| {
+        if let Err(e) = check_container_created(&data) {
+            return TestResult::Failed(e);
+        }
+        let pid = data.state.as_ref().unwrap().pid.unwrap();
+        match container_mount(pid, "/").and_then(|m| check_propagation(&m, propagation)) {
+            Ok(()) => TestResult::Passed,
+            Err(e) => TestResult::Failed(e),
+        }
+    })
+}
+
+fn test_volume_propagation(propagation: &'static str) -> TestResult {
+    let source = create_temp_dir(&generate_uuid()).expect("could not create volume source");
+    let spec = get_spec(None, Some((source.path(), propagation)));
+    test_outside_container(spec, &|data| {
+        if let Err(e) = check_container_created(&data) {
+            return TestResult::Failed(e);
+        }
+        let pid = data.state.as_ref().unwrap().pid.unwrap();
+        match container_mount(pid, VOLUME).and_then(|m| check_propagation(&m, propagation)) {
+            Ok(()) => TestResult::Passed,
+            Err(e) => TestResult::Failed(e),
+        }
+    })
+}
+
+pub fn get_propagation_tests<'a>() -> TestGroup<'a> {
+    let mut tg = TestGroup::new("mount_propagation");
+    let tests: Vec<_> = vec![
+        Box::new(Test::new(
+            "rootfs_shared",
+            Box::new(|| test_rootfs_propagation("shared")),
+        )),
+        Box::new(Test::new(
+            "rootfs_slave",
+            Box::new(|| test_rootfs_propagation("slave")),
+        )),
+        Box::new(Test::new(
+            "rootfs_private",
+            Box::new(|| test_rootfs_propagation("private")),
+        )),
+        Box::new(Test::new(
+            "rootfs_unbindable",
+            Box::new(|| test_rootfs_propagation("unbindable")),
+        )),
+        Box::new(Test::new(
+            "volume_rshared",
+            Box::new(|| test_volume_propagation("rshared")),
+        )),
+        Box::new(Test::new(
+            "volume_rslave",
+            Box::new(|| test_volume_propagation("rslave")),
+        )),
+        Box::new(Test::new(
+            "volume_rprivate",
+            Box::new(|| test_volume_propagation("rprivate")),
+        )),
+    ];
+    tg.add(tests);
+    tg
+}
@@ -1,20 +1,22 @@
 use crate::utils::{get_runtime_path, test_outside_container};
 use anyhow::{anyhow, bail, Result};
+use libcontainer::{container::ContainerProcessState, seccomp::agent};
 use oci_spec::runtime::{
     Arch, LinuxBuilder, LinuxSeccompAction, LinuxSeccompBuilder, LinuxSyscallBuilder, SpecBuilder,
 };
 use std::{
+    os::unix::io::RawFd,
     path::PathBuf,
     sync::mpsc::{self, Receiver, Sender},
     thread,
 };
 use test_framework::{Test, TestGroup, TestResult};
 
-mod seccomp_agent;
-
 const SECCOMP_LISTENER_PATH: &str = "/tmp/youki_seccomp_agent.unix";
 const SECCOMP_METADATA: &str = "Hello World! This is an opaque seccomp metadata string";
 
+type SeccompAgentResult = Result<(ContainerProcessState, RawFd)>;
+
 fn get_seccomp_listener() -> PathBuf {
     let seccomp_listener_path = PathBuf::from(SECCOMP_LISTENER_PATH);
     // We will have to clean up leftover unix domain socket from previous runs.
@@ -56,15 +58,13 @@ fn test_seccomp_notify() -> Result<()> {
         .unwrap();
 
     // two threads. One run container life cycle. Another one run seccomp agent...
-    let (sender, receiver): (
-        Sender<seccomp_agent::SeccompAgentResult>,
-        Receiver<seccomp_agent::SeccompAgentResult>,
-    ) = mpsc::channel();
+    let (sender, receiver): (Sender<SeccompAgentResult>, Receiver<SeccompAgentResult>) =
+        mpsc::channel();
     // We have to launch the seccomp agent before we launch the container.
     // Otherwise, the container creation will be blocked on trying to send to
     // the seccomp listener and never returns.
     let child = thread::spawn(move || {
-        let res = seccomp_agent::recv_seccomp_listener(&seccomp_listener_path);
+        let res = agent::receive(&seccomp_listener_path);
         sender
             .send(res)
             .expect("failed to send seccomp agent result back to main thread");
@@ -29,6 +29,19 @@ use super::stats::Stats;
 
 pub const CGROUP_PROCS: &str = "cgroup.procs";
 pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
+pub const HYBRID_UNIFIED_ROOT: &str = "/sys/fs/cgroup/unified";
+
+/// The BPF program which restricts the devices of a cgroup v2. The id of the
+/// attached program is stored with the container, so that a later manager of
+/// the cgroup replaces and detaches exactly this program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceProgram {
+    /// Id of the program attached to the cgroup
+    pub id: Option<u32>,
+    /// Directory in a bpf filesystem to pin the program to, which keeps it
+    /// inspectable with tools like bpftool
+    pub pin_dir: Option<PathBuf>,
+}
 
 pub trait CgroupManager {
     /// Adds a task specified by its pid to the cgroup
@@ -48,6 +61,16 @@ pub trait CgroupManager {
 
     // Gets the PIDs inside the cgroup
     fn get_all_pids(&self) -> Result<Vec<Pid>>;
+
+    /// Sets the device program of the cgroup, which is replaced by apply and
+    /// detached by remove
+    fn set_device_program(&self, _program: DeviceProgram) {}
+
+    /// Returns the device program of the cgroup, which holds the id of the
+    /// program attached by apply
+    fn device_program(&self) -> DeviceProgram {
+        DeviceProgram::default()
+    }
 }
 
 #[derive(Debug)]
@@ -135,6 +158,9 @@ pub fn read_cgroup_file<P: AsRef<Path>>(path: P) -> Result<String> {
 ///   an additional unified hierarchy which doesn't have any
 ///   controllers attached. Resource control can purely be achieved
 ///   through the cgroup v1 hierarchy, not through the cgroup v2 hierarchy.
+///   The unified hierarchy is still used for features that do not depend
+///   on controllers, like pressure stall information, cgroup.events and
+///   BPF device control.
 pub fn get_cgroup_setup() -> Result<CgroupSetup> {
     let default_root = Path::new(DEFAULT_CGROUP_ROOT);
     match default_root.exists() {
@@ -154,7 +180,7 @@ pub fn get_cgroup_setup() -> Result<CgroupSetup> {
             }
 
             if stat.filesystem_type() == TMPFS_MAGIC {
-                let unified = Path::new("/sys/fs/cgroup/unified");
+                let unified = Path::new(HYBRID_UNIFIED_ROOT);
                 if Path::new(unified).exists() {
                     let stat = statfs(unified)
                         .with_context(|| format!("failed to stat {}", unified.display()))?;
@@ -179,10 +205,17 @@ pub fn create_cgroup_manager<P: Into<PathBuf>>(
     let cgroup_setup = get_cgroup_setup()?;
 
     match cgroup_setup {
-        CgroupSetup::Legacy | CgroupSetup::Hybrid => {
+        CgroupSetup::Legacy => {
             log::info!("cgroup manager V1 will be used");
             Ok(Box::new(v1::manager::Manager::new(cgroup_path.into())?))
         }
+        CgroupSetup::Hybrid => {
+            log::info!("cgroup manager V1 with unified hierarchy will be used");
+            Ok(Box::new(v1::manager::Manager::new_hybrid(
+                cgroup_path.into(),
+                Path::new(HYBRID_UNIFIED_ROOT),
+            )?))
+        }
         CgroupSetup::Unified => {
             if systemd_cgroup {
                 if !booted()? {
@@ -253,6 +286,17 @@ impl PathBufExt for PathBuf {
     }
 }
 
+/// Returns the device rules every container gets, which allow the default
+/// devices as well as the devices which are created by the kernel in the
+/// container, like the ptys.
+pub fn default_device_rules() -> Vec<LinuxDeviceCgroup> {
+    default_devices()
+        .iter()
+        .map(|d| d.into())
+        .chain(default_allow_devices())
+        .collect()
+}
+
 pub(crate) fn default_allow_devices() -> Vec<LinuxDeviceCgroup> {
     vec![
         LinuxDeviceCgroupBuilder::default()
@@ -304,14 +348,16 @@ pub(crate) fn default_allow_devices() -> Vec<LinuxDeviceCgroup> {
     ]
 }
 
-pub(crate) fn default_devices() -> Vec<LinuxDevice> {
+/// Returns the devices which are created in every container. The devices are
+/// allowed by [`default_device_rules`].
+pub fn default_devices() -> Vec<LinuxDevice> {
     vec![
         LinuxDeviceBuilder::default()
             .path(PathBuf::from("/dev/null"))
             .typ(LinuxDeviceType::C)
             .major(1)
             .minor(3)
-            .file_mode(0o066u32)
+            .file_mode(0o666u32)
             .build()
             .unwrap(),
         LinuxDeviceBuilder::default()
@@ -319,7 +365,7 @@ pub(crate) fn default_devices() -> Vec<LinuxDevice> {
             .typ(LinuxDeviceType::C)
             .major(1)
             .minor(5)
-            .file_mode(0o066u32)
+            .file_mode(0o666u32)
             .build()
             .unwrap(),
         LinuxDeviceBuilder::default()
@@ -327,7 +373,7 @@ pub(crate) fn default_devices() -> Vec<LinuxDevice> {
             .typ(LinuxDeviceType::C)
             .major(1)
             .minor(7)
-            .file_mode(0o066u32)
+            .file_mode(0o666u32)
             .build()
             .unwrap(),
         LinuxDeviceBuilder::default()
@@ -335,7 +381,7 @@ pub(crate) fn default_devices() -> Vec<LinuxDevice> {
             .typ(LinuxDeviceType::C)
             .major(5)
             .minor(0)
-            .file_mode(0o066u32)
+            .file_mode(0o666u32)
             .build()
             .unwrap(),
         LinuxDeviceBuilder::default()
@@ -343,7 +389,7 @@ pub(crate) fn default_devices() -> Vec<LinuxDevice> {
             .typ(LinuxDeviceType::C)
             .major(1)
             .minor(9)
-            .file_mode(0o066u32)
+            .file_mode(0o666u32)
             .build()
             .unwrap(),
         LinuxDeviceBuilder::default()
@@ -351,12 +397,32 @@ pub(crate) fn default_devices() -> Vec<LinuxDevice> {
             .typ(LinuxDeviceType::C)
             .major(1)
             .minor(8)
-            .file_mode(0o066u32)
+            .file_mode(0o666u32)
             .build()
             .unwrap(),
     ]
 }
 
+/// Returns a device which is not created by default, but which a container
+/// can opt in to: fuse for /dev/fuse and tun for /dev/net/tun.
+pub fn optional_device(name: &str) -> Option<LinuxDevice> {
+    let (path, major, minor) = match name {
+        "fuse" => ("/dev/fuse", 10, 229),
+        "tun" => ("/dev/net/tun", 10, 200),
+        _ => return None,
+    };
+    Some(
+        LinuxDeviceBuilder::default()
+            .path(PathBuf::from(path))
+            .typ(LinuxDeviceType::C)
+            .major(major)
+            .minor(minor)
+            .file_mode(0o666u32)
+            .build()
+            .unwrap(),
+    )
+}
+
 /// Attempts to delete the path the requested number of times.
 pub(crate) fn delete_with_retry<P: AsRef<Path>, L: Into<Option<Duration>>>(
     path: P,
@@ -23,6 +23,12 @@ pub struct Stats {
     pub blkio: BlkioStats,
     /// Memory statistics for the cgroup
     pub memory: MemoryStats,
+    /// Cpuset statistics for the cgroup
+    pub cpuset: CpuSetStats,
+    /// Network statistics for the cgroup
+    pub network: NetworkStats,
+    /// Pressure stall information for the cgroup
+    pub psi: PsiStats,
 }
 
 impl Default for Stats {
@@ -33,6 +39,9 @@ impl Default for Stats {
             hugetlb: HashMap::new(),
             blkio: BlkioStats::default(),
             memory: MemoryStats::default(),
+            cpuset: CpuSetStats::default(),
+            network: NetworkStats::default(),
+            psi: PsiStats::default(),
         }
     }
 }
@@ -121,8 +130,10 @@ pub struct MemoryStats {
     pub cache: u64,
     /// Returns true if hierarchical accounting is enabled
     pub hierarchy: bool,
-    /// Various memory statistics
+    /// Various memory statistics of the cgroup itself
     pub stats: HashMap<String, u64>,
+    /// Various memory statistics of the cgroup including all descendants
+    pub hierarchical_stats: HashMap<String, u64>,
 }
 
 impl Default for MemoryStats {
@@ -135,6 +146,7 @@ impl Default for MemoryStats {
             cache: 0,
             hierarchy: false,
             stats: HashMap::default(),
+            hierarchical_stats: HashMap::default(),
         }
     }
 }
@@ -265,6 +277,106 @@ impl Default for HugeTlbStats {
     }
 }
 
+/// Reports cpuset stats for a cgroup
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct CpuSetStats {
+    /// Cpus the tasks of the cgroup are effectively allowed to run on
+    pub cpus: Vec<usize>,
+    /// Memory nodes the tasks of the cgroup are effectively allowed to use
+    pub mems: Vec<usize>,
+}
+
+impl Default for CpuSetStats {
+    fn default() -> Self {
+        Self {
+            cpus: Vec::new(),
+            mems: Vec::new(),
+        }
+    }
+}
+
+/// Reports network stats for a cgroup
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct NetworkStats {
+    /// Class identifier that is used to tag network packets
+    pub class_id: Option<u32>,
+    /// Priority of network traffic per interface
+    pub priorities: HashMap<String, u32>,
+}
+
+impl Default for NetworkStats {
+    fn default() -> Self {
+        Self {
+            class_id: None,
+            priorities: HashMap::new(),
+        }
+    }
+}
+
+/// Reports pressure stall information for a cgroup. Resources for which
+/// the kernel does not provide pressure information are reported as None.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PsiStats {
+    /// Pressure stall information for cpu
+    pub cpu: Option<PsiData>,
+    /// Pressure stall information for memory
+    pub memory: Option<PsiData>,
+    /// Pressure stall information for io
+    pub io: Option<PsiData>,
+}
+
+impl Default for PsiStats {
+    fn default() -> Self {
+        Self {
+            cpu: None,
+            memory: None,
+            io: None,
+        }
+    }
+}
+
+/// Reports the pressure stall information of a single resource
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PsiData {
+    /// Share of time in which at least some tasks were stalled
+    pub some: PsiValues,
+    /// Share of time in which all non-idle tasks were stalled
+    pub full: PsiValues,
+}
+
+impl Default for PsiData {
+    fn default() -> Self {
+        Self {
+            some: PsiValues::default(),
+            full: PsiValues::default(),
+        }
+    }
+}
+
+/// Reports stall time averages and the absolute stall time
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PsiValues {
+    /// Percentage of stalled time over the last 10 seconds
+    pub avg10: f64,
+    /// Percentage of stalled time over the last 60 seconds
+    pub avg60: f64,
+    /// Percentage of stalled time over the last 300 seconds
+    pub avg300: f64,
+    /// Total stall time in microseconds
+    pub total: u64,
+}
+
+impl Default for PsiValues {
+    fn default() -> Self {
+        Self {
+            avg10: 0.0,
+            avg60: 0.0,
+            avg300: 0.0,
+            total: 0,
+        }
+    }
+}
+
 /// Reports which hugepage sizes are supported by the system
 pub fn supported_page_sizes() -> Result<Vec<String>> {
     let mut sizes = Vec::new();
@@ -411,6 +523,43 @@ pub fn parse_device_number(device: &str) -> Result<(u64, u64)> {
     Ok((numbers[0].parse()?, numbers[1].parse()?))
 }
 
+/// Parses a list of cpus or memory nodes in the format used by the cpuset
+/// controller, e.g. "0-3,6,8-9"
+/// # Example
+/// ```
+/// use libcgroups::stats::parse_cpu_list;
+///
+/// let cpus = parse_cpu_list("0-2,5").unwrap();
+/// assert_eq!(cpus, vec![0, 1, 2, 5]);
+/// ```
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>> {
+    let mut entries = Vec::new();
+    for range in list.trim().split_terminator(',') {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start
+                    .parse()
+                    .with_context(|| format!("failed to parse range start {}", range))?;
+                let end: usize = end
+                    .parse()
+                    .with_context(|| format!("failed to parse range end {}", range))?;
+                if start > end {
+                    bail!("invalid range {}", range);
+                }
+
+                entries.extend(start..=end);
+            }
+            None => entries.push(
+                range
+                    .parse()
+                    .with_context(|| format!("failed to parse {}", range))?,
+            ),
+        }
+    }
+
+    Ok(entries)
+}
+
 /// Returns cgroup pid statistics
 pub fn pid_stats(cgroup_path: &Path) -> Result<PidStats> {
     let mut stats = PidStats::default();
@@ -430,6 +579,72 @@ pub fn pid_stats(cgroup_path: &Path) -> Result<PidStats> {
     Ok(stats)
 }
 
+/// Returns the pressure stall information of a cgroup v2 directory. Pressure
+/// files that do not exist, e.g. because the kernel was built without
+/// CONFIG_PSI, are skipped.
+pub fn psi_stats(cgroup_path: &Path) -> Result<PsiStats> {
+    let mut stats = PsiStats::default();
+
+    let psi_files = [
+        ("cpu.pressure", &mut stats.cpu),
+        ("memory.pressure", &mut stats.memory),
+        ("io.pressure", &mut stats.io),
+    ];
+
+    for (file, data) in psi_files {
+        let file_path = cgroup_path.join(file);
+        if file_path.exists() {
+            *data = Some(parse_psi(&file_path)?);
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Parses a pressure file, which consists of a 'some' and, depending
+/// on the resource and the kernel version, a 'full' line
+fn parse_psi(file_path: &Path) -> Result<PsiData> {
+    let mut data = PsiData::default();
+    let content = common::read_cgroup_file(file_path)?;
+    for line in content.lines() {
+        let mut fields = line.split_ascii_whitespace();
+        let values = match fields.next() {
+            Some("some") => &mut data.some,
+            Some("full") => &mut data.full,
+            _ => bail!(
+                "invalid pressure entry {:?} in {}",
+                line,
+                file_path.display()
+            ),
+        };
+
+        for field in fields {
+            let (key, value) = field.split_once('=').with_context(|| {
+                format!(
+                    "invalid pressure value {:?} in {}",
+                    field,
+                    file_path.display()
+                )
+            })?;
+
+            let parse_avg = |v: &str| -> Result<f64> {
+                v.parse()
+                    .with_context(|| format!("failed to parse pressure average {}", v))
+            };
+
+            match key {
+                "avg10" => values.avg10 = parse_avg(value)?,
+                "avg60" => values.avg60 = parse_avg(value)?,
+                "avg300" => values.avg300 = parse_avg(value)?,
+                "total" => values.total = parse_value(value)?,
+                _ => continue,
+            }
+        }
+    }
+
+    Ok(data)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::test::{create_temp_dir, set_fixture};
@@ -618,6 +833,69 @@ mod tests {
         assert_eq!((major, minor), (8, 0));
     }
 
+    #[test]
+    fn test_parse_cpu_list() {
+        let cpus = parse_cpu_list("0-3,6,8-9\n").unwrap();
+        assert_eq!(cpus, vec![0, 1, 2, 3, 6, 8, 9]);
+    }
+
+    #[test]
+    fn test_parse_empty_cpu_list() {
+        let cpus = parse_cpu_list("\n").unwrap();
+        assert!(cpus.is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_cpu_list() {
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a-b").is_err());
+    }
+
+    #[test]
+    fn test_parse_psi() {
+        let tmp = create_temp_dir("test_parse_psi").unwrap();
+        let file_content = [
+            "some avg10=1.50 avg60=0.75 avg300=0.25 total=12345",
+            "full avg10=0.50 avg60=0.00 avg300=0.00 total=6789",
+        ]
+        .join("\n");
+        let file_path = set_fixture(&tmp, "memory.pressure", &file_content).unwrap();
+
+        let actual = parse_psi(&file_path).unwrap();
+        let expected = PsiData {
+            some: PsiValues {
+                avg10: 1.5,
+                avg60: 0.75,
+                avg300: 0.25,
+                total: 12345,
+            },
+            full: PsiValues {
+                avg10: 0.5,
+                avg60: 0.0,
+                avg300: 0.0,
+                total: 6789,
+            },
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_psi_stats_missing_files() {
+        let tmp = create_temp_dir("test_psi_stats_missing_files").unwrap();
+        set_fixture(
+            &tmp,
+            "cpu.pressure",
+            "some avg10=0.00 avg60=0.00 avg300=0.00 total=42\n",
+        )
+        .unwrap();
+
+        let actual = psi_stats(&tmp).unwrap();
+        assert_eq!(actual.cpu.map(|c| c.some.total), Some(42));
+        assert!(actual.memory.is_none());
+        assert!(actual.io.is_none());
+    }
+
     #[test]
     fn test_parse_invalid_device_number() {
         let result = parse_device_number("a:b");
@@ -5,12 +5,17 @@ use nix::unistd;
 use oci_spec::runtime::LinuxCpu;
 use unistd::Pid;
 
-use crate::common::{self, ControllerOpt, CGROUP_PROCS};
+use crate::{
+    common::{self, ControllerOpt, CGROUP_PROCS},
+    stats::{self, CpuSetStats, StatsProvider},
+};
 
 use super::{util, Controller, ControllerType};
 
 const CGROUP_CPUSET_CPUS: &str = "cpuset.cpus";
 const CGROUP_CPUSET_MEMS: &str = "cpuset.mems";
+const CGROUP_CPUSET_EFFECTIVE_CPUS: &str = "cpuset.effective_cpus";
+const CGROUP_CPUSET_EFFECTIVE_MEMS: &str = "cpuset.effective_mems";
 
 pub struct CpuSet {}
 
@@ -49,6 +54,20 @@ impl Controller for CpuSet {
     }
 }
 
+impl StatsProvider for CpuSet {
+    type Stats = CpuSetStats;
+
+    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
+        let cpus = common::read_cgroup_file(cgroup_path.join(CGROUP_CPUSET_EFFECTIVE_CPUS))?;
+        let mems = common::read_cgroup_file(cgroup_path.join(CGROUP_CPUSET_EFFECTIVE_MEMS))?;
+
+        Ok(CpuSetStats {
+            cpus: stats::parse_cpu_list(&cpus).context("failed to parse effective cpus")?,
+            mems: stats::parse_cpu_list(&mems).context("failed to parse effective mems")?,
+        })
+    }
+}
+
 impl CpuSet {
     fn apply(cgroup_path: &Path, cpuset: &LinuxCpu) -> Result<()> {
         if let Some(cpus) = &cpuset.cpus() {
@@ -93,7 +112,7 @@ mod tests {
     use std::fs;
 
     use super::*;
-    use crate::test::setup;
+    use crate::test::{create_temp_dir, set_fixture, setup};
     use oci_spec::runtime::LinuxCpuBuilder;
 
     #[test]
@@ -131,4 +150,16 @@ mod tests {
             .unwrap_or_else(|_| panic!("read {} file content", CGROUP_CPUSET_MEMS));
         assert_eq!(content, "1-3");
     }
+
+    #[test]
+    fn test_stat_cpuset() {
+        let tmp = create_temp_dir("test_stat_cpuset").expect("create temp dir for test");
+        set_fixture(&tmp, CGROUP_CPUSET_EFFECTIVE_CPUS, "0-2,4\n").unwrap();
+        set_fixture(&tmp, CGROUP_CPUSET_EFFECTIVE_MEMS, "0\n").unwrap();
+
+        let stats = CpuSet::stats(&tmp).expect("get cgroup stats");
+
+        assert_eq!(stats.cpus, vec![0, 1, 2, 4]);
+        assert_eq!(stats.mems, vec![0]);
+    }
 }
@@ -3,7 +3,7 @@ use std::path::Path;
 use anyhow::Result;
 
 use super::controller::Controller;
-use crate::common::{self, default_allow_devices, default_devices, ControllerOpt};
+use crate::common::{self, default_device_rules, ControllerOpt};
 use oci_spec::runtime::LinuxDeviceCgroup;
 
 pub struct Devices {}
@@ -20,12 +20,7 @@ impl Controller for Devices {
             }
         }
 
-        for d in [
-            default_devices().iter().map(|d| d.into()).collect(),
-            default_allow_devices(),
-        ]
-        .concat()
-        {
+        for d in default_device_rules() {
             Self::apply_device(&d, cgroup_root)?;
         }
 
@@ -64,7 +59,7 @@ mod tests {
         let tmp =
             create_temp_dir("test_set_default_devices").expect("create temp directory for test");
 
-        default_allow_devices().iter().for_each(|d| {
+        default_device_rules().iter().for_each(|d| {
             // NOTE: We reset the fixtures every iteration because files aren't appended
             // so what happens in the tests is you get strange overwrites which can contain
             // remaining bytes from the last iteration. Resetting the files more appropriately
@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::fs;
 use std::path::Path;
 use std::time::Duration;
@@ -17,11 +18,19 @@ use super::{
     perf_event::PerfEvent, pids::Pids, util, Controller,
 };
 
-use crate::common::{self, CgroupManager, ControllerOpt, FreezerState, PathBufExt, CGROUP_PROCS};
-use crate::stats::{Stats, StatsProvider};
+use crate::common::{
+    self, CgroupManager, ControllerOpt, DeviceProgram, FreezerState, PathBufExt, CGROUP_PROCS,
+};
+use crate::stats::{self, Stats, StatsProvider};
+#[cfg(feature = "cgroupsv2_devices")]
+use crate::v2::devices::Devices as BpfDevices;
 
 pub struct Manager {
     subsystems: HashMap<CtrlType, PathBuf>,
+    /// Path of the cgroup in the unified hierarchy of a hybrid setup
+    unified: Option<PathBuf>,
+    /// Device program attached to the cgroup in the unified hierarchy
+    device_program: RefCell<DeviceProgram>,
 }
 
 impl Manager {
@@ -36,7 +45,28 @@ impl Manager {
             }
         }
 
-        Ok(Manager { subsystems })
+        Ok(Manager {
+            subsystems,
+            unified: None,
+            device_program: RefCell::new(DeviceProgram::default()),
+        })
+    }
+
+    /// Constructs a new cgroup manager for a hybrid setup. Resource control is done
+    /// through the v1 subsystems, but the container is additionally placed into the
+    /// unified hierarchy mounted at unified_root, so that features which are only
+    /// available with cgroup v2 (e.g. pressure stall information, cgroup.events and
+    /// BPF device control) can be used.
+    pub fn new_hybrid(cgroup_path: PathBuf, unified_root: &Path) -> Result<Self> {
+        let mut manager = Self::new(cgroup_path.clone())?;
+        manager.unified = Some(unified_root.to_path_buf().join_safely(&cgroup_path)?);
+        Ok(manager)
+    }
+
+    /// Returns the path of the cgroup in the unified hierarchy, if the manager
+    /// has been created for a hybrid setup
+    pub fn unified_path(&self) -> Option<&Path> {
+        self.unified.as_deref()
     }
 
     fn get_subsystem_path(cgroup_path: &Path, subsystem: &CtrlType) -> Result<PathBuf> {
@@ -124,6 +154,13 @@ impl CgroupManager for Manager {
             }
         }
 
+        if let Some(unified) = &self.unified {
+            // the unified hierarchy has no controllers attached in a hybrid
+            // setup, so the cgroup does not need any further configuration
+            fs::create_dir_all(unified)?;
+            common::write_cgroup_file(unified.join(CGROUP_PROCS), pid)?;
+        }
+
         Ok(())
     }
 
@@ -145,6 +182,15 @@ impl CgroupManager for Manager {
             }
         }
 
+        #[cfg(feature = "cgroupsv2_devices")]
+        if let Some(unified) = &self.unified {
+            BpfDevices::apply_devices(
+                unified,
+                controller_opt.resources.devices(),
+                &mut self.device_program.borrow_mut(),
+            )?;
+        }
+
         Ok(())
     }
 
@@ -164,6 +210,16 @@ impl CgroupManager for Manager {
             }
         }
 
+        if let Some(unified) = &self.unified {
+            #[cfg(feature = "cgroupsv2_devices")]
+            BpfDevices::detach(unified, &self.device_program.borrow())?;
+
+            if unified.exists() {
+                log::debug!("remove cgroup {:?}", unified);
+                common::delete_with_retry(unified, 4, Duration::from_millis(100))?;
+            }
+        }
+
         Ok(())
     }
 
@@ -191,10 +247,29 @@ impl CgroupManager for Manager {
                 CtrlType::HugeTlb => stats.hugetlb = HugeTlb::stats(subsystem.1)?,
                 CtrlType::Blkio => stats.blkio = Blkio::stats(subsystem.1)?,
                 CtrlType::Memory => stats.memory = Memory::stats(subsystem.1)?,
+                CtrlType::CpuSet => stats.cpuset = CpuSet::stats(subsystem.1)?,
+                CtrlType::NetworkClassifier => {
+                    stats.network.class_id = NetworkClassifier::stats(subsystem.1)?
+                }
+                CtrlType::NetworkPriority => {
+                    stats.network.priorities = NetworkPriority::stats(subsystem.1)?
+                }
                 _ => continue,
             }
         }
 
+        if let Some(unified) = &self.unified {
+            stats.psi = stats::psi_stats(unified)?;
+        }
+
         Ok(stats)
     }
+
+    fn set_device_program(&self, program: DeviceProgram) {
+        self.device_program.replace(program);
+    }
+
+    fn device_program(&self) -> DeviceProgram {
+        self.device_program.borrow().clone()
+    }
 }
@@ -24,6 +24,8 @@ const CGROUP_KERNEL_TCP_MEMORY_LIMIT: &str = "memory.kmem.tcp.limit_in_bytes";
 
 // Shows various memory statistics
 const MEMORY_STAT: &str = "memory.stat";
+// Prefix for hierarchical memory statistics
+const MEMORY_STAT_TOTAL_PREFIX: &str = "total_";
 //
 const MEMORY_USE_HIERARCHY: &str = "memory.use_hierarchy";
 // Prefix for memory cgroup files
@@ -120,16 +122,17 @@ impl StatsProvider for Memory {
         let kernel = Self::get_memory_data(cgroup_path, MEMORY_KERNEL_PREFIX)?;
         let kernel_tcp = Self::get_memory_data(cgroup_path, MEMORY_KERNEL_TCP_PREFIX)?;
         let hierarchy = Self::hierarchy_enabled(cgroup_path)?;
-        let stats = Self::get_stat_data(cgroup_path)?;
+        let (stats, hierarchical_stats) = Self::split_stat_data(Self::get_stat_data(cgroup_path)?);
 
         Ok(MemoryStats {
             memory,
             memswap,
             kernel,
             kernel_tcp,
-            cache: stats["cache"],
+            cache: stats.get("cache").copied().unwrap_or_default(),
             hierarchy,
             stats,
+            hierarchical_stats,
         })
     }
 }
@@ -166,6 +169,25 @@ impl Memory {
         stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))
     }
 
+    // memory.stat contains the statistics of the cgroup itself as well as the
+    // statistics including all descendants, which are prefixed with 'total_'.
+    // These are split into separate maps with the prefix removed, so that the
+    // keys are the same as for the local statistics.
+    fn split_stat_data(
+        stat_data: HashMap<String, u64>,
+    ) -> (HashMap<String, u64>, HashMap<String, u64>) {
+        let mut local = HashMap::with_capacity(stat_data.len() / 2);
+        let mut hierarchical = HashMap::with_capacity(stat_data.len() / 2);
+        for (key, value) in stat_data {
+            match key.strip_prefix(MEMORY_STAT_TOTAL_PREFIX) {
+                Some(stripped) => hierarchical.insert(stripped.to_owned(), value),
+                None => local.insert(key, value),
+            };
+        }
+
+        (local, hierarchical)
+    }
+
     fn get_memory_usage(cgroup_root: &Path) -> Result<u64> {
         let path = cgroup_root.join(CGROUP_MEMORY_USAGE);
         let mut contents = String::new();
@@ -627,4 +649,36 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_stat_split_memory_stats() {
+        let stat_data: HashMap<String, u64> = [
+            ("cache".to_owned(), 1024),
+            ("rss".to_owned(), 2048),
+            ("hierarchical_memory_limit".to_owned(), 9223372036854771712),
+            ("total_cache".to_owned(), 4096),
+            ("total_rss".to_owned(), 8192),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        let (local, hierarchical) = Memory::split_stat_data(stat_data);
+        let expected_local: HashMap<String, u64> = [
+            ("cache".to_owned(), 1024),
+            ("rss".to_owned(), 2048),
+            ("hierarchical_memory_limit".to_owned(), 9223372036854771712),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let expected_hierarchical: HashMap<String, u64> =
+            [("cache".to_owned(), 4096), ("rss".to_owned(), 8192)]
+                .iter()
+                .cloned()
+                .collect();
+
+        assert_eq!(local, expected_local);
+        assert_eq!(hierarchical, expected_hierarchical);
+    }
 }
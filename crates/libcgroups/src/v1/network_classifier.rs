@@ -1,11 +1,16 @@
-use std::path::Path;
+use std::{convert::TryFrom, path::Path};
 
 use anyhow::{Context, Result};
 
 use super::Controller;
-use crate::common::{self, ControllerOpt};
+use crate::{
+    common::{self, ControllerOpt},
+    stats::{self, StatsProvider},
+};
 use oci_spec::runtime::LinuxNetwork;
 
+const CGROUP_NET_CLS_CLASSID: &str = "net_cls.classid";
+
 pub struct NetworkClassifier {}
 
 impl Controller for NetworkClassifier {
@@ -27,10 +32,26 @@ impl Controller for NetworkClassifier {
     }
 }
 
+impl StatsProvider for NetworkClassifier {
+    type Stats = Option<u32>;
+
+    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
+        let class_id = stats::parse_single_value(&cgroup_path.join(CGROUP_NET_CLS_CLASSID))?;
+        // a class id of zero means that no class id has been assigned
+        if class_id == 0 {
+            return Ok(None);
+        }
+
+        let class_id = u32::try_from(class_id)
+            .with_context(|| format!("class id {} is out of range", class_id))?;
+        Ok(Some(class_id))
+    }
+}
+
 impl NetworkClassifier {
     fn apply(root_path: &Path, network: &LinuxNetwork) -> Result<()> {
         if let Some(class_id) = network.class_id() {
-            common::write_cgroup_file(root_path.join("net_cls.classid"), class_id)?;
+            common::write_cgroup_file(root_path.join(CGROUP_NET_CLS_CLASSID), class_id)?;
         }
 
         Ok(())
@@ -62,4 +83,24 @@ mod tests {
             std::fs::read_to_string(tmp.join("net_cls.classid")).expect("Read classID contents");
         assert_eq!(id.to_string(), content);
     }
+
+    #[test]
+    fn test_stat_network_classifier() {
+        let tmp = create_temp_dir("test_stat_network_classifier")
+            .expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_NET_CLS_CLASSID, "1048577\n").expect("set fixture for classID");
+
+        let class_id = NetworkClassifier::stats(&tmp).expect("get cgroup stats");
+        assert_eq!(class_id, Some(0x100001));
+    }
+
+    #[test]
+    fn test_stat_network_classifier_unset() {
+        let tmp = create_temp_dir("test_stat_network_classifier_unset")
+            .expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_NET_CLS_CLASSID, "0\n").expect("set fixture for classID");
+
+        let class_id = NetworkClassifier::stats(&tmp).expect("get cgroup stats");
+        assert_eq!(class_id, None);
+    }
 }
@@ -1,11 +1,16 @@
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
 use super::Controller;
-use crate::common::{self, ControllerOpt};
+use crate::{
+    common::{self, ControllerOpt},
+    stats::StatsProvider,
+};
 use oci_spec::runtime::LinuxNetwork;
 
+const CGROUP_NET_PRIO_IFPRIOMAP: &str = "net_prio.ifpriomap";
+
 pub struct NetworkPriority {}
 
 impl Controller for NetworkPriority {
@@ -27,11 +32,42 @@ impl Controller for NetworkPriority {
     }
 }
 
+impl StatsProvider for NetworkPriority {
+    type Stats = HashMap<String, u32>;
+
+    fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
+        let file_path = cgroup_path.join(CGROUP_NET_PRIO_IFPRIOMAP);
+        let mut priorities = HashMap::new();
+        for entry in common::read_cgroup_file(&file_path)?.lines() {
+            let fields: Vec<&str> = entry.split_ascii_whitespace().collect();
+            if fields.len() != 2 {
+                bail!(
+                    "priority map at {} contains entries that do not conform to 'interface priority'",
+                    file_path.display()
+                );
+            }
+
+            let priority = fields[1].parse().with_context(|| {
+                format!(
+                    "failed to parse priority {} of interface {}",
+                    fields[1], fields[0]
+                )
+            })?;
+            priorities.insert(fields[0].to_owned(), priority);
+        }
+
+        Ok(priorities)
+    }
+}
+
 impl NetworkPriority {
     fn apply(root_path: &Path, network: &LinuxNetwork) -> Result<()> {
         if let Some(ni_priorities) = network.priorities() {
             let priorities: String = ni_priorities.iter().map(|p| p.to_string()).collect();
-            common::write_cgroup_file_str(root_path.join("net_prio.ifpriomap"), priorities.trim())?;
+            common::write_cgroup_file_str(
+                root_path.join(CGROUP_NET_PRIO_IFPRIOMAP),
+                priorities.trim(),
+            )?;
         }
 
         Ok(())
@@ -73,4 +109,19 @@ mod tests {
             std::fs::read_to_string(tmp.join("net_prio.ifpriomap")).expect("Read classID contents");
         assert_eq!(priorities_string.trim(), content);
     }
+
+    #[test]
+    fn test_stat_network_priorities() {
+        let tmp = create_temp_dir("test_stat_network_priorities")
+            .expect("create temp directory for test");
+        set_fixture(&tmp, CGROUP_NET_PRIO_IFPRIOMAP, "lo 0\neth0 5\n")
+            .expect("set fixture for priority map");
+
+        let priorities = NetworkPriority::stats(&tmp).expect("get cgroup stats");
+        let expected: HashMap<String, u32> = [("lo".to_owned(), 0), ("eth0".to_owned(), 5)]
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(priorities, expected);
+    }
 }
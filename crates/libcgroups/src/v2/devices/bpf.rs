@@ -1,5 +1,8 @@
 use anyhow::{bail, Result};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::io::RawFd;
+use std::path::Path;
 use std::ptr;
 
 // FIXME: add tests
@@ -103,6 +106,70 @@ pub fn prog_attach(prog_fd: RawFd, cgroup_fd: RawFd) -> Result<()> {
     Ok(())
 }
 
+/// Atomically replaces the attached program replace_fd with prog_fd. This requires
+/// BPF_F_REPLACE, which is supported since Linux 5.6. Older kernels reject the flag
+/// with EINVAL.
+pub fn prog_attach_replace(prog_fd: RawFd, cgroup_fd: RawFd, replace_fd: RawFd) -> Result<()> {
+    let opts = libbpf_sys::bpf_prog_attach_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_prog_attach_opts>() as _,
+        flags: libbpf_sys::BPF_F_ALLOW_MULTI | libbpf_sys::BPF_F_REPLACE,
+        replace_prog_fd: replace_fd,
+    };
+
+    let ret = unsafe {
+        libbpf_sys::bpf_prog_attach_xattr(prog_fd, cgroup_fd, libbpf_sys::BPF_CGROUP_DEVICE, &opts)
+    };
+
+    if ret != 0 {
+        return Err(errno::errno().into());
+    }
+    Ok(())
+}
+
+/// Pins the program to path, which has to be located in a bpf filesystem.
+/// The pinned program stays loaded until the path is removed.
+pub fn prog_pin(prog_fd: RawFd, path: &Path) -> Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let ret = unsafe { libbpf_sys::bpf_obj_pin(prog_fd, path.as_ptr()) };
+    if ret != 0 {
+        return Err(errno::errno().into());
+    }
+    Ok(())
+}
+
+/// Returns the instructions of a loaded program, as translated by the kernel
+pub fn prog_get_insns(prog_fd: RawFd) -> Result<Vec<u8>> {
+    // the first call only determines the size of the instructions
+    let mut info: libbpf_sys::bpf_prog_info = unsafe { std::mem::zeroed() };
+    prog_get_info(prog_fd, &mut info)?;
+
+    let mut insns = vec![0_u8; info.xlated_prog_len as usize];
+    let mut info: libbpf_sys::bpf_prog_info = unsafe { std::mem::zeroed() };
+    info.xlated_prog_len = insns.len() as u32;
+    info.xlated_prog_insns = insns.as_mut_ptr() as u64;
+    prog_get_info(prog_fd, &mut info)?;
+
+    // the program may have been replaced with a larger one in the meantime
+    insns.truncate(info.xlated_prog_len as usize);
+    Ok(insns)
+}
+
+fn prog_get_info(prog_fd: RawFd, info: &mut libbpf_sys::bpf_prog_info) -> Result<()> {
+    let mut info_len = std::mem::size_of::<libbpf_sys::bpf_prog_info>() as u32;
+    let ret = unsafe {
+        libbpf_sys::bpf_obj_get_info_by_fd(
+            prog_fd,
+            info as *mut libbpf_sys::bpf_prog_info as *mut std::ffi::c_void,
+            &mut info_len,
+        )
+    };
+
+    if ret != 0 {
+        return Err(errno::errno().into());
+    }
+    Ok(())
+}
+
 pub fn bump_memlock_rlimit() -> Result<()> {
     let rlimit = libc::rlimit {
         rlim_cur: 128 << 20,
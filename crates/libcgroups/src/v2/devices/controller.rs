@@ -1,14 +1,15 @@
-use std::os::unix::io::AsRawFd;
-use std::path::Path;
+use std::fs;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 use super::*;
 use nix::fcntl::OFlag;
 use nix::sys::stat::Mode;
 use oci_spec::runtime::LinuxDeviceCgroup;
 
-use crate::common::{default_allow_devices, default_devices, ControllerOpt};
+use crate::common::{default_device_rules, ControllerOpt, DeviceProgram};
 use crate::v2::controller::Controller;
 
 const LICENSE: &str = "Apache";
@@ -21,17 +22,82 @@ impl Controller for Devices {
         return Ok(());
 
         #[cfg(feature = "cgroupsv2_devices")]
-        return Self::apply_devices(cgroup_root, controller_opt.resources.devices());
+        return Self::apply_devices(
+            cgroup_root,
+            controller_opt.resources.devices(),
+            &mut DeviceProgram::default(),
+        );
     }
 }
 
 impl Devices {
+    /// Attaches the device program built from the rules to the cgroup. It
+    /// replaces the program of device_program, if that is still attached, and
+    /// stores the id of the new program in device_program.
     pub fn apply_devices(
         cgroup_root: &Path,
         linux_devices: &Option<Vec<LinuxDeviceCgroup>>,
+        device_program: &mut DeviceProgram,
     ) -> Result<()> {
         log::debug!("Apply Devices cgroup config");
 
+        let emulator = Self::effective_rules(linux_devices)?;
+        let prog = program::Program::from_rules(&emulator.rules, emulator.default_allow)?;
+        let fd = Self::open_cgroup(cgroup_root)?;
+
+        // Increase `ulimit -l` limit to avoid BPF_PROG_LOAD error (#2167).
+        // This limit is not inherited into the container.
+        bpf::bump_memlock_rlimit()?;
+        let prog_fd = bpf::prog_load(LICENSE, prog.bytecodes())?;
+
+        let attached = bpf::prog_query(fd.as_raw_fd())?;
+        let old_prog = Self::find_program(&attached, device_program.id);
+        if let (Some(id), None) = (device_program.id, old_prog) {
+            log::warn!(
+                "device program {} is no longer attached to {}",
+                id,
+                cgroup_root.display()
+            );
+        }
+        Self::attach_program(prog_fd, fd.as_raw_fd(), old_prog)?;
+
+        let prog_id = bpf::prog_query(fd.as_raw_fd())?
+            .into_iter()
+            .map(|p| p.id)
+            .find(|id| attached.iter().all(|old| old.id != *id))
+            .context("attached device program is not listed for cgroup")?;
+        device_program.id = Some(prog_id);
+        log::debug!(
+            "attached device program {} to {}",
+            prog_id,
+            cgroup_root.display()
+        );
+
+        if let Some(pin_dir) = &device_program.pin_dir {
+            let pin_path = Self::pin_path(pin_dir, cgroup_root);
+            if pin_path.exists() {
+                fs::remove_file(&pin_path)
+                    .with_context(|| format!("failed to unpin {}", pin_path.display()))?;
+            }
+
+            bpf::prog_pin(prog_fd, &pin_path).with_context(|| {
+                format!("failed to pin device program to {}", pin_path.display())
+            })?;
+            log::debug!(
+                "pinned device program {} to {}",
+                prog_id,
+                pin_path.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the device rules that result from folding the user-defined and the
+    /// default rules, i.e. the rules the device program of a container is built from
+    pub fn effective_rules(
+        linux_devices: &Option<Vec<LinuxDeviceCgroup>>,
+    ) -> Result<emulator::Emulator> {
         // FIXME: should we start as "deny all"?
         let mut emulator = emulator::Emulator::with_default_allow(false);
 
@@ -43,50 +109,139 @@ impl Devices {
             }
         }
 
-        for d in [
-            default_devices().iter().map(|d| d.into()).collect(),
-            default_allow_devices(),
-        ]
-        .concat()
-        {
+        for d in default_device_rules() {
             log::debug!("apply default rule: {:?}", d);
             emulator.add_rule(&d)?;
         }
 
-        let prog = program::Program::from_rules(&emulator.rules, emulator.default_allow)?;
+        Ok(emulator)
+    }
 
-        // Increase `ulimit -l` limit to avoid BPF_PROG_LOAD error (#2167).
-        // This limit is not inherited into the container.
-        bpf::bump_memlock_rlimit()?;
-        let prog_fd = bpf::prog_load(LICENSE, prog.bytecodes())?;
+    /// Detaches the device program from the cgroup, if it still exists, and
+    /// removes the pinned program
+    pub fn detach(cgroup_root: &Path, device_program: &DeviceProgram) -> Result<()> {
+        if let (Some(id), true) = (device_program.id, cgroup_root.exists()) {
+            let fd = Self::open_cgroup(cgroup_root)?;
+            let attached = bpf::prog_query(fd.as_raw_fd())?;
+            if let Some(prog) = Self::find_program(&attached, Some(id)) {
+                bpf::prog_detach2(prog.fd, fd.as_raw_fd())
+                    .with_context(|| format!("failed to detach device program {}", id))?;
+            }
+        }
+
+        if let Some(pin_dir) = &device_program.pin_dir {
+            let pin_path = Self::pin_path(pin_dir, cgroup_root);
+            if pin_path.exists() {
+                fs::remove_file(&pin_path)
+                    .with_context(|| format!("failed to unpin {}", pin_path.display()))?;
+            }
+        }
+
+        Ok(())
+    }
 
-        // FIXME: simple way to attach BPF program
-        //  1. get list of existing attached programs
-        //  2. attach this program (not use BPF_F_REPLACE, see below)
-        //  3. detach all programs of 1
-        //
-        // runc will use BPF_F_REPLACE to replace currently attached progam if:
-        //   1. BPF_F_REPLACE is supported by kernel
-        //   2. there is exactly one attached program
-        // https://github.com/opencontainers/runc/blob/8e6871a3b14bb74e0ef358aca3b9f8f9cb80f041/libcontainer/cgroups/ebpf/ebpf_linux.go#L165
-        //
-        // IMHO, this is too complicated, and in most cases, we just attach program once without
-        // already attached programs.
-
-        let fd = nix::dir::Dir::open(
+    fn open_cgroup(cgroup_root: &Path) -> Result<nix::dir::Dir> {
+        nix::dir::Dir::open(
             cgroup_root.as_os_str(),
             OFlag::O_RDONLY | OFlag::O_DIRECTORY,
             Mode::from_bits(0o600).unwrap(),
-        )?;
+        )
+        .with_context(|| format!("failed to open cgroup {}", cgroup_root.display()))
+    }
+
+    fn find_program(attached: &[bpf::ProgramInfo], id: Option<u32>) -> Option<&bpf::ProgramInfo> {
+        id.and_then(|id| attached.iter().find(|prog| prog.id == id))
+    }
 
-        let old_progs = bpf::prog_query(fd.as_raw_fd())?;
-        bpf::prog_attach(prog_fd, fd.as_raw_fd())?;
-        for old_prog in old_progs {
-            bpf::prog_detach2(old_prog.fd, fd.as_raw_fd())?;
+    // Attaching a new program and detaching the old one afterwards leaves a window in
+    // which both programs are attached, i.e. the old rules are still enforced. Like runc,
+    // we atomically replace the program we attached before with BPF_F_REPLACE. Other
+    // programs attached to the cgroup are left alone. Kernels older than 5.6 do not
+    // support BPF_F_REPLACE, in which case we fall back to attach and detach.
+    // https://github.com/opencontainers/runc/blob/8e6871a3b14bb74e0ef358aca3b9f8f9cb80f041/libcontainer/cgroups/ebpf/ebpf_linux.go#L165
+    fn attach_program(
+        prog_fd: RawFd,
+        cgroup_fd: RawFd,
+        old_prog: Option<&bpf::ProgramInfo>,
+    ) -> Result<()> {
+        if let Some(old_prog) = old_prog {
+            match bpf::prog_attach_replace(prog_fd, cgroup_fd, old_prog.fd) {
+                Ok(_) => return Ok(()),
+                Err(e) if e.downcast_ref::<errno::Errno>() == Some(&errno::Errno(libc::EINVAL)) => {
+                    log::debug!(
+                        "BPF_F_REPLACE is not supported, falling back to attach and detach"
+                    );
+                }
+                Err(e) => {
+                    return Err(
+                        e.context(format!("failed to replace device program {}", old_prog.id))
+                    )
+                }
+            }
+        }
+
+        bpf::prog_attach(prog_fd, cgroup_fd)?;
+        if let Some(old_prog) = old_prog {
+            bpf::prog_detach2(old_prog.fd, cgroup_fd)?;
         }
 
         Ok(())
     }
+
+    // the pinned program is named after the cgroup, so that it is replaced on update
+    fn pin_path(pin_dir: &Path, cgroup_root: &Path) -> PathBuf {
+        let name: String = cgroup_root
+            .to_string_lossy()
+            .trim_matches('/')
+            .replace('/', "_");
+        pin_dir.join(format!("youki_devices_{}", name))
+    }
 }
 
 // FIXME: add tests, but how to?
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_path() {
+        let pin_path = Devices::pin_path(
+            Path::new("/sys/fs/bpf"),
+            Path::new("/sys/fs/cgroup/youki/74f1a4cb3801"),
+        );
+        assert_eq!(
+            pin_path,
+            PathBuf::from("/sys/fs/bpf/youki_devices_sys_fs_cgroup_youki_74f1a4cb3801")
+        );
+    }
+
+    #[test]
+    fn test_apply_devices_relative_path() {
+        // the program has to be attached to the cgroup below the cgroup root,
+        // not to the path of the cgroup relative to the root
+        let mut device_program = DeviceProgram::default();
+        let err =
+            Devices::apply_devices(Path::new("youki/74f1a4cb3801"), &None, &mut device_program)
+                .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("failed to open cgroup youki/74f1a4cb3801"));
+        assert_eq!(device_program, DeviceProgram::default());
+    }
+
+    #[test]
+    fn test_find_program() {
+        let attached = vec![
+            bpf::ProgramInfo { id: 7, fd: 10 },
+            bpf::ProgramInfo { id: 9, fd: 11 },
+        ];
+        assert_eq!(
+            Devices::find_program(&attached, Some(9)).map(|p| p.fd),
+            Some(11)
+        );
+        assert!(Devices::find_program(&attached, Some(8)).is_none());
+        // without a stored id no program is replaced, even if only one is attached
+        assert!(Devices::find_program(&attached[..1], None).is_none());
+    }
+}
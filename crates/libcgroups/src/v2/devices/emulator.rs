@@ -1,20 +1,30 @@
-use anyhow::Result;
-use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceType};
+use anyhow::{bail, Result};
+use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceCgroupBuilder, LinuxDeviceType};
 
 // For cgroup v1 compatiblity, runc implements a device emulator to caculate the final rules given
 // a list of user-defined rules.
 // https://github.com/opencontainers/runc/commit/2353ffec2bb670a200009dc7a54a56b93145f141
 //
-// I chose to implement a very simple algorithm, which will just work in most cases, but with
-// diversion from cgroupv1 in some cases:
-//  1. just add used-defined rules one by one
-//  2. discard existing rules when encountering a rule with type='a', and change to deny/allow all
-//     list according the 'allow' of the rule
-//  3. bpf program will check rule one by one in *reversed* order, return action of first rule
-//     which matches device access operation
+// This is a port of runc's emulator, which folds the rules the same way the devices cgroup v1
+// controller does:
+//  1. a rule with type='a' discards all existing rules and switches to deny/allow all according
+//     to the 'allow' of the rule
+//  2. a rule whose 'allow' differs from the default is added as an exception. Exceptions with
+//     the same type, major and minor are merged by combining their access
+//  3. a rule whose 'allow' equals the default removes its access from the exception with the
+//     same type, major and minor. If there is no such exception, the rule has no effect
 //
+// As a result all rules of the emulator have the opposite 'allow' of the default, so the order
+// in which the bpf program checks them does not matter.
+//
+// The only diversion from cgroup v1 is the same as in runc: removing access which is granted by
+// a partially matching wildcard exception is an error instead of being silently ignored, as
+// cgroup v1 would not remove it either.
+
+const ACCESS_READ: u8 = 1;
+const ACCESS_WRITE: u8 = 1 << 1;
+const ACCESS_MKNOD: u8 = 1 << 2;
 
-// FIXME: should we use runc's implementation?
 pub struct Emulator {
     pub default_allow: bool,
     pub rules: Vec<LinuxDeviceCgroup>,
@@ -36,22 +46,401 @@ impl Emulator {
     }
 
     pub fn add_rule(&mut self, rule: &LinuxDeviceCgroup) -> Result<()> {
-        // special case, switch to blacklist or whitelist and clear all existing rules
-        // NOTE: we ignore other fields when type='a', this is same as cgroup v1 and runc
-        if rule.typ().unwrap_or_default() == LinuxDeviceType::A {
-            self.default_allow = rule.allow();
-            self.rules.clear();
-            return Ok(());
+        let typ = rule.typ().unwrap_or_default();
+        match typ {
+            // special case, switch to blacklist or whitelist and clear all existing rules
+            // NOTE: we ignore other fields when type='a', this is same as cgroup v1 and runc
+            LinuxDeviceType::A => {
+                self.default_allow = rule.allow();
+                self.rules.clear();
+                return Ok(());
+            }
+            LinuxDeviceType::U | LinuxDeviceType::P => {
+                bail!(
+                    "device type {:?} is not supported by the devices cgroup",
+                    typ
+                )
+            }
+            LinuxDeviceType::C | LinuxDeviceType::B => {}
         }
 
+        let access = parse_access(rule.access())?;
         // empty access match nothing, just discard this rule
-        if rule.access().is_none() {
+        if access == 0 {
             return Ok(());
         }
 
-        self.rules.push(rule.clone());
+        // negative major and minor numbers are treated as wildcards, like in the bpf program
+        let major = rule.major().filter(|m| *m >= 0);
+        let minor = rule.minor().filter(|m| *m >= 0);
+
+        if rule.allow() == self.default_allow {
+            self.remove_exception(typ, major, minor, access)
+        } else {
+            self.add_exception(typ, major, minor, access)
+        }
+    }
+
+    fn add_exception(
+        &mut self,
+        typ: LinuxDeviceType,
+        major: Option<i64>,
+        minor: Option<i64>,
+        access: u8,
+    ) -> Result<()> {
+        let merged_access = match self.find_exception(typ, major, minor) {
+            Some(index) => parse_access(self.rules.remove(index).access())? | access,
+            None => access,
+        };
+
+        self.rules.push(build_rule(
+            !self.default_allow,
+            typ,
+            major,
+            minor,
+            merged_access,
+        )?);
+        Ok(())
+    }
+
+    fn remove_exception(
+        &mut self,
+        typ: LinuxDeviceType,
+        major: Option<i64>,
+        minor: Option<i64>,
+        access: u8,
+    ) -> Result<()> {
+        // cgroup v1 only removes access from the exception which matches exactly. Removing access
+        // granted by a wildcard exception would require splitting it, which neither the kernel nor
+        // we can do, so give an error instead of leaving the access in place silently.
+        for (partial_major, partial_minor) in [(None, minor), (major, None), (None, None)] {
+            if (partial_major, partial_minor) == (major, minor) {
+                continue;
+            }
+
+            if let Some(index) = self.find_exception(typ, partial_major, partial_minor) {
+                if parse_access(self.rules[index].access())? & access != 0 {
+                    bail!(
+                        "cannot remove access {} of {:?} device {}:{} as it is granted by the wildcard rule {}",
+                        access_string(access),
+                        typ,
+                        id_string(major),
+                        id_string(minor),
+                        self.rules[index].to_string().trim(),
+                    );
+                }
+            }
+        }
+
+        if let Some(index) = self.find_exception(typ, major, minor) {
+            let remaining_access = parse_access(self.rules.remove(index).access())? & !access;
+            if remaining_access != 0 {
+                self.rules.push(build_rule(
+                    !self.default_allow,
+                    typ,
+                    major,
+                    minor,
+                    remaining_access,
+                )?);
+            }
+        }
+
         Ok(())
     }
+
+    fn find_exception(
+        &self,
+        typ: LinuxDeviceType,
+        major: Option<i64>,
+        minor: Option<i64>,
+    ) -> Option<usize> {
+        self.rules.iter().position(|r| {
+            r.typ() == Some(typ)
+                && r.major().filter(|m| *m >= 0) == major
+                && r.minor().filter(|m| *m >= 0) == minor
+        })
+    }
 }
 
-// FIXME: add some tests
+fn parse_access(access: &Option<String>) -> Result<u8> {
+    let mut v = 0;
+    if let Some(access) = access {
+        for c in access.chars() {
+            v |= match c {
+                'r' => ACCESS_READ,
+                'w' => ACCESS_WRITE,
+                'm' => ACCESS_MKNOD,
+                _ => bail!("invalid access: {}", c),
+            };
+        }
+    }
+
+    Ok(v)
+}
+
+fn access_string(access: u8) -> String {
+    [(ACCESS_READ, 'r'), (ACCESS_WRITE, 'w'), (ACCESS_MKNOD, 'm')]
+        .iter()
+        .filter(|(flag, _)| access & flag != 0)
+        .map(|(_, c)| c)
+        .collect()
+}
+
+fn id_string(id: Option<i64>) -> String {
+    id.map(|id| id.to_string())
+        .unwrap_or_else(|| "*".to_owned())
+}
+
+fn build_rule(
+    allow: bool,
+    typ: LinuxDeviceType,
+    major: Option<i64>,
+    minor: Option<i64>,
+    access: u8,
+) -> Result<LinuxDeviceCgroup> {
+    let mut builder = LinuxDeviceCgroupBuilder::default()
+        .allow(allow)
+        .typ(typ)
+        .access(access_string(access));
+    if let Some(major) = major {
+        builder = builder.major(major);
+    }
+    if let Some(minor) = minor {
+        builder = builder.minor(minor);
+    }
+
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::devices::program::Program;
+    use quickcheck::{Arbitrary, Gen, TestResult};
+
+    fn rule(
+        allow: bool,
+        typ: LinuxDeviceType,
+        major: i64,
+        minor: i64,
+        access: &str,
+    ) -> LinuxDeviceCgroup {
+        let mut builder = LinuxDeviceCgroupBuilder::default()
+            .allow(allow)
+            .typ(typ)
+            .access(access);
+        if major >= 0 {
+            builder = builder.major(major);
+        }
+        if minor >= 0 {
+            builder = builder.minor(minor);
+        }
+
+        builder.build().unwrap()
+    }
+
+    fn rule_strings(emulator: &Emulator) -> Vec<String> {
+        let mut rules: Vec<String> = emulator
+            .rules
+            .iter()
+            .map(|r| r.to_string().trim().to_owned())
+            .collect();
+        rules.sort();
+        rules
+    }
+
+    #[test]
+    fn test_merge_exceptions() {
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator
+            .add_rules(&[
+                rule(true, LinuxDeviceType::C, 1, 3, "r"),
+                rule(true, LinuxDeviceType::C, 1, 3, "w"),
+                rule(true, LinuxDeviceType::C, 1, 5, "m"),
+            ])
+            .expect("add rules");
+
+        assert_eq!(rule_strings(&emulator), vec!["c 1:3 rw", "c 1:5 m"]);
+    }
+
+    #[test]
+    fn test_remove_exceptions() {
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator
+            .add_rules(&[
+                rule(true, LinuxDeviceType::C, 1, 3, "rwm"),
+                rule(true, LinuxDeviceType::B, 8, 0, "r"),
+                rule(false, LinuxDeviceType::C, 1, 3, "w"),
+                rule(false, LinuxDeviceType::B, 8, 0, "rw"),
+                // no exception matches exactly, so this has no effect
+                rule(false, LinuxDeviceType::C, 1, -1, "m"),
+            ])
+            .expect("add rules");
+
+        assert_eq!(rule_strings(&emulator), vec!["c 1:3 rm"]);
+    }
+
+    #[test]
+    fn test_wildcard_type_resets_rules() {
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator
+            .add_rules(&[
+                rule(true, LinuxDeviceType::C, 1, 3, "rwm"),
+                rule(true, LinuxDeviceType::A, -1, -1, "r"),
+                rule(false, LinuxDeviceType::B, 8, -1, "w"),
+            ])
+            .expect("add rules");
+
+        assert!(emulator.default_allow);
+        assert_eq!(rule_strings(&emulator), vec!["b 8:* w"]);
+    }
+
+    #[test]
+    fn test_punch_hole_in_wildcard() {
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator
+            .add_rule(&rule(true, LinuxDeviceType::C, -1, 3, "rw"))
+            .expect("add rule");
+
+        assert!(emulator
+            .add_rule(&rule(false, LinuxDeviceType::C, 1, 3, "w"))
+            .is_err());
+        // the access does not overlap with the wildcard exception
+        assert!(emulator
+            .add_rule(&rule(false, LinuxDeviceType::C, 1, 3, "m"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_unsupported_device_type() {
+        let mut emulator = Emulator::with_default_allow(false);
+        assert!(emulator
+            .add_rule(&rule(true, LinuxDeviceType::P, 1, 3, "rwm"))
+            .is_err());
+    }
+
+    // Reference model of the devices cgroup v1 controller, following
+    // devcgroup_update_access and devcgroup_legacy_check_permission in security/device_cgroup.c
+    struct V1Model {
+        default_allow: bool,
+        exceptions: Vec<(LinuxDeviceType, Option<i64>, Option<i64>, u8)>,
+    }
+
+    impl V1Model {
+        fn new() -> Self {
+            Self {
+                default_allow: false,
+                exceptions: Vec::new(),
+            }
+        }
+
+        fn write(&mut self, rule: &LinuxDeviceCgroup) {
+            let typ = rule.typ().unwrap_or_default();
+            if typ == LinuxDeviceType::A {
+                self.default_allow = rule.allow();
+                self.exceptions.clear();
+                return;
+            }
+
+            let major = rule.major().filter(|m| *m >= 0);
+            let minor = rule.minor().filter(|m| *m >= 0);
+            let access = parse_access(rule.access()).unwrap();
+            let existing = self
+                .exceptions
+                .iter()
+                .position(|e| (e.0, e.1, e.2) == (typ, major, minor));
+
+            if rule.allow() == self.default_allow {
+                // dev_exception_rm
+                if let Some(index) = existing {
+                    self.exceptions[index].3 &= !access;
+                    if self.exceptions[index].3 == 0 {
+                        self.exceptions.remove(index);
+                    }
+                }
+            } else if let Some(index) = existing {
+                // dev_exception_add
+                self.exceptions[index].3 |= access;
+            } else if access != 0 {
+                self.exceptions.push((typ, major, minor, access));
+            }
+        }
+
+        fn is_allowed(&self, typ: LinuxDeviceType, major: i64, minor: i64, access: u8) -> bool {
+            let mut matching = self.exceptions.iter().filter(|e| {
+                e.0 == typ
+                    && e.1.map(|m| m == major).unwrap_or(true)
+                    && e.2.map(|m| m == minor).unwrap_or(true)
+            });
+
+            if self.default_allow {
+                // match_exception_partial: any overlapping access is denied
+                !matching.any(|e| e.3 & access != 0)
+            } else {
+                // match_exception: all access must be granted by one exception
+                matching.any(|e| access & !e.3 == 0)
+            }
+        }
+    }
+
+    const TYPES: &[LinuxDeviceType] = &[LinuxDeviceType::C, LinuxDeviceType::B];
+    const IDS: &[i64] = &[-1, 1, 2, 3];
+
+    #[derive(Clone, Debug)]
+    struct TestRule(LinuxDeviceCgroup);
+
+    // generates rules from a small set of device numbers, so that rules actually overlap
+    impl Arbitrary for TestRule {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let typ = if u8::arbitrary(g) % 8 == 0 {
+                LinuxDeviceType::A
+            } else {
+                *g.choose(TYPES).unwrap()
+            };
+            let access = access_string(u8::arbitrary(g) % 8);
+
+            TestRule(rule(
+                bool::arbitrary(g),
+                typ,
+                *g.choose(IDS).unwrap(),
+                *g.choose(IDS).unwrap(),
+                &access,
+            ))
+        }
+    }
+
+    quickcheck! {
+        fn property_test_emulator_matches_v1(default_allow: bool, rules: Vec<TestRule>) -> TestResult {
+            let mut emulator = Emulator::with_default_allow(default_allow);
+            let mut model = V1Model::new();
+            model.default_allow = default_allow;
+
+            for TestRule(rule) in &rules {
+                if emulator.add_rule(rule).is_err() {
+                    // rules that would punch a hole into a wildcard rule are rejected on purpose
+                    return TestResult::discard();
+                }
+                model.write(rule);
+            }
+
+            let prog = Program::from_rules(&emulator.rules, emulator.default_allow).unwrap();
+            for typ in TYPES {
+                for major in 0..5 {
+                    for minor in 0..5 {
+                        for access in 1..8 {
+                            let expected = model.is_allowed(*typ, major, minor, access);
+                            let actual = prog
+                                .execute(*typ, major as u32, minor as u32, access_string(access))
+                                .unwrap();
+                            if expected != (actual == 1) {
+                                return TestResult::failed();
+                            }
+                        }
+                    }
+                }
+            }
+
+            TestResult::passed()
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use nix::fcntl::OFlag;
+use nix::sys::stat::Mode;
+use oci_spec::runtime::LinuxDeviceType;
+
+use super::{bpf, program};
+
+/// A device program that is attached to a cgroup, as loaded into the kernel
+pub struct AttachedProgram {
+    pub id: u32,
+    pub bytecodes: Vec<u8>,
+}
+
+impl AttachedProgram {
+    /// Returns the disassembled instructions of the program, one per line
+    pub fn disassemble(&self) -> Vec<String> {
+        program::disassemble_to_lines(&self.bytecodes)
+    }
+
+    /// Evaluates the program for an access (a combination of 'r', 'w' and 'm')
+    /// to a device and returns if the access would be allowed
+    pub fn is_allowed(
+        &self,
+        typ: LinuxDeviceType,
+        major: u32,
+        minor: u32,
+        access: &str,
+    ) -> Result<bool> {
+        let result = program::execute(&self.bytecodes, typ, major, minor, access.to_owned())
+            .with_context(|| format!("failed to execute device program {}", self.id))?;
+        Ok(result == 1)
+    }
+}
+
+/// Returns the device programs that are attached to the cgroup. If more than one
+/// program is attached, an access is only allowed if all of them allow it.
+pub fn attached_programs(cgroup_root: &Path) -> Result<Vec<AttachedProgram>> {
+    let fd = nix::dir::Dir::open(
+        cgroup_root.as_os_str(),
+        OFlag::O_RDONLY | OFlag::O_DIRECTORY,
+        Mode::from_bits(0o600).unwrap(),
+    )
+    .with_context(|| format!("failed to open cgroup {}", cgroup_root.display()))?;
+
+    let mut programs = Vec::new();
+    for prog in bpf::prog_query(fd.as_raw_fd())? {
+        let bytecodes = bpf::prog_get_insns(prog.fd);
+        let _ = nix::unistd::close(prog.fd);
+
+        programs.push(AttachedProgram {
+            id: prog.id,
+            bytecodes: bytecodes
+                .with_context(|| format!("failed to get instructions of program {}", prog.id))?,
+        });
+    }
+
+    Ok(programs)
+}
@@ -1,6 +1,7 @@
 pub mod bpf;
 pub mod controller;
 pub mod emulator;
+pub mod inspect;
 pub mod program;
 
 pub use controller::Devices;
@@ -1,7 +1,7 @@
 use anyhow::{bail, Result};
 use oci_spec::runtime::*;
 
-use rbpf::disassembler::disassemble;
+use rbpf::disassembler::{disassemble, to_insn_vec};
 use rbpf::insn_builder::Arch as RbpfArch;
 use rbpf::insn_builder::*;
 
@@ -124,7 +124,6 @@ impl Program {
 
         if has_access {
             next_rule_offset -= 3;
-            // if (R3 & access != R3 /* use R1 as a temp var */) goto next rule
             self.prog
                 .mov(Source::Reg, RbpfArch::X32)
                 .set_dst(1)
@@ -137,12 +136,26 @@ impl Program {
                 .set_imm(access as i32)
                 .push();
 
-            self.prog
-                .jump_conditional(Cond::NotEquals, Source::Reg)
-                .set_dst(1)
-                .set_src(3)
-                .set_off(next_rule_offset)
-                .push();
+            if rule.allow() {
+                // an allow rule must grant all requested access, like match_exception in cgroup v1
+                // if (R3 & access != R3 /* use R1 as a temp var */) goto next rule
+                self.prog
+                    .jump_conditional(Cond::NotEquals, Source::Reg)
+                    .set_dst(1)
+                    .set_src(3)
+                    .set_off(next_rule_offset)
+                    .push();
+            } else {
+                // a deny rule matches if any requested access is denied, like
+                // match_exception_partial in cgroup v1
+                // if (R3 & access == 0 /* use R1 as a temp var */) goto next rule
+                self.prog
+                    .jump_conditional(Cond::Equals, Source::Imm)
+                    .set_dst(1)
+                    .set_imm(0)
+                    .set_off(next_rule_offset)
+                    .push();
+            }
         }
 
         if has_major {
@@ -189,13 +202,33 @@ impl Program {
         minor: u32,
         access: String,
     ) -> Result<u64> {
-        let mut mem = bpf_cgroup_dev_ctx(typ, major, minor, access)?;
-        let vm = rbpf::EbpfVmRaw::new(Some(self.prog.into_bytes()))?;
-        let result = vm.execute_program(&mut mem[..])?;
-        Ok(result)
+        execute(self.prog.into_bytes(), typ, major, minor, access)
     }
 }
 
+/// Returns the disassembled instructions of a device program, one per line
+pub fn disassemble_to_lines(bytecodes: &[u8]) -> Vec<String> {
+    to_insn_vec(bytecodes)
+        .into_iter()
+        .map(|insn| insn.desc)
+        .collect()
+}
+
+/// Executes the device program for an access to a device. Returns 1 if the access
+/// is allowed and 0 if it is denied.
+pub fn execute(
+    bytecodes: &[u8],
+    typ: LinuxDeviceType,
+    major: u32,
+    minor: u32,
+    access: String,
+) -> Result<u64> {
+    let mut mem = bpf_cgroup_dev_ctx(typ, major, minor, access)?;
+    let vm = rbpf::EbpfVmRaw::new(Some(bytecodes))?;
+    let result = vm.execute_program(&mut mem[..])?;
+    Ok(result)
+}
+
 fn bpf_dev_type(typ: LinuxDeviceType) -> Result<u32> {
     let dev_type: u32 = match typ {
         LinuxDeviceType::C => libbpf_sys::BPF_DEVCG_DEV_CHAR,
@@ -306,6 +339,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_disassemble_to_lines() {
+        let prog = build_bpf_program(&Some(vec![])).unwrap();
+        let lines = disassemble_to_lines(prog.bytecodes());
+
+        // 6 instructions to load the context, followed by returning the default
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[6], "mov32 r0, 0x0");
+        assert_eq!(lines[7], "exit");
+    }
+
     #[test]
     fn test_devices_deny_all() {
         let rules = vec![];
@@ -451,9 +495,9 @@ mod tests {
                             "execute {:?} {} {} {} -> {:?}",
                             ty, major, minor, access, ret
                         );
-                        if *ty == LinuxDeviceType::C && *major == 10 && access.eq(&"r") {
-                            assert_eq!(ret.unwrap(), 0);
-                        } else if *ty == LinuxDeviceType::C
+                        // like in cgroup v1 the deny rule has no effect, as there is no allow
+                        // rule for exactly the same devices which it could remove access from
+                        if *ty == LinuxDeviceType::C
                             && *minor == 20
                             && (access.eq(&"r") || access.eq(&"w"))
                         {
@@ -1,4 +1,5 @@
 use std::{
+    cell::RefCell,
     fs::{self},
     os::unix::fs::PermissionsExt,
     path::{Component::RootDir, Path, PathBuf},
@@ -27,13 +28,16 @@ use super::{
     util::{self, CGROUP_SUBTREE_CONTROL},
 };
 use crate::{
-    common::{self, CgroupManager, ControllerOpt, FreezerState, PathBufExt, CGROUP_PROCS},
-    stats::{Stats, StatsProvider},
+    common::{
+        self, CgroupManager, ControllerOpt, DeviceProgram, FreezerState, PathBufExt, CGROUP_PROCS,
+    },
+    stats::{self, Stats, StatsProvider},
 };
 pub struct Manager {
     root_path: PathBuf,
     cgroup_path: PathBuf,
     full_path: PathBuf,
+    device_program: RefCell<DeviceProgram>,
 }
 
 impl Manager {
@@ -46,6 +50,7 @@ impl Manager {
             root_path,
             cgroup_path,
             full_path,
+            device_program: RefCell::new(DeviceProgram::default()),
         })
     }
 
@@ -109,7 +114,11 @@ impl CgroupManager for Manager {
         }
 
         #[cfg(feature = "cgroupsv2_devices")]
-        Devices::apply(controller_opt, &self.cgroup_path)?;
+        Devices::apply_devices(
+            &self.full_path,
+            controller_opt.resources.devices(),
+            &mut self.device_program.borrow_mut(),
+        )?;
 
         for pseudoctlr in PSEUDO_CONTROLLER_TYPES {
             if let PseudoControllerType::Unified = pseudoctlr {
@@ -125,6 +134,9 @@ impl CgroupManager for Manager {
     }
 
     fn remove(&self) -> Result<()> {
+        #[cfg(feature = "cgroupsv2_devices")]
+        Devices::detach(&self.full_path, &self.device_program.borrow())?;
+
         if self.full_path.exists() {
             log::debug!("remove cgroup {:?}", self.full_path);
             let procs_path = self.full_path.join(CGROUP_PROCS);
@@ -161,14 +173,25 @@ impl CgroupManager for Manager {
                 ControllerType::Pids => stats.pids = Pids::stats(&self.full_path)?,
                 ControllerType::Memory => stats.memory = Memory::stats(&self.full_path)?,
                 ControllerType::Io => stats.blkio = Io::stats(&self.full_path)?,
+                ControllerType::CpuSet => stats.cpuset = CpuSet::stats(&self.full_path)?,
                 _ => continue,
             }
         }
 
+        stats.psi = stats::psi_stats(&self.full_path)?;
+
         Ok(stats)
     }
 
     fn get_all_pids(&self) -> Result<Vec<Pid>> {
         common::get_all_pids(&self.full_path)
     }
+
+    fn set_device_program(&self, program: DeviceProgram) {
+        self.device_program.replace(program);
+    }
+
+    fn device_program(&self) -> DeviceProgram {
+        self.device_program.borrow().clone()
+    }
 }
@@ -32,11 +32,15 @@ impl StatsProvider for Memory {
     type Stats = MemoryStats;
 
     fn stats(cgroup_path: &Path) -> Result<Self::Stats> {
+        // memory.stat is always hierarchical on cgroup v2. As the container cgroup
+        // is a leaf, its local statistics are the same as its hierarchical ones.
+        let stat_data = stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))?;
         let stats = MemoryStats {
             memory: Self::get_memory_data(cgroup_path, "memory", "oom")?,
             memswap: Self::get_memory_data(cgroup_path, "memory.swap", "fail")?,
             hierarchy: true,
-            stats: stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))?,
+            stats: stat_data.clone(),
+            hierarchical_stats: stat_data,
             ..Default::default()
         };
 
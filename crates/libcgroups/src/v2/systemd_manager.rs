@@ -1,4 +1,5 @@
 use std::{
+    cell::RefCell,
     fs::{self},
     os::unix::fs::PermissionsExt,
     path::Component::RootDir,
@@ -14,7 +15,7 @@ use super::{
     controller::Controller, controller_type::ControllerType, cpu::Cpu, cpuset::CpuSet,
     freezer::Freezer, hugetlb::HugeTlb, io::Io, memory::Memory, pids::Pids,
 };
-use crate::common::{self, CgroupManager, ControllerOpt, FreezerState, PathBufExt};
+use crate::common::{self, CgroupManager, ControllerOpt, DeviceProgram, FreezerState, PathBufExt};
 use crate::stats::Stats;
 
 const CGROUP_PROCS: &str = "cgroup.procs";
@@ -34,6 +35,7 @@ pub struct SystemDCGroupManager {
     root_path: PathBuf,
     cgroups_path: PathBuf,
     full_path: PathBuf,
+    device_program: RefCell<DeviceProgram>,
 }
 
 /// Represents the systemd cgroups path:
@@ -57,6 +59,7 @@ impl SystemDCGroupManager {
             root_path,
             cgroups_path,
             full_path,
+            device_program: RefCell::new(DeviceProgram::default()),
         })
     }
 
@@ -244,11 +247,17 @@ impl CgroupManager for SystemDCGroupManager {
         }
 
         #[cfg(feature = "cgroupsv2_devices")]
-        Devices::apply(controller_opt, &self.full_path)?;
+        Devices::apply_devices(
+            &self.full_path,
+            controller_opt.resources.devices(),
+            &mut self.device_program.borrow_mut(),
+        )?;
         Ok(())
     }
 
     fn remove(&self) -> Result<()> {
+        #[cfg(feature = "cgroupsv2_devices")]
+        Devices::detach(&self.full_path, &self.device_program.borrow())?;
         Ok(())
     }
 
@@ -269,6 +278,14 @@ impl CgroupManager for SystemDCGroupManager {
     fn get_all_pids(&self) -> Result<Vec<Pid>> {
         common::get_all_pids(&self.full_path)
     }
+
+    fn set_device_program(&self, program: DeviceProgram) {
+        self.device_program.replace(program);
+    }
+
+    fn device_program(&self) -> DeviceProgram {
+        self.device_program.borrow().clone()
+    }
 }
 
 #[cfg(test)]
@@ -3,8 +3,11 @@ use crate::syscall::Syscall;
 use caps::Capability as CapsCapability;
 use caps::*;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use oci_spec::runtime::{Capabilities, Capability as SpecCapability, LinuxCapabilities};
+use std::{fs, path::Path};
+
+const CAP_LAST_CAP: &str = "/proc/sys/kernel/cap_last_cap";
 
 /// Converts a list of capability types to capabilities has set
 fn to_set(caps: &Capabilities) -> CapsHashSet {
@@ -17,6 +20,50 @@ fn to_set(caps: &Capabilities) -> CapsHashSet {
     capabilities
 }
 
+/// Returns the capabilities which are supported by the running kernel
+pub fn supported() -> CapsHashSet {
+    match last_cap(Path::new(CAP_LAST_CAP)) {
+        Ok(last_cap) => caps::all()
+            .into_iter()
+            .filter(|c| c.index() <= last_cap)
+            .collect(),
+        Err(err) => {
+            // procfs may not be mounted, e.g. in the container, but the
+            // bounding set can be queried for any capability the kernel knows
+            log::debug!("failed to read the last capability: {:?}", err);
+            caps::all()
+                .into_iter()
+                .filter(|c| unsafe { libc::prctl(libc::PR_CAPBSET_READ, c.index() as libc::c_ulong) } >= 0)
+                .collect()
+        }
+    }
+}
+
+fn last_cap(cap_last_cap: &Path) -> Result<u8> {
+    let content = fs::read_to_string(cap_last_cap)
+        .with_context(|| format!("failed to read {}", cap_last_cap.display()))?;
+    content
+        .trim()
+        .parse()
+        .with_context(|| format!("failed to parse {} as capability", content.trim()))
+}
+
+/// Removes the capabilities which are not supported by the kernel from the
+/// set, as the kernel would reject the whole set otherwise
+fn filter_supported(cset: CapSet, caps: CapsHashSet, supported: &CapsHashSet) -> CapsHashSet {
+    let (supported, unsupported): (CapsHashSet, CapsHashSet) =
+        caps.into_iter().partition(|c| supported.contains(c));
+    if !unsupported.is_empty() {
+        log::warn!(
+            "ignoring {:?} capabilities {:?}, which are not supported by the kernel",
+            cset,
+            unsupported
+        );
+    }
+
+    supported
+}
+
 pub trait CapabilityExt {
     /// Convert self to caps::Capability
     fn to_cap(&self) -> caps::Capability;
@@ -126,33 +173,66 @@ impl CapabilityExt for SpecCapability {
 /// see https://man7.org/linux/man-pages/man7/capabilities.7.html for more information
 pub fn reset_effective<S: Syscall + ?Sized>(syscall: &S) -> Result<()> {
     log::debug!("reset all caps");
-    syscall.set_capability(CapSet::Effective, &caps::all())?;
+    syscall
+        .set_capability(CapSet::Effective, &supported())
+        .context("failed to reset effective capabilities")?;
     Ok(())
 }
 
-/// Drop any extra granted capabilities, and reset to defaults which are in oci specification
+/// Drop any extra granted capabilities, and reset to defaults which are in oci specification.
+/// This has to be called after the user has been changed with set_id, as changing to a non root
+/// user clears the ambient set.
 pub fn drop_privileges<S: Syscall + ?Sized>(cs: &LinuxCapabilities, syscall: &S) -> Result<()> {
-    log::debug!("dropping bounding capabilities to {:?}", cs.bounding());
-    if let Some(bounding) = cs.bounding() {
-        syscall.set_capability(CapSet::Bounding, &to_set(bounding))?;
+    let supported = supported();
+    let set = |cset: CapSet, caps: CapsHashSet| -> Result<()> {
+        log::debug!("setting {:?} capabilities to {:?}", cset, caps);
+        syscall
+            .set_capability(cset, &caps)
+            .with_context(|| format!("failed to set {:?} capabilities to {:?}", cset, caps))
+    };
+    let spec_set = |cset: CapSet, caps: &Option<Capabilities>| {
+        caps.as_ref()
+            .map(|caps| filter_supported(cset, to_set(caps), &supported))
+    };
+
+    // The kernel only accepts new inheritable capabilities which are in the
+    // bounding set and were inheritable or permitted before, and new
+    // effective capabilities which are permitted. Therefore, the inheritable
+    // and effective sets are changed before the permitted set is reduced.
+    let permitted = spec_set(CapSet::Permitted, cs.permitted());
+    let inheritable = spec_set(CapSet::Inheritable, cs.inheritable());
+    if let Some(bounding) = spec_set(CapSet::Bounding, cs.bounding()) {
+        set(CapSet::Bounding, bounding)?;
     }
 
-    if let Some(effective) = cs.effective() {
-        syscall.set_capability(CapSet::Effective, &to_set(effective))?;
+    if let Some(inheritable) = &inheritable {
+        set(CapSet::Inheritable, inheritable.clone())?;
     }
 
-    if let Some(permitted) = cs.permitted() {
-        syscall.set_capability(CapSet::Permitted, &to_set(permitted))?;
+    if let Some(effective) = spec_set(CapSet::Effective, cs.effective()) {
+        set(CapSet::Effective, effective)?;
     }
 
-    if let Some(inheritable) = cs.inheritable() {
-        syscall.set_capability(CapSet::Inheritable, &to_set(inheritable))?;
+    if let Some(permitted) = &permitted {
+        set(CapSet::Permitted, permitted.clone())?;
     }
 
-    if let Some(ambient) = cs.ambient() {
+    if let Some(ambient) = spec_set(CapSet::Ambient, cs.ambient()) {
+        // ambient capabilities have to be both permitted and inheritable
+        let (ambient, ignored): (CapsHashSet, CapsHashSet) = ambient.into_iter().partition(|c| {
+            permitted.as_ref().map_or(true, |p| p.contains(c))
+                && inheritable.as_ref().map_or(true, |i| i.contains(c))
+        });
+        if !ignored.is_empty() {
+            log::warn!(
+                "ignoring ambient capabilities {:?}, which are not permitted and inheritable",
+                ignored
+            );
+        }
+
         // check specifically for ambient, as those might not always be available
-        if let Err(e) = syscall.set_capability(CapSet::Ambient, &to_set(ambient)) {
-            log::error!("failed to set ambient capabilities: {}", e);
+        if let Err(e) = set(CapSet::Ambient, ambient) {
+            log::error!("{:?}", e);
         }
     }
 
@@ -166,6 +246,7 @@ mod tests {
 
     use super::*;
     use crate::syscall::test::TestHelperSyscall;
+    use crate::utils::create_temp_dir;
 
     #[test]
     fn test_reset_effective() {
@@ -176,7 +257,64 @@ mod tests {
             .into_iter()
             .map(|(_capset, caps)| caps)
             .collect();
-        assert_eq!(set_capability_args, vec![caps::all()]);
+        assert_eq!(set_capability_args, vec![supported()]);
+    }
+
+    #[test]
+    fn test_last_cap() -> Result<()> {
+        let tmp = create_temp_dir("test_last_cap")?;
+        let path = tmp.join("cap_last_cap");
+        fs::write(&path, "40\n")?;
+        assert_eq!(last_cap(&path)?, 40);
+
+        fs::write(&path, "invalid")?;
+        assert!(last_cap(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_supported() {
+        let supported = supported();
+        // capabilities before CAP_AUDIT_READ are supported since Linux 3.16
+        assert!(caps::all()
+            .iter()
+            .filter(|c| c.index() <= CapsCapability::CAP_AUDIT_READ.index())
+            .all(|c| supported.contains(c)));
+    }
+
+    #[test]
+    fn test_filter_supported() {
+        let supported: CapsHashSet = vec![CapsCapability::CAP_CHOWN, CapsCapability::CAP_KILL]
+            .into_iter()
+            .collect();
+        let caps: CapsHashSet = vec![CapsCapability::CAP_KILL, CapsCapability::CAP_BPF]
+            .into_iter()
+            .collect();
+        let got = filter_supported(CapSet::Permitted, caps, &supported);
+        assert_eq!(got, vec![CapsCapability::CAP_KILL].into_iter().collect());
+    }
+
+    #[test]
+    fn test_drop_privileges_ambient() {
+        let permitted = vec![SpecCapability::Kill, SpecCapability::Chown];
+        let inheritable = vec![SpecCapability::Kill, SpecCapability::NetRaw];
+        let ambient = vec![
+            SpecCapability::Kill,
+            SpecCapability::Chown,
+            SpecCapability::NetRaw,
+        ];
+        let input = LinuxCapabilitiesBuilder::default()
+            .permitted(permitted.into_iter().collect::<Capabilities>())
+            .inheritable(inheritable.into_iter().collect::<Capabilities>())
+            .ambient(ambient.into_iter().collect::<Capabilities>())
+            .build()
+            .unwrap();
+        let test_command = TestHelperSyscall::default();
+        drop_privileges(&input, &test_command).unwrap();
+
+        let (cset, got) = test_command.get_set_capability_args().pop().unwrap();
+        assert_eq!(format!("{:?}", cset), format!("{:?}", CapSet::Ambient));
+        assert_eq!(got, vec![CapsCapability::CAP_KILL].into_iter().collect());
     }
 
     #[test]
@@ -568,9 +706,9 @@ mod tests {
                     .unwrap(),
                 want: vec![
                     (CapSet::Bounding, cps.clone()),
+                    (CapSet::Inheritable, cps.clone()),
                     (CapSet::Effective, cps.clone()),
                     (CapSet::Permitted, cps.clone()),
-                    (CapSet::Inheritable, cps.clone()),
                     (CapSet::Ambient, cps.clone()),
                 ],
             },
@@ -584,9 +722,9 @@ mod tests {
                     .unwrap(),
                 want: vec![
                     (CapSet::Bounding, cps.clone()),
+                    (CapSet::Inheritable, cps.clone()),
                     (CapSet::Effective, cps.clone()),
                     (CapSet::Permitted, cps.clone()),
-                    (CapSet::Inheritable, cps.clone()),
                     (CapSet::Ambient, cps.clone()),
                 ],
             },
@@ -602,9 +740,9 @@ mod tests {
                     .unwrap(),
                 want: vec![
                     (CapSet::Bounding, cps.clone()),
+                    (CapSet::Inheritable, cps.clone()),
                     (CapSet::Effective, cps.clone()),
                     (CapSet::Permitted, cps.clone()),
-                    (CapSet::Inheritable, cps.clone()),
                     (CapSet::Ambient, cps),
                 ],
             },
@@ -1,13 +1,17 @@
 use super::{Container, ContainerStatus};
 use crate::{
     hooks,
+    intel_rdt::IntelRdt,
     notify_socket::NotifyListener,
     process::{self, args::ContainerArgs},
+    rootfs::overlay::OverlayRootfs,
     rootless::Rootless,
+    spec_extensions::SpecExtensions,
     syscall::Syscall,
     utils,
 };
 use anyhow::{bail, Context, Result};
+use libcgroups::common::DeviceProgram;
 use oci_spec::runtime::Spec;
 use std::{fs, io::Write, os::unix::prelude::RawFd, path::PathBuf};
 
@@ -37,6 +41,10 @@ pub(super) struct ContainerBuilderImpl<'a> {
     pub container: Option<Container>,
     /// File descriptos preserved/passed to the container init process.
     pub preserve_fds: i32,
+    /// Parts of the spec, which are unknown to the runtime spec types
+    pub extensions: SpecExtensions,
+    /// Flag indicating if the seccomp profile was created by the seccomp audit mode
+    pub seccomp_audit: bool,
 }
 
 impl<'a> ContainerBuilderImpl<'a> {
@@ -56,6 +64,9 @@ impl<'a> ContainerBuilderImpl<'a> {
         let linux = self.spec.linux().as_ref().context("no linux in spec")?;
         let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), &self.container_id);
         let cmanager = libcgroups::common::create_cgroup_manager(&cgroups_path, self.use_systemd)?;
+        if let Some(container) = &self.container {
+            cmanager.set_device_program(container.device_program());
+        }
         let process = self.spec.process().as_ref().context("No process in spec")?;
 
         if self.init {
@@ -111,19 +122,47 @@ impl<'a> ContainerBuilderImpl<'a> {
             container: &self.container,
             rootless: &self.rootless,
             cgroup_manager: cmanager,
+            extensions: &self.extensions,
+            seccomp_audit: self.seccomp_audit,
         };
 
-        let init_pid = process::container_main_process::container_main_process(&container_args)?;
+        let (init_pid, device_program) =
+            process::container_main_process::container_main_process(&container_args)?;
 
         // if file to write the pid to is specified, write pid of the child
         if let Some(pid_file) = &self.pid_file {
             fs::write(&pid_file, format!("{}", init_pid)).context("failed to write pid file")?;
         }
 
+        // The container process is still waiting for the start of the container,
+        // so it has not created any children, which would have to be moved into
+        // the resctrl group as well.
+        if let Some(intel_rdt) = linux.intel_rdt() {
+            if self.init {
+                let created = IntelRdt::from_spec(intel_rdt, &self.container_id)
+                    .and_then(|rdt| rdt.apply(init_pid))
+                    .context("failed to apply intel rdt")?;
+                if let Some(container) = &mut self.container {
+                    container.set_intel_rdt_created(created);
+                }
+            } else {
+                IntelRdt::lookup(intel_rdt, &self.container_id)
+                    .and_then(|rdt| rdt.context("resctrl filesystem is not mounted"))
+                    .and_then(|rdt| rdt.add_task(init_pid))
+                    .context("failed to join intel rdt group")?;
+            }
+        }
+
         if let Some(container) = &mut self.container {
-            // update status and pid of the container process
+            // update status and pid of the container process and remember the device
+            // program, so that it can be detached when the container is deleted
+            let device_program = DeviceProgram {
+                id: device_program,
+                ..container.device_program()
+            };
             container
                 .set_status(ContainerStatus::Created)
+                .set_device_program(&device_program)
                 .set_creator(nix::unistd::geteuid().as_raw())
                 .set_pid(init_pid.as_raw())
                 .save()
@@ -137,13 +176,37 @@ impl<'a> ContainerBuilderImpl<'a> {
         let linux = self.spec.linux().as_ref().context("no linux in spec")?;
         let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), &self.container_id);
         let cmanager = libcgroups::common::create_cgroup_manager(&cgroups_path, self.use_systemd)?;
+        if let Some(container) = &self.container {
+            cmanager.set_device_program(container.device_program());
+        }
 
         let mut errors = Vec::new();
         if let Err(e) = cmanager.remove().context("failed to remove cgroup") {
             errors.push(e.to_string());
         }
 
+        // the resctrl group of the container is left alone, if a tenant failed
+        if let (Some(intel_rdt), true) = (linux.intel_rdt(), self.init) {
+            let created = self
+                .container
+                .as_ref()
+                .map_or(false, |c| c.intel_rdt_created());
+            let res = IntelRdt::lookup(intel_rdt, &self.container_id)
+                .and_then(|rdt| rdt.map_or(Ok(()), |rdt| rdt.remove(created)))
+                .context("failed to remove intel rdt group");
+            if let Err(e) = res {
+                errors.push(e.to_string());
+            }
+        }
+
         if let Some(container) = &self.container {
+            let res = OverlayRootfs::from_spec(self.spec, container.bundle(), &container.root)
+                .and_then(|overlay| overlay.map_or(Ok(()), |overlay| overlay.remove(&self.rootfs)))
+                .context("failed to remove overlay rootfs");
+            if let Err(e) = res {
+                errors.push(e.to_string());
+            }
+
             if container.root.exists() {
                 if let Err(e) = fs::remove_dir_all(&container.root)
                     .with_context(|| format!("could not delete {:?}", container.root))
@@ -8,6 +8,7 @@ use chrono::DateTime;
 use nix::unistd::Pid;
 
 use chrono::Utc;
+use libcgroups::common::DeviceProgram;
 use oci_spec::runtime::Spec;
 use procfs::process::Process;
 
@@ -125,6 +126,37 @@ impl Container {
         self
     }
 
+    pub fn seccomp_audit(&self) -> bool {
+        self.state.seccomp_audit.unwrap_or(false)
+    }
+
+    pub fn set_seccomp_audit(&mut self, audit: bool) -> &mut Self {
+        self.state.seccomp_audit = Some(audit);
+        self
+    }
+
+    pub fn intel_rdt_created(&self) -> bool {
+        self.state.intel_rdt_created.unwrap_or(false)
+    }
+
+    pub fn set_intel_rdt_created(&mut self, created: bool) -> &mut Self {
+        self.state.intel_rdt_created = Some(created);
+        self
+    }
+
+    pub fn device_program(&self) -> DeviceProgram {
+        DeviceProgram {
+            id: self.state.device_program,
+            pin_dir: self.state.bpf_pin_dir.clone(),
+        }
+    }
+
+    pub fn set_device_program(&mut self, program: &DeviceProgram) -> &mut Self {
+        self.state.device_program = program.id;
+        self.state.bpf_pin_dir = program.pin_dir.clone();
+        self
+    }
+
     pub fn status(&self) -> ContainerStatus {
         self.state.status
     }
@@ -261,6 +293,40 @@ mod tests {
         assert_eq!(container.systemd(), Some(false));
     }
 
+    #[test]
+    fn test_get_set_seccomp_audit() {
+        let mut container = Container::default();
+        assert!(!container.seccomp_audit());
+        container.set_seccomp_audit(true);
+        assert!(container.seccomp_audit());
+        assert_eq!(container.state.seccomp_audit, Some(true));
+    }
+
+    #[test]
+    fn test_get_set_intel_rdt_created() {
+        let mut container = Container::default();
+        assert!(!container.intel_rdt_created());
+        container.set_intel_rdt_created(true);
+        assert!(container.intel_rdt_created());
+    }
+
+    #[test]
+    fn test_get_set_device_program() {
+        let mut container = Container::default();
+        assert_eq!(container.device_program(), DeviceProgram::default());
+        let program = DeviceProgram {
+            id: Some(42),
+            pin_dir: Some(PathBuf::from("/sys/fs/bpf")),
+        };
+        container.set_device_program(&program);
+        assert_eq!(container.device_program(), program);
+        assert_eq!(container.state.device_program, Some(42));
+        assert_eq!(
+            container.state.bpf_pin_dir,
+            Some(PathBuf::from("/sys/fs/bpf"))
+        );
+    }
+
     #[test]
     fn test_get_set_creator() {
         let mut container = Container::default();
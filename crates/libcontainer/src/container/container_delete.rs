@@ -1,5 +1,7 @@
 use super::{Container, ContainerStatus};
 use crate::hooks;
+use crate::intel_rdt::IntelRdt;
+use crate::rootfs::overlay::OverlayRootfs;
 use crate::utils;
 use anyhow::{bail, Context, Result};
 use libcgroups;
@@ -41,19 +43,30 @@ impl Container {
                 })?;
                 log::debug!("spec: {:?}", spec);
 
+                if let Some(overlay) = OverlayRootfs::from_spec(&spec, self.bundle(), &self.root)? {
+                    let rootfs = spec.root().as_ref().context("no root in spec")?.path();
+                    overlay
+                        .remove(rootfs)
+                        .context("failed to remove overlay rootfs")?;
+                }
+
+                let linux = spec.linux().as_ref().context("no linux in spec")?;
+                // the resctrl group is removed while the container state, which
+                // records if the group was created for the container, exists
+                if let Some(intel_rdt) = linux.intel_rdt() {
+                    if let Some(rdt) = IntelRdt::lookup(intel_rdt, self.id())? {
+                        rdt.remove(self.intel_rdt_created())
+                            .context("failed to remove intel rdt group")?;
+                    }
+                }
+
                 // remove the directory storing container state
                 log::debug!("remove dir {:?}", self.root);
                 fs::remove_dir_all(&self.root).with_context(|| {
                     format!("failed to remove container dir {}", self.root.display())
                 })?;
 
-                let cgroups_path = utils::get_cgroup_path(
-                    spec.linux()
-                        .as_ref()
-                        .context("no linux in spec")?
-                        .cgroups_path(),
-                    self.id(),
-                );
+                let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), self.id());
 
                 // remove the cgroup created for the container
                 // check https://man7.org/linux/man-pages/man7/cgroups.7.html
@@ -64,6 +77,7 @@ impl Container {
                 let cmanager =
                     libcgroups::common::create_cgroup_manager(&cgroups_path, use_systemd)
                         .context("failed to create cgroup manager")?;
+                cmanager.set_device_program(self.device_program());
                 cmanager.remove().with_context(|| {
                     format!("failed to remove cgroup {}", cgroups_path.display())
                 })?;
@@ -1,9 +1,22 @@
 use std::{thread, time::Duration};
 
+use crate::intel_rdt::{IntelRdt, IntelRdtStats};
 use crate::utils;
 
 use super::{Container, ContainerStatus};
 use anyhow::{bail, Context, Result};
+use libcgroups::{common::CgroupManager, stats::Stats};
+use serde::Serialize;
+
+/// Reports the statistics of the cgroup and of the Intel RDT group of a
+/// container
+#[derive(Debug, Serialize)]
+pub struct ContainerStats {
+    #[serde(flatten)]
+    pub cgroup: Stats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intel_rdt: Option<IntelRdtStats>,
+}
 
 impl Container {
     /// Displays container events
@@ -30,14 +43,13 @@ impl Container {
             bail!("{} is not in running state", self.id());
         }
 
-        let cgroups_path = utils::get_cgroup_path(
-            self.spec()?
-                .linux()
-                .as_ref()
-                .context("no linux in spec")?
-                .cgroups_path(),
-            self.id(),
-        );
+        let spec = self.spec()?;
+        let linux = spec.linux().as_ref().context("no linux in spec")?;
+        let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), self.id());
+        let intel_rdt = match linux.intel_rdt() {
+            Some(intel_rdt) => IntelRdt::lookup(intel_rdt, self.id())?,
+            None => None,
+        };
         let use_systemd = self
             .systemd()
             .context("Could not determine cgroup manager")?;
@@ -45,11 +57,11 @@ impl Container {
         let cgroup_manager = libcgroups::common::create_cgroup_manager(cgroups_path, use_systemd)?;
         match stats {
             true => {
-                let stats = cgroup_manager.stats()?;
+                let stats = Self::stats(cgroup_manager.as_ref(), &intel_rdt)?;
                 println!("{}", serde_json::to_string_pretty(&stats)?);
             }
             false => loop {
-                let stats = cgroup_manager.stats()?;
+                let stats = Self::stats(cgroup_manager.as_ref(), &intel_rdt)?;
                 println!("{}", serde_json::to_string_pretty(&stats)?);
                 thread::sleep(Duration::from_secs(interval as u64));
             },
@@ -57,4 +69,19 @@ impl Container {
 
         Ok(())
     }
+
+    fn stats(
+        cgroup_manager: &dyn CgroupManager,
+        intel_rdt: &Option<IntelRdt>,
+    ) -> Result<ContainerStats> {
+        let intel_rdt = match intel_rdt {
+            Some(intel_rdt) => Some(intel_rdt.stats().context("failed to get intel rdt stats")?),
+            None => None,
+        };
+
+        Ok(ContainerStats {
+            cgroup: cgroup_manager.stats()?,
+            intel_rdt,
+        })
+    }
 }
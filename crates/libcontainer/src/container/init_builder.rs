@@ -1,4 +1,5 @@
 use anyhow::{bail, Context, Result};
+use libcgroups::common::DeviceProgram;
 use nix::unistd;
 use oci_spec::runtime::Spec;
 use rootless::Rootless;
@@ -7,7 +8,10 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::{apparmor, notify_socket::NOTIFY_FILE, rootless, tty, utils};
+use crate::{
+    apparmor, notify_socket::NOTIFY_FILE, rootfs::device, rootless, seccomp, selinux,
+    spec_extensions, spec_extensions::SpecExtensions, tty, utils,
+};
 
 use super::{
     builder::ContainerBuilder, builder_impl::ContainerBuilderImpl, Container, ContainerStatus,
@@ -18,6 +22,8 @@ pub struct InitContainerBuilder<'a> {
     base: ContainerBuilder<'a>,
     bundle: PathBuf,
     use_systemd: bool,
+    seccomp_audit: Option<PathBuf>,
+    bpf_pin_dir: Option<PathBuf>,
 }
 
 impl<'a> InitContainerBuilder<'a> {
@@ -28,6 +34,8 @@ impl<'a> InitContainerBuilder<'a> {
             base: builder,
             bundle,
             use_systemd: true,
+            seccomp_audit: None,
+            bpf_pin_dir: None,
         }
     }
 
@@ -37,15 +45,36 @@ impl<'a> InitContainerBuilder<'a> {
         self
     }
 
+    /// Runs the container in seccomp audit mode. Syscalls denied by the seccomp
+    /// profile are sent to the seccomp agent listening on the given path
+    /// instead, see [`seccomp::audit`].
+    pub fn with_seccomp_audit<P: Into<PathBuf>>(mut self, listener_path: Option<P>) -> Self {
+        self.seccomp_audit = listener_path.map(|p| p.into());
+        self
+    }
+
+    /// Pins the BPF program which restricts the devices of the container to the
+    /// given directory in a bpf filesystem, so that it can be inspected with
+    /// tools like bpftool. Only used with cgroup v2.
+    pub fn with_bpf_pin_dir<P: Into<PathBuf>>(mut self, pin_dir: Option<P>) -> Self {
+        self.bpf_pin_dir = pin_dir.map(|p| p.into());
+        self
+    }
+
     /// Creates a new container
     pub fn build(self) -> Result<Container> {
-        let spec = self.load_spec()?;
+        let (spec, extensions) = self.load_spec()?;
         let container_dir = self.create_container_dir()?;
         self.save_spec(&spec, &container_dir)?;
 
         let mut container = self.create_container_state(&container_dir)?;
         container
             .set_systemd(self.use_systemd)
+            .set_seccomp_audit(self.seccomp_audit.is_some())
+            .set_device_program(&DeviceProgram {
+                id: None,
+                pin_dir: self.bpf_pin_dir.clone(),
+            })
             .set_annotations(spec.annotations().clone());
 
         unistd::chdir(&container_dir)?;
@@ -79,6 +108,8 @@ impl<'a> InitContainerBuilder<'a> {
             notify_path,
             container: Some(container.clone()),
             preserve_fds: self.base.preserve_fds,
+            extensions,
+            seccomp_audit: self.seccomp_audit.is_some(),
         };
 
         builder_impl.create()?;
@@ -99,13 +130,33 @@ impl<'a> InitContainerBuilder<'a> {
         Ok(container_dir)
     }
 
-    fn load_spec(&self) -> Result<Spec> {
+    fn load_spec(&self) -> Result<(Spec, SpecExtensions)> {
         let source_spec_path = self.bundle.join("config.json");
-        let mut spec = Spec::load(&source_spec_path)?;
+        let (mut spec, extensions) = spec_extensions::load_spec(&source_spec_path)?;
         Self::validate_spec(&spec).context("failed to validate runtime spec")?;
 
         spec.canonicalize_rootfs(&self.bundle)?;
-        Ok(spec)
+        device::add_optional_devices(&mut spec).context("failed to add optional devices")?;
+        if let Some(listener_path) = &self.seccomp_audit {
+            Self::audit_seccomp(&mut spec, listener_path)?;
+        }
+        Ok((spec, extensions))
+    }
+
+    fn audit_seccomp(spec: &mut Spec, listener_path: &Path) -> Result<()> {
+        let mut linux = spec
+            .linux()
+            .clone()
+            .context("seccomp audit requires linux in the runtime spec")?;
+        let profile = linux
+            .seccomp()
+            .as_ref()
+            .context("seccomp audit requires a seccomp profile in the runtime spec")?;
+        let audit = seccomp::audit::audit_profile(profile, listener_path)
+            .context("failed to create seccomp audit profile")?;
+        linux.set_seccomp(Some(audit));
+        spec.set_linux(Some(linux));
+        Ok(())
     }
 
     fn validate_spec(spec: &Spec) -> Result<()> {
@@ -126,6 +177,16 @@ impl<'a> InitContainerBuilder<'a> {
                     );
                 }
             }
+
+            if let Some(label) = process.selinux_label() {
+                if !selinux::is_enabled()? {
+                    bail!(
+                        "selinux label {} is specified in runtime spec, \
+                    but selinux is not activated on this system",
+                        label
+                    );
+                }
+            }
         }
 
         Ok(())
@@ -97,6 +97,18 @@ pub struct State {
     pub creator: Option<u32>,
     // Specifies if systemd should be used to manage cgroups
     pub use_systemd: Option<bool>,
+    // Id of the BPF program which restricts the devices of the cgroup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_program: Option<u32>,
+    // Directory in a bpf filesystem the device program is pinned to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bpf_pin_dir: Option<PathBuf>,
+    // Specifies if the seccomp profile was created by the seccomp audit mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seccomp_audit: Option<bool>,
+    // Specifies if the resctrl group of the CLOS id was created for the container
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intel_rdt_created: Option<bool>,
 }
 
 impl State {
@@ -118,6 +130,10 @@ impl State {
             created: None,
             creator: None,
             use_systemd: None,
+            device_program: None,
+            bpf_pin_dir: None,
+            seccomp_audit: None,
+            intel_rdt_created: None,
         }
     }
 
@@ -18,7 +18,10 @@ use std::{
 };
 
 use crate::{capabilities::CapabilityExt, container::builder_impl::ContainerBuilderImpl};
-use crate::{notify_socket::NotifySocket, rootless::Rootless, tty, utils};
+use crate::{
+    notify_socket::NotifySocket, rootless::Rootless, scheduling::Scheduling,
+    spec_extensions::SpecExtensions, time_namespace::TimeNamespace, tty, utils,
+};
 
 use super::{builder::ContainerBuilder, Container};
 
@@ -100,6 +103,14 @@ impl<'a> TenantContainerBuilder<'a> {
             .context("failed to load init spec")?;
         self.adapt_spec_for_tenant(&mut spec, &container)
             .context("failed to adapt spec for tenant")?;
+        let extensions = SpecExtensions {
+            time_namespace: self
+                .get_time_namespace(&container)
+                .context("failed to get time namespace of the container")?,
+            scheduling: self
+                .get_scheduling()
+                .context("failed to get scheduling attributes")?,
+        };
 
         log::debug!("{:#?}", spec);
 
@@ -128,6 +139,8 @@ impl<'a> TenantContainerBuilder<'a> {
             notify_path: notify_path.clone(),
             container: None,
             preserve_fds: self.base.preserve_fds,
+            extensions,
+            seccomp_audit: container.seccomp_audit(),
         };
 
         builder_impl.create()?;
@@ -193,7 +206,9 @@ impl<'a> TenantContainerBuilder<'a> {
 
         let init_process = procfs::process::Process::new(container.pid().unwrap().as_raw())?;
         let ns = self.get_namespaces(init_process.namespaces()?)?;
-        let linux = LinuxBuilder::default().namespaces(ns).build()?;
+        let mut linux = LinuxBuilder::default().namespaces(ns).build()?;
+        // the tenant joins the resctrl group of the container
+        linux.set_intel_rdt(spec.linux().as_ref().and_then(|l| l.intel_rdt().clone()));
 
         spec.set_process(Some(process)).set_linux(Some(linux));
         Ok(())
@@ -212,6 +227,18 @@ impl<'a> TenantContainerBuilder<'a> {
         Ok(process_spec)
     }
 
+    // The scheduling attributes are not known to the runtime spec types, so they
+    // are read from the raw process.json.
+    fn get_scheduling(&self) -> Result<Scheduling> {
+        match &self.process {
+            Some(process) => {
+                let process: serde_json::Value = serde_json::from_reader(utils::open(process)?)?;
+                Scheduling::from_process(&process)
+            }
+            None => Ok(Scheduling::default()),
+        }
+    }
+
     fn get_working_dir(&self) -> Result<Option<PathBuf>> {
         if let Some(cwd) = &self.cwd {
             if cwd.is_relative() {
@@ -335,6 +362,24 @@ impl<'a> TenantContainerBuilder<'a> {
         Ok(tenant_namespaces)
     }
 
+    // The time namespace is not known to the runtime spec, so it is joined
+    // separately from the other namespaces.
+    fn get_time_namespace(&self, container: &Container) -> Result<Option<TimeNamespace>> {
+        let pid = container
+            .pid()
+            .context("could not retrieve container init pid")?;
+        let init_process = procfs::process::Process::new(pid.as_raw())?;
+        let time_namespace = init_process
+            .namespaces()?
+            .into_iter()
+            .find(|ns| ns.ns_type == "time")
+            .map(|ns| TimeNamespace {
+                path: Some(ns.path),
+                ..Default::default()
+            });
+        Ok(time_namespace)
+    }
+
     fn should_use_systemd(&self, container: &Container) -> bool {
         if let Some(use_systemd) = container.systemd() {
             return use_systemd;
@@ -0,0 +1,492 @@
+//! Intel Resource Director Technology (RDT) allocates the L3 cache and the
+//! memory bandwidth to groups of tasks. Each group is a directory of the
+//! resctrl filesystem, identified by its class of service id (CLOS id).
+//! See https://www.kernel.org/doc/html/latest/x86/resctrl.html
+use anyhow::{bail, Context, Result};
+use nix::{mount::MsFlags, unistd::Pid};
+use oci_spec::runtime::LinuxIntelRdt;
+use serde::Serialize;
+use std::{
+    ffi::OsStr,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::utils;
+
+const RESCTRL_FSTYPE: &str = "resctrl";
+const RESCTRL_MOUNT_POINT: &str = "/sys/fs/resctrl";
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+
+/// Monitoring data of a cache domain
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct MonitoringStats {
+    /// Name of the monitoring domain, e.g. mon_L3_00
+    pub domain: String,
+    /// Current L3 cache occupancy in bytes
+    pub llc_occupancy: Option<u64>,
+    /// Total memory bandwidth in bytes
+    pub mbm_total_bytes: Option<u64>,
+    /// Memory bandwidth to the local NUMA node in bytes
+    pub mbm_local_bytes: Option<u64>,
+}
+
+/// Reports the resource allocation and monitoring data of an RDT group
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct IntelRdtStats {
+    /// Schemata of the group as reported by the kernel
+    pub schemata: String,
+    /// Monitoring data per cache domain
+    pub monitoring: Vec<MonitoringStats>,
+}
+
+/// The resctrl group of a container
+#[derive(Debug, Clone)]
+pub struct IntelRdt {
+    /// Mount point of the resctrl filesystem
+    root: PathBuf,
+    /// Name of the group, which is the CLOS id
+    clos_id: String,
+    /// Groups which are named after their CLOS id may be shared with other
+    /// containers and are therefore only removed with the container, if they
+    /// were created for it.
+    owned: bool,
+    l3_cache_schema: Option<String>,
+    mem_bw_schema: Option<String>,
+}
+
+impl IntelRdt {
+    /// Creates the resctrl group of a container in the resctrl filesystem
+    /// mounted at root. Without a CLOS id, the group is named after the
+    /// container.
+    pub fn new(root: PathBuf, intel_rdt: &LinuxIntelRdt, container_id: &str) -> Result<Self> {
+        let (clos_id, owned) = match intel_rdt.clos_id() {
+            Some(clos_id) => (clos_id.clone(), false),
+            None => (container_id.to_owned(), true),
+        };
+        if clos_id.is_empty() || clos_id.contains('/') || clos_id == "." || clos_id == ".." {
+            bail!("{:?} is not a valid closID", clos_id);
+        }
+
+        Ok(Self {
+            root,
+            clos_id,
+            owned,
+            l3_cache_schema: intel_rdt.l3_cache_schema().clone(),
+            mem_bw_schema: intel_rdt.mem_bw_schema().clone(),
+        })
+    }
+
+    /// Creates the resctrl group of a container in the resctrl filesystem of
+    /// the host, which is mounted if necessary.
+    pub fn from_spec(intel_rdt: &LinuxIntelRdt, container_id: &str) -> Result<Self> {
+        let root = match find_resctrl(Path::new(MOUNTINFO))? {
+            Some(root) => root,
+            None => mount_resctrl()?,
+        };
+        Self::new(root, intel_rdt, container_id)
+    }
+
+    /// Looks up the resctrl group of an existing container. Returns None if
+    /// the resctrl filesystem is not mounted.
+    pub fn lookup(intel_rdt: &LinuxIntelRdt, container_id: &str) -> Result<Option<Self>> {
+        match find_resctrl(Path::new(MOUNTINFO))? {
+            Some(root) => Ok(Some(Self::new(root, intel_rdt, container_id)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.root.join(&self.clos_id)
+    }
+
+    /// Creates the group if it does not exist yet, writes the schemata and
+    /// moves the process into the group. Children of the process inherit the
+    /// group. Returns true if the group was created.
+    pub fn apply(&self, pid: Pid) -> Result<bool> {
+        self.validate()?;
+
+        let path = self.path();
+        let created = !path.exists();
+        if created {
+            // the kernel populates the new directory with the control files
+            fs::create_dir(&path)
+                .with_context(|| format!("failed to create resctrl group {:?}", path))?;
+        }
+
+        let schemata = self.schemata();
+        if !schemata.is_empty() {
+            utils::write_file(path.join("schemata"), schemata)
+                .with_context(|| format!("failed to write schemata of {:?}", path))?;
+        }
+
+        self.add_task(pid)?;
+        Ok(created)
+    }
+
+    /// Moves the process into the existing group, e.g. a process executed in
+    /// the container by a tenant.
+    pub fn add_task(&self, pid: Pid) -> Result<()> {
+        let path = self.path();
+        utils::write_file(path.join("tasks"), pid.to_string())
+            .with_context(|| format!("failed to add {} to resctrl group {:?}", pid, path))
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.l3_cache_schema.is_some() && !self.root.join("info/L3").exists() {
+            bail!("l3CacheSchema is specified, but L3 cache allocation is not supported");
+        }
+
+        if self.mem_bw_schema.is_some() && !self.root.join("info/MB").exists() {
+            bail!("memBwSchema is specified, but memory bandwidth allocation is not supported");
+        }
+
+        Ok(())
+    }
+
+    /// Schemata as written to the schemata file. Each schema may consist of
+    /// several lines, one per resource.
+    fn schemata(&self) -> String {
+        [&self.l3_cache_schema, &self.mem_bw_schema]
+            .iter()
+            .filter_map(|schema| schema.as_deref())
+            .flat_map(|schema| schema.lines())
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    /// Reads the schemata and the monitoring data of the group
+    pub fn stats(&self) -> Result<IntelRdtStats> {
+        let path = self.path();
+        let schemata = fs::read_to_string(path.join("schemata"))
+            .with_context(|| format!("failed to read schemata of {:?}", path))?;
+
+        let mut monitoring = Vec::new();
+        let mon_data = path.join("mon_data");
+        if mon_data.exists() {
+            for entry in
+                fs::read_dir(&mon_data).with_context(|| format!("failed to read {:?}", mon_data))?
+            {
+                let domain = entry?.path();
+                monitoring.push(MonitoringStats {
+                    domain: domain
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    llc_occupancy: read_counter(&domain.join("llc_occupancy"))?,
+                    mbm_total_bytes: read_counter(&domain.join("mbm_total_bytes"))?,
+                    mbm_local_bytes: read_counter(&domain.join("mbm_local_bytes"))?,
+                });
+            }
+            monitoring.sort_by(|a, b| a.domain.cmp(&b.domain));
+        }
+
+        Ok(IntelRdtStats {
+            schemata,
+            monitoring,
+        })
+    }
+
+    /// Removes the group, if it is named after the container or if it was
+    /// created by apply, as reported by created. The tasks of a removed group
+    /// are moved back to the default group by the kernel.
+    pub fn remove(&self, created: bool) -> Result<()> {
+        let path = self.path();
+        if !(self.owned || created) || !path.exists() {
+            return Ok(());
+        }
+
+        // the control files can't be removed, but rmdir succeeds nonetheless
+        fs::remove_dir(&path).with_context(|| format!("failed to remove resctrl group {:?}", path))
+    }
+}
+
+/// Counters which are not supported by the hardware are missing or report
+/// "Unavailable".
+fn read_counter(path: &Path) -> Result<Option<u64>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+    Ok(content.trim().parse().ok())
+}
+
+/// Finds the mount point of the resctrl filesystem in mountinfo
+fn find_resctrl(mountinfo: &Path) -> Result<Option<PathBuf>> {
+    let content =
+        fs::read_to_string(mountinfo).with_context(|| format!("failed to read {:?}", mountinfo))?;
+    for line in content.lines() {
+        // the optional fields are terminated by a single hyphen, which is
+        // followed by the filesystem type
+        let (mount, fs) = match line.split_once(" - ") {
+            Some(fields) => fields,
+            None => continue,
+        };
+        if fs.split_whitespace().next() != Some(RESCTRL_FSTYPE) {
+            continue;
+        }
+
+        if let Some(mount_point) = mount.split_whitespace().nth(4) {
+            return Ok(Some(unescape_mount_point(mount_point)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Decodes the octal escapes of the mountinfo, which the kernel uses for the
+/// space, tab, newline and backslash in paths, e.g. "\040" for a space.
+fn unescape_mount_point(mount_point: &str) -> PathBuf {
+    let bytes = mount_point.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i..i + 4) {
+            Some([b'\\', digits @ ..]) if digits.iter().all(|d| (b'0'..=b'7').contains(d)) => {
+                digits
+                    .iter()
+                    .try_fold(0u8, |value, d| value.checked_mul(8)?.checked_add(d - b'0'))
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsStr::from_bytes(&unescaped))
+}
+
+fn mount_resctrl() -> Result<PathBuf> {
+    let mount_point = PathBuf::from(RESCTRL_MOUNT_POINT);
+    if !mount_point.exists() {
+        bail!(
+            "Intel RDT is not supported, {:?} does not exist",
+            mount_point
+        );
+    }
+
+    nix::mount::mount(
+        Some(RESCTRL_FSTYPE),
+        &mount_point,
+        Some(RESCTRL_FSTYPE),
+        MsFlags::empty(),
+        None::<&str>,
+    )
+    .with_context(|| format!("failed to mount resctrl on {:?}", mount_point))?;
+    Ok(mount_point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::set_fixture;
+    use crate::utils::{create_temp_dir, TempDir};
+    use oci_spec::runtime::LinuxIntelRdtBuilder;
+
+    fn setup_resctrl(test_name: &str) -> Result<TempDir> {
+        let tmp = create_temp_dir(test_name)?;
+        set_fixture(&tmp, "info/L3/cbm_mask", "fff")?;
+        set_fixture(&tmp, "info/MB/min_bandwidth", "10")?;
+        Ok(tmp)
+    }
+
+    #[test]
+    fn test_apply() -> Result<()> {
+        let tmp = setup_resctrl("test_intel_rdt_apply")?;
+        let intel_rdt = LinuxIntelRdtBuilder::default()
+            .l3_cache_schema("L3:0=ff;1=f0")
+            .mem_bw_schema("MB:0=70;1=20")
+            .build()?;
+        let rdt = IntelRdt::new(tmp.to_path_buf(), &intel_rdt, "container")?;
+        assert!(rdt.apply(Pid::from_raw(1000))?);
+
+        let group = tmp.join("container");
+        assert_eq!(rdt.path(), group);
+        assert_eq!(
+            fs::read_to_string(group.join("schemata"))?,
+            "L3:0=ff;1=f0\nMB:0=70;1=20\n"
+        );
+        assert_eq!(fs::read_to_string(group.join("tasks"))?, "1000");
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_unsupported() -> Result<()> {
+        let tmp = create_temp_dir("test_intel_rdt_apply_unsupported")?;
+        set_fixture(&tmp, "info/L3/cbm_mask", "fff")?;
+        let intel_rdt = LinuxIntelRdtBuilder::default()
+            .mem_bw_schema("MB:0=70")
+            .build()?;
+        let rdt = IntelRdt::new(tmp.to_path_buf(), &intel_rdt, "container")?;
+        assert!(rdt.apply(Pid::from_raw(1000)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_clos_id() -> Result<()> {
+        let tmp = setup_resctrl("test_intel_rdt_clos_id")?;
+        let intel_rdt = LinuxIntelRdtBuilder::default().clos_id("shared").build()?;
+        fs::create_dir(tmp.join("shared"))?;
+        let rdt = IntelRdt::new(tmp.to_path_buf(), &intel_rdt, "container")?;
+        assert!(!rdt.apply(Pid::from_raw(1000))?);
+        assert_eq!(rdt.path(), tmp.join("shared"));
+        assert!(!tmp.join("shared/schemata").exists());
+
+        // a shared group, which existed before, is kept
+        rdt.remove(false)?;
+        assert!(tmp.join("shared").exists());
+
+        for invalid in &["", "..", "a/b"] {
+            let intel_rdt = LinuxIntelRdtBuilder::default().clos_id(*invalid).build()?;
+            assert!(IntelRdt::new(tmp.to_path_buf(), &intel_rdt, "container").is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove() -> Result<()> {
+        let tmp = setup_resctrl("test_intel_rdt_remove")?;
+        let intel_rdt = LinuxIntelRdtBuilder::default().build()?;
+        let rdt = IntelRdt::new(tmp.to_path_buf(), &intel_rdt, "container")?;
+        // the control files of the fake group are regular files, which a
+        // real resctrl group would not prevent from being removed
+        fs::create_dir(rdt.path())?;
+        rdt.remove(false)?;
+        assert!(!rdt.path().exists());
+        // removing a group twice is fine
+        rdt.remove(false)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_created_clos_id() -> Result<()> {
+        let tmp = setup_resctrl("test_intel_rdt_remove_created_clos_id")?;
+        let intel_rdt = LinuxIntelRdtBuilder::default().clos_id("created").build()?;
+        let rdt = IntelRdt::new(tmp.to_path_buf(), &intel_rdt, "container")?;
+        let created = rdt.apply(Pid::from_raw(1000))?;
+        assert!(created);
+        // the fake control files would prevent rmdir
+        fs::remove_file(rdt.path().join("tasks"))?;
+        rdt.remove(created)?;
+        assert!(!rdt.path().exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_task() -> Result<()> {
+        let tmp = setup_resctrl("test_intel_rdt_add_task")?;
+        set_fixture(&tmp, "container/schemata", "L3:0=ff\n")?;
+        let intel_rdt = LinuxIntelRdtBuilder::default()
+            .l3_cache_schema("L3:0=f0")
+            .build()?;
+        let rdt = IntelRdt::new(tmp.to_path_buf(), &intel_rdt, "container")?;
+        rdt.add_task(Pid::from_raw(2000))?;
+        assert_eq!(fs::read_to_string(tmp.join("container/tasks"))?, "2000");
+        // the schemata is only written when the group is applied
+        assert_eq!(
+            fs::read_to_string(tmp.join("container/schemata"))?,
+            "L3:0=ff\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> Result<()> {
+        let tmp = setup_resctrl("test_intel_rdt_stats")?;
+        set_fixture(&tmp, "container/schemata", "L3:0=ff\nMB:0=70\n")?;
+        set_fixture(&tmp, "container/mon_data/mon_L3_01/llc_occupancy", "2048\n")?;
+        set_fixture(
+            &tmp,
+            "container/mon_data/mon_L3_01/mbm_total_bytes",
+            "Unavailable\n",
+        )?;
+        set_fixture(&tmp, "container/mon_data/mon_L3_00/llc_occupancy", "1024\n")?;
+        set_fixture(
+            &tmp,
+            "container/mon_data/mon_L3_00/mbm_total_bytes",
+            "4096\n",
+        )?;
+        set_fixture(
+            &tmp,
+            "container/mon_data/mon_L3_00/mbm_local_bytes",
+            "512\n",
+        )?;
+
+        let intel_rdt = LinuxIntelRdtBuilder::default().build()?;
+        let stats = IntelRdt::new(tmp.to_path_buf(), &intel_rdt, "container")?.stats()?;
+        assert_eq!(
+            stats,
+            IntelRdtStats {
+                schemata: "L3:0=ff\nMB:0=70\n".to_owned(),
+                monitoring: vec![
+                    MonitoringStats {
+                        domain: "mon_L3_00".to_owned(),
+                        llc_occupancy: Some(1024),
+                        mbm_total_bytes: Some(4096),
+                        mbm_local_bytes: Some(512),
+                    },
+                    MonitoringStats {
+                        domain: "mon_L3_01".to_owned(),
+                        llc_occupancy: Some(2048),
+                        mbm_total_bytes: None,
+                        mbm_local_bytes: None,
+                    },
+                ],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_resctrl() -> Result<()> {
+        let tmp = create_temp_dir("test_find_resctrl")?;
+        let mountinfo = tmp.join("mountinfo");
+        fs::write(
+            &mountinfo,
+            "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+             35 22 0:30 / /sys/fs/resctrl rw,relatime shared:15 - resctrl resctrl rw\n",
+        )?;
+        assert_eq!(
+            find_resctrl(&mountinfo)?,
+            Some(PathBuf::from("/sys/fs/resctrl"))
+        );
+
+        fs::write(
+            &mountinfo,
+            "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n",
+        )?;
+        assert_eq!(find_resctrl(&mountinfo)?, None);
+
+        fs::write(
+            &mountinfo,
+            "35 22 0:30 / /mnt/res\\040ctrl rw,relatime - resctrl resctrl rw\n",
+        )?;
+        assert_eq!(
+            find_resctrl(&mountinfo)?,
+            Some(PathBuf::from("/mnt/res ctrl"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unescape_mount_point() {
+        assert_eq!(
+            unescape_mount_point("/a\\040b\\011c\\012d\\134e"),
+            PathBuf::from("/a b\tc\nd\\e")
+        );
+        // incomplete or invalid escapes are kept as they are
+        assert_eq!(unescape_mount_point("/a\\04"), PathBuf::from("/a\\04"));
+        assert_eq!(unescape_mount_point("/a\\777"), PathBuf::from("/a\\777"));
+    }
+}
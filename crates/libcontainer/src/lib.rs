@@ -3,13 +3,18 @@ pub mod capabilities;
 pub mod container;
 pub mod dbus;
 pub mod hooks;
+pub mod intel_rdt;
 pub mod namespaces;
 pub mod notify_socket;
 pub mod process;
 pub mod rootfs;
 pub mod rootless;
+pub mod scheduling;
 pub mod seccomp;
+pub mod selinux;
 pub mod signal;
+pub mod spec_extensions;
 pub mod syscall;
+pub mod time_namespace;
 pub mod tty;
 pub mod utils;
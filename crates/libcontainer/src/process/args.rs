@@ -4,6 +4,7 @@ use std::os::unix::prelude::RawFd;
 use std::path::PathBuf;
 
 use crate::rootless::Rootless;
+use crate::spec_extensions::SpecExtensions;
 use crate::{container::Container, notify_socket::NotifyListener, syscall::Syscall};
 
 pub struct ContainerArgs<'a> {
@@ -27,4 +28,8 @@ pub struct ContainerArgs<'a> {
     pub rootless: &'a Option<Rootless<'a>>,
     /// Cgroup Manager
     pub cgroup_manager: Box<dyn CgroupManager>,
+    /// Parts of the spec, which are unknown to the runtime spec types
+    pub extensions: &'a SpecExtensions,
+    /// Flag indicating if the seccomp profile was created by the seccomp audit mode
+    pub seccomp_audit: bool,
 }
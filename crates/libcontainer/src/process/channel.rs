@@ -47,10 +47,12 @@ impl MainSender {
         Ok(())
     }
 
-    pub fn intermediate_ready(&mut self, pid: Pid) -> Result<()> {
-        // Send over the IntermediateReady follow by the pid.
+    pub fn intermediate_ready(&mut self, pid: Pid, device_program: Option<u32>) -> Result<()> {
+        // Send over the IntermediateReady follow by the pid and the id of the
+        // device program attached to the cgroup.
         log::debug!("sending init pid ({:?})", pid);
-        self.sender.send(Message::IntermediateReady(pid.as_raw()))?;
+        self.sender
+            .send(Message::IntermediateReady(pid.as_raw(), device_program))?;
 
         Ok(())
     }
@@ -73,14 +75,16 @@ pub struct MainReceiver {
 impl MainReceiver {
     /// Waits for associated intermediate process to send ready message
     /// and return the pid of init process which is forked by intermediate process
-    pub fn wait_for_intermediate_ready(&mut self) -> Result<Pid> {
+    pub fn wait_for_intermediate_ready(&mut self) -> Result<(Pid, Option<u32>)> {
         let msg = self
             .receiver
             .recv()
             .context("failed to receive a message from the intermediate process")?;
 
         match msg {
-            Message::IntermediateReady(pid) => Ok(Pid::from_raw(pid)),
+            Message::IntermediateReady(pid, device_program) => {
+                Ok((Pid::from_raw(pid), device_program))
+            }
             _ => bail!(
                 "receive unexpected message {:?} waiting for intermediate ready",
                 msg
@@ -211,6 +215,14 @@ impl InitSender {
         Ok(())
     }
 
+    // sends a detached id mapped mount, which the init process attaches to the
+    // rootfs
+    pub fn idmapped_mount(&mut self, fd: RawFd) -> Result<()> {
+        self.sender.send_fds(Message::IdmappedMount, &[fd])?;
+
+        Ok(())
+    }
+
     pub fn close(&self) -> Result<()> {
         self.sender.close()
     }
@@ -221,6 +233,24 @@ pub struct InitReceiver {
 }
 
 impl InitReceiver {
+    pub fn wait_for_idmapped_mount(&mut self) -> Result<RawFd> {
+        let (msg, fds) = self
+            .receiver
+            .recv_with_fds::<[RawFd; 1]>()
+            .context("failed to wait for id mapped mount")?;
+
+        match msg {
+            Message::IdmappedMount => match fds {
+                Some(fds) => Ok(fds[0]),
+                None => bail!("expecting fd of id mapped mount"),
+            },
+            msg => bail!(
+                "receive unexpected message {:?} waiting for id mapped mount",
+                msg
+            ),
+        }
+    }
+
     pub fn wait_for_seccomp_request_done(&mut self) -> Result<()> {
         let msg = self
             .receiver
@@ -450,15 +480,16 @@ mod tests {
         match unsafe { unistd::fork()? } {
             unistd::ForkResult::Parent { child } => {
                 wait::waitpid(child, None)?;
-                let pid = receiver
+                let (pid, device_program) = receiver
                     .wait_for_intermediate_ready()
                     .with_context(|| "Failed to wait for intermadiate ready")?;
                 receiver.close()?;
                 assert_eq!(pid, child);
+                assert_eq!(device_program, Some(42));
             }
             unistd::ForkResult::Child => {
                 let pid = unistd::getpid();
-                sender.intermediate_ready(pid)?;
+                sender.intermediate_ready(pid, Some(42))?;
                 sender.close()?;
                 std::process::exit(0);
             }
@@ -1,9 +1,15 @@
 use super::args::ContainerArgs;
-use crate::apparmor;
-use crate::syscall::Syscall;
+use crate::syscall::{openat2, Syscall};
+use crate::{apparmor, selinux};
 use crate::{
-    capabilities, hooks, namespaces::Namespaces, process::channel, rootfs::RootFS,
-    rootless::Rootless, seccomp, tty, utils,
+    capabilities, hooks,
+    namespaces::Namespaces,
+    process::channel,
+    rootfs::idmap,
+    rootfs::overlay::OverlayRootfs,
+    rootfs::RootFS,
+    rootless::{Rootless, RootlessMounts},
+    seccomp, tty, utils,
 };
 use anyhow::{bail, Context, Result};
 use nix::mount::mount as nix_mount;
@@ -11,42 +17,16 @@ use nix::mount::MsFlags;
 use nix::sched::CloneFlags;
 use nix::{
     fcntl,
+    sys::stat,
     unistd::{self, Gid, Uid},
 };
-use oci_spec::runtime::{LinuxNamespaceType, Spec, User};
+use oci_spec::runtime::{LinuxNamespaceType, LinuxSeccomp, Spec, User};
 use std::collections::HashMap;
 use std::{
     env, fs,
     path::{Path, PathBuf},
 };
 
-// Get a list of open fds for the calling process.
-fn get_open_fds() -> Result<Vec<i32>> {
-    const PROCFS_FD_PATH: &str = "/proc/self/fd";
-    utils::ensure_procfs(Path::new(PROCFS_FD_PATH))
-        .with_context(|| format!("{} is not the actual procfs", PROCFS_FD_PATH))?;
-
-    let fds: Vec<i32> = fs::read_dir(PROCFS_FD_PATH)?
-        .filter_map(|entry| match entry {
-            Ok(entry) => Some(entry.path()),
-            Err(_) => None,
-        })
-        .filter_map(|path| path.file_name().map(|file_name| file_name.to_owned()))
-        .filter_map(|file_name| file_name.to_str().map(String::from))
-        .filter_map(|file_name| -> Option<i32> {
-            // Convert the file name from string into i32. Since we are looking
-            // at /proc/<pid>/fd, anything that's not a number (i32) can be
-            // ignored. We are only interested in opened fds.
-            match file_name.parse() {
-                Ok(fd) => Some(fd),
-                Err(_) => None,
-            }
-        })
-        .collect();
-
-    Ok(fds)
-}
-
 // Cleanup any extra file descriptors, so the new container process will not
 // leak a file descriptor from before execve gets executed. The first 3 fd will
 // stay open: stdio, stdout, and stderr. We would further preserve the next
@@ -55,7 +35,7 @@ fn get_open_fds() -> Result<Vec<i32>> {
 // fds immediatly since we at least still need it for the pipe used to wait on
 // starting the container.
 fn cleanup_file_descriptors(preserve_fds: i32) -> Result<()> {
-    let open_fds = get_open_fds().with_context(|| "Failed to obtain opened fds")?;
+    let open_fds = utils::get_open_fds().with_context(|| "Failed to obtain opened fds")?;
     // Include stdin, stdout, and stderr for fd 0, 1, and 2 respectively.
     let min_fd = preserve_fds + 3;
     let to_be_cleaned_up_fds: Vec<i32> = open_fds
@@ -92,26 +72,31 @@ fn sysctl(kernel_params: &HashMap<String, String>) -> Result<()> {
 // The first time we bind mount, other flags are ignored,
 // so we need to mount it once and then remount it with the necessary flags specified.
 // https://man7.org/linux/man-pages/man2/mount.2.html
+// The path is opened without following symlinks and mounted through its fd, so
+// that the container can't redirect the mount with a symlink.
 fn readonly_path(path: &Path, syscall: &dyn Syscall) -> Result<()> {
-    if let Err(err) = syscall.mount(
-        Some(path),
-        path,
+    let fd = match openat2::open_no_symlinks(path) {
+        Ok(fd) => fd,
+        // ignore error if path is not exist.
+        Err(err) if matches!(err.downcast_ref(), Some(nix::errno::Errno::ENOENT)) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    let res = syscall.mount(
+        Some(&openat2::fd_path(fd)),
+        &openat2::fd_path(fd),
         None,
         MsFlags::MS_BIND | MsFlags::MS_REC,
         None,
-    ) {
-        if let Some(errno) = err.downcast_ref() {
-            // ignore error if path is not exist.
-            if matches!(errno, nix::errno::Errno::ENOENT) {
-                return Ok(());
-            }
-        }
-        bail!(err)
-    }
-
-    syscall.mount(
-        Some(path),
-        path,
+    );
+    let _ = unistd::close(fd);
+    res?;
+
+    // the fd refers to the mount below the bind mount, so the bind mount is
+    // opened again to remount it
+    let fd = openat2::open_no_symlinks(path)?;
+    let res = syscall.mount(
+        Some(&openat2::fd_path(fd)),
+        &openat2::fd_path(fd),
         None,
         MsFlags::MS_NOSUID
             | MsFlags::MS_NODEV
@@ -120,7 +105,9 @@ fn readonly_path(path: &Path, syscall: &dyn Syscall) -> Result<()> {
             | MsFlags::MS_REMOUNT
             | MsFlags::MS_RDONLY,
         None,
-    )?;
+    );
+    let _ = unistd::close(fd);
+    res?;
 
     log::debug!("readonly path {:?} mounted", path);
     Ok(())
@@ -129,35 +116,42 @@ fn readonly_path(path: &Path, syscall: &dyn Syscall) -> Result<()> {
 // For files, bind mounts /dev/null over the top of the specified path.
 // For directories, mounts read-only tmpfs over the top of the specified path.
 fn masked_path(path: &str, mount_label: &Option<String>) -> Result<()> {
-    match nix_mount::<str, str, str, str>(
-        Some("/dev/null"),
-        path,
-        None::<&str>,
-        MsFlags::MS_BIND,
-        None::<&str>,
-    ) {
+    let fd = match openat2::open_no_symlinks(Path::new(path)) {
+        Ok(fd) => fd,
         // ignore error if path is not exist.
-        Err(nix::errno::Errno::ENOENT) => {
+        Err(err) if matches!(err.downcast_ref(), Some(nix::errno::Errno::ENOENT)) => {
             log::warn!("masked path {:?} not exist", path);
             return Ok(());
         }
-        Err(nix::errno::Errno::ENOTDIR) => {
+        Err(err) => return Err(err),
+    };
+
+    let target = openat2::fd_path(fd);
+    let res = match stat::fstat(fd) {
+        Ok(st) if st.st_mode & libc::S_IFMT == libc::S_IFDIR => {
             let label = match mount_label {
                 Some(l) => format!("context={}", l),
                 None => "".to_string(),
             };
-            let _ = nix_mount(
+            nix_mount(
                 Some("tmpfs"),
-                path,
+                &target,
                 Some("tmpfs"),
                 MsFlags::MS_RDONLY,
                 Some(label.as_str()),
-            );
+            )
         }
-        Err(err) => bail!(err),
-        Ok(_) => {}
+        Ok(_) => nix_mount(
+            Some("/dev/null"),
+            &target,
+            None::<&str>,
+            MsFlags::MS_BIND,
+            None::<&str>,
+        ),
+        Err(errno) => Err(errno),
     };
-    Ok(())
+    let _ = unistd::close(fd);
+    res.with_context(|| format!("failed to mask {:?}", path))
 }
 
 // Enter into rest of namespace. Note, we already entered into user and pid
@@ -213,6 +207,28 @@ pub fn container_init_process(
     }
 
     if args.init {
+        let rootfs = RootFS::new();
+        rootfs
+            .prepare_propagation(linux, rootfs_path)
+            .context("failed to prepare mount propagation")?;
+
+        // the rootfs has to be assembled from the image layers before anything
+        // looks into it, but after the propagation has been changed, so that the
+        // overlay is not mounted on the host
+        if let Some(container) = container {
+            if let Some(overlay) =
+                OverlayRootfs::from_spec(spec, container.bundle(), &container.root)?
+            {
+                overlay
+                    .mount(
+                        syscall,
+                        rootfs_path,
+                        namespaces.get(LinuxNamespaceType::User).is_some(),
+                    )
+                    .context("failed to mount overlay rootfs")?;
+            }
+        }
+
         // create_container hook needs to be called after the namespace setup, but
         // before pivot_root is called. This runs in the container namespaces.
         if let Some(hooks) = hooks {
@@ -220,16 +236,28 @@ pub fn container_init_process(
                 .context("Failed to run create container hooks")?;
         }
 
+        // the main process sends a detached mount for each id mapped mount
+        let idmapped_mounts = idmap::idmapped_mounts(spec)
+            .map(|_| init_receiver.wait_for_idmapped_mount())
+            .collect::<Result<Vec<_>>>()?;
+
         let bind_service = namespaces.get(LinuxNamespaceType::User).is_some();
-        let rootfs = RootFS::new();
-        rootfs
-            .prepare_rootfs(
-                spec,
-                rootfs_path,
-                bind_service,
-                namespaces.get(LinuxNamespaceType::Cgroup).is_some(),
-            )
-            .with_context(|| "Failed to prepare rootfs")?;
+        let rootless_mounts = args
+            .rootless
+            .as_ref()
+            .map(|rootless| RootlessMounts::new(rootless, &namespaces));
+        let res = rootfs.prepare_rootfs(
+            spec,
+            rootfs_path,
+            bind_service,
+            namespaces.get(LinuxNamespaceType::Cgroup).is_some(),
+            &idmapped_mounts,
+            rootless_mounts.as_ref(),
+        );
+        for fd in idmapped_mounts {
+            let _ = unistd::close(fd);
+        }
+        res.with_context(|| "Failed to prepare rootfs")?;
 
         // Entering into the rootfs jail. If mount namespace is specified, then
         // we use pivot_root, but if we are on the host mount namespace, we will
@@ -261,6 +289,13 @@ pub fn container_init_process(
             .with_context(|| format!("failed to apply apparmor profile {}", profile))?;
     }
 
+    if let Some(label) = proc.selinux_label() {
+        selinux::set_keycreate_label(label)
+            .with_context(|| format!("failed to set selinux keycreate label {}", label))?;
+        selinux::set_exec_label(label)
+            .with_context(|| format!("failed to set selinux exec label {}", label))?;
+    }
+
     if let Some(true) = spec.root().as_ref().map(|r| r.readonly().unwrap_or(false)) {
         nix_mount(
             None::<&str>,
@@ -300,6 +335,14 @@ pub fn container_init_process(
         }
     };
 
+    // Realtime scheduling policies and raising the priorities require
+    // CAP_SYS_NICE, so the scheduling attributes have to be applied before the
+    // user is changed and the capabilities are dropped.
+    args.extensions
+        .scheduling
+        .apply(syscall)
+        .context("failed to apply scheduling attributes")?;
+
     set_supplementary_gids(proc.user(), args.rootless, syscall)
         .context("failed to set supplementary gids")?;
 
@@ -315,8 +358,8 @@ pub fn container_init_process(
     // as close to exec as possible.
     if let Some(seccomp) = linux.seccomp() {
         if proc.no_new_privileges().is_none() {
-            let notify_fd =
-                seccomp::initialize_seccomp(seccomp).context("failed to execute seccomp")?;
+            let notify_fd = initialize_seccomp(seccomp, args.seccomp_audit)
+                .context("failed to execute seccomp")?;
             sync_seccomp(notify_fd, main_sender, init_receiver)
                 .context("failed to sync seccomp")?;
         }
@@ -382,13 +425,21 @@ pub fn container_init_process(
         .iter()
         .for_each(|(key, value)| env::set_var(key, value));
 
+    // The execution domain only affects the payload, so it is set as late as
+    // possible, but before seccomp may deny the personality syscall.
+    if let Some(personality) = linux.personality() {
+        syscall
+            .personality(personality)
+            .context("failed to set personality")?;
+    }
+
     // Initialize seccomp profile right before we are ready to execute the
     // payload so as few syscalls will happen between here and payload exec. The
     // notify socket will still need network related syscalls.
     if let Some(seccomp) = linux.seccomp() {
         if proc.no_new_privileges().is_some() {
-            let notify_fd =
-                seccomp::initialize_seccomp(seccomp).context("failed to execute seccomp")?;
+            let notify_fd = initialize_seccomp(seccomp, args.seccomp_audit)
+                .context("failed to execute seccomp")?;
             sync_seccomp(notify_fd, main_sender, init_receiver)
                 .context("failed to sync seccomp")?;
         }
@@ -491,6 +542,14 @@ fn set_supplementary_gids(
     Ok(())
 }
 
+fn initialize_seccomp(seccomp: &LinuxSeccomp, audit: bool) -> Result<Option<i32>> {
+    if audit {
+        seccomp::initialize_audit_seccomp(seccomp)
+    } else {
+        seccomp::initialize_seccomp(seccomp)
+    }
+}
+
 fn sync_seccomp(
     fd: Option<i32>,
     main_sender: &mut channel::MainSender,
@@ -512,45 +571,17 @@ fn sync_seccomp(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::syscall::{
-        syscall::create_syscall,
-        test::{MountArgs, TestHelperSyscall},
-    };
+    use crate::syscall::{syscall::create_syscall, test::TestHelperSyscall};
     use nix::{fcntl, sys, unistd};
     use oci_spec::runtime::{LinuxNamespaceBuilder, SpecBuilder, UserBuilder};
     use serial_test::serial;
-    use std::{fs, os::unix::prelude::AsRawFd};
+    use std::fs;
 
     // Note: We have to run these tests here as serial. The main issue is that
     // these tests has a dependency on the system state. The
     // cleanup_file_descriptors test is especially evil when running with other
     // tests because it would ran around close down different fds.
 
-    #[test]
-    #[serial]
-    fn test_get_open_fds() -> Result<()> {
-        let file = fs::File::open("/dev/null")?;
-        let fd = file.as_raw_fd();
-        let open_fds = super::get_open_fds()?;
-
-        if !open_fds.iter().any(|&v| v == fd) {
-            bail!("Failed to find the opened dev null fds: {:?}", open_fds);
-        }
-
-        // explicitly close the file before the test case returns.
-        drop(file);
-
-        // The stdio fds should also be contained in the list of opened fds.
-        if !vec![0, 1, 2]
-            .iter()
-            .all(|&stdio_fd| open_fds.iter().any(|&open_fd| open_fd == stdio_fd))
-        {
-            bail!("Failed to find the stdio fds: {:?}", open_fds);
-        }
-
-        Ok(())
-    }
-
     #[test]
     #[serial]
     fn test_cleanup_file_descriptors() -> Result<()> {
@@ -572,34 +603,32 @@ mod tests {
         let syscall = create_syscall();
         readonly_path(Path::new("/proc/sys"), syscall.as_ref())?;
 
-        let want = vec![
-            MountArgs {
-                source: Some(PathBuf::from("/proc/sys")),
-                target: PathBuf::from("/proc/sys"),
-                fstype: None,
-                flags: MsFlags::MS_BIND | MsFlags::MS_REC,
-                data: None,
-            },
-            MountArgs {
-                source: Some(PathBuf::from("/proc/sys")),
-                target: PathBuf::from("/proc/sys"),
-                fstype: None,
-                flags: MsFlags::MS_NOSUID
-                    | MsFlags::MS_NODEV
-                    | MsFlags::MS_NOEXEC
-                    | MsFlags::MS_BIND
-                    | MsFlags::MS_REMOUNT
-                    | MsFlags::MS_RDONLY,
-                data: None,
-            },
-        ];
         let got = syscall
             .as_any()
             .downcast_ref::<TestHelperSyscall>()
             .unwrap()
             .get_mount_args();
 
-        assert_eq!(want, *got);
+        // the path is mounted through the fd it was opened with
+        for args in &got {
+            assert!(args.target.starts_with("/proc/self/fd"));
+            assert_eq!(args.source.as_ref(), Some(&args.target));
+            assert_eq!(args.fstype, None);
+            assert_eq!(args.data, None);
+        }
+        let flags: Vec<MsFlags> = got.iter().map(|args| args.flags).collect();
+        assert_eq!(
+            flags,
+            vec![
+                MsFlags::MS_BIND | MsFlags::MS_REC,
+                MsFlags::MS_NOSUID
+                    | MsFlags::MS_NODEV
+                    | MsFlags::MS_NOEXEC
+                    | MsFlags::MS_BIND
+                    | MsFlags::MS_REMOUNT
+                    | MsFlags::MS_RDONLY,
+            ]
+        );
         assert_eq!(got.len(), 2);
         Ok(())
     }
@@ -66,6 +66,14 @@ pub fn container_intermediate_process(
             .with_context(|| format!("Failed to enter pid namespace: {:?}", pid_namespace))?;
     }
 
+    // Like the pid namespace, the time namespace only applies to the children
+    // and the clock offsets have to be set before the init process is forked.
+    if let Some(time_namespace) = &args.extensions.time_namespace {
+        time_namespace
+            .apply()
+            .with_context(|| format!("Failed to enter time namespace: {:?}", time_namespace))?;
+    }
+
     // this needs to be done before we create the init process, so that the init
     // process will already be captured by the cgroup
     if args.rootless.is_none() {
@@ -95,7 +103,7 @@ pub fn container_intermediate_process(
     // is done. We notify the container main process about the pid we just
     // forked for container init process.
     main_sender
-        .intermediate_ready(pid)
+        .intermediate_ready(pid, args.cgroup_manager.device_program().id)
         .context("failed to send child ready from intermediate process")?;
 
     // Close unused senders here so we don't have lingering socket around.
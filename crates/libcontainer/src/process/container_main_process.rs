@@ -1,6 +1,7 @@
 use crate::{
     container::ContainerProcessState,
     process::{args::ContainerArgs, channel, container_intermediate_process, fork},
+    rootfs::idmap,
     rootless::Rootless,
     seccomp, utils,
 };
@@ -12,7 +13,9 @@ use nix::{
 use oci_spec::runtime;
 use std::path::Path;
 
-pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
+/// Creates the container process and returns the pid of the init process and
+/// the id of the device program attached to the cgroup of the container.
+pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, Option<u32>)> {
     // We use a set of channels to communicate between parent and child process.
     // Each channel is uni-directional. Because we will pass these channel to
     // forked process, we have to be deligent about closing any unused channel.
@@ -55,7 +58,26 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
 
     // The intermediate process will send the init pid once it forks the init
     // process.  The intermediate process should exit after this point.
-    let init_pid = main_receiver.wait_for_intermediate_ready()?;
+    let (init_pid, device_program) = main_receiver.wait_for_intermediate_ready()?;
+
+    // Id mapped mounts have to be created outside of the user namespace of the
+    // container. The init process attaches them while preparing the rootfs.
+    if container_args.init {
+        let userns = Path::new("/proc")
+            .join(init_pid.as_raw().to_string())
+            .join("ns/user");
+        let fds =
+            idmap::create_idmapped_mounts(container_args.syscall, container_args.spec, &userns)
+                .context("failed to create id mapped mounts")?;
+        let mut res = Ok(());
+        for fd in fds {
+            if res.is_ok() {
+                res = init_sender.idmapped_mount(fd);
+            }
+            let _ = unistd::close(fd);
+        }
+        res.context("failed to send id mapped mounts to init")?;
+    }
 
     if let Some(linux) = container_args.spec.linux() {
         if let Some(seccomp) = linux.seccomp() {
@@ -89,7 +111,7 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
 
     log::debug!("init pid is {:?}", init_pid);
 
-    Ok(init_pid)
+    Ok((init_pid, device_program))
 }
 
 fn sync_seccomp(
@@ -3,10 +3,11 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Message {
-    IntermediateReady(i32),
+    IntermediateReady(i32, Option<u32>),
     InitReady,
     WriteMapping,
     MappingWritten,
     SeccompNotify,
     SeccompNotifyDone,
+    IdmappedMount,
 }
@@ -2,15 +2,21 @@ use super::utils::to_sflag;
 use crate::syscall::{syscall::create_syscall, Syscall};
 use crate::utils::{self, PathBufExt};
 use anyhow::{bail, Context, Result};
+use libcgroups::common::optional_device;
 use nix::{
+    errno::Errno,
     fcntl::{open, OFlag},
     mount::MsFlags,
     sys::stat::{umask, Mode},
     unistd::{close, Gid, Uid},
 };
-use oci_spec::runtime::LinuxDevice;
+use oci_spec::runtime::{LinuxDevice, LinuxDeviceCgroup, Spec};
 use std::path::{Path, PathBuf};
 
+/// Annotation listing the optional devices the container opts in to,
+/// separated by commas, e.g. "fuse,tun". See libcgroups::common::optional_device
+pub const DEVICES_ANNOTATION: &str = "org.youki.devices";
+
 pub struct Device {
     syscall: Box<dyn Syscall>,
 }
@@ -28,12 +34,15 @@ impl Device {
         }
     }
 
+    /// Creates the devices in the rootfs. The devices are bind mounted from
+    /// the host if bind is set, and if mknod is not permitted, e.g. in a user
+    /// namespace which is not owned by the initial user namespace.
     pub fn create_devices<'a, I>(&self, rootfs: &Path, devices: I, bind: bool) -> Result<()>
     where
         I: IntoIterator<Item = &'a LinuxDevice>,
     {
         let old_mode = umask(Mode::from_bits_truncate(0o000));
-        devices
+        let res = devices
             .into_iter()
             .map(|dev| {
                 if !dev.path().starts_with("/dev") {
@@ -41,13 +50,23 @@ impl Device {
                 }
 
                 if bind {
-                    self.bind_dev(rootfs, dev)
-                } else {
-                    self.mknod_dev(rootfs, dev)
+                    return self.bind_dev(rootfs, dev);
+                }
+
+                match self.mknod_dev(rootfs, dev) {
+                    Err(err) if matches!(err.downcast_ref(), Some(Errno::EPERM)) => {
+                        log::debug!(
+                            "mknod of {:?} is not permitted, bind mounting it instead",
+                            dev.path()
+                        );
+                        self.bind_dev(rootfs, dev)
+                    }
+                    res => res,
                 }
             })
-            .collect::<Result<Vec<_>>>()?;
+            .collect::<Result<Vec<_>>>();
         umask(old_mode);
+        res?;
 
         Ok(())
     }
@@ -56,11 +75,14 @@ impl Device {
         let full_container_path = create_container_dev_path(rootfs, dev)
             .with_context(|| format!("could not create container path for device {:?}", dev))?;
 
+        // the mount point must not be a symlink, which the rootfs could use to
+        // redirect the device
         let fd = open(
             &full_container_path,
-            OFlag::O_RDWR | OFlag::O_CREAT,
+            OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC,
             Mode::from_bits_truncate(0o644),
-        )?;
+        )
+        .with_context(|| format!("failed to create mount point {:?}", full_container_path))?;
         close(fd)?;
         self.syscall.mount(
             Some(dev.path()),
@@ -70,6 +92,15 @@ impl Device {
             None,
         )?;
 
+        // the owner of the spec is ignored like in runc, as the device of the
+        // host can't be owned by another user in the container
+        if dev.uid().is_some() || dev.gid().is_some() {
+            log::debug!(
+                "{:?} is bind mounted from the host and keeps the owner of the host",
+                dev.path()
+            );
+        }
+
         Ok(())
     }
 
@@ -90,16 +121,67 @@ impl Device {
             Mode::from_bits_truncate(dev.file_mode().unwrap_or(0)),
             makedev(dev.major(), dev.minor()),
         )?;
-        self.syscall.chown(
+        // the owner is given as ids in the user namespace of the container,
+        // chown fails with EINVAL if they are not mapped
+        if let Err(err) = self.syscall.chown(
             &full_container_path,
             dev.uid().map(Uid::from_raw),
             dev.gid().map(Gid::from_raw),
-        )?;
+        ) {
+            if let Some(Errno::EINVAL) = err.downcast_ref() {
+                bail!(
+                    "owner {:?}:{:?} of device {:?} is not mapped in the user namespace",
+                    dev.uid(),
+                    dev.gid(),
+                    dev.path()
+                );
+            }
+            return Err(err);
+        }
 
         Ok(())
     }
 }
 
+/// Adds the optional devices the container opts in to with the annotation
+/// org.youki.devices to the devices of the spec. The cgroup rules allowing
+/// the devices are generated from the same devices.
+pub fn add_optional_devices(spec: &mut Spec) -> Result<()> {
+    let names: Vec<String> = match spec
+        .annotations()
+        .as_ref()
+        .and_then(|annotations| annotations.get(DEVICES_ANNOTATION))
+    {
+        Some(names) => names
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect(),
+        None => return Ok(()),
+    };
+
+    let mut linux = spec.linux().clone().context("no linux in spec")?;
+    let mut devices = linux.devices().clone().unwrap_or_default();
+    let mut resources = linux.resources().clone().unwrap_or_default();
+    let mut rules = resources.devices().clone().unwrap_or_default();
+    for name in names {
+        let device = optional_device(&name)
+            .with_context(|| format!("unknown device {:?} in {}", name, DEVICES_ANNOTATION))?;
+        if devices.iter().any(|d| d.path() == device.path()) {
+            continue;
+        }
+        log::debug!("add optional device {:?}", device.path());
+        rules.push(LinuxDeviceCgroup::from(&device));
+        devices.push(device);
+    }
+
+    resources.set_devices(Some(rules));
+    linux.set_resources(Some(resources));
+    linux.set_devices(Some(devices));
+    spec.set_linux(Some(linux));
+    Ok(())
+}
+
 fn create_container_dev_path(rootfs: &Path, dev: &LinuxDevice) -> Result<PathBuf> {
     let relative_dev_path = dev
         .path()
@@ -127,6 +209,7 @@ mod tests {
         unistd::{Gid, Uid},
     };
     use oci_spec::runtime::{LinuxDeviceBuilder, LinuxDeviceType};
+    use std::collections::HashMap;
     use std::path::PathBuf;
 
     #[test]
@@ -259,4 +342,71 @@ mod tests {
             .get_mknod_args()[0];
         assert_eq!(want, *got);
     }
+
+    #[test]
+    fn test_create_devices_mknod_not_permitted() {
+        let tmp_dir = TempDir::new("/tmp/test_create_devices_mknod_not_permitted").unwrap();
+        let device = Device::new();
+        let syscall = device
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        syscall.set_mknod_error(Errno::EPERM);
+        let devices = vec![LinuxDeviceBuilder::default()
+            .path(PathBuf::from("/dev/null"))
+            .major(1)
+            .minor(3)
+            .typ(LinuxDeviceType::C)
+            .build()
+            .unwrap()];
+
+        assert!(device
+            .create_devices(tmp_dir.path(), &devices, false)
+            .is_ok());
+
+        assert_eq!(syscall.get_mknod_args().len(), 1);
+        assert!(syscall.get_chown_args().is_empty());
+        assert_eq!(
+            syscall.get_mount_args(),
+            vec![MountArgs {
+                source: Some(PathBuf::from("/dev/null")),
+                target: tmp_dir.path().join("dev/null"),
+                fstype: Some("bind".to_string()),
+                flags: MsFlags::MS_BIND,
+                data: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_add_optional_devices() -> Result<()> {
+        let mut spec = Spec::default();
+        add_optional_devices(&mut spec)?;
+        assert_eq!(spec, Spec::default());
+
+        let mut annotations = HashMap::new();
+        annotations.insert(DEVICES_ANNOTATION.to_string(), "fuse, tun,fuse".to_string());
+        spec.set_annotations(Some(annotations));
+        add_optional_devices(&mut spec)?;
+
+        let linux = spec.linux().as_ref().unwrap();
+        let devices = linux.devices().as_ref().unwrap();
+        let paths: Vec<&Path> = devices.iter().map(|d| d.path().as_path()).collect();
+        assert_eq!(
+            paths,
+            vec![Path::new("/dev/fuse"), Path::new("/dev/net/tun")]
+        );
+        let rules: Vec<LinuxDeviceCgroup> = devices.iter().map(|d| d.into()).collect();
+        let resources = linux.resources().as_ref().unwrap();
+        assert!(rules
+            .iter()
+            .all(|rule| resources.devices().as_ref().unwrap().contains(rule)));
+
+        let mut annotations = HashMap::new();
+        annotations.insert(DEVICES_ANNOTATION.to_string(), "kvm".to_string());
+        spec.set_annotations(Some(annotations));
+        assert!(add_optional_devices(&mut spec).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,218 @@
+//! ID-mapped mounts show the files of a bind mount source with their owners
+//! mapped into the user namespace of the container, so that neither the
+//! files have to be chowned nor the container sees them owned by nobody.
+//! The kernel only allows a process privileged over the filesystem to id map a
+//! mount, and only before the mount is attached. Therefore the main process
+//! creates detached id mapped mounts and sends them to the init process, which
+//! attaches them to the rootfs.
+use super::utils::parse_mount;
+use crate::syscall::{
+    mount_api::{
+        self, MountAttr, AT_EMPTY_PATH, AT_RECURSIVE, MOUNT_ATTR_IDMAP, OPEN_TREE_CLOEXEC,
+        OPEN_TREE_CLONE,
+    },
+    Syscall,
+};
+use anyhow::{bail, Context, Result};
+use nix::{
+    fcntl::{self, OFlag},
+    mount::MsFlags,
+    sys::stat::Mode,
+    unistd,
+};
+use oci_spec::runtime::{LinuxNamespaceType, Mount, Spec};
+use std::{os::unix::io::RawFd, path::Path};
+
+/// Id maps the mount
+const IDMAP: &str = "idmap";
+/// Id maps the mount and all submounts of a recursive bind mount
+const RIDMAP: &str = "ridmap";
+
+pub fn is_idmapped(mount: &Mount) -> bool {
+    mount
+        .options()
+        .iter()
+        .flatten()
+        .any(|o| o == IDMAP || o == RIDMAP)
+}
+
+/// Returns the mounts of the spec which have to be id mapped, in the order
+/// they are mounted.
+pub fn idmapped_mounts(spec: &Spec) -> impl Iterator<Item = &Mount> {
+    spec.mounts().iter().flatten().filter(|m| is_idmapped(m))
+}
+
+/// Creates a detached id mapped mount for every id mapped mount of the spec.
+/// The ids are mapped with the user namespace userns, which is the user
+/// namespace of the container.
+pub fn create_idmapped_mounts(
+    syscall: &dyn Syscall,
+    spec: &Spec,
+    userns: &Path,
+) -> Result<Vec<RawFd>> {
+    let mounts: Vec<&Mount> = idmapped_mounts(spec).collect();
+    if mounts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let has_userns = spec
+        .linux()
+        .as_ref()
+        .and_then(|l| l.namespaces().as_ref())
+        .map(|namespaces| {
+            namespaces
+                .iter()
+                .any(|ns| ns.typ() == LinuxNamespaceType::User)
+        })
+        .unwrap_or(false);
+    if !has_userns {
+        bail!("id mapped mounts require a user namespace");
+    }
+
+    if !mount_api::mount_setattr_supported() {
+        bail!("id mapped mounts are not supported by the kernel");
+    }
+
+    let userns_fd = fcntl::open(userns, OFlag::O_RDONLY | OFlag::O_CLOEXEC, Mode::empty())
+        .with_context(|| format!("failed to open user namespace {:?}", userns))?;
+    let mut fds = Vec::with_capacity(mounts.len());
+    for mount in mounts {
+        match create_idmapped_mount(syscall, mount, userns_fd) {
+            Ok(fd) => fds.push(fd),
+            Err(err) => {
+                fds.into_iter().for_each(|fd| {
+                    let _ = unistd::close(fd);
+                });
+                let _ = unistd::close(userns_fd);
+                return Err(err);
+            }
+        }
+    }
+
+    let _ = unistd::close(userns_fd);
+    Ok(fds)
+}
+
+fn create_idmapped_mount(syscall: &dyn Syscall, mount: &Mount, userns_fd: RawFd) -> Result<RawFd> {
+    if mount.typ().as_deref() != Some("bind") {
+        bail!("only bind mounts can be id mapped: {:?}", mount);
+    }
+
+    let source = mount.source().as_ref().context("no source in mount spec")?;
+    let (flags, _) = parse_mount(mount);
+    let mut open_flags = OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC;
+    if flags.contains(MsFlags::MS_REC) {
+        open_flags |= AT_RECURSIVE;
+    }
+    let fd = syscall
+        .open_tree(libc::AT_FDCWD, Some(source), open_flags)
+        .with_context(|| format!("failed to clone mount {:?}", source))?;
+
+    let mut setattr_flags = AT_EMPTY_PATH;
+    if mount.options().iter().flatten().any(|o| o == RIDMAP) {
+        setattr_flags |= AT_RECURSIVE;
+    }
+    let attr = MountAttr {
+        attr_set: MOUNT_ATTR_IDMAP,
+        userns_fd: userns_fd as u64,
+        ..Default::default()
+    };
+    if let Err(err) = syscall.mount_setattr(fd, None, setattr_flags, &attr) {
+        let _ = unistd::close(fd);
+        return Err(err.context(format!("failed to id map mount {:?}", source)));
+    }
+
+    Ok(fd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syscall::test::{MountSetattrArgs, OpenTreeArgs, TestHelperSyscall, TEST_MOUNT_FD};
+    use oci_spec::runtime::MountBuilder;
+    use std::path::PathBuf;
+
+    fn bind_mount(options: &[&str]) -> Mount {
+        MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_is_idmapped() {
+        assert!(is_idmapped(&bind_mount(&["bind", "idmap"])));
+        assert!(is_idmapped(&bind_mount(&["rbind", "ridmap"])));
+        assert!(!is_idmapped(&bind_mount(&["rbind", "ro"])));
+    }
+
+    #[test]
+    fn test_create_idmapped_mount() -> Result<()> {
+        let syscall = TestHelperSyscall::default();
+        let fd = create_idmapped_mount(&syscall, &bind_mount(&["rbind", "ridmap"]), 42)?;
+        assert_eq!(fd, TEST_MOUNT_FD);
+        assert_eq!(
+            syscall.get_open_tree_args(),
+            vec![OpenTreeArgs {
+                dirfd: libc::AT_FDCWD,
+                path: Some(PathBuf::from("/srv/data")),
+                flags: OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC | AT_RECURSIVE,
+            }]
+        );
+        assert_eq!(
+            syscall.get_mount_setattr_args(),
+            vec![MountSetattrArgs {
+                dirfd: TEST_MOUNT_FD,
+                path: None,
+                flags: AT_EMPTY_PATH | AT_RECURSIVE,
+                attr: MountAttr {
+                    attr_set: MOUNT_ATTR_IDMAP,
+                    userns_fd: 42,
+                    ..Default::default()
+                },
+            }]
+        );
+
+        let syscall = TestHelperSyscall::default();
+        create_idmapped_mount(&syscall, &bind_mount(&["bind", "idmap"]), 42)?;
+        assert_eq!(
+            syscall.get_open_tree_args()[0].flags,
+            OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC
+        );
+        assert_eq!(syscall.get_mount_setattr_args()[0].flags, AT_EMPTY_PATH);
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_idmapped_mount_not_bind() {
+        let syscall = TestHelperSyscall::default();
+        let mount = MountBuilder::default()
+            .destination(PathBuf::from("/tmp"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .options(vec!["idmap".to_string()])
+            .build()
+            .unwrap();
+        assert!(create_idmapped_mount(&syscall, &mount, 42).is_err());
+        assert!(syscall.get_open_tree_args().is_empty());
+    }
+
+    #[test]
+    fn test_create_idmapped_mounts_without_userns() -> Result<()> {
+        let syscall = TestHelperSyscall::default();
+        let mut spec = Spec::default();
+        assert!(
+            create_idmapped_mounts(&syscall, &spec, Path::new("/proc/self/ns/user"))?.is_empty()
+        );
+
+        spec.set_mounts(Some(vec![bind_mount(&["bind", "idmap"])]));
+        let mut linux = spec.linux().clone().unwrap();
+        linux.set_namespaces(None);
+        spec.set_linux(Some(linux));
+        assert!(create_idmapped_mounts(&syscall, &spec, Path::new("/proc/self/ns/user")).is_err());
+        Ok(())
+    }
+}
@@ -6,6 +6,8 @@ pub(crate) mod rootfs;
 pub use rootfs::RootFS;
 
 pub(super) mod device;
+pub(super) mod idmap;
 pub(super) mod mount;
+pub(super) mod overlay;
 pub(super) mod symlink;
 pub(super) mod utils;
@@ -1,9 +1,16 @@
 use super::{
     symlink::Symlink,
-    utils::{find_parent_mount, parse_mount},
+    utils::{find_parent_mount, parse_mount, parse_propagation, parse_recursive_attr},
 };
+use crate::syscall::mount_api::{
+    self, MountAttr, AT_EMPTY_PATH, AT_RECURSIVE, AT_SYMLINK_NOFOLLOW, FSCONFIG_CMD_CREATE,
+    FSCONFIG_SET_FLAG, FSCONFIG_SET_STRING, FSMOUNT_CLOEXEC, FSOPEN_CLOEXEC,
+    MOVE_MOUNT_F_EMPTY_PATH, MOVE_MOUNT_T_EMPTY_PATH, OPEN_TREE_CLOEXEC, OPEN_TREE_CLONE,
+};
+use crate::syscall::openat2::{self, RESOLVE_IN_ROOT, RESOLVE_NO_MAGICLINKS};
 use crate::utils::PathBufExt;
 use crate::{
+    selinux,
     syscall::{syscall::create_syscall, Syscall},
     utils,
 };
@@ -12,10 +19,11 @@ use libcgroups::common::{
     CgroupSetup::{Hybrid, Legacy, Unified},
     DEFAULT_CGROUP_ROOT,
 };
-use nix::{errno::Errno, mount::MsFlags};
+use nix::{errno::Errno, fcntl::OFlag, mount::MsFlags, unistd};
 use oci_spec::runtime::{Mount as SpecMount, MountBuilder as SpecMountBuilder};
 use procfs::process::{MountOptFields, Process};
 use std::borrow::Cow;
+use std::os::unix::io::RawFd;
 use std::path::{Path, PathBuf};
 use std::{
     collections::HashMap,
@@ -27,6 +35,38 @@ pub struct MountOptions<'a> {
     pub root: &'a Path,
     pub label: Option<&'a str>,
     pub cgroup_ns: bool,
+    /// O_PATH fd of the rootfs. If set, mounts are created with the new mount
+    /// API relative to this fd instead of with mount(2).
+    pub rootfs_fd: Option<RawFd>,
+}
+
+/// Resolves the destination of the mount in the rootfs. If the rootfs fd is
+/// set, the destination is resolved with openat2 in the scope of the fd, so
+/// that symlinks swapped in concurrently can't redirect the mount outside of
+/// the rootfs.
+fn resolve_destination(m: &SpecMount, options: &MountOptions) -> Result<PathBuf> {
+    if let Some(rootfs_fd) = options.rootfs_fd {
+        match openat2::resolve_in_root(rootfs_fd, m.destination()) {
+            Ok(resolved) => return Ok(options.root.join(resolved)),
+            // kernels before linux 5.6 don't support openat2
+            Err(err) if matches!(err.downcast_ref(), Some(Errno::ENOSYS)) => {}
+            Err(err) => {
+                return Err(err.context(format!(
+                    "failed to resolve {:?} in {:?}",
+                    m.destination(),
+                    options.root
+                )))
+            }
+        }
+    }
+
+    utils::secure_join(options.root, m.destination()).with_context(|| {
+        format!(
+            "failed to join {:?} with {:?}",
+            options.root,
+            m.destination()
+        )
+    })
 }
 
 pub struct Mount {
@@ -65,22 +105,17 @@ impl Mount {
             }
             _ => {
                 if *mount.destination() == PathBuf::from("/dev") {
-                    self.mount_into_container(
-                        mount,
-                        options.root,
-                        flags & !MsFlags::MS_RDONLY,
-                        &data,
-                        options.label,
-                    )
-                    .with_context(|| format!("failed to mount /dev: {:?}", mount))?;
+                    self.mount_into_container(mount, options, flags & !MsFlags::MS_RDONLY, &data)
+                        .with_context(|| format!("failed to mount /dev: {:?}", mount))?;
                 } else {
-                    self.mount_into_container(mount, options.root, flags, &data, options.label)
+                    self.mount_into_container(mount, options, flags, &data)
                         .with_context(|| format!("failed to mount: {:?}", mount))?;
                 }
             }
         }
 
-        Ok(())
+        self.set_propagation(mount, options)?;
+        self.set_recursive_attr(mount, options)
     }
     fn mount_cgroup_v1(&self, cgroup_mount: &SpecMount, options: &MountOptions) -> Result<()> {
         log::debug!("Mounting cgroup v1 filesystem");
@@ -190,10 +225,9 @@ impl Mount {
 
         self.mount_into_container(
             &subsystem_mount,
-            options.root,
+            options,
             MsFlags::MS_NOEXEC | MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
             &data,
-            options.label,
         )
         .with_context(|| format!("failed to mount {:?}", subsystem_mount))
     }
@@ -274,7 +308,7 @@ impl Mount {
         log::debug!("{:?}", cgroup_mount);
 
         if self
-            .mount_into_container(&cgroup_mount, options.root, flags, data, options.label)
+            .mount_into_container(&cgroup_mount, options, flags, data)
             .context("failed to mount into container")
             .is_err()
         {
@@ -298,14 +332,8 @@ impl Mount {
                 .context("failed to build cgroup bind mount")?;
             log::debug!("{:?}", bind_mount);
 
-            self.mount_into_container(
-                &bind_mount,
-                options.root,
-                flags | MsFlags::MS_BIND,
-                data,
-                options.label,
-            )
-            .context("failed to bind mount cgroup hierarchy")?;
+            self.mount_into_container(&bind_mount, options, flags | MsFlags::MS_BIND, data)
+                .context("failed to bind mount cgroup hierarchy")?;
         }
 
         Ok(())
@@ -338,25 +366,15 @@ impl Mount {
     fn mount_into_container(
         &self,
         m: &SpecMount,
-        rootfs: &Path,
+        options: &MountOptions,
         flags: MsFlags,
         data: &str,
-        label: Option<&str>,
     ) -> Result<()> {
         let typ = m.typ().as_deref();
-        let mut d = data.to_string();
-
-        if let Some(l) = label {
-            if typ != Some("proc") && typ != Some("sysfs") {
-                match data.is_empty() {
-                    true => d = format!("context=\"{}\"", l),
-                    false => d = format!("{},context=\"{}\"", data, l),
-                }
-            }
-        }
+        let rootfs = options.root;
+        let label = options.label;
 
-        let dest_for_host = utils::secure_join(rootfs, m.destination())
-            .with_context(|| format!("failed to join {:?} with {:?}", rootfs, m.destination()))?;
+        let dest_for_host = resolve_destination(m, options)?;
 
         let dest = Path::new(&dest_for_host);
         let source = m
@@ -366,23 +384,24 @@ impl Mount {
         let src = if typ == Some("bind") {
             let src = canonicalize(source)
                 .with_context(|| format!("failed to canonicalize: {:?}", source))?;
-            let dir = if src.is_file() {
-                Path::new(&dest).parent().unwrap()
-            } else {
-                Path::new(&dest)
-            };
 
-            create_dir_all(&dir)
-                .with_context(|| format!("failed to create dir for bind mount: {:?}", dir))?;
-
-            if src.is_file() {
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .open(&dest)
-                    .with_context(|| format!("failed to create file for bind mount: {:?}", src))?;
+            if let Some(l) = label {
+                let relabel = m.options().as_ref().and_then(|options| {
+                    options.iter().find_map(|o| match o.as_str() {
+                        "z" => Some(true),
+                        "Z" => Some(false),
+                        _ => None,
+                    })
+                });
+                if let Some(shared) = relabel {
+                    if selinux::is_enabled()? {
+                        selinux::relabel(&src, l, shared)
+                            .with_context(|| format!("failed to relabel {:?}", src))?;
+                    }
+                }
             }
 
+            create_bind_mount_point(&src, dest)?;
             src
         } else {
             create_dir_all(&dest)
@@ -391,6 +410,23 @@ impl Mount {
             PathBuf::from(source)
         };
 
+        if let Some(rootfs_fd) = options.rootfs_fd {
+            let dest = dest.strip_prefix(rootfs).unwrap_or(dest);
+            return self
+                .mount_at(m, rootfs_fd, dest, &src, flags, data, label)
+                .with_context(|| format!("failed to mount {:?} to {:?}", src, m.destination()));
+        }
+
+        let mut d = data.to_string();
+        if let Some(l) = label {
+            if typ != Some("proc") && typ != Some("sysfs") {
+                match data.is_empty() {
+                    true => d = format!("context=\"{}\"", l),
+                    false => d = format!("{},context=\"{}\"", data, l),
+                }
+            }
+        }
+
         if let Err(err) = self.syscall.mount(Some(&*src), dest, typ, flags, Some(&*d)) {
             if let Some(errno) = err.downcast_ref() {
                 if !matches!(errno, Errno::EINVAL) {
@@ -403,16 +439,256 @@ impl Mount {
                 .with_context(|| format!("failed to mount {:?} to {:?}", src, dest))?;
         }
 
-        if typ == Some("bind")
-            && flags.intersects(
-                !(MsFlags::MS_REC
-                    | MsFlags::MS_REMOUNT
-                    | MsFlags::MS_BIND
-                    | MsFlags::MS_PRIVATE
-                    | MsFlags::MS_SHARED
-                    | MsFlags::MS_SLAVE),
+        if typ == Some("bind") {
+            self.remount_bind(dest, flags)?;
+        }
+
+        Ok(())
+    }
+
+    /// Mounts with the new mount API to dest, which is relative to the rootfs
+    /// fd. In contrast to mount(2) the filesystem options are applied one by
+    /// one, so that an error names the rejected option together with the
+    /// message the filesystem logged to the fs context.
+    #[allow(clippy::too_many_arguments)]
+    fn mount_at(
+        &self,
+        m: &SpecMount,
+        rootfs_fd: RawFd,
+        dest: &Path,
+        src: &Path,
+        flags: MsFlags,
+        data: &str,
+        label: Option<&str>,
+    ) -> Result<()> {
+        let typ = m.typ().as_deref();
+        let mount_fd = if flags.contains(MsFlags::MS_BIND) {
+            let mut open_flags = OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC;
+            if flags.contains(MsFlags::MS_REC) {
+                open_flags |= AT_RECURSIVE;
+            }
+            let sb_flags = mount_api::sb_flag_params(flags);
+            if !sb_flags.is_empty() {
+                bail!(
+                    "{} can't be applied to the bind mount of {:?}, as the superblock is not created by the mount",
+                    sb_flags.join(","),
+                    src
+                );
+            }
+            let fd = self
+                .syscall
+                .open_tree(libc::AT_FDCWD, Some(src), open_flags)?;
+            let attr = mount_api::mount_attr(flags);
+            if attr != MountAttr::default() {
+                // like the remount of mount(2), the attributes only apply to
+                // the top mount, the submounts are changed by the r* options
+                if let Err(err) = self.syscall.mount_setattr(fd, None, AT_EMPTY_PATH, &attr) {
+                    let _ = unistd::close(fd);
+                    return Err(err.context("failed to set mount attributes"));
+                }
+            }
+            fd
+        } else {
+            let fstype = typ.context("no type in mount spec")?;
+            let fs_fd = self.syscall.fsopen(fstype, FSOPEN_CLOEXEC)?;
+            let res = self
+                .configure_fs(fs_fd, typ, src, flags, data, label)
+                .and_then(|_| {
+                    self.syscall
+                        .fsmount(fs_fd, FSMOUNT_CLOEXEC, mount_api::attr_flags(flags))
+                });
+            let _ = unistd::close(fs_fd);
+            res?
+        };
+
+        let res = self.attach_mount(mount_fd, rootfs_fd, dest);
+        let _ = unistd::close(mount_fd);
+        res
+    }
+
+    /// Applies the source, the options and the label to a new fs context and
+    /// creates the superblock.
+    fn configure_fs(
+        &self,
+        fs_fd: RawFd,
+        typ: Option<&str>,
+        src: &Path,
+        flags: MsFlags,
+        data: &str,
+        label: Option<&str>,
+    ) -> Result<()> {
+        self.syscall
+            .fsconfig(
+                fs_fd,
+                FSCONFIG_SET_STRING,
+                Some("source"),
+                Some(&src.to_string_lossy()),
+            )
+            .context("invalid source")?;
+
+        for param in mount_api::sb_flag_params(flags) {
+            self.syscall
+                .fsconfig(fs_fd, FSCONFIG_SET_FLAG, Some(param), None)
+                .with_context(|| format!("invalid mount flag {:?}", param))?;
+        }
+
+        for option in data.split(',').filter(|o| !o.is_empty()) {
+            let res = match option.split_once('=') {
+                Some((key, value)) => {
+                    self.syscall
+                        .fsconfig(fs_fd, FSCONFIG_SET_STRING, Some(key), Some(value))
+                }
+                None => self
+                    .syscall
+                    .fsconfig(fs_fd, FSCONFIG_SET_FLAG, Some(option), None),
+            };
+            res.with_context(|| format!("invalid mount option {:?}", option))?;
+        }
+
+        if let Some(l) = label {
+            if typ != Some("proc") && typ != Some("sysfs") {
+                // filesystems without support for labels reject the context
+                // option, mount(2) is retried without it in that case as well
+                if let Err(err) =
+                    self.syscall
+                        .fsconfig(fs_fd, FSCONFIG_SET_STRING, Some("context"), Some(l))
+                {
+                    log::warn!("failed to apply mount label {:?}: {:?}", l, err);
+                }
+            }
+        }
+
+        self.syscall
+            .fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None)
+            .context("failed to create filesystem")
+    }
+
+    /// Moves the detached mount to dest, which is resolved in the scope of
+    /// the rootfs fd, so that no symlink in any component of dest can lead
+    /// out of the rootfs.
+    fn attach_mount(&self, mount_fd: RawFd, rootfs_fd: RawFd, dest: &Path) -> Result<()> {
+        let dest_fd = openat2::openat2(
+            rootfs_fd,
+            dest,
+            OFlag::O_PATH | OFlag::O_NOFOLLOW,
+            RESOLVE_IN_ROOT | RESOLVE_NO_MAGICLINKS,
+        )
+        .with_context(|| format!("failed to open mount point {:?}", dest))?;
+
+        let res = self
+            .syscall
+            .move_mount(
+                mount_fd,
+                None,
+                dest_fd,
+                None,
+                MOVE_MOUNT_F_EMPTY_PATH | MOVE_MOUNT_T_EMPTY_PATH,
             )
+            .context("failed to attach mount");
+        let _ = unistd::close(dest_fd);
+        res
+    }
+
+    /// Changes the propagation type of the mount, e.g. to share the mounts
+    /// which are created below an rshared volume with the host. Each type is
+    /// applied by a mount call of its own after the mount was created, as the
+    /// kernel ignores all other flags if a propagation type is given.
+    fn set_propagation(&self, m: &SpecMount, options: &MountOptions) -> Result<()> {
+        let propagation = parse_propagation(m);
+        if propagation.is_empty() {
+            return Ok(());
+        }
+
+        let dest = resolve_destination(m, options)?;
+        for flags in propagation {
+            self.syscall
+                .mount(None, &dest, None, flags, None)
+                .with_context(|| {
+                    format!("failed to change propagation of {:?} to {:?}", dest, flags)
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Attaches a detached id mapped mount, which was created for the mount by
+    /// the main process, to its destination in the rootfs. See idmap.rs
+    pub fn setup_idmapped_mount(
+        &self,
+        m: &SpecMount,
+        options: &MountOptions,
+        fd: RawFd,
+    ) -> Result<()> {
+        log::debug!("Mounting id mapped {:?}", m);
+        let (flags, _) = parse_mount(m);
+        let dest = resolve_destination(m, options)?;
+        let source = m
+            .source()
+            .as_ref()
+            .with_context(|| "no source in mount spec".to_string())?;
+        let src = canonicalize(source)
+            .with_context(|| format!("failed to canonicalize: {:?}", source))?;
+        create_bind_mount_point(&src, &dest)?;
+
+        self.syscall
+            .move_mount(
+                fd,
+                None,
+                libc::AT_FDCWD,
+                Some(&dest),
+                MOVE_MOUNT_F_EMPTY_PATH,
+            )
+            .with_context(|| format!("failed to attach id mapped mount to {:?}", dest))?;
+        self.remount_bind(&dest, flags)?;
+        self.set_propagation(m, options)?;
+        self.set_recursive_attr(m, options)
+    }
+
+    /// Applies the recursive mount options like rro to the mount and all of
+    /// its submounts. There is no fallback for kernels without mount_setattr,
+    /// as the submounts would silently keep their attributes otherwise.
+    fn set_recursive_attr(&self, m: &SpecMount, options: &MountOptions) -> Result<()> {
+        let attr = parse_recursive_attr(m);
+        if attr == MountAttr::default() {
+            return Ok(());
+        }
+
+        let dest = resolve_destination(m, options)?;
+        let (dirfd, path) = match options.rootfs_fd {
+            Some(fd) => (fd, dest.strip_prefix(options.root).unwrap_or(&dest)),
+            None => (libc::AT_FDCWD, dest.as_path()),
+        };
+
+        if let Err(err) =
+            self.syscall
+                .mount_setattr(dirfd, Some(path), AT_RECURSIVE | AT_SYMLINK_NOFOLLOW, &attr)
         {
+            if let Some(Errno::ENOSYS) = err.downcast_ref() {
+                bail!(
+                    "recursive mount options of {:?} require mount_setattr, which is supported since linux 5.12",
+                    m.destination()
+                );
+            }
+            return Err(err.context(format!(
+                "failed to set recursive mount attributes of {:?}",
+                dest
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Flags other than the propagation flags are ignored when a bind mount
+    /// is created, so they are applied by a remount.
+    fn remount_bind(&self, dest: &Path, flags: MsFlags) -> Result<()> {
+        if flags.intersects(
+            !(MsFlags::MS_REC
+                | MsFlags::MS_REMOUNT
+                | MsFlags::MS_BIND
+                | MsFlags::MS_PRIVATE
+                | MsFlags::MS_SHARED
+                | MsFlags::MS_SLAVE),
+        ) {
             self.syscall
                 .mount(Some(dest), dest, None, flags | MsFlags::MS_REMOUNT, None)
                 .with_context(|| format!("Failed to remount: {:?}", dest))?;
@@ -422,14 +698,41 @@ impl Mount {
     }
 }
 
+/// Creates the destination of a bind mount, which is a file if the source is a
+/// file and a directory otherwise.
+fn create_bind_mount_point(src: &Path, dest: &Path) -> Result<()> {
+    let dir = if src.is_file() {
+        dest.parent().unwrap()
+    } else {
+        dest
+    };
+
+    create_dir_all(&dir)
+        .with_context(|| format!("failed to create dir for bind mount: {:?}", dir))?;
+
+    if src.is_file() {
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&dest)
+            .with_context(|| format!("failed to create file for bind mount: {:?}", src))?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
 
     use super::*;
-    use crate::syscall::test::{MountArgs, TestHelperSyscall};
+    use crate::syscall::test::{
+        FsconfigArgs, FsmountArgs, MountArgs, MountSetattrArgs, MoveMountArgs, OpenTreeArgs,
+        TestHelperSyscall, TEST_MOUNT_FD,
+    };
     use crate::utils::create_temp_dir;
     use anyhow::Result;
+    use nix::{fcntl, sys::stat::Mode};
 
     #[test]
     fn test_mount_to_container() {
@@ -452,8 +755,14 @@ mod tests {
                 .unwrap();
             let (flags, data) = parse_mount(mount);
 
+            let options = MountOptions {
+                root: tmp_dir.path(),
+                label: Some("defaults"),
+                cgroup_ns: false,
+                rootfs_fd: None,
+            };
             assert!(m
-                .mount_into_container(mount, tmp_dir.path(), flags, &data, Some("defaults"))
+                .mount_into_container(mount, &options, flags, &data)
                 .is_ok());
 
             let want = vec![MountArgs {
@@ -490,8 +799,14 @@ mod tests {
                 .open(tmp_dir.path().join("null"))
                 .unwrap();
 
+            let options = MountOptions {
+                root: tmp_dir.path(),
+                label: None,
+                cgroup_ns: false,
+                rootfs_fd: None,
+            };
             assert!(m
-                .mount_into_container(mount, tmp_dir.path(), flags, &data, None)
+                .mount_into_container(mount, &options, flags, &data)
                 .is_ok());
 
             let want = vec![
@@ -522,6 +837,451 @@ mod tests {
         }
     }
 
+    fn open_rootfs(rootfs: &Path) -> Result<RawFd> {
+        fcntl::open(
+            rootfs,
+            OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(Into::into)
+    }
+
+    #[test]
+    fn test_mount_at() -> Result<()> {
+        let tmp_dir = create_temp_dir("test_mount_at")?;
+        let rootfs_fd = open_rootfs(&tmp_dir)?;
+        let m = Mount::new();
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/dev/pts"))
+            .typ("devpts")
+            .source(PathBuf::from("devpts"))
+            .options(vec![
+                "nosuid".to_string(),
+                "noexec".to_string(),
+                "newinstance".to_string(),
+                "ptmxmode=0666".to_string(),
+            ])
+            .build()?;
+        let (flags, data) = parse_mount(&mount);
+        let options = MountOptions {
+            root: &tmp_dir,
+            label: Some("defaults"),
+            cgroup_ns: false,
+            rootfs_fd: Some(rootfs_fd),
+        };
+
+        let res = m.mount_into_container(&mount, &options, flags, &data);
+        let _ = unistd::close(rootfs_fd);
+        res?;
+
+        assert!(tmp_dir.join("dev/pts").is_dir());
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert!(syscall.get_mount_args().is_empty());
+        assert_eq!(
+            syscall.get_fsopen_args(),
+            vec![("devpts".to_string(), FSOPEN_CLOEXEC)]
+        );
+        let fsconfig = |cmd, key: Option<&str>, value: Option<&str>| FsconfigArgs {
+            fd: TEST_MOUNT_FD,
+            cmd,
+            key: key.map(|k| k.to_string()),
+            value: value.map(|v| v.to_string()),
+        };
+        assert_eq!(
+            syscall.get_fsconfig_args(),
+            vec![
+                fsconfig(FSCONFIG_SET_STRING, Some("source"), Some("devpts")),
+                fsconfig(FSCONFIG_SET_FLAG, Some("newinstance"), None),
+                fsconfig(FSCONFIG_SET_STRING, Some("ptmxmode"), Some("0666")),
+                fsconfig(FSCONFIG_SET_STRING, Some("context"), Some("defaults")),
+                fsconfig(FSCONFIG_CMD_CREATE, None, None),
+            ]
+        );
+        assert_eq!(
+            syscall.get_fsmount_args(),
+            vec![FsmountArgs {
+                fd: TEST_MOUNT_FD,
+                flags: FSMOUNT_CLOEXEC,
+                attr_flags: mount_api::MOUNT_ATTR_NOSUID | mount_api::MOUNT_ATTR_NOEXEC,
+            }]
+        );
+        let move_mount = syscall.get_move_mount_args();
+        assert_eq!(move_mount.len(), 1);
+        assert_eq!(move_mount[0].from_dirfd, TEST_MOUNT_FD);
+        assert_eq!(move_mount[0].to_path, None);
+        assert_eq!(
+            move_mount[0].flags,
+            MOVE_MOUNT_F_EMPTY_PATH | MOVE_MOUNT_T_EMPTY_PATH
+        );
+        assert!(syscall.get_mount_setattr_args().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_at_bind() -> Result<()> {
+        let tmp_dir = create_temp_dir("test_mount_at_bind")?;
+        let source = tmp_dir.join("data");
+        fs::create_dir(&source)?;
+        let rootfs = tmp_dir.join("rootfs");
+        fs::create_dir(&rootfs)?;
+        let rootfs_fd = open_rootfs(&rootfs)?;
+        let m = Mount::new();
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec![
+                "rbind".to_string(),
+                "ro".to_string(),
+                "rprivate".to_string(),
+            ])
+            .build()?;
+        let (flags, data) = parse_mount(&mount);
+        let options = MountOptions {
+            root: &rootfs,
+            label: None,
+            cgroup_ns: false,
+            rootfs_fd: Some(rootfs_fd),
+        };
+
+        let res = m.mount_into_container(&mount, &options, flags, &data);
+        let _ = unistd::close(rootfs_fd);
+        res?;
+
+        assert!(rootfs.join("data").is_dir());
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert!(syscall.get_mount_args().is_empty());
+        assert!(syscall.get_fsopen_args().is_empty());
+        assert_eq!(
+            syscall.get_open_tree_args(),
+            vec![OpenTreeArgs {
+                dirfd: libc::AT_FDCWD,
+                path: Some(canonicalize(&source)?),
+                flags: OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC | AT_RECURSIVE,
+            }]
+        );
+        assert_eq!(
+            syscall.get_mount_setattr_args(),
+            vec![MountSetattrArgs {
+                dirfd: TEST_MOUNT_FD,
+                path: None,
+                flags: AT_EMPTY_PATH,
+                attr: MountAttr {
+                    attr_set: mount_api::MOUNT_ATTR_RDONLY,
+                    ..Default::default()
+                },
+            }]
+        );
+        assert_eq!(syscall.get_move_mount_args().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_at_sb_flags() -> Result<()> {
+        let tmp_dir = create_temp_dir("test_mount_at_sb_flags")?;
+        for (option, param) in [
+            ("sync", "sync"),
+            ("dirsync", "dirsync"),
+            ("lazytime", "lazytime"),
+        ] {
+            let rootfs_fd = open_rootfs(&tmp_dir)?;
+            let m = Mount::new();
+            let mount = SpecMountBuilder::default()
+                .destination(PathBuf::from("/tmp"))
+                .typ("tmpfs")
+                .source(PathBuf::from("tmpfs"))
+                .options(vec![option.to_string()])
+                .build()?;
+            let (flags, data) = parse_mount(&mount);
+            let options = MountOptions {
+                root: &tmp_dir,
+                label: None,
+                cgroup_ns: false,
+                rootfs_fd: Some(rootfs_fd),
+            };
+
+            let res = m.mount_into_container(&mount, &options, flags, &data);
+            let _ = unistd::close(rootfs_fd);
+            res?;
+
+            let syscall = m
+                .syscall
+                .as_any()
+                .downcast_ref::<TestHelperSyscall>()
+                .unwrap();
+            assert_eq!(
+                syscall.get_fsconfig_args()[1],
+                FsconfigArgs {
+                    fd: TEST_MOUNT_FD,
+                    cmd: FSCONFIG_SET_FLAG,
+                    key: Some(param.to_string()),
+                    value: None,
+                }
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_at_bind_sb_flags() -> Result<()> {
+        let tmp_dir = create_temp_dir("test_mount_at_bind_sb_flags")?;
+        let source = tmp_dir.join("data");
+        fs::create_dir(&source)?;
+        let rootfs = tmp_dir.join("rootfs");
+        fs::create_dir(&rootfs)?;
+        for option in ["sync", "dirsync", "lazytime"] {
+            let rootfs_fd = open_rootfs(&rootfs)?;
+            let m = Mount::new();
+            let mount = SpecMountBuilder::default()
+                .destination(PathBuf::from("/data"))
+                .typ("bind")
+                .source(&source)
+                .options(vec!["rbind".to_string(), option.to_string()])
+                .build()?;
+            let (flags, data) = parse_mount(&mount);
+            let options = MountOptions {
+                root: &rootfs,
+                label: None,
+                cgroup_ns: false,
+                rootfs_fd: Some(rootfs_fd),
+            };
+
+            let res = m.mount_into_container(&mount, &options, flags, &data);
+            let _ = unistd::close(rootfs_fd);
+            assert!(res.is_err(), "{} should be rejected", option);
+
+            let syscall = m
+                .syscall
+                .as_any()
+                .downcast_ref::<TestHelperSyscall>()
+                .unwrap();
+            assert!(syscall.get_open_tree_args().is_empty());
+            assert!(syscall.get_move_mount_args().is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_at_bind_atime() -> Result<()> {
+        let tmp_dir = create_temp_dir("test_mount_at_bind_atime")?;
+        let source = tmp_dir.join("data");
+        fs::create_dir(&source)?;
+        let rootfs = tmp_dir.join("rootfs");
+        fs::create_dir(&rootfs)?;
+        let rootfs_fd = open_rootfs(&rootfs)?;
+        let m = Mount::new();
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec!["bind".to_string(), "noatime".to_string()])
+            .build()?;
+        let (flags, data) = parse_mount(&mount);
+        let options = MountOptions {
+            root: &rootfs,
+            label: None,
+            cgroup_ns: false,
+            rootfs_fd: Some(rootfs_fd),
+        };
+
+        let res = m.mount_into_container(&mount, &options, flags, &data);
+        let _ = unistd::close(rootfs_fd);
+        res?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert_eq!(
+            syscall.get_open_tree_args()[0].flags,
+            OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC
+        );
+        assert_eq!(
+            syscall.get_mount_setattr_args(),
+            vec![MountSetattrArgs {
+                dirfd: TEST_MOUNT_FD,
+                path: None,
+                flags: AT_EMPTY_PATH,
+                attr: MountAttr {
+                    attr_set: mount_api::MOUNT_ATTR_NOATIME,
+                    attr_clr: mount_api::MOUNT_ATTR__ATIME,
+                    ..Default::default()
+                },
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_propagation() -> Result<()> {
+        let tmp_dir = create_temp_dir("test_set_propagation")?;
+        let source = tmp_dir.join("data");
+        fs::create_dir(&source)?;
+        let rootfs = tmp_dir.join("rootfs");
+        let m = Mount::new();
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec![
+                "rbind".to_string(),
+                "rshared".to_string(),
+                "runbindable".to_string(),
+            ])
+            .build()?;
+        let options = MountOptions {
+            root: &rootfs,
+            label: None,
+            cgroup_ns: false,
+            rootfs_fd: None,
+        };
+
+        m.setup_mount(&mount, &options)?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        let propagation = |flags| MountArgs {
+            source: None,
+            target: rootfs.join("data"),
+            fstype: None,
+            flags,
+            data: None,
+        };
+        let got = syscall.get_mount_args();
+        assert_eq!(got.len(), 3);
+        assert_eq!(got[0].flags, MsFlags::MS_BIND | MsFlags::MS_REC);
+        assert_eq!(
+            got[1..],
+            [
+                propagation(MsFlags::MS_SHARED | MsFlags::MS_REC),
+                propagation(MsFlags::MS_UNBINDABLE | MsFlags::MS_REC),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_recursive_attr() -> Result<()> {
+        let tmp_dir = create_temp_dir("test_set_recursive_attr")?;
+        let source = tmp_dir.join("sys");
+        fs::create_dir(&source)?;
+        let rootfs = tmp_dir.join("rootfs");
+        let m = Mount::new();
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/sys"))
+            .typ("bind")
+            .source(&source)
+            .options(vec![
+                "rbind".to_string(),
+                "rro".to_string(),
+                "rnosuid".to_string(),
+            ])
+            .build()?;
+        let options = MountOptions {
+            root: &rootfs,
+            label: None,
+            cgroup_ns: false,
+            rootfs_fd: None,
+        };
+
+        m.setup_mount(&mount, &options)?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        // the recursive options are not passed to mount(2)
+        assert_eq!(
+            syscall.get_mount_args()[0].flags,
+            MsFlags::MS_BIND | MsFlags::MS_REC
+        );
+        assert_eq!(
+            syscall.get_mount_setattr_args(),
+            vec![MountSetattrArgs {
+                dirfd: libc::AT_FDCWD,
+                path: Some(rootfs.join("sys")),
+                flags: AT_RECURSIVE | AT_SYMLINK_NOFOLLOW,
+                attr: MountAttr {
+                    attr_set: mount_api::MOUNT_ATTR_RDONLY | mount_api::MOUNT_ATTR_NOSUID,
+                    ..Default::default()
+                },
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_setup_idmapped_mount() -> Result<()> {
+        let tmp_dir = create_temp_dir("test_setup_idmapped_mount")?;
+        let source = tmp_dir.join("data");
+        fs::create_dir(&source)?;
+        let rootfs = tmp_dir.join("rootfs");
+        let m = Mount::new();
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec![
+                "rbind".to_string(),
+                "ro".to_string(),
+                "idmap".to_string(),
+            ])
+            .build()?;
+        let options = MountOptions {
+            root: &rootfs,
+            label: None,
+            cgroup_ns: false,
+            rootfs_fd: None,
+        };
+
+        m.setup_idmapped_mount(&mount, &options, TEST_MOUNT_FD)?;
+
+        let dest = rootfs.join("data");
+        assert!(dest.is_dir());
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert_eq!(
+            syscall.get_move_mount_args(),
+            vec![MoveMountArgs {
+                from_dirfd: TEST_MOUNT_FD,
+                from_path: None,
+                to_dirfd: libc::AT_FDCWD,
+                to_path: Some(dest.clone()),
+                flags: MOVE_MOUNT_F_EMPTY_PATH,
+            }]
+        );
+        assert_eq!(
+            syscall.get_mount_args(),
+            vec![MountArgs {
+                source: Some(dest.clone()),
+                target: dest,
+                fstype: None,
+                flags: MsFlags::MS_BIND
+                    | MsFlags::MS_REC
+                    | MsFlags::MS_RDONLY
+                    | MsFlags::MS_REMOUNT,
+                data: None,
+            }]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_make_parent_mount_private() {
         let tmp_dir = create_temp_dir("test_make_parent_mount_private").unwrap();
@@ -564,6 +1324,7 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: true,
+            rootfs_fd: None,
         };
 
         let subsystem_name = "cpu";
@@ -615,6 +1376,7 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: false,
+            rootfs_fd: None,
         };
 
         let subsystem_name = "cpu";
@@ -672,6 +1434,7 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: true,
+            rootfs_fd: None,
         };
 
         let mounter = Mount::new();
@@ -741,6 +1504,7 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: true,
+            rootfs_fd: None,
         };
 
         let mounter = Mount::new();
@@ -0,0 +1,300 @@
+//! Assembles the rootfs of a container as an overlay of read only image layers,
+//! for callers which hand layer directories to youki instead of an unpacked
+//! rootfs. The layers are listed by the annotation `org.youki.rootfs.layers`,
+//! separated by colons with the topmost layer first, and are mounted on the
+//! root path of the spec. Changes made by the container are written to an
+//! upper directory in the container directory, which is removed together with
+//! the container.
+use crate::{syscall::Syscall, utils};
+use anyhow::{bail, Context, Result};
+use nix::mount::MsFlags;
+use oci_spec::runtime::Spec;
+use procfs::process::Process;
+use std::{
+    fs,
+    os::unix::{fs::PermissionsExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Annotation listing the layers of the rootfs
+pub const LAYERS_ANNOTATION: &str = "org.youki.rootfs.layers";
+const OVERLAY_DIR: &str = "overlay";
+const FUSE_OVERLAYFS: &str = "fuse-overlayfs";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlayRootfs {
+    /// Read only layers, the topmost layer first
+    lower_dirs: Vec<PathBuf>,
+    /// Receives the changes of the container
+    upper_dir: PathBuf,
+    /// Scratch directory of overlayfs, on the filesystem of the upper dir
+    work_dir: PathBuf,
+}
+
+impl OverlayRootfs {
+    /// Returns the overlay of the container, if the spec lists layers. Relative
+    /// layer paths are relative to the bundle.
+    pub fn from_spec(spec: &Spec, bundle: &Path, container_dir: &Path) -> Result<Option<Self>> {
+        let layers = match spec
+            .annotations()
+            .as_ref()
+            .and_then(|annotations| annotations.get(LAYERS_ANNOTATION))
+        {
+            Some(layers) => layers,
+            None => return Ok(None),
+        };
+
+        let lower_dirs: Vec<PathBuf> = layers
+            .split(':')
+            .filter(|layer| !layer.is_empty())
+            .map(|layer| bundle.join(layer))
+            .collect();
+        if lower_dirs.is_empty() {
+            bail!("{} does not list any layer", LAYERS_ANNOTATION);
+        }
+        // overlayfs separates its options by commas
+        if let Some(layer) = lower_dirs
+            .iter()
+            .find(|layer| layer.to_string_lossy().contains(','))
+        {
+            bail!("layer {:?} must not contain a comma", layer);
+        }
+
+        let overlay_dir = container_dir.join(OVERLAY_DIR);
+        Ok(Some(Self {
+            lower_dirs,
+            upper_dir: overlay_dir.join("upper"),
+            work_dir: overlay_dir.join("work"),
+        }))
+    }
+
+    /// Mounts the overlay on the rootfs. In a user namespace the kernel only
+    /// mounts overlayfs with the userxattr option, which is supported since
+    /// linux 5.11. fuse-overlayfs is used instead on older kernels, if it is
+    /// installed.
+    ///
+    /// The fuse-overlayfs daemon has to run in the mount namespace of the
+    /// container, so it is spawned by the init process. It therefore stays in
+    /// the pid namespace and the cgroup of the container, where it is visible to
+    /// the payload, is accounted to the container and is killed together with
+    /// it. It inherits neither the stdio nor any other fd of the runtime.
+    pub fn mount(&self, syscall: &dyn Syscall, rootfs: &Path, userns: bool) -> Result<()> {
+        if let Some(layer) = self.lower_dirs.iter().find(|layer| !layer.is_dir()) {
+            bail!("layer {:?} is not a directory", layer);
+        }
+        utils::create_dir_all(&self.upper_dir)?;
+        utils::create_dir_all(&self.work_dir)?;
+
+        let data = self.mount_data();
+        if !userns {
+            return syscall
+                .mount(
+                    Some(Path::new("overlay")),
+                    rootfs,
+                    Some("overlay"),
+                    MsFlags::empty(),
+                    Some(&data),
+                )
+                .with_context(|| format!("failed to mount overlay on {:?}", rootfs));
+        }
+
+        let err = match syscall.mount(
+            Some(Path::new("overlay")),
+            rootfs,
+            Some("overlay"),
+            MsFlags::empty(),
+            Some(&format!("{},userxattr", data)),
+        ) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        log::debug!(
+            "failed to mount overlayfs in user namespace, trying {}: {:?}",
+            FUSE_OVERLAYFS,
+            err
+        );
+        let fuse_overlayfs = match utils::lookup_binary(FUSE_OVERLAYFS)? {
+            Some(fuse_overlayfs) => fuse_overlayfs,
+            None => {
+                return Err(err.context(format!(
+                    "failed to mount overlay on {:?} and {} is not installed",
+                    rootfs, FUSE_OVERLAYFS
+                )))
+            }
+        };
+        // the fds of the runtime, e.g. the channels to the main process, the
+        // console socket and the fds preserved for the payload, are closed on exec
+        let fds: Vec<i32> = utils::get_open_fds()?
+            .into_iter()
+            .filter(|fd| *fd > 2)
+            .collect();
+        let mut command = Command::new(&fuse_overlayfs);
+        command
+            .arg("-o")
+            .arg(&data)
+            .arg(rootfs)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        // SAFETY: fcntl is async-signal-safe and fds is not modified
+        unsafe {
+            command.pre_exec(move || {
+                for fd in &fds {
+                    libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                }
+                Ok(())
+            });
+        }
+        let status = command
+            .status()
+            .with_context(|| format!("failed to run {:?}", fuse_overlayfs))?;
+        if !status.success() {
+            bail!(
+                "{:?} failed to mount overlay on {:?}: {}",
+                fuse_overlayfs,
+                rootfs,
+                status
+            );
+        }
+
+        Ok(())
+    }
+
+    fn mount_data(&self) -> String {
+        let lower_dirs: Vec<String> = self
+            .lower_dirs
+            .iter()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .collect();
+        format!(
+            "lowerdir={},upperdir={},workdir={}",
+            lower_dirs.join(":"),
+            self.upper_dir.display(),
+            self.work_dir.display()
+        )
+    }
+
+    /// Unmounts the overlay, if it was mounted outside of a mount namespace,
+    /// and removes the upper and work directories.
+    pub fn remove(&self, rootfs: &Path) -> Result<()> {
+        let mounted = Process::myself()?.mountinfo()?.into_iter().any(|mi| {
+            mi.mount_point == rootfs && (mi.fs_type == "overlay" || mi.fs_type.starts_with("fuse"))
+        });
+        if mounted {
+            nix::mount::umount2(rootfs, nix::mount::MntFlags::MNT_DETACH)
+                .with_context(|| format!("failed to unmount overlay from {:?}", rootfs))?;
+        }
+
+        // overlayfs creates a directory without any permissions in the work
+        // dir, which can't be removed by a rootless user otherwise
+        let work = self.work_dir.join("work");
+        if work.exists() {
+            fs::set_permissions(&work, fs::Permissions::from_mode(0o700))
+                .with_context(|| format!("failed to change permissions of {:?}", work))?;
+        }
+
+        if let Some(overlay_dir) = self.upper_dir.parent() {
+            if overlay_dir.exists() {
+                fs::remove_dir_all(overlay_dir)
+                    .with_context(|| format!("failed to remove {:?}", overlay_dir))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syscall::test::{MountArgs, TestHelperSyscall};
+    use crate::utils::create_temp_dir;
+    use std::collections::HashMap;
+
+    fn spec_with_layers(layers: &str) -> Spec {
+        let mut spec = Spec::default();
+        let mut annotations = HashMap::new();
+        annotations.insert(LAYERS_ANNOTATION.to_string(), layers.to_string());
+        spec.set_annotations(Some(annotations));
+        spec
+    }
+
+    #[test]
+    fn test_from_spec() -> Result<()> {
+        let bundle = Path::new("/bundle");
+        let container_dir = Path::new("/run/youki/test");
+        assert_eq!(
+            OverlayRootfs::from_spec(&Spec::default(), bundle, container_dir)?,
+            None
+        );
+
+        let overlay =
+            OverlayRootfs::from_spec(&spec_with_layers("top:/layers/base"), bundle, container_dir)?
+                .unwrap();
+        assert_eq!(
+            overlay.lower_dirs,
+            vec![PathBuf::from("/bundle/top"), PathBuf::from("/layers/base")]
+        );
+        assert_eq!(
+            overlay.mount_data(),
+            "lowerdir=/bundle/top:/layers/base,upperdir=/run/youki/test/overlay/upper,\
+            workdir=/run/youki/test/overlay/work"
+        );
+
+        assert!(OverlayRootfs::from_spec(&spec_with_layers(":"), bundle, container_dir).is_err());
+        assert!(OverlayRootfs::from_spec(&spec_with_layers("a,b"), bundle, container_dir).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_and_remove() -> Result<()> {
+        let tmp = create_temp_dir("test_overlay_mount_and_remove")?;
+        let bundle = tmp.join("bundle");
+        let container_dir = tmp.join("container");
+        let rootfs = bundle.join("rootfs");
+        fs::create_dir_all(bundle.join("layer"))?;
+        fs::create_dir_all(&rootfs)?;
+        let overlay =
+            OverlayRootfs::from_spec(&spec_with_layers("layer"), &bundle, &container_dir)?.unwrap();
+
+        for userns in [false, true] {
+            let syscall = TestHelperSyscall::default();
+            overlay.mount(&syscall, &rootfs, userns)?;
+            assert!(overlay.upper_dir.is_dir());
+            assert!(overlay.work_dir.is_dir());
+            let mut data = overlay.mount_data();
+            if userns {
+                data.push_str(",userxattr");
+            }
+            assert_eq!(
+                syscall.get_mount_args(),
+                vec![MountArgs {
+                    source: Some(PathBuf::from("overlay")),
+                    target: rootfs.clone(),
+                    fstype: Some("overlay".to_string()),
+                    flags: MsFlags::empty(),
+                    data: Some(data),
+                }]
+            );
+        }
+
+        overlay.remove(&rootfs)?;
+        assert!(!container_dir.join(OVERLAY_DIR).exists());
+        assert!(container_dir.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_missing_layer() -> Result<()> {
+        let tmp = create_temp_dir("test_overlay_mount_missing_layer")?;
+        let overlay =
+            OverlayRootfs::from_spec(&spec_with_layers("missing"), &tmp, &tmp.join("container"))?
+                .unwrap();
+        let syscall = TestHelperSyscall::default();
+        assert!(overlay.mount(&syscall, &tmp, false).is_err());
+        assert!(syscall.get_mount_args().is_empty());
+        Ok(())
+    }
+}
@@ -1,14 +1,22 @@
 use super::{
     device::Device,
+    idmap,
     mount::{Mount, MountOptions},
     symlink::Symlink,
-    utils::default_devices,
+    utils::propagation_flags,
 };
-use crate::syscall::{syscall::create_syscall, Syscall};
+use crate::rootless::RootlessMounts;
+use crate::syscall::{mount_api, syscall::create_syscall, Syscall};
 use anyhow::{bail, Context, Result};
-use nix::mount::MsFlags;
+use libcgroups::common::default_devices;
+use nix::{
+    fcntl::{self, OFlag},
+    mount::MsFlags,
+    sys::stat::Mode,
+    unistd,
+};
 use oci_spec::runtime::{Linux, Spec};
-use std::path::Path;
+use std::{os::unix::io::RawFd, path::Path};
 
 /// Holds information about rootfs
 pub struct RootFS {
@@ -28,34 +36,41 @@ impl RootFS {
         }
     }
 
+    /// Changes the propagation of the mounts in the mount namespace of the
+    /// container, so that mounts on the rootfs don't propagate to the host.
+    /// This has to be done before anything is mounted on the rootfs.
+    pub fn prepare_propagation(&self, linux: &Linux, rootfs: &Path) -> Result<()> {
+        // an unbindable root can't be bind mounted to the rootfs, therefore
+        // it is made unbindable after pivot_root only
+        let flags = match rootfs_propagation(linux)? {
+            Some(flags) if !flags.contains(MsFlags::MS_UNBINDABLE) => flags,
+            _ => MsFlags::MS_SLAVE | MsFlags::MS_REC,
+        };
+
+        self.syscall
+            .mount(None, Path::new("/"), None, flags, None)
+            .context("failed to mount rootfs")?;
+
+        Mount::new()
+            .make_parent_mount_private(rootfs)
+            .context("failed to change parent mount of rootfs private")
+    }
+
+    /// Sets up the mounts and devices of the rootfs. The propagation has to
+    /// be prepared with prepare_propagation first.
     pub fn prepare_rootfs(
         &self,
         spec: &Spec,
         rootfs: &Path,
         bind_devices: bool,
         cgroup_ns: bool,
+        idmapped_mounts: &[RawFd],
+        rootless_mounts: Option<&RootlessMounts>,
     ) -> Result<()> {
         log::debug!("Prepare rootfs: {:?}", rootfs);
-        let mut flags = MsFlags::MS_REC;
         let linux = spec.linux().as_ref().context("no linux in spec")?;
-
-        match linux.rootfs_propagation().as_deref() {
-            Some("shared") => flags |= MsFlags::MS_SHARED,
-            Some("private") => flags |= MsFlags::MS_PRIVATE,
-            Some("slave" | "unbindable") | None => flags |= MsFlags::MS_SLAVE,
-            Some(uknown) => bail!("unknown rootfs_propagation: {}", uknown),
-        }
-
-        self.syscall
-            .mount(None, Path::new("/"), None, flags, None)
-            .context("failed to mount rootfs")?;
-
         let mounter = Mount::new();
 
-        mounter
-            .make_parent_mount_private(rootfs)
-            .context("failed to change parent mount of rootfs private")?;
-
         log::debug!("mount root fs {:?}", rootfs);
         self.syscall.mount(
             Some(rootfs),
@@ -65,19 +80,41 @@ impl RootFS {
             None,
         )?;
 
+        // the mounts are created relative to the rootfs fd, if the kernel
+        // supports the new mount API including mount_setattr (linux 5.12),
+        // which is needed for the attributes of bind mounts, and with
+        // mount(2) otherwise
+        let rootfs_fd = if mount_api::fsopen_supported() && mount_api::mount_setattr_supported() {
+            let fd = fcntl::open(
+                rootfs,
+                OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+                Mode::empty(),
+            )
+            .with_context(|| format!("failed to open rootfs {:?}", rootfs))?;
+            Some(fd)
+        } else {
+            log::debug!("new mount api is not supported, falling back to mount(2)");
+            None
+        };
+
         let global_options = MountOptions {
             root: rootfs,
             label: linux.mount_label().as_deref(),
             cgroup_ns,
+            rootfs_fd,
         };
 
-        if let Some(mounts) = spec.mounts() {
-            for mount in mounts {
-                mounter
-                    .setup_mount(mount, &global_options)
-                    .with_context(|| format!("failed to setup mount {:#?}", mount))?;
-            }
+        let res = Self::setup_mounts(
+            &mounter,
+            spec,
+            &global_options,
+            idmapped_mounts,
+            rootless_mounts,
+        );
+        if let Some(fd) = rootfs_fd {
+            let _ = unistd::close(fd);
         }
+        res?;
 
         let symlinker = Symlink::new();
         symlinker
@@ -102,16 +139,41 @@ impl RootFS {
         Ok(())
     }
 
-    /// Change propagation type of rootfs as specified in spec.
-    pub fn adjust_root_mount_propagation(&self, linux: &Linux) -> Result<()> {
-        let rootfs_propagation = linux.rootfs_propagation().as_deref();
-        let flags = match rootfs_propagation {
-            Some("shared") => Some(MsFlags::MS_SHARED),
-            Some("unbindable") => Some(MsFlags::MS_UNBINDABLE),
-            _ => None,
-        };
+    fn setup_mounts(
+        mounter: &Mount,
+        spec: &Spec,
+        options: &MountOptions,
+        idmapped_mounts: &[RawFd],
+        rootless_mounts: Option<&RootlessMounts>,
+    ) -> Result<()> {
+        if let Some(mounts) = spec.mounts() {
+            // the id mapped mounts were created in the order of the mounts
+            let mut idmapped_mounts = idmapped_mounts.iter();
+            for mount in mounts {
+                if idmap::is_idmapped(mount) {
+                    let fd = idmapped_mounts
+                        .next()
+                        .with_context(|| format!("no id mapped mount for {:?}", mount))?;
+                    mounter.setup_idmapped_mount(mount, options, *fd)
+                } else if let Some(rootless_mounts) = rootless_mounts {
+                    rootless_mounts
+                        .rewrite(mount)
+                        .and_then(|mount| mounter.setup_mount(&mount, options))
+                } else {
+                    mounter.setup_mount(mount, options)
+                }
+                .with_context(|| format!("failed to setup mount {:#?}", mount))?;
+            }
+        }
 
-        if let Some(flags) = flags {
+        Ok(())
+    }
+
+    /// Change propagation type of rootfs as specified in spec. This is done
+    /// after pivot_root, as the rootfs became a bind mount of its own, which
+    /// doesn't necessarily have the propagation type of the mount below.
+    pub fn adjust_root_mount_propagation(&self, linux: &Linux) -> Result<()> {
+        if let Some(flags) = rootfs_propagation(linux)? {
             log::debug!("make root mount {:?}", flags);
             self.syscall
                 .mount(None, Path::new("/"), None, flags, None)?;
@@ -120,3 +182,110 @@ impl RootFS {
         Ok(())
     }
 }
+
+/// Returns the recursive propagation flags of the rootfs propagation in the
+/// spec, if set.
+fn rootfs_propagation(linux: &Linux) -> Result<Option<MsFlags>> {
+    match linux.rootfs_propagation().as_deref() {
+        Some(propagation) => match propagation_flags(propagation) {
+            Some(flags) => Ok(Some(flags | MsFlags::MS_REC)),
+            None => bail!("unknown rootfs_propagation: {}", propagation),
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syscall::test::{MountArgs, TestHelperSyscall};
+    use crate::utils::create_temp_dir;
+    use oci_spec::runtime::LinuxBuilder;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_adjust_root_mount_propagation() -> Result<()> {
+        let cases = [
+            ("shared", MsFlags::MS_SHARED),
+            ("slave", MsFlags::MS_SLAVE),
+            ("private", MsFlags::MS_PRIVATE),
+            ("unbindable", MsFlags::MS_UNBINDABLE),
+            ("rshared", MsFlags::MS_SHARED),
+        ];
+        for (propagation, flags) in cases {
+            let rootfs = RootFS::new();
+            let linux = LinuxBuilder::default()
+                .rootfs_propagation(propagation)
+                .build()?;
+            rootfs.adjust_root_mount_propagation(&linux)?;
+
+            let got = rootfs
+                .syscall
+                .as_any()
+                .downcast_ref::<TestHelperSyscall>()
+                .unwrap()
+                .get_mount_args();
+            assert_eq!(
+                got,
+                vec![MountArgs {
+                    source: None,
+                    target: PathBuf::from("/"),
+                    fstype: None,
+                    flags: flags | MsFlags::MS_REC,
+                    data: None,
+                }]
+            );
+        }
+
+        let rootfs = RootFS::new();
+        rootfs.adjust_root_mount_propagation(&LinuxBuilder::default().build()?)?;
+        assert!(rootfs
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args()
+            .is_empty());
+
+        let linux = LinuxBuilder::default()
+            .rootfs_propagation("unknown")
+            .build()?;
+        assert!(rootfs.adjust_root_mount_propagation(&linux).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepare_propagation() -> Result<()> {
+        let tmp = create_temp_dir("test_prepare_propagation")?;
+        let cases = [
+            (None, MsFlags::MS_SLAVE | MsFlags::MS_REC),
+            (Some("private"), MsFlags::MS_PRIVATE | MsFlags::MS_REC),
+            // the root is made unbindable after pivot_root
+            (Some("unbindable"), MsFlags::MS_SLAVE | MsFlags::MS_REC),
+        ];
+        for (propagation, flags) in cases {
+            let rootfs = RootFS::new();
+            let mut linux = LinuxBuilder::default().build()?;
+            linux.set_rootfs_propagation(propagation.map(String::from));
+            rootfs.prepare_propagation(&linux, &tmp)?;
+
+            let got = rootfs
+                .syscall
+                .as_any()
+                .downcast_ref::<TestHelperSyscall>()
+                .unwrap()
+                .get_mount_args();
+            assert_eq!(
+                got,
+                vec![MountArgs {
+                    source: None,
+                    target: PathBuf::from("/"),
+                    fstype: None,
+                    flags,
+                    data: None,
+                }]
+            );
+        }
+        Ok(())
+    }
+}
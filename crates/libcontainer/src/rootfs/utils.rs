@@ -1,61 +1,12 @@
+use crate::syscall::mount_api::{
+    MountAttr, MOUNT_ATTR_NOATIME, MOUNT_ATTR_NODEV, MOUNT_ATTR_NOEXEC, MOUNT_ATTR_NOSUID,
+    MOUNT_ATTR_RDONLY, MOUNT_ATTR__ATIME,
+};
 use anyhow::{anyhow, Result};
 use nix::{mount::MsFlags, sys::stat::SFlag, NixPath};
-use oci_spec::runtime::{LinuxDevice, LinuxDeviceBuilder, LinuxDeviceType, Mount};
+use oci_spec::runtime::{LinuxDeviceType, Mount};
 use procfs::process::MountInfo;
-use std::path::{Path, PathBuf};
-
-pub fn default_devices() -> Vec<LinuxDevice> {
-    vec![
-        LinuxDeviceBuilder::default()
-            .path(PathBuf::from("/dev/null"))
-            .typ(LinuxDeviceType::C)
-            .major(1)
-            .minor(3)
-            .file_mode(0o066u32)
-            .build()
-            .unwrap(),
-        LinuxDeviceBuilder::default()
-            .path(PathBuf::from("/dev/zero"))
-            .typ(LinuxDeviceType::C)
-            .major(1)
-            .minor(5)
-            .file_mode(0o066u32)
-            .build()
-            .unwrap(),
-        LinuxDeviceBuilder::default()
-            .path(PathBuf::from("/dev/full"))
-            .typ(LinuxDeviceType::C)
-            .major(1)
-            .minor(7)
-            .file_mode(0o066u32)
-            .build()
-            .unwrap(),
-        LinuxDeviceBuilder::default()
-            .path(PathBuf::from("/dev/tty"))
-            .typ(LinuxDeviceType::C)
-            .major(5)
-            .minor(0)
-            .file_mode(0o066u32)
-            .build()
-            .unwrap(),
-        LinuxDeviceBuilder::default()
-            .path(PathBuf::from("/dev/urandom"))
-            .typ(LinuxDeviceType::C)
-            .major(1)
-            .minor(9)
-            .file_mode(0o066u32)
-            .build()
-            .unwrap(),
-        LinuxDeviceBuilder::default()
-            .path(PathBuf::from("/dev/random"))
-            .typ(LinuxDeviceType::C)
-            .major(1)
-            .minor(8)
-            .file_mode(0o066u32)
-            .build()
-            .unwrap(),
-    ]
-}
+use std::path::Path;
 
 pub fn to_sflag(dev_type: LinuxDeviceType) -> SFlag {
     match dev_type {
@@ -84,6 +35,8 @@ pub fn parse_mount(m: &Mount) -> (MsFlags, String) {
                 "sync" => Some((false, MsFlags::MS_SYNCHRONOUS)),
                 "async" => Some((true, MsFlags::MS_SYNCHRONOUS)),
                 "dirsync" => Some((false, MsFlags::MS_DIRSYNC)),
+                "lazytime" => Some((false, MsFlags::MS_LAZYTIME)),
+                "nolazytime" => Some((true, MsFlags::MS_LAZYTIME)),
                 "remount" => Some((false, MsFlags::MS_REMOUNT)),
                 "mand" => Some((false, MsFlags::MS_MANDLOCK)),
                 "nomand" => Some((true, MsFlags::MS_MANDLOCK)),
@@ -93,18 +46,20 @@ pub fn parse_mount(m: &Mount) -> (MsFlags, String) {
                 "nodiratime" => Some((false, MsFlags::MS_NODIRATIME)),
                 "bind" => Some((false, MsFlags::MS_BIND)),
                 "rbind" => Some((false, MsFlags::MS_BIND | MsFlags::MS_REC)),
-                "unbindable" => Some((false, MsFlags::MS_UNBINDABLE)),
-                "runbindable" => Some((false, MsFlags::MS_UNBINDABLE | MsFlags::MS_REC)),
-                "private" => Some((true, MsFlags::MS_PRIVATE)),
-                "rprivate" => Some((true, MsFlags::MS_PRIVATE | MsFlags::MS_REC)),
-                "shared" => Some((true, MsFlags::MS_SHARED)),
-                "rshared" => Some((true, MsFlags::MS_SHARED | MsFlags::MS_REC)),
-                "slave" => Some((true, MsFlags::MS_SLAVE)),
-                "rslave" => Some((true, MsFlags::MS_SLAVE | MsFlags::MS_REC)),
                 "relatime" => Some((true, MsFlags::MS_RELATIME)),
                 "norelatime" => Some((true, MsFlags::MS_RELATIME)),
                 "strictatime" => Some((true, MsFlags::MS_STRICTATIME)),
                 "nostrictatime" => Some((true, MsFlags::MS_STRICTATIME)),
+                // selinux relabeling of bind mounts, not passed to the kernel
+                "z" | "Z" => Some((false, MsFlags::empty())),
+                // id mapped mounts are created with mount_setattr, see idmap.rs
+                "idmap" | "ridmap" => Some((false, MsFlags::empty())),
+                // propagation types, see parse_propagation
+                "unbindable" | "runbindable" | "private" | "rprivate" | "shared" | "rshared"
+                | "slave" | "rslave" => Some((false, MsFlags::empty())),
+                // recursive mount attributes, see parse_recursive_attr
+                "rro" | "rrw" | "rnosuid" | "rsuid" | "rnodev" | "rdev" | "rnoexec" | "rexec"
+                | "rnoatime" => Some((false, MsFlags::empty())),
                 _ => None,
             } {
                 if is_clear {
@@ -120,6 +75,77 @@ pub fn parse_mount(m: &Mount) -> (MsFlags, String) {
     (flags, data.join(","))
 }
 
+/// Parses the propagation types of the mount in the order of the options. The
+/// kernel changes the propagation type with a mount(2) call of its own, which
+/// ignores all other flags, therefore each type is applied after the mount was
+/// created.
+pub fn parse_propagation(m: &Mount) -> Vec<MsFlags> {
+    let mut propagation = Vec::new();
+    if let Some(options) = &m.options() {
+        for s in options {
+            if let Some(flags) = propagation_flags(s) {
+                propagation.push(flags);
+            }
+        }
+    }
+    propagation
+}
+
+/// Returns the flags of a propagation type like rshared, which are used for
+/// the mounts of the spec as well as for the rootfs propagation.
+pub fn propagation_flags(propagation: &str) -> Option<MsFlags> {
+    match propagation {
+        "private" => Some(MsFlags::MS_PRIVATE),
+        "rprivate" => Some(MsFlags::MS_PRIVATE | MsFlags::MS_REC),
+        "shared" => Some(MsFlags::MS_SHARED),
+        "rshared" => Some(MsFlags::MS_SHARED | MsFlags::MS_REC),
+        "slave" => Some(MsFlags::MS_SLAVE),
+        "rslave" => Some(MsFlags::MS_SLAVE | MsFlags::MS_REC),
+        "unbindable" => Some(MsFlags::MS_UNBINDABLE),
+        "runbindable" => Some(MsFlags::MS_UNBINDABLE | MsFlags::MS_REC),
+        _ => None,
+    }
+}
+
+/// Parses the recursive mount options, which apply to the mount and all of its
+/// submounts. mount(2) can only change the attributes of a single mount, so
+/// these are applied with mount_setattr(2) and AT_RECURSIVE after the mount
+/// was created.
+pub fn parse_recursive_attr(m: &Mount) -> MountAttr {
+    let mut attr = MountAttr::default();
+    if let Some(options) = &m.options() {
+        for s in options {
+            if let Some((is_clear, flag)) = match s.as_str() {
+                "rro" => Some((false, MOUNT_ATTR_RDONLY)),
+                "rrw" => Some((true, MOUNT_ATTR_RDONLY)),
+                "rnosuid" => Some((false, MOUNT_ATTR_NOSUID)),
+                "rsuid" => Some((true, MOUNT_ATTR_NOSUID)),
+                "rnodev" => Some((false, MOUNT_ATTR_NODEV)),
+                "rdev" => Some((true, MOUNT_ATTR_NODEV)),
+                "rnoexec" => Some((false, MOUNT_ATTR_NOEXEC)),
+                "rexec" => Some((true, MOUNT_ATTR_NOEXEC)),
+                "rnoatime" => Some((false, MOUNT_ATTR_NOATIME)),
+                _ => None,
+            } {
+                if is_clear {
+                    attr.attr_set &= !flag;
+                    attr.attr_clr |= flag;
+                } else {
+                    attr.attr_set |= flag;
+                    attr.attr_clr &= !flag;
+                }
+            }
+        }
+    }
+
+    // the atime attributes are a mode, the previous mode has to be cleared
+    // to change it
+    if attr.attr_set & MOUNT_ATTR_NOATIME != 0 {
+        attr.attr_clr |= MOUNT_ATTR__ATIME;
+    }
+    attr
+}
+
 /// Find parent mount of rootfs in given mount infos
 pub fn find_parent_mount<'a>(rootfs: &Path, mount_infos: &'a [MountInfo]) -> Result<&'a MountInfo> {
     // find the longest mount point
@@ -136,6 +162,7 @@ mod tests {
     use super::*;
     use anyhow::Context;
     use oci_spec::runtime::MountBuilder;
+    use std::path::PathBuf;
 
     #[test]
     fn test_find_parent_mount() -> anyhow::Result<()> {
@@ -324,6 +351,37 @@ mod tests {
                     .unwrap()
             )
         );
+        assert_eq!(
+            (MsFlags::MS_BIND | MsFlags::MS_REC, "".to_string()),
+            parse_mount(
+                &MountBuilder::default()
+                    .destination(PathBuf::from("/data"))
+                    .typ("bind")
+                    .source(PathBuf::from("/srv/data"))
+                    .options(vec!["rbind".to_string(), "Z".to_string()])
+                    .build()
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            (
+                MsFlags::MS_BIND | MsFlags::MS_REC | MsFlags::MS_RDONLY,
+                "".to_string()
+            ),
+            parse_mount(
+                &MountBuilder::default()
+                    .destination(PathBuf::from("/data"))
+                    .typ("bind")
+                    .source(PathBuf::from("/srv/data"))
+                    .options(vec![
+                        "rbind".to_string(),
+                        "ro".to_string(),
+                        "ridmap".to_string(),
+                    ])
+                    .build()
+                    .unwrap()
+            )
+        );
         // this case is just for coverage purpose
         assert_eq!(
             (
@@ -335,7 +393,7 @@ mod tests {
                     | MsFlags::MS_NOATIME
                     | MsFlags::MS_NODIRATIME
                     | MsFlags::MS_BIND
-                    | MsFlags::MS_UNBINDABLE,
+                    | MsFlags::MS_REC,
                 "".to_string()
             ),
             parse_mount(
@@ -380,4 +438,123 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_parse_propagation() {
+        let mount = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec![
+                "rbind".to_string(),
+                "rshared".to_string(),
+                "ro".to_string(),
+                "slave".to_string(),
+            ])
+            .build()
+            .unwrap();
+        assert_eq!(
+            parse_propagation(&mount),
+            vec![MsFlags::MS_SHARED | MsFlags::MS_REC, MsFlags::MS_SLAVE]
+        );
+        // the propagation types are not passed to the mount of the filesystem
+        assert_eq!(
+            parse_mount(&mount),
+            (
+                MsFlags::MS_BIND | MsFlags::MS_REC | MsFlags::MS_RDONLY,
+                "".to_string()
+            )
+        );
+        assert!(parse_propagation(
+            &MountBuilder::default()
+                .destination(PathBuf::from("/proc"))
+                .typ("proc")
+                .source(PathBuf::from("proc"))
+                .build()
+                .unwrap()
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_parse_recursive_attr() {
+        assert_eq!(
+            MountAttr::default(),
+            parse_recursive_attr(
+                &MountBuilder::default()
+                    .destination(PathBuf::from("/data"))
+                    .typ("bind")
+                    .source(PathBuf::from("/srv/data"))
+                    .options(vec!["rbind".to_string(), "ro".to_string()])
+                    .build()
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            MountAttr {
+                attr_set: MOUNT_ATTR_RDONLY | MOUNT_ATTR_NOSUID | MOUNT_ATTR_NODEV,
+                ..Default::default()
+            },
+            parse_recursive_attr(
+                &MountBuilder::default()
+                    .destination(PathBuf::from("/sys"))
+                    .typ("bind")
+                    .source(PathBuf::from("/sys"))
+                    .options(vec![
+                        "rbind".to_string(),
+                        "rro".to_string(),
+                        "rnosuid".to_string(),
+                        "rnodev".to_string(),
+                    ])
+                    .build()
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            MountAttr {
+                attr_set: MOUNT_ATTR_NOEXEC | MOUNT_ATTR_NOATIME,
+                attr_clr: MOUNT_ATTR_RDONLY | MOUNT_ATTR__ATIME,
+                ..Default::default()
+            },
+            parse_recursive_attr(
+                &MountBuilder::default()
+                    .destination(PathBuf::from("/data"))
+                    .typ("bind")
+                    .source(PathBuf::from("/srv/data"))
+                    .options(vec![
+                        "rbind".to_string(),
+                        "rro".to_string(),
+                        "rrw".to_string(),
+                        "rnoexec".to_string(),
+                        "rnoatime".to_string(),
+                    ])
+                    .build()
+                    .unwrap()
+            )
+        );
+        // the recursive options are neither mount flags nor filesystem data
+        assert_eq!(
+            (MsFlags::MS_BIND | MsFlags::MS_REC, "".to_string()),
+            parse_mount(
+                &MountBuilder::default()
+                    .destination(PathBuf::from("/data"))
+                    .typ("bind")
+                    .source(PathBuf::from("/srv/data"))
+                    .options(vec![
+                        "rbind".to_string(),
+                        "rro".to_string(),
+                        "rrw".to_string(),
+                        "rnosuid".to_string(),
+                        "rsuid".to_string(),
+                        "rnodev".to_string(),
+                        "rdev".to_string(),
+                        "rnoexec".to_string(),
+                        "rexec".to_string(),
+                        "rnoatime".to_string(),
+                    ])
+                    .build()
+                    .unwrap()
+            )
+        );
+    }
 }
@@ -1,10 +1,13 @@
-use crate::{namespaces::Namespaces, utils};
+use crate::{namespaces::Namespaces, syscall::mount_api, utils};
 use anyhow::{bail, Context, Result};
 use nix::unistd::Pid;
-use oci_spec::runtime::{Linux, LinuxIdMapping, LinuxNamespace, LinuxNamespaceType, Mount, Spec};
+use oci_spec::runtime::{
+    Linux, LinuxIdMapping, LinuxNamespace, LinuxNamespaceType, Mount, MountBuilder, Spec,
+};
+use std::borrow::Cow;
 use std::path::Path;
+use std::path::PathBuf;
 use std::process::Command;
-use std::{env, path::PathBuf};
 
 #[derive(Debug, Clone, Default)]
 pub struct Rootless<'a> {
@@ -81,6 +84,136 @@ impl<'a> Rootless<'a> {
     }
 }
 
+/// Rewrites the mounts of the spec, which can't be mounted in a rootless
+/// container as they are, similar to the adjustments runc makes to rootless
+/// specs. The kernel only permits mounting sysfs and mqueue to the owner of the
+/// network and ipc namespace respectively, and ids which are not mapped in the
+/// user namespace can't be passed as mount options.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RootlessMounts {
+    /// The network namespace is created together with the user namespace
+    own_network_ns: bool,
+    /// The ipc namespace is created together with the user namespace
+    own_ipc_ns: bool,
+    uid_mappings: Vec<LinuxIdMapping>,
+    gid_mappings: Vec<LinuxIdMapping>,
+    /// The submounts of a bind mount can be made read only by mount_setattr
+    recursive_ro: bool,
+}
+
+impl RootlessMounts {
+    pub fn new(rootless: &Rootless, namespaces: &Namespaces) -> Self {
+        // namespaces which are joined by path belong to another user namespace
+        let owned = |typ| matches!(namespaces.get(typ), Some(ns) if ns.path().is_none());
+        Self {
+            own_network_ns: owned(LinuxNamespaceType::Network),
+            own_ipc_ns: owned(LinuxNamespaceType::Ipc),
+            uid_mappings: rootless.uid_mappings.cloned().unwrap_or_default(),
+            gid_mappings: rootless.gid_mappings.cloned().unwrap_or_default(),
+            recursive_ro: mount_api::mount_setattr_supported(),
+        }
+    }
+
+    /// Returns the mount to create in place of the mount of the spec
+    pub fn rewrite<'m>(&self, mount: &'m Mount) -> Result<Cow<'m, Mount>> {
+        match mount.typ().as_deref() {
+            Some("sysfs") if !self.own_network_ns => {
+                log::info!(
+                    "rootless container without network namespace can't mount sysfs, bind mounting /sys read only to {:?} instead",
+                    mount.destination()
+                );
+                let mut options: Vec<String> = ["rbind", "nosuid", "noexec", "nodev", "ro"]
+                    .iter()
+                    .map(|o| o.to_string())
+                    .collect();
+                // the submounts of /sys are made read only as well if the
+                // kernel supports mount_setattr, which was added in linux 5.12
+                if self.recursive_ro {
+                    options.push("rro".to_string());
+                }
+                let sys = MountBuilder::default()
+                    .source("/sys")
+                    .destination(mount.destination())
+                    .typ("bind")
+                    .options(options)
+                    .build()
+                    .context("failed to build bind mount of /sys")?;
+                Ok(Cow::Owned(sys))
+            }
+            Some("mqueue") if !self.own_ipc_ns => {
+                log::info!(
+                    "rootless container without ipc namespace can't mount mqueue, mounting tmpfs to {:?} instead",
+                    mount.destination()
+                );
+                let tmpfs = MountBuilder::default()
+                    .source("tmpfs")
+                    .destination(mount.destination())
+                    .typ("tmpfs")
+                    .options(
+                        ["nosuid", "noexec", "nodev", "mode=1777"]
+                            .iter()
+                            .map(|o| o.to_string())
+                            .collect::<Vec<String>>(),
+                    )
+                    .build()
+                    .context("failed to build tmpfs for mqueue")?;
+                Ok(Cow::Owned(tmpfs))
+            }
+            _ if has_id_options(mount) => {
+                let options = mount.options().as_ref().unwrap();
+                let mut kept = Vec::with_capacity(options.len());
+                for opt in options {
+                    if !self.is_option_mapped(opt)? {
+                        log::info!(
+                            "option {} of {:?} is not mapped in the rootless container, removing it",
+                            opt,
+                            mount.destination()
+                        );
+                        continue;
+                    }
+                    kept.push(opt.clone());
+                }
+                if kept.len() == options.len() {
+                    return Ok(Cow::Borrowed(mount));
+                }
+
+                let mut rewritten = mount.clone();
+                rewritten.set_options(Some(kept));
+                Ok(Cow::Owned(rewritten))
+            }
+            _ => Ok(Cow::Borrowed(mount)),
+        }
+    }
+
+    fn is_option_mapped(&self, opt: &str) -> Result<bool> {
+        if let Some(uid) = opt.strip_prefix("uid=") {
+            return Ok(is_id_mapped(uid.parse()?, &self.uid_mappings));
+        }
+        if let Some(gid) = opt.strip_prefix("gid=") {
+            return Ok(is_id_mapped(gid.parse()?, &self.gid_mappings));
+        }
+        Ok(true)
+    }
+}
+
+/// The filesystems which the kernel creates below /dev, like devpts with
+/// gid=5, fall back to the ids of the container process if the uid and gid
+/// options are removed. Their unmapped id options are removed instead of
+/// failing the validation.
+fn has_id_options(mount: &Mount) -> bool {
+    let dev_fs = match mount.typ().as_deref() {
+        Some("devpts") => true,
+        Some("tmpfs") => mount.destination().starts_with("/dev"),
+        _ => false,
+    };
+    dev_fs
+        && mount
+            .options()
+            .iter()
+            .flatten()
+            .any(|opt| opt.starts_with("uid=") || opt.starts_with("gid="))
+}
+
 impl<'a> From<&'a Linux> for Rootless<'a> {
     fn from(linux: &'a Linux) -> Self {
         let namespaces = Namespaces::from(linux.namespaces().as_ref());
@@ -171,7 +304,8 @@ fn validate_mounts(
     uid_mappings: &[LinuxIdMapping],
     gid_mappings: &[LinuxIdMapping],
 ) -> Result<()> {
-    for mount in mounts {
+    // the id options of these are rewritten by RootlessMounts
+    for mount in mounts.iter().filter(|m| !has_id_options(m)) {
         if let Some(options) = mount.options() {
             for opt in options {
                 if opt.starts_with("uid=") && !is_id_mapped(opt[4..].parse()?, uid_mappings) {
@@ -202,8 +336,8 @@ pub fn lookup_map_binaries(spec: &Linux) -> Result<Option<(PathBuf, PathBuf)>> {
             return Ok(None);
         }
 
-        let uidmap = lookup_map_binary("newuidmap")?;
-        let gidmap = lookup_map_binary("newgidmap")?;
+        let uidmap = utils::lookup_binary("newuidmap")?;
+        let gidmap = utils::lookup_binary("newgidmap")?;
 
         match (uidmap, gidmap) {
             (Some(newuidmap), Some(newgidmap)) => Ok(Some((newuidmap, newgidmap))),
@@ -214,14 +348,6 @@ pub fn lookup_map_binaries(spec: &Linux) -> Result<Option<(PathBuf, PathBuf)>> {
     }
 }
 
-fn lookup_map_binary(binary: &str) -> Result<Option<PathBuf>> {
-    let paths = env::var("PATH").context("could not find PATH")?;
-    Ok(paths
-        .split_terminator(':')
-        .map(|p| Path::new(p).join(binary))
-        .find(|p| p.exists()))
-}
-
 fn write_id_mapping(
     pid: Pid,
     map_file: &str,
@@ -261,3 +387,111 @@ fn write_id_mapping(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::{LinuxBuilder, LinuxIdMappingBuilder, LinuxNamespaceBuilder};
+
+    fn rootless_mounts(namespaces: &[LinuxNamespaceType]) -> Result<RootlessMounts> {
+        let mapping = LinuxIdMappingBuilder::default()
+            .host_id(1000u32)
+            .container_id(0u32)
+            .size(1u32)
+            .build()?;
+        let namespaces = namespaces
+            .iter()
+            .map(|typ| LinuxNamespaceBuilder::default().typ(*typ).build())
+            .collect::<Result<Vec<_>, _>>()?;
+        let linux = LinuxBuilder::default()
+            .namespaces(namespaces)
+            .uid_mappings(vec![mapping.clone()])
+            .gid_mappings(vec![mapping])
+            .build()?;
+        let rootless = Rootless::from(&linux);
+        Ok(RootlessMounts::new(
+            &rootless,
+            &Namespaces::from(linux.namespaces().as_ref()),
+        ))
+    }
+
+    fn mount(destination: &str, typ: &str, options: &[&str]) -> Result<Mount> {
+        Ok(MountBuilder::default()
+            .destination(destination)
+            .typ(typ)
+            .source(typ)
+            .options(options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+            .build()?)
+    }
+
+    #[test]
+    fn test_rewrite_sysfs_and_mqueue() -> Result<()> {
+        let sysfs = mount("/sys", "sysfs", &["nosuid", "noexec", "nodev", "ro"])?;
+        let mqueue = mount("/dev/mqueue", "mqueue", &["nosuid", "noexec", "nodev"])?;
+
+        let mut rootless_mounts = rootless_mounts(&[LinuxNamespaceType::User])?;
+        rootless_mounts.recursive_ro = false;
+        let rewritten = rootless_mounts.rewrite(&sysfs)?;
+        assert_eq!(rewritten.typ().as_deref(), Some("bind"));
+        assert_eq!(rewritten.source().as_deref(), Some(Path::new("/sys")));
+        assert_eq!(rewritten.destination(), Path::new("/sys"));
+        assert_eq!(
+            rewritten.options().as_ref().unwrap(),
+            &vec!["rbind", "nosuid", "noexec", "nodev", "ro"]
+        );
+        // the submounts of /sys are read only as well with mount_setattr
+        rootless_mounts.recursive_ro = true;
+        let rewritten = rootless_mounts.rewrite(&sysfs)?;
+        assert_eq!(
+            rewritten.options().as_ref().unwrap(),
+            &vec!["rbind", "nosuid", "noexec", "nodev", "ro", "rro"]
+        );
+        let rewritten = rootless_mounts.rewrite(&mqueue)?;
+        assert_eq!(rewritten.typ().as_deref(), Some("tmpfs"));
+        assert_eq!(rewritten.destination(), Path::new("/dev/mqueue"));
+
+        let rootless_mounts = rootless_mounts(&[
+            LinuxNamespaceType::User,
+            LinuxNamespaceType::Network,
+            LinuxNamespaceType::Ipc,
+        ])?;
+        assert!(matches!(rootless_mounts.rewrite(&sysfs)?, Cow::Borrowed(_)));
+        assert!(matches!(
+            rootless_mounts.rewrite(&mqueue)?,
+            Cow::Borrowed(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_id_options() -> Result<()> {
+        let rootless_mounts = rootless_mounts(&[LinuxNamespaceType::User])?;
+        let devpts = mount(
+            "/dev/pts",
+            "devpts",
+            &["nosuid", "newinstance", "mode=0620", "gid=5"],
+        )?;
+        let rewritten = rootless_mounts.rewrite(&devpts)?;
+        assert_eq!(
+            rewritten.options().as_ref().unwrap(),
+            &vec![
+                "nosuid".to_string(),
+                "newinstance".to_string(),
+                "mode=0620".to_string()
+            ]
+        );
+
+        let shm = mount("/dev/shm", "tmpfs", &["mode=1777", "uid=0", "gid=0"])?;
+        assert!(matches!(rootless_mounts.rewrite(&shm)?, Cow::Borrowed(_)));
+        assert!(has_id_options(&shm));
+        let shm = mount("/dev/shm", "tmpfs", &["mode=1777"])?;
+        assert!(!has_id_options(&shm));
+
+        // the unmapped gid of devpts is rewritten and doesn't fail validation
+        let data = mount("/data", "tmpfs", &["gid=5"])?;
+        let mapping = rootless_mounts.gid_mappings.clone();
+        assert!(validate_mounts(&[devpts], &mapping, &mapping).is_ok());
+        assert!(validate_mounts(&[data], &mapping, &mapping).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,369 @@
+//! Scheduling attributes of the container process. The CPU scheduler
+//! (`process.scheduler`) and the I/O priority (`process.ioPriority`) are newer
+//! than the runtime spec types, therefore they are read from the raw JSON of
+//! the process by this module.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::syscall::Syscall;
+
+/// Size of the first version of struct sched_attr, which has no utilization
+/// clamping fields.
+const SCHED_ATTR_SIZE_VER0: u32 = 48;
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+/// Number of priority levels of the best effort and realtime I/O classes
+const IOPRIO_NR_LEVELS: i64 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SchedPolicy {
+    #[serde(rename = "SCHED_OTHER")]
+    Other = 0,
+    #[serde(rename = "SCHED_FIFO")]
+    Fifo = 1,
+    #[serde(rename = "SCHED_RR")]
+    RoundRobin = 2,
+    #[serde(rename = "SCHED_BATCH")]
+    Batch = 3,
+    #[serde(rename = "SCHED_ISO")]
+    Iso = 4,
+    #[serde(rename = "SCHED_IDLE")]
+    Idle = 5,
+    #[serde(rename = "SCHED_DEADLINE")]
+    Deadline = 6,
+}
+
+impl SchedPolicy {
+    fn is_realtime(self) -> bool {
+        matches!(self, SchedPolicy::Fifo | SchedPolicy::RoundRobin)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SchedFlag {
+    #[serde(rename = "SCHED_FLAG_RESET_ON_FORK")]
+    ResetOnFork = 0x01,
+    #[serde(rename = "SCHED_FLAG_RECLAIM")]
+    Reclaim = 0x02,
+    #[serde(rename = "SCHED_FLAG_DL_OVERRUN")]
+    DlOverrun = 0x04,
+    #[serde(rename = "SCHED_FLAG_KEEP_POLICY")]
+    KeepPolicy = 0x08,
+    #[serde(rename = "SCHED_FLAG_KEEP_PARAMS")]
+    KeepParams = 0x10,
+    #[serde(rename = "SCHED_FLAG_UTIL_CLAMP_MIN")]
+    UtilClampMin = 0x20,
+    #[serde(rename = "SCHED_FLAG_UTIL_CLAMP_MAX")]
+    UtilClampMax = 0x40,
+}
+
+/// Scheduling policy and parameters of the container process, see
+/// sched_setattr(2)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Scheduler {
+    pub policy: SchedPolicy,
+    pub nice: Option<i32>,
+    pub priority: Option<i32>,
+    pub flags: Option<Vec<SchedFlag>>,
+    /// Runtime of SCHED_DEADLINE in nanoseconds
+    pub runtime: Option<u64>,
+    /// Deadline of SCHED_DEADLINE in nanoseconds
+    pub deadline: Option<u64>,
+    /// Period of SCHED_DEADLINE in nanoseconds
+    pub period: Option<u64>,
+}
+
+/// Layout of struct sched_attr, as expected by the sched_setattr syscall
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchedAttr {
+    pub size: u32,
+    pub sched_policy: u32,
+    pub sched_flags: u64,
+    pub sched_nice: i32,
+    pub sched_priority: u32,
+    pub sched_runtime: u64,
+    pub sched_deadline: u64,
+    pub sched_period: u64,
+}
+
+impl From<&Scheduler> for SchedAttr {
+    fn from(scheduler: &Scheduler) -> Self {
+        SchedAttr {
+            size: SCHED_ATTR_SIZE_VER0,
+            sched_policy: scheduler.policy as u32,
+            sched_flags: scheduler
+                .flags
+                .iter()
+                .flatten()
+                .fold(0, |flags, flag| flags | *flag as u64),
+            sched_nice: scheduler.nice.unwrap_or_default(),
+            sched_priority: scheduler.priority.unwrap_or_default() as u32,
+            sched_runtime: scheduler.runtime.unwrap_or_default(),
+            sched_deadline: scheduler.deadline.unwrap_or_default(),
+            sched_period: scheduler.period.unwrap_or_default(),
+        }
+    }
+}
+
+impl Scheduler {
+    fn validate(&self) -> Result<()> {
+        // SCHED_ISO is reserved, but has never been implemented by the kernel
+        if self.policy == SchedPolicy::Iso {
+            bail!("scheduler policy SCHED_ISO is not supported");
+        }
+
+        // the clamp values are fields of the second version of sched_attr,
+        // which the spec has no values for
+        if let Some(flag) = self
+            .flags
+            .iter()
+            .flatten()
+            .find(|flag| matches!(flag, SchedFlag::UtilClampMin | SchedFlag::UtilClampMax))
+        {
+            bail!("scheduler flag {:?} is not supported", flag);
+        }
+
+        if let Some(nice) = self.nice {
+            if !(-20..=19).contains(&nice) {
+                bail!("scheduler nice value {} is not in range -20..=19", nice);
+            }
+        }
+
+        let priority = self.priority.unwrap_or_default();
+        if self.policy.is_realtime() {
+            if !(1..=99).contains(&priority) {
+                bail!(
+                    "scheduler priority {} of {:?} is not in range 1..=99",
+                    priority,
+                    self.policy
+                );
+            }
+        } else if priority != 0 {
+            bail!("scheduler priority is only supported by SCHED_FIFO and SCHED_RR");
+        }
+
+        let deadline_params = [self.runtime, self.deadline, self.period];
+        if self.policy == SchedPolicy::Deadline {
+            let runtime = self.runtime.unwrap_or_default();
+            let deadline = self.deadline.unwrap_or_default();
+            // a period of zero defaults to the deadline in the kernel
+            let period = self.period.filter(|p| *p != 0).unwrap_or(deadline);
+            if runtime == 0 || runtime > deadline || deadline > period {
+                bail!("SCHED_DEADLINE requires 0 < runtime <= deadline <= period");
+            }
+        } else if deadline_params.iter().any(|p| p.unwrap_or_default() != 0) {
+            bail!("runtime, deadline and period are only supported by SCHED_DEADLINE");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IoPriorityClass {
+    #[serde(rename = "IOPRIO_CLASS_RT")]
+    RealTime = 1,
+    #[serde(rename = "IOPRIO_CLASS_BE")]
+    BestEffort = 2,
+    #[serde(rename = "IOPRIO_CLASS_IDLE")]
+    Idle = 3,
+}
+
+/// I/O scheduling class and priority of the container process, see
+/// ioprio_set(2)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoPriority {
+    pub class: IoPriorityClass,
+    #[serde(default)]
+    pub priority: i64,
+}
+
+impl IoPriority {
+    fn validate(&self) -> Result<()> {
+        if !(0..IOPRIO_NR_LEVELS).contains(&self.priority) {
+            bail!(
+                "I/O priority {} is not in range 0..{}",
+                self.priority,
+                IOPRIO_NR_LEVELS
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Encodes class and priority as expected by the ioprio_set syscall
+    pub fn value(&self) -> i32 {
+        ((self.class as i32) << IOPRIO_CLASS_SHIFT) | self.priority as i32
+    }
+}
+
+/// Scheduling attributes of a process of the runtime spec
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scheduling {
+    pub scheduler: Option<Scheduler>,
+    pub io_priority: Option<IoPriority>,
+}
+
+impl Scheduling {
+    /// Reads the scheduling attributes from a process of the runtime spec in
+    /// JSON representation.
+    pub fn from_process(process: &Value) -> Result<Self> {
+        let scheduler: Option<Scheduler> = match process.get("scheduler") {
+            Some(scheduler) => Some(
+                serde_json::from_value(scheduler.clone())
+                    .context("failed to parse process.scheduler")?,
+            ),
+            None => None,
+        };
+        let io_priority: Option<IoPriority> = match process.get("ioPriority") {
+            Some(io_priority) => Some(
+                serde_json::from_value(io_priority.clone())
+                    .context("failed to parse process.ioPriority")?,
+            ),
+            None => None,
+        };
+
+        if let Some(scheduler) = &scheduler {
+            scheduler.validate()?;
+        }
+        if let Some(io_priority) = &io_priority {
+            io_priority.validate()?;
+        }
+
+        Ok(Scheduling {
+            scheduler,
+            io_priority,
+        })
+    }
+
+    /// Reads the scheduling attributes of the process of a runtime spec in
+    /// JSON representation.
+    pub fn from_spec(spec: &Value) -> Result<Self> {
+        match spec.get("process") {
+            Some(process) => Self::from_process(process),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Applies the scheduling attributes to the calling process. Realtime
+    /// policies and priorities require CAP_SYS_NICE, so this has to be done
+    /// before the capabilities are dropped.
+    pub fn apply(&self, syscall: &dyn Syscall) -> Result<()> {
+        if let Some(scheduler) = &self.scheduler {
+            syscall
+                .sched_setattr(scheduler)
+                .with_context(|| format!("failed to set scheduler {:?}", scheduler))?;
+        }
+
+        if let Some(io_priority) = &self.io_priority {
+            syscall
+                .ioprio_set(io_priority)
+                .with_context(|| format!("failed to set I/O priority {:?}", io_priority))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syscall::test::TestHelperSyscall;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_process() -> Result<()> {
+        let scheduling = Scheduling::from_process(&json!({
+            "args": ["sleep", "1"],
+            "scheduler": {
+                "policy": "SCHED_FIFO",
+                "priority": 50,
+                "flags": ["SCHED_FLAG_RESET_ON_FORK"],
+            },
+            "ioPriority": { "class": "IOPRIO_CLASS_BE", "priority": 4 },
+        }))?;
+
+        let scheduler = scheduling.scheduler.unwrap();
+        assert_eq!(scheduler.policy, SchedPolicy::Fifo);
+        assert_eq!(
+            SchedAttr::from(&scheduler),
+            SchedAttr {
+                size: SCHED_ATTR_SIZE_VER0,
+                sched_policy: 1,
+                sched_flags: 0x01,
+                sched_priority: 50,
+                ..Default::default()
+            }
+        );
+
+        let io_priority = scheduling.io_priority.unwrap();
+        assert_eq!(io_priority.class, IoPriorityClass::BestEffort);
+        assert_eq!(io_priority.value(), (2 << 13) | 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_spec_without_scheduling() -> Result<()> {
+        let scheduling = Scheduling::from_spec(&json!({ "process": { "args": ["sh"] } }))?;
+        assert_eq!(scheduling, Scheduling::default());
+        assert_eq!(
+            Scheduling::from_spec(&json!({ "ociVersion": "1.0.2-dev" }))?,
+            Scheduling::default()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_process_invalid() {
+        let invalid = vec![
+            json!({ "scheduler": { "policy": "SCHED_UNKNOWN" } }),
+            json!({ "scheduler": { "policy": "SCHED_ISO" } }),
+            json!({ "scheduler": { "policy": "SCHED_OTHER", "flags": ["SCHED_FLAG_UTIL_CLAMP_MIN"] } }),
+            json!({ "scheduler": { "policy": "SCHED_OTHER", "flags": ["SCHED_FLAG_UTIL_CLAMP_MAX"] } }),
+            json!({ "scheduler": { "policy": "SCHED_OTHER", "nice": 20 } }),
+            json!({ "scheduler": { "policy": "SCHED_OTHER", "priority": 1 } }),
+            json!({ "scheduler": { "policy": "SCHED_RR", "priority": 100 } }),
+            json!({ "scheduler": { "policy": "SCHED_BATCH", "runtime": 100 } }),
+            json!({
+                "scheduler": {
+                    "policy": "SCHED_DEADLINE",
+                    "runtime": 200, "deadline": 100, "period": 300,
+                }
+            }),
+            json!({ "ioPriority": { "class": "IOPRIO_CLASS_RT", "priority": 8 } }),
+        ];
+
+        for process in invalid {
+            assert!(
+                Scheduling::from_process(&process).is_err(),
+                "{} should be invalid",
+                process
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply() -> Result<()> {
+        let scheduling = Scheduling::from_process(&json!({
+            "scheduler": {
+                "policy": "SCHED_DEADLINE",
+                "runtime": 10_000_000, "deadline": 20_000_000, "period": 30_000_000,
+            },
+            "ioPriority": { "class": "IOPRIO_CLASS_IDLE" },
+        }))?;
+        let syscall = TestHelperSyscall::default();
+        scheduling.apply(&syscall)?;
+
+        assert_eq!(
+            syscall.get_sched_setattr_args(),
+            vec![scheduling.scheduler.clone().unwrap()]
+        );
+        assert_eq!(
+            syscall.get_ioprio_set_args(),
+            vec![scheduling.io_priority.unwrap()]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,546 @@
+//! A seccomp agent handles the syscalls of a container which hit a
+//! SCMP_ACT_NOTIFY rule. The runtime passes the seccomp notify fd together
+//! with the container process state to the listener path of the spec, where
+//! [`receive`] accepts it. An [`Agent`] then dispatches every notification to
+//! the [`Handler`] registered for the syscall, which decides whether the
+//! syscall is allowed, denied or emulated on behalf of the container.
+use super::libseccomp_backend::translate_syscall;
+use crate::container::ContainerProcessState;
+use anyhow::{bail, Context, Result};
+use libseccomp::*;
+use nix::{
+    errno::Errno,
+    poll::{self, PollFd, PollFlags},
+    sys::{socket, uio},
+    unistd,
+};
+use std::{
+    collections::HashMap,
+    fs::File,
+    os::unix::{fs::FileExt, io::RawFd},
+    path::Path,
+};
+
+const DEFAULT_BUFFER_SIZE: usize = 4096;
+/// Lets the syscall of the notification continue in the kernel
+const SECCOMP_USER_NOTIF_FLAG_CONTINUE: u32 = 1;
+
+/// Receives the container process state and the seccomp notify fd from the
+/// runtime. Only a single connection is accepted on the listener path, since
+/// the spec only sends the state once per container.
+pub fn receive(listener_path: &Path) -> Result<(ContainerProcessState, RawFd)> {
+    let socket = listen(listener_path)?;
+    let res = accept(socket);
+    let _ = unistd::close(socket);
+    res
+}
+
+/// Creates the listening socket at the listener path. The runtime fails to
+/// connect if the socket doesn't exist yet, so callers which run the agent in
+/// a thread should listen before the container is created.
+pub fn listen(listener_path: &Path) -> Result<RawFd> {
+    let addr = socket::SockAddr::new_unix(listener_path)?;
+    let socket = socket::socket(
+        socket::AddressFamily::Unix,
+        socket::SockType::Stream,
+        socket::SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .context("failed to create seccomp listener socket")?;
+    let res = socket::bind(socket, &addr)
+        .context("failed to bind to seccomp listener socket")
+        // Force the backlog to be 1 so in the case of an error, only one
+        // connection from clients will be waiting.
+        .and_then(|_| socket::listen(socket, 1).context("failed to listen on seccomp listener"));
+    if let Err(err) = res {
+        let _ = unistd::close(socket);
+        return Err(err);
+    }
+
+    Ok(socket)
+}
+
+/// Accepts a single connection on a socket returned by [`listen`] and receives
+/// the container process state and the seccomp notify fd from it.
+pub fn accept(socket: RawFd) -> Result<(ContainerProcessState, RawFd)> {
+    let conn = socket::accept(socket).context("failed to accept connection")?;
+
+    let mut cmsgspace = nix::cmsg_space!([RawFd; 1]);
+    let mut buf = vec![0u8; DEFAULT_BUFFER_SIZE];
+    let iov = [uio::IoVec::from_mut_slice(&mut buf)];
+    let msg = socket::recvmsg(
+        conn,
+        &iov,
+        Some(&mut cmsgspace),
+        socket::MsgFlags::MSG_CMSG_CLOEXEC,
+    );
+    let _ = unistd::close(conn);
+    let msg = msg.context("failed to receive message")?;
+
+    // We are expecting 1 SCM_RIGHTS message with 1 fd.
+    let cmsg = msg
+        .cmsgs()
+        .next()
+        .context("expecting at least 1 SCM_RIGHTS message")?;
+    let fd = match cmsg {
+        socket::ControlMessageOwned::ScmRights(fds) => {
+            if fds.len() != 1 {
+                bail!("expecting 1 fds, but received: {:?}", fds);
+            }
+
+            fds[0]
+        }
+        _ => {
+            bail!(
+                "expecting 1 SCM_RIGHTS message, but received {:?} instead",
+                cmsg
+            );
+        }
+    };
+
+    if msg.bytes >= DEFAULT_BUFFER_SIZE {
+        let _ = unistd::close(fd);
+        bail!("received more than the DEFAULT_BUFFER_SIZE");
+    }
+
+    // We have to truncate the message to the correct size, so serde can
+    // deserialized the data correctly.
+    buf.truncate(msg.bytes);
+    let state = match serde_json::from_slice(&buf) {
+        Ok(state) => state,
+        Err(err) => {
+            let _ = unistd::close(fd);
+            bail!(
+                "failed to parse the received message as container process state: {}",
+                err
+            );
+        }
+    };
+
+    Ok((state, fd))
+}
+
+/// A syscall of the container, which is waiting for the decision of the agent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    /// Cookie identifying the notification
+    pub id: u64,
+    /// Pid of the thread that made the syscall, as seen by the agent
+    pub pid: u32,
+    /// Syscall number for the architecture of the syscall
+    pub nr: i32,
+    /// AUDIT_ARCH_* value of the syscall
+    pub arch: u32,
+    pub instruction_pointer: u64,
+    pub args: [u64; 6],
+}
+
+impl From<&seccomp_notif> for Notification {
+    fn from(notif: &seccomp_notif) -> Self {
+        Notification {
+            id: notif.id,
+            pid: notif.pid,
+            nr: notif.data.nr,
+            arch: notif.data.arch,
+            instruction_pointer: notif.data.instruction_pointer,
+            args: notif.data.args,
+        }
+    }
+}
+
+/// The decision of a handler about a syscall
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    /// Let the kernel execute the syscall as if no filter was installed.
+    /// Arguments in the memory of the container may have been changed after
+    /// the handler inspected them, so this must not be used to make security
+    /// decisions based on pointer arguments.
+    Continue,
+    /// Fail the syscall with the given errno
+    Errno(i32),
+    /// Return the given value from the syscall without executing it, after the
+    /// handler emulated the syscall
+    Value(i64),
+}
+
+impl Response {
+    fn fill(self, resp: &mut seccomp_notif_resp, id: u64) {
+        resp.id = id;
+        resp.val = 0;
+        resp.error = 0;
+        resp.flags = 0;
+        match self {
+            Response::Continue => resp.flags = SECCOMP_USER_NOTIF_FLAG_CONTINUE,
+            Response::Errno(errno) => resp.error = -errno,
+            Response::Value(val) => resp.val = val,
+        }
+    }
+}
+
+/// Decides about the syscalls of the container it has been registered for.
+/// Closures with the same signature implement this trait as well.
+pub trait Handler {
+    fn handle(
+        &mut self,
+        state: &ContainerProcessState,
+        notifier: &Notifier,
+        notification: &Notification,
+    ) -> Result<Response>;
+}
+
+impl<F> Handler for F
+where
+    F: FnMut(&ContainerProcessState, &Notifier, &Notification) -> Result<Response>,
+{
+    fn handle(
+        &mut self,
+        state: &ContainerProcessState,
+        notifier: &Notifier,
+        notification: &Notification,
+    ) -> Result<Response> {
+        self(state, notifier, notification)
+    }
+}
+
+/// Owns the seccomp notify fd and gives handlers access to the memory of the
+/// process that made the syscall.
+#[derive(Debug)]
+pub struct Notifier {
+    fd: RawFd,
+}
+
+impl Notifier {
+    /// Checks that the notification is still pending. The process may have
+    /// been killed and its pid reused while the handler was running, so this
+    /// has to be checked after reading from the memory of the process and
+    /// before acting on what has been read.
+    pub fn is_valid(&self, notification: &Notification) -> bool {
+        unsafe { seccomp_notify_id_valid(self.fd, notification.id) == 0 }
+    }
+
+    /// Reads a nul terminated string, e.g. a path argument, from the memory of
+    /// the process that made the syscall.
+    pub fn read_string(&self, notification: &Notification, addr: u64) -> Result<String> {
+        let mem_path = format!("/proc/{}/mem", notification.pid);
+        let mem = File::open(&mem_path).with_context(|| format!("failed to open {}", mem_path))?;
+        if !self.is_valid(notification) {
+            bail!("notification {} is no longer valid", notification.id);
+        }
+
+        let mut buf = vec![0u8; libc::PATH_MAX as usize];
+        let mut len = 0;
+        while len < buf.len() {
+            let read = mem
+                .read_at(&mut buf[len..], addr + len as u64)
+                .with_context(|| format!("failed to read {}", mem_path))?;
+            if read == 0 {
+                break;
+            }
+            if let Some(end) = buf[len..len + read].iter().position(|b| *b == 0) {
+                buf.truncate(len + end);
+                if !self.is_valid(notification) {
+                    bail!("notification {} is no longer valid", notification.id);
+                }
+                return Ok(String::from_utf8(buf)?);
+            }
+            len += read;
+        }
+
+        bail!("string at {:#x} is not nul terminated", addr)
+    }
+
+    fn receive(&self, req: *mut seccomp_notif) -> Result<Notification> {
+        // The kernel rejects requests which are not zeroed.
+        // Ref: https://man7.org/linux/man-pages/man2/seccomp_unotify.2.html
+        unsafe { std::ptr::write_bytes(req, 0, 1) };
+        let res = unsafe { seccomp_notify_receive(self.fd, req) };
+        if res != 0 {
+            bail!("failed to receive seccomp notification: {}", res);
+        }
+
+        Ok(Notification::from(unsafe { &*req }))
+    }
+
+    fn respond(&self, resp: *mut seccomp_notif_resp) -> Result<()> {
+        let res = unsafe { seccomp_notify_respond(self.fd, resp) };
+        if res != 0 {
+            bail!("failed to respond to seccomp notification: {}", res);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.fd);
+    }
+}
+
+/// Receives the notifications of a container and responds with the decision
+/// of the registered handlers. Syscalls without a handler are passed to the
+/// default handler, which denies them with ENOSYS unless configured otherwise.
+pub struct Agent {
+    state: ContainerProcessState,
+    notifier: Notifier,
+    handlers: HashMap<i32, Box<dyn Handler>>,
+    default_handler: Box<dyn Handler>,
+}
+
+impl Agent {
+    /// Creates an agent which takes ownership of the seccomp notify fd.
+    pub fn new(state: ContainerProcessState, fd: RawFd) -> Self {
+        Agent {
+            state,
+            notifier: Notifier { fd },
+            handlers: HashMap::new(),
+            default_handler: Box::new(fixed_response(Response::Errno(libc::ENOSYS))),
+        }
+    }
+
+    /// Registers the handler for the notifications of the given syscall.
+    pub fn with_handler<H: Handler + 'static>(mut self, syscall: &str, handler: H) -> Result<Self> {
+        let nr = translate_syscall(syscall)?;
+        self.handlers.insert(nr, Box::new(handler));
+        Ok(self)
+    }
+
+    /// Sets the response for syscalls which have no handler.
+    pub fn with_default_response(self, response: Response) -> Self {
+        self.with_default_handler(fixed_response(response))
+    }
+
+    /// Sets the handler for syscalls which have no handler of their own.
+    pub fn with_default_handler<H: Handler + 'static>(mut self, handler: H) -> Self {
+        self.default_handler = Box::new(handler);
+        self
+    }
+
+    /// Handles notifications until all processes of the container have exited.
+    pub fn run(&mut self) -> Result<()> {
+        while self.handle_next()? {}
+        Ok(())
+    }
+
+    /// Waits for the next notification and responds to it. Returns false once
+    /// no process is using the filter anymore.
+    pub fn handle_next(&mut self) -> Result<bool> {
+        let mut fds = [PollFd::new(self.notifier.fd, PollFlags::POLLIN)];
+        loop {
+            match poll::poll(&mut fds, -1) {
+                Ok(_) => break,
+                Err(Errno::EINTR) => continue,
+                Err(err) => bail!("failed to poll the seccomp notify fd: {}", err),
+            }
+        }
+        let revents = fds[0].revents().unwrap_or_else(PollFlags::empty);
+        if !revents.contains(PollFlags::POLLIN) {
+            // POLLHUP is reported once the filter has no users left
+            return Ok(false);
+        }
+
+        let mut req: *mut seccomp_notif = std::ptr::null_mut();
+        let mut resp: *mut seccomp_notif_resp = std::ptr::null_mut();
+        let res = unsafe { seccomp_notify_alloc(&mut req, &mut resp) };
+        if res != 0 {
+            bail!("failed to allocate seccomp notification: {}", res);
+        }
+        let res = self.dispatch(req, resp);
+        unsafe { seccomp_notify_free(req, resp) };
+        res.map(|_| true)
+    }
+
+    fn dispatch(&mut self, req: *mut seccomp_notif, resp: *mut seccomp_notif_resp) -> Result<()> {
+        let notification = match self.notifier.receive(req) {
+            Ok(notification) => notification,
+            // the process may have been killed between poll and receive
+            Err(err) => {
+                log::debug!("{}", err);
+                return Ok(());
+            }
+        };
+
+        let handler = match self.handlers.get_mut(&notification.nr) {
+            Some(handler) => handler,
+            None => &mut self.default_handler,
+        };
+        let response = handler
+            .handle(&self.state, &self.notifier, &notification)
+            .unwrap_or_else(|err| {
+                log::warn!(
+                    "seccomp handler for syscall {} failed: {:?}",
+                    notification.nr,
+                    err
+                );
+                Response::Errno(libc::EPERM)
+            });
+
+        response.fill(unsafe { &mut *resp }, notification.id);
+        if let Err(err) = self.notifier.respond(resp) {
+            // The syscall was interrupted or the process was killed, which is
+            // not an error of the agent.
+            log::debug!("{}", err);
+        }
+
+        Ok(())
+    }
+}
+
+fn fixed_response(response: Response) -> impl Handler {
+    move |_: &ContainerProcessState, _: &Notifier, _: &Notification| Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seccomp::initialize_seccomp;
+    use crate::seccomp::test_utils::arg_eq;
+    use crate::utils::test_utils;
+    use oci_spec::runtime::{
+        Arch, LinuxSeccomp, LinuxSeccompAction, LinuxSeccompArg, LinuxSeccompBuilder,
+        LinuxSyscallBuilder,
+    };
+    use serial_test::serial;
+    use std::ffi::CString;
+    use std::thread;
+
+    fn notify_profile(syscall: &str, args: Vec<LinuxSeccompArg>) -> Result<LinuxSeccomp> {
+        let mut builder = LinuxSyscallBuilder::default()
+            .names(vec![syscall.to_string()])
+            .action(LinuxSeccompAction::ScmpActNotify);
+        if !args.is_empty() {
+            builder = builder.args(args);
+        }
+
+        Ok(LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .architectures(vec![Arch::ScmpArchNative])
+            .syscalls(vec![builder.build()?])
+            .build()?)
+    }
+
+    // Loads the profile for the calling thread and handles the given number of
+    // notifications with an agent in another thread. The agent thread inherits
+    // the filter, so the profile must not match the syscalls of the agent.
+    fn spawn_agent<F>(
+        profile: &LinuxSeccomp,
+        notifications: usize,
+        agent: F,
+    ) -> Result<thread::JoinHandle<Result<()>>>
+    where
+        F: FnOnce(RawFd) -> Result<Agent> + Send + 'static,
+    {
+        let _ = prctl::set_no_new_privileges(true);
+        let fd = initialize_seccomp(profile)?.context("no seccomp notify fd")?;
+        Ok(thread::spawn(move || {
+            let mut agent = agent(fd)?;
+            for _ in 0..notifications {
+                agent.handle_next()?;
+            }
+            Ok(())
+        }))
+    }
+
+    fn join(agent: thread::JoinHandle<Result<()>>) -> Result<()> {
+        match agent.join() {
+            Ok(res) => res,
+            Err(err) => bail!("agent thread panicked: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_response_fill() {
+        let mut resp = seccomp_notif_resp {
+            id: 0,
+            val: 1,
+            error: 1,
+            flags: 0,
+        };
+        Response::Continue.fill(&mut resp, 42);
+        assert_eq!(resp.id, 42);
+        assert_eq!(resp.flags, SECCOMP_USER_NOTIF_FLAG_CONTINUE);
+        assert_eq!((resp.val, resp.error), (0, 0));
+
+        Response::Errno(libc::EAGAIN).fill(&mut resp, 43);
+        assert_eq!(resp.error, -libc::EAGAIN);
+        assert_eq!((resp.val, resp.flags), (0, 0));
+
+        Response::Value(7).fill(&mut resp, 44);
+        assert_eq!(resp.val, 7);
+        assert_eq!((resp.error, resp.flags), (0, 0));
+    }
+
+    #[test]
+    #[serial]
+    fn test_agent_default_response() -> Result<()> {
+        let profile = notify_profile("getcwd", vec![])?;
+        test_utils::test_in_child_process(|| {
+            let agent = spawn_agent(&profile, 1, |fd| {
+                Ok(Agent::new(ContainerProcessState::default(), fd)
+                    .with_default_response(Response::Errno(libc::EAGAIN)))
+            })?;
+            let ret = unistd::getcwd();
+            join(agent)?;
+            if ret != Err(Errno::EAGAIN) {
+                bail!("getcwd didn't fail with the errno of the agent: {:?}", ret);
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    #[serial]
+    fn test_agent_continue() -> Result<()> {
+        let profile = notify_profile("getcwd", vec![])?;
+        test_utils::test_in_child_process(|| {
+            let agent = spawn_agent(&profile, 1, |fd| {
+                Agent::new(ContainerProcessState::default(), fd).with_handler(
+                    "getcwd",
+                    |_: &ContainerProcessState, notifier: &Notifier, n: &Notification| {
+                        if !notifier.is_valid(n) {
+                            bail!("notification is not valid");
+                        }
+                        Ok(Response::Continue)
+                    },
+                )
+            })?;
+            let ret = unistd::getcwd();
+            join(agent)?;
+            if ret.is_err() {
+                bail!("getcwd wasn't continued by the handler: {:?}", ret);
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    #[serial]
+    fn test_agent_read_string() -> Result<()> {
+        // only mkdirat calls with this dirfd reach the agent
+        let dirfd = 1000;
+        let profile = notify_profile("mkdirat", vec![arg_eq(0, dirfd)?])?;
+        test_utils::test_in_child_process(|| {
+            let agent = spawn_agent(&profile, 1, |fd| {
+                Agent::new(ContainerProcessState::default(), fd).with_handler(
+                    "mkdirat",
+                    |_: &ContainerProcessState, notifier: &Notifier, n: &Notification| {
+                        if notifier.read_string(n, n.args[1])? != "youki/agent" {
+                            return Ok(Response::Errno(libc::EINVAL));
+                        }
+                        Ok(Response::Value(0))
+                    },
+                )
+            })?;
+            let path = CString::new("youki/agent")?;
+            let ret = unsafe { libc::syscall(libc::SYS_mkdirat, dirfd, path.as_ptr(), 0o700) };
+            join(agent)?;
+            if ret != 0 {
+                bail!("mkdirat wasn't emulated by the handler: {}", ret);
+            }
+
+            Ok(())
+        })
+    }
+}
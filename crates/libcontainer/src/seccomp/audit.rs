@@ -0,0 +1,262 @@
+//! Audit mode for developing seccomp profiles. The profile of a container is
+//! rewritten so that every syscall it would deny is sent to a seccomp agent
+//! instead, which records the syscall and lets it continue. From the recorded
+//! violations, a profile is suggested which allows the syscalls the workload
+//! actually needs.
+use super::NOTIFY_BOOTSTRAP_SYSCALLS;
+use anyhow::{bail, Result};
+use oci_spec::runtime::{LinuxSeccomp, LinuxSeccompAction, LinuxSyscall, LinuxSyscallBuilder};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Returns true for actions which prevent the syscall from being executed.
+fn is_deny(action: LinuxSeccompAction) -> bool {
+    matches!(
+        action,
+        LinuxSeccompAction::ScmpActKill
+            | LinuxSeccompAction::ScmpActKillProcess
+            | LinuxSeccompAction::ScmpActTrap
+            | LinuxSeccompAction::ScmpActErrno
+            | LinuxSeccompAction::ScmpActTrace
+    )
+}
+
+/// Rewrites every deny action of the profile to SCMP_ACT_NOTIFY, so that the
+/// agent listening on listener_path is asked about every violation. The
+/// syscalls needed to pass the notify fd to the agent are always allowed and
+/// therefore never reported.
+pub fn audit_profile(seccomp: &LinuxSeccomp, listener_path: &Path) -> Result<LinuxSeccomp> {
+    if super::is_notify(seccomp) {
+        bail!("seccomp audit cannot be used with a profile that uses SCMP_ACT_NOTIFY");
+    }
+
+    let mut syscalls = vec![LinuxSyscallBuilder::default()
+        .names(
+            NOTIFY_BOOTSTRAP_SYSCALLS
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>(),
+        )
+        .action(LinuxSeccompAction::ScmpActAllow)
+        .build()?];
+    for syscall in seccomp.syscalls().iter().flatten() {
+        let mut syscall = syscall.clone();
+        if is_deny(syscall.action()) {
+            let names = without(syscall.names(), |name| {
+                NOTIFY_BOOTSTRAP_SYSCALLS.contains(&name)
+            });
+            if names.is_empty() {
+                continue;
+            }
+            syscall
+                .set_names(names)
+                .set_action(LinuxSeccompAction::ScmpActNotify);
+        }
+        syscalls.push(syscall);
+    }
+
+    let mut audit = seccomp.clone();
+    if is_deny(seccomp.default_action()) {
+        audit.set_default_action(LinuxSeccompAction::ScmpActNotify);
+    }
+    audit
+        .set_syscalls(Some(syscalls))
+        .set_listener_path(Some(listener_path.to_owned()));
+    Ok(audit)
+}
+
+/// Suggests a profile based on the original profile, which additionally
+/// allows the syscalls that were reported as violations. The violating
+/// syscalls are removed from all deny rules, including rules which only deny
+/// some of their arguments, as the audit can't tell which arguments are used.
+pub fn suggest_profile(
+    seccomp: &LinuxSeccomp,
+    violations: &BTreeSet<String>,
+) -> Result<LinuxSeccomp> {
+    let mut syscalls: Vec<LinuxSyscall> = Vec::new();
+    for syscall in seccomp.syscalls().iter().flatten() {
+        let mut syscall = syscall.clone();
+        if is_deny(syscall.action()) {
+            let names = without(syscall.names(), |name| violations.contains(name));
+            if names.is_empty() {
+                continue;
+            }
+            syscall.set_names(names);
+        }
+        syscalls.push(syscall);
+    }
+
+    if !violations.is_empty() {
+        syscalls.push(
+            LinuxSyscallBuilder::default()
+                .names(violations.iter().cloned().collect::<Vec<_>>())
+                .action(LinuxSeccompAction::ScmpActAllow)
+                .build()?,
+        );
+    }
+
+    let mut suggested = seccomp.clone();
+    suggested.set_syscalls(Some(syscalls));
+    Ok(suggested)
+}
+
+fn without<F: Fn(&str) -> bool>(names: &[String], removed: F) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| !removed(name.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Records the violations of a container started with an audit profile, until
+/// all processes of the container have exited. The runtime connects to the
+/// listener socket, which has to be created with [`super::agent::listen`]
+/// before the container.
+#[cfg(feature = "libseccomp")]
+pub fn record_violations(listener: std::os::unix::io::RawFd) -> Result<BTreeSet<String>> {
+    use super::agent::{self, Agent, Notification, Notifier, Response};
+    use crate::container::ContainerProcessState;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let (state, fd) = agent::accept(listener)?;
+    let violations = Rc::new(RefCell::new(BTreeSet::new()));
+    let recorder = Rc::clone(&violations);
+    Agent::new(state, fd)
+        .with_default_handler(
+            move |_: &ContainerProcessState, _: &Notifier, n: &Notification| {
+                match syscall_name(n.arch, n.nr) {
+                    Some(name) => {
+                        recorder.borrow_mut().insert(name);
+                    }
+                    None => log::warn!("unknown syscall {} of arch {:#x}", n.nr, n.arch),
+                }
+                Ok(Response::Continue)
+            },
+        )
+        .run()?;
+
+    let violations = violations.borrow().clone();
+    Ok(violations)
+}
+
+#[cfg(feature = "libseccomp")]
+fn syscall_name(arch: u32, nr: i32) -> Option<String> {
+    use std::ffi::CStr;
+
+    // libseccomp uses the AUDIT_ARCH_* values of the kernel as arch tokens
+    let name = unsafe { libseccomp::seccomp_syscall_resolve_num_arch(arch, nr) };
+    if name.is_null() {
+        return None;
+    }
+
+    let res = unsafe { CStr::from_ptr(name) }
+        .to_str()
+        .ok()
+        .map(|s| s.to_owned());
+    unsafe { libc::free(name as *mut libc::c_void) };
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::{Arch, LinuxSeccompBuilder};
+
+    fn rule(names: &[&str], action: LinuxSeccompAction) -> Result<LinuxSyscall> {
+        Ok(LinuxSyscallBuilder::default()
+            .names(names.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+            .action(action)
+            .build()?)
+    }
+
+    fn profile(default_action: LinuxSeccompAction) -> Result<LinuxSeccomp> {
+        Ok(LinuxSeccompBuilder::default()
+            .default_action(default_action)
+            .architectures(vec![Arch::ScmpArchNative])
+            .syscalls(vec![
+                rule(&["read", "getcwd"], LinuxSeccompAction::ScmpActAllow)?,
+                rule(&["mount", "write"], LinuxSeccompAction::ScmpActErrno)?,
+                rule(&["reboot"], LinuxSeccompAction::ScmpActKill)?,
+                rule(&["mkdir"], LinuxSeccompAction::ScmpActLog)?,
+            ])
+            .build()?)
+    }
+
+    fn actions(seccomp: &LinuxSeccomp) -> Vec<(Vec<String>, LinuxSeccompAction)> {
+        seccomp
+            .syscalls()
+            .iter()
+            .flatten()
+            .map(|s| (s.names().clone(), s.action()))
+            .collect()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_audit_profile() -> Result<()> {
+        let listener = Path::new("/run/youki/audit.sock");
+        let audit = audit_profile(&profile(LinuxSeccompAction::ScmpActErrno)?, listener)?;
+        assert_eq!(audit.default_action(), LinuxSeccompAction::ScmpActNotify);
+        assert_eq!(audit.listener_path().as_deref(), Some(listener));
+        assert_eq!(
+            actions(&audit),
+            vec![
+                (
+                    names(NOTIFY_BOOTSTRAP_SYSCALLS),
+                    LinuxSeccompAction::ScmpActAllow
+                ),
+                (names(&["read", "getcwd"]), LinuxSeccompAction::ScmpActAllow),
+                (names(&["mount"]), LinuxSeccompAction::ScmpActNotify),
+                (names(&["reboot"]), LinuxSeccompAction::ScmpActNotify),
+                (names(&["mkdir"]), LinuxSeccompAction::ScmpActLog),
+            ]
+        );
+        // the rewritten profile is only accepted in audit mode
+        super::super::check_audit_seccomp(&audit)?;
+        assert!(super::super::check_seccomp(&audit).is_err());
+
+        let audit = audit_profile(&profile(LinuxSeccompAction::ScmpActAllow)?, listener)?;
+        assert_eq!(audit.default_action(), LinuxSeccompAction::ScmpActAllow);
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_notify_profile() -> Result<()> {
+        let mut seccomp = profile(LinuxSeccompAction::ScmpActErrno)?;
+        seccomp.set_syscalls(Some(vec![rule(
+            &["getcwd"],
+            LinuxSeccompAction::ScmpActNotify,
+        )?]));
+        assert!(audit_profile(&seccomp, Path::new("/run/youki/audit.sock")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_suggest_profile() -> Result<()> {
+        let seccomp = profile(LinuxSeccompAction::ScmpActErrno)?;
+        let violations: BTreeSet<String> =
+            names(&["reboot", "mount", "chroot"]).into_iter().collect();
+        let suggested = suggest_profile(&seccomp, &violations)?;
+        assert_eq!(suggested.default_action(), LinuxSeccompAction::ScmpActErrno);
+        assert_eq!(
+            actions(&suggested),
+            vec![
+                (names(&["read", "getcwd"]), LinuxSeccompAction::ScmpActAllow),
+                (names(&["write"]), LinuxSeccompAction::ScmpActErrno),
+                (names(&["mkdir"]), LinuxSeccompAction::ScmpActLog),
+                (
+                    names(&["chroot", "mount", "reboot"]),
+                    LinuxSeccompAction::ScmpActAllow
+                ),
+            ]
+        );
+
+        let suggested = suggest_profile(&seccomp, &BTreeSet::new())?;
+        assert_eq!(actions(&suggested), actions(&seccomp));
+        Ok(())
+    }
+}
@@ -0,0 +1,543 @@
+//! Seccomp backend which compiles and loads filters with libseccomp
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use libseccomp::scmp_compare::*;
+use libseccomp::*;
+use nix::errno::Errno;
+use oci_spec::runtime::Arch;
+use oci_spec::runtime::LinuxSeccomp;
+use oci_spec::runtime::LinuxSeccompAction;
+use oci_spec::runtime::LinuxSeccompOperator;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::os::unix::io::AsRawFd;
+
+#[derive(Debug)]
+struct Compare {
+    // The zero-indexed index of the syscall arguement.
+    arg: libc::c_uint,
+    op: Option<scmp_compare>,
+    datum_a: Option<scmp_datum_t>,
+    datum_b: Option<scmp_datum_t>,
+}
+
+impl Compare {
+    pub fn new(args: u32) -> Self {
+        Compare {
+            arg: args as libc::c_uint,
+            op: None,
+            datum_a: None,
+            datum_b: None,
+        }
+    }
+
+    pub fn op(mut self, op: scmp_compare) -> Self {
+        self.op = Some(op);
+
+        self
+    }
+
+    pub fn datum_a(mut self, datum: scmp_datum_t) -> Self {
+        self.datum_a = Some(datum);
+
+        self
+    }
+
+    pub fn datum_b(mut self, datum: scmp_datum_t) -> Self {
+        self.datum_b = Some(datum);
+
+        self
+    }
+
+    pub fn build(self) -> Result<scmp_arg_cmp> {
+        if let Some((op, datum_a)) = self.op.zip(self.datum_a) {
+            Ok(scmp_arg_cmp {
+                arg: self.arg,
+                op,
+                datum_a,
+                // datum_b is optional for a number of op, since these op only
+                // requires one value. For example, the SCMP_OP_EQ or equal op
+                // requires only one value. We set the datum_b to 0 in the case
+                // that only one value is required.
+                datum_b: self.datum_b.unwrap_or(0),
+            })
+        } else {
+            bail!("op and datum_a is required: {:?}", self);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    action: u32,
+    syscall_nr: i32,
+    comparators: Vec<scmp_arg_cmp>,
+}
+
+impl Rule {
+    pub fn new(action: u32, syscall_number: i32) -> Self {
+        Rule {
+            action,
+            syscall_nr: syscall_number,
+            comparators: vec![],
+        }
+    }
+
+    pub fn add_comparator(&mut self, cmp: scmp_arg_cmp) {
+        self.comparators.push(cmp);
+    }
+}
+
+/// A seccomp filter compiled by libseccomp, which can be loaded into the
+/// kernel or exported for inspection.
+#[derive(Debug)]
+pub struct FilterContext {
+    ctx: scmp_filter_ctx,
+}
+
+impl FilterContext {
+    pub fn default(default_action: u32) -> Result<FilterContext> {
+        let filter_ctx = unsafe { seccomp_init(default_action) };
+        if filter_ctx.is_null() {
+            bail!("Failed to initialized seccomp profile")
+        }
+
+        Ok(FilterContext { ctx: filter_ctx })
+    }
+
+    pub fn add_rule(&mut self, rule: &Rule) -> Result<()> {
+        let res = match rule.comparators.len() {
+            0 => unsafe { seccomp_rule_add(self.ctx, rule.action, rule.syscall_nr, 0) },
+            _ => unsafe {
+                seccomp_rule_add_array(
+                    self.ctx,
+                    rule.action,
+                    rule.syscall_nr,
+                    rule.comparators.len() as u32,
+                    rule.comparators.as_ptr(),
+                )
+            },
+        };
+        if res != 0 {
+            bail!("Failed to add rule. Errno: {}, Rule: {:?}", res, rule);
+        }
+
+        Ok(())
+    }
+
+    pub fn add_arch(&mut self, arch: u32) -> Result<()> {
+        let res = unsafe { seccomp_arch_add(self.ctx, arch) };
+        if res != 0 && nix::Error::from_i32(res.abs()) != nix::Error::EEXIST {
+            // The architecture already existed in the profile, so we can
+            // safely ignore the error here. Otherwise, error out.
+            bail!("Failed to add architecture {}. Errno: {}", arch, res);
+        }
+
+        Ok(())
+    }
+
+    pub fn set_attr(&mut self, attr: scmp_filter_attr, value: u32) -> Result<()> {
+        let res = unsafe { seccomp_attr_set(self.ctx, attr, value) };
+        if res != 0 {
+            bail!("Failed to set filter attribute {:?}. Errno: {}", attr, res);
+        }
+
+        Ok(())
+    }
+
+    pub fn load(&self) -> Result<()> {
+        let res = unsafe { seccomp_load(self.ctx) };
+        if res != 0 {
+            bail!("Failed to load seccomp profile: {}", res);
+        }
+
+        Ok(())
+    }
+
+    /// Writes the filter as human readable Pseudo Filter Code (PFC) to dest.
+    pub fn export_pfc<F: AsRawFd>(&self, dest: &F) -> Result<()> {
+        let res = unsafe { seccomp_export_pfc(self.ctx, dest.as_raw_fd()) };
+        if res != 0 {
+            bail!("Failed to export seccomp profile as pfc: {}", res);
+        }
+
+        Ok(())
+    }
+
+    /// Writes the filter as the raw BPF program, which would be loaded into
+    /// the kernel, to dest.
+    pub fn export_bpf<F: AsRawFd>(&self, dest: &F) -> Result<()> {
+        let res = unsafe { seccomp_export_bpf(self.ctx, dest.as_raw_fd()) };
+        if res != 0 {
+            bail!("Failed to export seccomp profile as bpf: {}", res);
+        }
+
+        Ok(())
+    }
+
+    pub fn notify_fd(&self) -> Result<Option<i32>> {
+        let res = unsafe { seccomp_notify_fd(self.ctx) };
+        if res > 0 {
+            return Ok(Some(res));
+        }
+
+        // -1 indicates the notify fd is not set. This can happen if no seccomp
+        // notify filter is set.
+        if res == -1 {
+            return Ok(None);
+        }
+
+        match nix::errno::from_i32(res.abs()) {
+            Errno::EINVAL => {
+                bail!("invalid seccomp context used to call notify fd");
+            }
+            Errno::EFAULT => {
+                bail!("internal libseccomp fault; likely no seccomp filter is loaded");
+            }
+            Errno::EOPNOTSUPP => {
+                bail!("seccomp notify filter not supported");
+            }
+
+            _ => {
+                bail!("unknown error from return code: {}", res);
+            }
+        };
+    }
+}
+
+impl Drop for FilterContext {
+    fn drop(&mut self) {
+        unsafe { seccomp_release(self.ctx) };
+    }
+}
+
+pub(crate) fn translate_syscall(syscall_name: &str) -> Result<i32> {
+    let c_syscall_name = CString::new(syscall_name)
+        .with_context(|| format!("Failed to convert syscall {:?} to cstring", syscall_name))?;
+    let res = unsafe { seccomp_syscall_resolve_name(c_syscall_name.as_ptr()) };
+    if res == __NR_SCMP_ERROR {
+        bail!("Failed to resolve syscall from name: {:?}", syscall_name);
+    }
+
+    Ok(res)
+}
+
+fn translate_action(action: LinuxSeccompAction, errno: Option<u32>) -> u32 {
+    let errno = errno.unwrap_or(libc::EPERM as u32);
+    match action {
+        LinuxSeccompAction::ScmpActKill => SCMP_ACT_KILL,
+        LinuxSeccompAction::ScmpActTrap => SCMP_ACT_TRAP,
+        LinuxSeccompAction::ScmpActErrno => SCMP_ACT_ERRNO(errno),
+        LinuxSeccompAction::ScmpActTrace => SCMP_ACT_TRACE(errno),
+        LinuxSeccompAction::ScmpActAllow => SCMP_ACT_ALLOW,
+        LinuxSeccompAction::ScmpActKillProcess => SCMP_ACT_KILL_PROCESS,
+        LinuxSeccompAction::ScmpActNotify => SCMP_ACT_NOTIFY,
+        LinuxSeccompAction::ScmpActLog => SCMP_ACT_LOG,
+    }
+}
+
+fn translate_op(op: LinuxSeccompOperator) -> scmp_compare {
+    match op {
+        LinuxSeccompOperator::ScmpCmpNe => SCMP_CMP_NE,
+        LinuxSeccompOperator::ScmpCmpLt => SCMP_CMP_LT,
+        LinuxSeccompOperator::ScmpCmpLe => SCMP_CMP_LE,
+        LinuxSeccompOperator::ScmpCmpEq => SCMP_CMP_EQ,
+        LinuxSeccompOperator::ScmpCmpGe => SCMP_CMP_GE,
+        LinuxSeccompOperator::ScmpCmpGt => SCMP_CMP_GT,
+        LinuxSeccompOperator::ScmpCmpMaskedEq => SCMP_CMP_MASKED_EQ,
+    }
+}
+
+fn translate_arch(arch: Arch) -> scmp_arch {
+    match arch {
+        Arch::ScmpArchNative => SCMP_ARCH_NATIVE,
+        Arch::ScmpArchX86 => SCMP_ARCH_X86,
+        Arch::ScmpArchX86_64 => SCMP_ARCH_X86_64,
+        Arch::ScmpArchX32 => SCMP_ARCH_X32,
+        Arch::ScmpArchArm => SCMP_ARCH_ARM,
+        Arch::ScmpArchAarch64 => SCMP_ARCH_AARCH64,
+        Arch::ScmpArchMips => SCMP_ARCH_MIPS,
+        Arch::ScmpArchMips64 => SCMP_ARCH_MIPS64,
+        Arch::ScmpArchMips64n32 => SCMP_ARCH_MIPS64N32,
+        Arch::ScmpArchMipsel => SCMP_ARCH_MIPSEL,
+        Arch::ScmpArchMipsel64 => SCMP_ARCH_MIPSEL64,
+        Arch::ScmpArchMipsel64n32 => SCMP_ARCH_MIPSEL64N32,
+        Arch::ScmpArchPpc => SCMP_ARCH_PPC,
+        Arch::ScmpArchPpc64 => SCMP_ARCH_PPC64,
+        Arch::ScmpArchPpc64le => SCMP_ARCH_PPC64LE,
+        Arch::ScmpArchS390 => SCMP_ARCH_S390,
+        Arch::ScmpArchS390x => SCMP_ARCH_S390X,
+    }
+}
+
+// Translates a seccomp filter flag of the runtime spec into the corresponding
+// libseccomp filter attribute and the API level which is required to use it.
+// See seccomp_api_get in libseccomp for the meaning of the API levels.
+fn translate_flag(flag: &str) -> Result<(scmp_filter_attr, u32)> {
+    match flag {
+        "SECCOMP_FILTER_FLAG_TSYNC" => Ok((scmp_filter_attr::SCMP_FLTATR_CTL_TSYNC, 2)),
+        "SECCOMP_FILTER_FLAG_LOG" => Ok((scmp_filter_attr::SCMP_FLTATR_CTL_LOG, 3)),
+        "SECCOMP_FILTER_FLAG_SPEC_ALLOW" => Ok((scmp_filter_attr::SCMP_FLTATR_CTL_SSB, 4)),
+        "SECCOMP_FILTER_FLAG_WAIT_KILLABLE_RECV" => {
+            Ok((scmp_filter_attr::SCMP_FLTATR_CTL_WAITKILL, 7))
+        }
+        _ => bail!("unknown seccomp filter flag {}", flag),
+    }
+}
+
+fn libseccomp_version() -> String {
+    let version = unsafe { &*seccomp_version() };
+    format!("{}.{}.{}", version.major, version.minor, version.micro)
+}
+
+fn set_flags(ctx: &mut FilterContext, seccomp: &LinuxSeccomp) -> Result<()> {
+    let flags = match seccomp.flags() {
+        Some(flags) => flags,
+        None => return Ok(()),
+    };
+
+    // The API level depends on both the libseccomp version and the features
+    // supported by the running kernel.
+    let api_level = unsafe { seccomp_api_get() };
+    for flag in flags {
+        let (attr, mut required_level) = translate_flag(flag)?;
+        if matches!(attr, scmp_filter_attr::SCMP_FLTATR_CTL_TSYNC) && super::is_notify(seccomp) {
+            // TSYNC can only be combined with notify since API level 6
+            required_level = 6;
+        }
+
+        if api_level < required_level {
+            bail!(
+                "seccomp filter flag {} requires API level {}, but only level {} \
+                is supported by libseccomp {} and the running kernel",
+                flag,
+                required_level,
+                api_level,
+                libseccomp_version()
+            );
+        }
+
+        ctx.set_attr(attr, 1).with_context(|| {
+            format!(
+                "failed to set seccomp filter flag {} with libseccomp {}",
+                flag,
+                libseccomp_version()
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Compiles the seccomp profile of the runtime spec into a filter without
+/// loading it.
+pub fn compile(seccomp: &LinuxSeccomp) -> Result<FilterContext> {
+    super::check_seccomp(seccomp)?;
+    build(seccomp)
+}
+
+// Compiles the profile without checking it first.
+pub(super) fn build(seccomp: &LinuxSeccomp) -> Result<FilterContext> {
+    // TODO: fix default action error number. The spec repo doesn't have it yet.
+    let default_action = translate_action(seccomp.default_action(), None);
+    let mut ctx = FilterContext::default(default_action)?;
+
+    if let Some(architectures) = seccomp.architectures() {
+        for &arch in architectures {
+            let arch_token = translate_arch(arch);
+            ctx.add_arch(arch_token as u32)
+                .context("failed to add arch to seccomp")?;
+        }
+    }
+
+    // The SCMP_FLTATR_CTL_NNP controls if the seccomp load function will set
+    // the new privilege bit automatically in prctl. Normally this is a good
+    // thing, but for us we need better control. Based on the spec, if OCI
+    // runtime spec doesn't set the no new privileges in Process, we should not
+    // set it here.  If the seccomp load operation fails without enough
+    // privilege, so be it. To prevent this automatic behavior, we unset the
+    // value here.
+    ctx.set_attr(scmp_filter_attr::SCMP_FLTATR_CTL_NNP, 0)
+        .context("failed to unset the no new privileges bit for seccomp")?;
+
+    set_flags(&mut ctx, seccomp).context("failed to set seccomp filter flags")?;
+
+    if let Some(syscalls) = seccomp.syscalls() {
+        for syscall in syscalls {
+            let action = translate_action(syscall.action(), syscall.errno_ret());
+            if action == default_action {
+                // When the action is the same as the default action, the rule is redundent. We can
+                // skip this here to avoid failing when we add the rules.
+                log::warn!(
+                    "Detect a seccomp action that is the same as the default action: {:?}",
+                    syscall
+                );
+                continue;
+            }
+
+            let mut comparators = Vec::new();
+            for arg in syscall.args().iter().flatten() {
+                let cmp = Compare::new(arg.index() as u32)
+                    .op(translate_op(arg.op()))
+                    .datum_a(arg.value())
+                    .datum_b(arg.value_two().unwrap_or(0))
+                    .build()
+                    .context("Failed to build a seccomp compare rule")?;
+                comparators.push(cmp);
+            }
+
+            for name in syscall.names() {
+                let syscall_number = match translate_syscall(name) {
+                    Ok(x) => x,
+                    Err(_) => {
+                        // If we failed to resolve the syscall by name, likely the kernel
+                        // doeesn't support this syscall. So it is safe to skip...
+                        log::warn!(
+                            "Failed to resolve syscall, likely kernel doesn't support this. {:?}",
+                            name
+                        );
+                        continue;
+                    }
+                };
+
+                for rule in build_rules(action, syscall_number, &comparators) {
+                    ctx.add_rule(&rule).with_context(|| {
+                        format!(
+                            "failed to add seccomp rule: {:?}. Syscall: {:?}",
+                            &rule, name,
+                        )
+                    })?;
+                }
+            }
+        }
+    }
+
+    Ok(ctx)
+}
+
+// All comparators of a syscall entry have to match for the action to be
+// taken, so they are combined into a single rule. libseccomp refuses to add a
+// rule with several comparisons of the same argument, though. In that case
+// we follow runc and fall back to one rule per comparator, which means that
+// the action is taken if any of them matches.
+fn build_rules(action: u32, syscall_number: i32, comparators: &[scmp_arg_cmp]) -> Vec<Rule> {
+    let mut seen_args = HashSet::new();
+    let repeated_arg = comparators.iter().any(|cmp| !seen_args.insert(cmp.arg));
+
+    if repeated_arg {
+        comparators
+            .iter()
+            .map(|&cmp| {
+                let mut rule = Rule::new(action, syscall_number);
+                rule.add_comparator(cmp);
+                rule
+            })
+            .collect()
+    } else {
+        let mut rule = Rule::new(action, syscall_number);
+        for &cmp in comparators {
+            rule.add_comparator(cmp);
+        }
+        vec![rule]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seccomp::test_utils::{arg_eq, exported, profile, run_bpf};
+
+    fn run(program: &[u8], syscall: &str, args: [u64; 6]) -> Result<u32> {
+        let arch = unsafe { seccomp_arch_native() };
+        run_bpf(program, arch, translate_syscall(syscall)?, args)
+    }
+
+    #[test]
+    fn test_multiple_args_are_combined() -> Result<()> {
+        let seccomp = profile("personality", vec![arg_eq(0, 1)?, arg_eq(1, 2)?])?;
+        let filter = compile(&seccomp)?;
+        let program = exported(|f| filter.export_bpf(f))?;
+
+        let errno = SCMP_ACT_ERRNO(libc::EAGAIN as u32);
+        assert_eq!(run(&program, "personality", [1, 2, 0, 0, 0, 0])?, errno);
+        assert_eq!(
+            run(&program, "personality", [1, 0, 0, 0, 0, 0])?,
+            SCMP_ACT_ALLOW
+        );
+        assert_eq!(
+            run(&program, "personality", [0, 2, 0, 0, 0, 0])?,
+            SCMP_ACT_ALLOW
+        );
+        assert_eq!(run(&program, "getcwd", [1, 2, 0, 0, 0, 0])?, SCMP_ACT_ALLOW);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_repeated_arg_is_any_of() -> Result<()> {
+        let seccomp = profile("personality", vec![arg_eq(0, 1)?, arg_eq(0, 2)?])?;
+        let filter = compile(&seccomp)?;
+        let program = exported(|f| filter.export_bpf(f))?;
+
+        let errno = SCMP_ACT_ERRNO(libc::EAGAIN as u32);
+        assert_eq!(run(&program, "personality", [1, 0, 0, 0, 0, 0])?, errno);
+        assert_eq!(run(&program, "personality", [2, 0, 0, 0, 0, 0])?, errno);
+        assert_eq!(
+            run(&program, "personality", [3, 0, 0, 0, 0, 0])?,
+            SCMP_ACT_ALLOW
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_pfc() -> Result<()> {
+        let seccomp = profile("personality", vec![arg_eq(0, 1)?])?;
+        let filter = compile(&seccomp)?;
+        let pfc = String::from_utf8(exported(|f| filter.export_pfc(f))?)?;
+        assert!(pfc.contains("personality"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_rules() -> Result<()> {
+        let cmp = |arg| {
+            Compare::new(arg)
+                .op(SCMP_CMP_EQ)
+                .datum_a(1)
+                .build()
+                .unwrap()
+        };
+
+        let rules = build_rules(SCMP_ACT_ALLOW, 1, &[]);
+        assert_eq!(rules.len(), 1);
+        assert!(rules[0].comparators.is_empty());
+
+        let rules = build_rules(SCMP_ACT_ALLOW, 1, &[cmp(0), cmp(1), cmp(2)]);
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].comparators.len(), 3);
+
+        let rules = build_rules(SCMP_ACT_ALLOW, 1, &[cmp(0), cmp(1), cmp(0)]);
+        assert_eq!(rules.len(), 3);
+        assert!(rules.iter().all(|r| r.comparators.len() == 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_translate_flag() -> Result<()> {
+        assert!(matches!(
+            translate_flag("SECCOMP_FILTER_FLAG_LOG")?,
+            (scmp_filter_attr::SCMP_FLTATR_CTL_LOG, 3)
+        ));
+        assert!(matches!(
+            translate_flag("SECCOMP_FILTER_FLAG_SPEC_ALLOW")?,
+            (scmp_filter_attr::SCMP_FLTATR_CTL_SSB, 4)
+        ));
+        assert!(translate_flag("SECCOMP_FILTER_FLAG_UNKNOWN").is_err());
+
+        Ok(())
+    }
+}
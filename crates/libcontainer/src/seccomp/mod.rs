@@ -1,235 +1,39 @@
+//! Seccomp filters of the runtime spec. By default, filters are compiled and
+//! loaded with libseccomp. Building with the seccomp_native feature, but
+//! without the libseccomp feature, replaces it with a pure Rust backend, so
+//! that no C library is required.
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
-use libseccomp::scmp_compare::*;
-use libseccomp::*;
-use nix::errno::Errno;
-use oci_spec::runtime::Arch;
 use oci_spec::runtime::LinuxSeccomp;
 use oci_spec::runtime::LinuxSeccompAction;
-use oci_spec::runtime::LinuxSeccompOperator;
-use std::ffi::CString;
 use std::os::unix::io;
 
-#[derive(Debug)]
-struct Compare {
-    // The zero-indexed index of the syscall arguement.
-    arg: libc::c_uint,
-    op: Option<scmp_compare>,
-    datum_a: Option<scmp_datum_t>,
-    datum_b: Option<scmp_datum_t>,
-}
-
-impl Compare {
-    pub fn new(args: u32) -> Self {
-        Compare {
-            arg: args as libc::c_uint,
-            op: None,
-            datum_a: None,
-            datum_b: None,
-        }
-    }
-
-    pub fn op(mut self, op: scmp_compare) -> Self {
-        self.op = Some(op);
-
-        self
-    }
-
-    pub fn datum_a(mut self, datum: scmp_datum_t) -> Self {
-        self.datum_a = Some(datum);
-
-        self
-    }
-
-    pub fn datum_b(mut self, datum: scmp_datum_t) -> Self {
-        self.datum_b = Some(datum);
-
-        self
-    }
-
-    pub fn build(self) -> Result<scmp_arg_cmp> {
-        if let Some((op, datum_a)) = self.op.zip(self.datum_a) {
-            Ok(scmp_arg_cmp {
-                arg: self.arg,
-                op,
-                datum_a,
-                // datum_b is optional for a number of op, since these op only
-                // requires one value. For example, the SCMP_OP_EQ or equal op
-                // requires only one value. We set the datum_b to 0 in the case
-                // that only one value is required.
-                datum_b: self.datum_b.unwrap_or(0),
-            })
-        } else {
-            bail!("op and datum_a is required: {:?}", self);
-        }
-    }
-}
-
-#[derive(Debug)]
-struct Rule {
-    action: u32,
-    syscall_nr: i32,
-    comparators: Vec<scmp_arg_cmp>,
-}
-
-impl Rule {
-    pub fn new(action: u32, syscall_number: i32) -> Self {
-        Rule {
-            action,
-            syscall_nr: syscall_number,
-            comparators: vec![],
-        }
-    }
-
-    pub fn add_comparator(&mut self, cmp: scmp_arg_cmp) {
-        self.comparators.push(cmp);
-    }
-}
-
-#[derive(Debug)]
-struct FilterContext {
-    ctx: scmp_filter_ctx,
-}
-
-impl FilterContext {
-    pub fn default(default_action: u32) -> Result<FilterContext> {
-        let filter_ctx = unsafe { seccomp_init(default_action) };
-        if filter_ctx.is_null() {
-            bail!("Failed to initialized seccomp profile")
-        }
-
-        Ok(FilterContext { ctx: filter_ctx })
-    }
-
-    pub fn add_rule(&mut self, rule: &Rule) -> Result<()> {
-        let res = match rule.comparators.len() {
-            0 => unsafe { seccomp_rule_add(self.ctx, rule.action, rule.syscall_nr, 0) },
-            _ => unsafe {
-                seccomp_rule_add_array(
-                    self.ctx,
-                    rule.action,
-                    rule.syscall_nr,
-                    rule.comparators.len() as u32,
-                    rule.comparators.as_ptr(),
-                )
-            },
-        };
-        if res != 0 {
-            bail!("Failed to add rule. Errno: {}, Rule: {:?}", res, rule);
-        }
-
-        Ok(())
-    }
-
-    pub fn add_arch(&mut self, arch: u32) -> Result<()> {
-        let res = unsafe { seccomp_arch_add(self.ctx, arch) };
-        if res != 0 && nix::Error::from_i32(res.abs()) != nix::Error::EEXIST {
-            // The architecture already existed in the profile, so we can
-            // safely ignore the error here. Otherwise, error out.
-            bail!("Failed to add architecture {}. Errno: {}", arch, res);
-        }
-
-        Ok(())
-    }
-
-    pub fn load(&self) -> Result<()> {
-        let res = unsafe { seccomp_load(self.ctx) };
-        if res != 0 {
-            bail!("Failed to load seccomp profile: {}", res);
-        }
-
-        Ok(())
-    }
-
-    pub fn notify_fd(&self) -> Result<Option<i32>> {
-        let res = unsafe { seccomp_notify_fd(self.ctx) };
-        if res > 0 {
-            return Ok(Some(res));
-        }
-
-        // -1 indicates the notify fd is not set. This can happen if no seccomp
-        // notify filter is set.
-        if res == -1 {
-            return Ok(None);
-        }
-
-        match nix::errno::from_i32(res.abs()) {
-            Errno::EINVAL => {
-                bail!("invalid seccomp context used to call notify fd");
-            }
-            Errno::EFAULT => {
-                bail!("internal libseccomp fault; likely no seccomp filter is loaded");
-            }
-            Errno::EOPNOTSUPP => {
-                bail!("seccomp notify filter not supported");
-            }
-
-            _ => {
-                bail!("unknown error from return code: {}", res);
-            }
-        };
-    }
-}
-
-fn translate_syscall(syscall_name: &str) -> Result<i32> {
-    let c_syscall_name = CString::new(syscall_name)
-        .with_context(|| format!("Failed to convert syscall {:?} to cstring", syscall_name))?;
-    let res = unsafe { seccomp_syscall_resolve_name(c_syscall_name.as_ptr()) };
-    if res == __NR_SCMP_ERROR {
-        bail!("Failed to resolve syscall from name: {:?}", syscall_name);
-    }
-
-    Ok(res)
-}
+#[cfg(feature = "libseccomp")]
+pub mod agent;
+pub mod audit;
+#[cfg(feature = "libseccomp")]
+pub mod libseccomp_backend;
+#[cfg(feature = "seccomp_native")]
+pub mod native_backend;
+#[cfg(test)]
+mod test_utils;
 
-fn translate_action(action: LinuxSeccompAction, errno: Option<u32>) -> u32 {
-    let errno = errno.unwrap_or(libc::EPERM as u32);
-    match action {
-        LinuxSeccompAction::ScmpActKill => SCMP_ACT_KILL,
-        LinuxSeccompAction::ScmpActTrap => SCMP_ACT_TRAP,
-        LinuxSeccompAction::ScmpActErrno => SCMP_ACT_ERRNO(errno),
-        LinuxSeccompAction::ScmpActTrace => SCMP_ACT_TRACE(errno),
-        LinuxSeccompAction::ScmpActAllow => SCMP_ACT_ALLOW,
-        LinuxSeccompAction::ScmpActKillProcess => SCMP_ACT_KILL_PROCESS,
-        LinuxSeccompAction::ScmpActNotify => SCMP_ACT_NOTIFY,
-        LinuxSeccompAction::ScmpActLog => SCMP_ACT_LOG,
-    }
-}
+#[cfg(feature = "libseccomp")]
+use libseccomp_backend::build;
+#[cfg(feature = "libseccomp")]
+pub use libseccomp_backend::{compile, FilterContext};
+#[cfg(all(feature = "seccomp_native", not(feature = "libseccomp")))]
+use native_backend::build;
+#[cfg(all(feature = "seccomp_native", not(feature = "libseccomp")))]
+pub use native_backend::{compile, FilterContext};
 
-fn translate_op(op: LinuxSeccompOperator) -> scmp_compare {
-    match op {
-        LinuxSeccompOperator::ScmpCmpNe => SCMP_CMP_NE,
-        LinuxSeccompOperator::ScmpCmpLt => SCMP_CMP_LT,
-        LinuxSeccompOperator::ScmpCmpLe => SCMP_CMP_LE,
-        LinuxSeccompOperator::ScmpCmpEq => SCMP_CMP_EQ,
-        LinuxSeccompOperator::ScmpCmpGe => SCMP_CMP_GE,
-        LinuxSeccompOperator::ScmpCmpGt => SCMP_CMP_GT,
-        LinuxSeccompOperator::ScmpCmpMaskedEq => SCMP_CMP_MASKED_EQ,
-    }
-}
+#[cfg(not(any(feature = "libseccomp", feature = "seccomp_native")))]
+compile_error!("either the libseccomp or the seccomp_native feature has to be enabled");
 
-fn translate_arch(arch: Arch) -> scmp_arch {
-    match arch {
-        Arch::ScmpArchNative => SCMP_ARCH_NATIVE,
-        Arch::ScmpArchX86 => SCMP_ARCH_X86,
-        Arch::ScmpArchX86_64 => SCMP_ARCH_X86_64,
-        Arch::ScmpArchX32 => SCMP_ARCH_X32,
-        Arch::ScmpArchArm => SCMP_ARCH_ARM,
-        Arch::ScmpArchAarch64 => SCMP_ARCH_AARCH64,
-        Arch::ScmpArchMips => SCMP_ARCH_MIPS,
-        Arch::ScmpArchMips64 => SCMP_ARCH_MIPS64,
-        Arch::ScmpArchMips64n32 => SCMP_ARCH_MIPS64N32,
-        Arch::ScmpArchMipsel => SCMP_ARCH_MIPSEL,
-        Arch::ScmpArchMipsel64 => SCMP_ARCH_MIPSEL64,
-        Arch::ScmpArchMipsel64n32 => SCMP_ARCH_MIPSEL64N32,
-        Arch::ScmpArchPpc => SCMP_ARCH_PPC,
-        Arch::ScmpArchPpc64 => SCMP_ARCH_PPC64,
-        Arch::ScmpArchPpc64le => SCMP_ARCH_PPC64LE,
-        Arch::ScmpArchS390 => SCMP_ARCH_S390,
-        Arch::ScmpArchS390x => SCMP_ARCH_S390X,
-    }
-}
+/// Syscalls the container process makes after loading a filter, until the
+/// notify fd has been passed on to the seccomp agent.
+pub const NOTIFY_BOOTSTRAP_SYSCALLS: &[&str] = &["write", "sendmsg", "brk", "mmap", "munmap"];
 
 fn check_seccomp(seccomp: &LinuxSeccomp) -> Result<()> {
     // We don't support notify as default action. After the seccomp filter is
@@ -246,6 +50,27 @@ fn check_seccomp(seccomp: &LinuxSeccomp) -> Result<()> {
         bail!("SCMP_ACT_NOTIFY cannot be used as default action");
     }
 
+    check_notify_rules(seccomp)
+}
+
+// Profiles created by audit::audit_profile are the only ones which may use
+// notify as default action. They allow the syscalls needed to pass the notify
+// fd on explicitly.
+fn check_audit_seccomp(seccomp: &LinuxSeccomp) -> Result<()> {
+    if !NOTIFY_BOOTSTRAP_SYSCALLS
+        .iter()
+        .all(|name| is_allowed(seccomp, name))
+    {
+        bail!(
+            "seccomp audit profile has to allow {:?}",
+            NOTIFY_BOOTSTRAP_SYSCALLS
+        );
+    }
+
+    check_notify_rules(seccomp)
+}
+
+fn check_notify_rules(seccomp: &LinuxSeccomp) -> Result<()> {
     if let Some(syscalls) = seccomp.syscalls() {
         for syscall in syscalls {
             if syscall.action() == LinuxSeccompAction::ScmpActNotify {
@@ -261,103 +86,27 @@ fn check_seccomp(seccomp: &LinuxSeccomp) -> Result<()> {
     Ok(())
 }
 
-pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
-    if seccomp.flags().is_some() {
-        // runc did not support this, so let's skip it for now.
-        bail!("seccomp flags are not yet supported");
-    }
-
-    check_seccomp(seccomp)?;
-
-    // TODO: fix default action error number. The spec repo doesn't have it yet.
-    let default_action = translate_action(seccomp.default_action(), None);
-    let mut ctx = FilterContext::default(default_action)?;
-
-    if let Some(architectures) = seccomp.architectures() {
-        for &arch in architectures {
-            let arch_token = translate_arch(arch);
-            ctx.add_arch(arch_token as u32)
-                .context("failed to add arch to seccomp")?;
-        }
-    }
-
-    // The SCMP_FLTATR_CTL_NNP controls if the seccomp load function will set
-    // the new privilege bit automatically in prctl. Normally this is a good
-    // thing, but for us we need better control. Based on the spec, if OCI
-    // runtime spec doesn't set the no new privileges in Process, we should not
-    // set it here.  If the seccomp load operation fails without enough
-    // privilege, so be it. To prevent this automatic behavior, we unset the
-    // value here.
-    let ret = unsafe { seccomp_attr_set(ctx.ctx, scmp_filter_attr::SCMP_FLTATR_CTL_NNP, 0) };
-    if ret != 0 {
-        bail!(
-            "failed to unset the no new privileges bit for seccomp: {}",
-            ret
-        );
-    }
+// Checks if the syscall is allowed regardless of its arguments.
+fn is_allowed(seccomp: &LinuxSeccomp, name: &str) -> bool {
+    seccomp.syscalls().iter().flatten().any(|syscall| {
+        syscall.action() == LinuxSeccompAction::ScmpActAllow
+            && syscall.args().as_ref().map_or(true, |args| args.is_empty())
+            && syscall.names().iter().any(|n| n == name)
+    })
+}
 
-    if let Some(syscalls) = seccomp.syscalls() {
-        for syscall in syscalls {
-            let action = translate_action(syscall.action(), syscall.errno_ret());
-            if action == default_action {
-                // When the action is the same as the default action, the rule is redundent. We can
-                // skip this here to avoid failing when we add the rules.
-                log::warn!(
-                    "Detect a seccomp action that is the same as the default action: {:?}",
-                    syscall
-                );
-                continue;
-            }
+pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
+    load(compile(seccomp)?, seccomp)
+}
 
-            for name in syscall.names() {
-                let syscall_number = match translate_syscall(name) {
-                    Ok(x) => x,
-                    Err(_) => {
-                        // If we failed to resolve the syscall by name, likely the kernel
-                        // doeesn't support this syscall. So it is safe to skip...
-                        log::warn!(
-                            "Failed to resolve syscall, likely kernel doesn't support this. {:?}",
-                            name
-                        );
-                        continue;
-                    }
-                };
-                // Not clear why but if there are multiple arg attached to one
-                // syscall rule, we have to add them seperatly. add_rule will
-                // return EINVAL. runc does the same but doesn't explain why.
-                match syscall.args() {
-                    Some(args) => {
-                        for arg in args {
-                            let mut rule = Rule::new(action, syscall_number);
-                            let cmp = Compare::new(arg.index() as u32)
-                                .op(translate_op(arg.op()))
-                                .datum_a(arg.value())
-                                .datum_b(arg.value_two().unwrap_or(0))
-                                .build()
-                                .context("Failed to build a seccomp compare rule")?;
-                            rule.add_comparator(cmp);
-                            ctx.add_rule(&rule).with_context(|| {
-                                format!(
-                                    "failed to add seccomp rule: {:?}. Syscall: {:?}",
-                                    &rule, name,
-                                )
-                            })?;
-                        }
-                    }
-                    None => {
-                        let rule = Rule::new(action, syscall_number);
-                        ctx.add_rule(&rule).with_context(|| {
-                            format!(
-                                "failed to add seccomp rule: {:?}. Syscall: {:?}",
-                                &rule, name,
-                            )
-                        })?;
-                    }
-                }
-            }
-        }
-    }
+/// Loads a profile created by [`audit::audit_profile`]. Unlike any other
+/// profile, it may use SCMP_ACT_NOTIFY as default action.
+pub fn initialize_audit_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
+    check_audit_seccomp(seccomp)?;
+    load(build(seccomp)?, seccomp)
+}
 
+fn load(ctx: FilterContext, seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
     // In order to use the SECCOMP_SET_MODE_FILTER operation, either the calling
     // thread must have the CAP_SYS_ADMIN capability in its user namespace, or
     // the thread must already have the no_new_privs bit set.
@@ -374,11 +123,12 @@ pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
 }
 
 pub fn is_notify(seccomp: &LinuxSeccomp) -> bool {
-    seccomp
-        .syscalls()
-        .iter()
-        .flatten()
-        .any(|syscall| syscall.action() == LinuxSeccompAction::ScmpActNotify)
+    seccomp.default_action() == LinuxSeccompAction::ScmpActNotify
+        || seccomp
+            .syscalls()
+            .iter()
+            .flatten()
+            .any(|syscall| syscall.action() == LinuxSeccompAction::ScmpActNotify)
 }
 
 #[cfg(test)]
@@ -459,6 +209,63 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[serial]
+    fn test_seccomp_flags() -> Result<()> {
+        let syscall = LinuxSyscallBuilder::default()
+            .names(vec![String::from("getcwd")])
+            .action(LinuxSeccompAction::ScmpActErrno)
+            .build()?;
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .architectures(vec![Arch::ScmpArchNative])
+            .flags(vec![
+                String::from("SECCOMP_FILTER_FLAG_TSYNC"),
+                String::from("SECCOMP_FILTER_FLAG_LOG"),
+            ])
+            .syscalls(vec![syscall])
+            .build()?;
+
+        test_utils::test_in_child_process(|| {
+            let _ = prctl::set_no_new_privileges(true);
+            initialize_seccomp(&seccomp_profile)?;
+            if nix::unistd::getcwd().is_ok() {
+                bail!("getcwd didn't error out as seccomp profile specified");
+            }
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_notify_default_action() -> Result<()> {
+        let bootstrap = LinuxSyscallBuilder::default()
+            .names(
+                NOTIFY_BOOTSTRAP_SYSCALLS
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>(),
+            )
+            .action(LinuxSeccompAction::ScmpActAllow)
+            .build()?;
+        let mut seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActNotify)
+            .architectures(vec![Arch::ScmpArchNative])
+            .syscalls(vec![bootstrap])
+            .build()?;
+        // notify as default action is rejected, even if the profile allows
+        // the syscalls to pass the notify fd on
+        assert!(check_seccomp(&seccomp_profile).is_err());
+        check_audit_seccomp(&seccomp_profile)?;
+
+        seccomp_profile.set_syscalls(None);
+        assert!(check_audit_seccomp(&seccomp_profile).is_err());
+
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_seccomp_notify() -> Result<()> {
@@ -0,0 +1,213 @@
+//! A small assembler for classic BPF programs with symbolic jump targets
+use anyhow::{bail, Result};
+
+/// Maximum number of instructions the kernel accepts in a seccomp program
+pub const BPF_MAXINSNS: usize = 4096;
+
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_ALU_AND_K: u16 = 0x54;
+const BPF_JMP_JA: u16 = 0x05;
+const BPF_RET_K: u16 = 0x06;
+
+/// An instruction of a classic BPF program, as loaded into the kernel
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// Conditional jumps, which compare the accumulator with a constant
+#[derive(Debug, Copy, Clone)]
+pub enum Jump {
+    Eq = 0x15,
+    Gt = 0x25,
+    Ge = 0x35,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Label(usize);
+
+/// The target of a conditional jump
+#[derive(Debug, Copy, Clone)]
+pub enum Target {
+    /// continue with the next instruction
+    Next,
+    Label(Label),
+}
+
+#[derive(Debug)]
+enum Insn {
+    Stmt(u16, u32),
+    Jump(Jump, u32, Target, Target),
+    Ja(Label),
+    Bind(Label),
+}
+
+#[derive(Debug, Default)]
+pub struct Assembler {
+    insns: Vec<Insn>,
+    labels: usize,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a label, which has to be bound to a position with bind
+    pub fn label(&mut self) -> Label {
+        self.labels += 1;
+        Label(self.labels - 1)
+    }
+
+    /// Binds the label to the position of the next instruction
+    pub fn bind(&mut self, label: Label) {
+        self.insns.push(Insn::Bind(label));
+    }
+
+    /// Loads the 32 bit word at offset of the seccomp data into the accumulator
+    pub fn load(&mut self, offset: u32) {
+        self.insns.push(Insn::Stmt(BPF_LD_W_ABS, offset));
+    }
+
+    pub fn and(&mut self, k: u32) {
+        self.insns.push(Insn::Stmt(BPF_ALU_AND_K, k));
+    }
+
+    pub fn jump(&mut self, jump: Jump, k: u32, jt: Target, jf: Target) {
+        self.insns.push(Insn::Jump(jump, k, jt, jf));
+    }
+
+    /// Unconditionally jumps to the label. Unlike conditional jumps, which
+    /// can only skip up to 255 instructions, this can reach any instruction.
+    pub fn ja(&mut self, label: Label) {
+        self.insns.push(Insn::Ja(label));
+    }
+
+    pub fn ret(&mut self, k: u32) {
+        self.insns.push(Insn::Stmt(BPF_RET_K, k));
+    }
+
+    /// Resolves all jump targets and returns the program
+    pub fn assemble(self) -> Result<Vec<SockFilter>> {
+        let mut positions = vec![None; self.labels];
+        let mut pos = 0;
+        for insn in &self.insns {
+            match insn {
+                Insn::Bind(Label(label)) => positions[*label] = Some(pos),
+                _ => pos += 1,
+            }
+        }
+
+        let offset = |from: usize, label: &Label| -> Result<usize> {
+            match positions[label.0] {
+                Some(to) if to > from => Ok(to - from - 1),
+                Some(_) => bail!("backward jumps are not allowed in bpf programs"),
+                None => bail!("jump to unbound label {}", label.0),
+            }
+        };
+        let cond_offset = |from: usize, target: &Target| -> Result<u8> {
+            match target {
+                Target::Next => Ok(0),
+                Target::Label(label) => {
+                    let offset = offset(from, label)?;
+                    if offset > u8::MAX as usize {
+                        bail!("conditional jump over {} instructions is too far", offset);
+                    }
+                    Ok(offset as u8)
+                }
+            }
+        };
+
+        let mut program = Vec::with_capacity(pos);
+        for insn in &self.insns {
+            let from = program.len();
+            let filter = match insn {
+                Insn::Bind(_) => continue,
+                Insn::Stmt(code, k) => SockFilter {
+                    code: *code,
+                    jt: 0,
+                    jf: 0,
+                    k: *k,
+                },
+                Insn::Jump(jump, k, jt, jf) => SockFilter {
+                    code: *jump as u16,
+                    jt: cond_offset(from, jt)?,
+                    jf: cond_offset(from, jf)?,
+                    k: *k,
+                },
+                Insn::Ja(label) => SockFilter {
+                    code: BPF_JMP_JA,
+                    jt: 0,
+                    jf: 0,
+                    k: offset(from, label)? as u32,
+                },
+            };
+            program.push(filter);
+        }
+
+        if program.len() > BPF_MAXINSNS {
+            bail!(
+                "bpf program has {} instructions, but at most {} are allowed",
+                program.len(),
+                BPF_MAXINSNS
+            );
+        }
+
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_jumps() -> Result<()> {
+        let mut asm = Assembler::new();
+        let skip = asm.label();
+        let end = asm.label();
+        asm.load(0);
+        asm.jump(Jump::Eq, 1, Target::Next, Target::Label(skip));
+        asm.ja(end);
+        asm.bind(skip);
+        asm.ret(1);
+        asm.bind(end);
+        asm.ret(2);
+
+        let program = asm.assemble()?;
+        assert_eq!(program.len(), 5);
+        assert_eq!((program[1].jt, program[1].jf), (0, 1));
+        assert_eq!((program[2].code, program[2].k), (BPF_JMP_JA, 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assemble_invalid_jumps() {
+        let mut asm = Assembler::new();
+        let unbound = asm.label();
+        asm.ja(unbound);
+        assert!(asm.assemble().is_err());
+
+        let mut asm = Assembler::new();
+        let back = asm.label();
+        asm.bind(back);
+        asm.ret(0);
+        asm.ja(back);
+        assert!(asm.assemble().is_err());
+
+        let mut asm = Assembler::new();
+        let far = asm.label();
+        asm.jump(Jump::Eq, 0, Target::Label(far), Target::Next);
+        for _ in 0..300 {
+            asm.ret(0);
+        }
+        asm.bind(far);
+        asm.ret(1);
+        assert!(asm.assemble().is_err());
+    }
+}
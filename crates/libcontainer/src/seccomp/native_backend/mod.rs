@@ -0,0 +1,885 @@
+//! Seccomp backend which compiles filters to classic BPF in Rust and loads
+//! them with the seccomp syscall, so that youki can be built without libseccomp.
+//!
+//! Only the native architecture is supported. Syscalls of other architectures
+//! and of the x32 ABI are killed, like libseccomp does for architectures which
+//! are not part of the filter. If several rules match a syscall, the rules are
+//! ordered like libseccomp orders them, independently of the profile: a rule
+//! with fewer conditions wins over the rules it is a prefix of, otherwise the
+//! rules are checked by argument index, operator and value.
+mod asm;
+mod syscalls;
+
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use oci_spec::runtime::{Arch, LinuxSeccomp, LinuxSeccompAction, LinuxSeccompOperator};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+use asm::{Assembler, Jump, Label, SockFilter, Target};
+
+const SECCOMP_SET_MODE_FILTER: libc::c_ulong = 1;
+
+const SECCOMP_FILTER_FLAG_TSYNC: libc::c_ulong = 1 << 0;
+const SECCOMP_FILTER_FLAG_LOG: libc::c_ulong = 1 << 1;
+const SECCOMP_FILTER_FLAG_SPEC_ALLOW: libc::c_ulong = 1 << 2;
+const SECCOMP_FILTER_FLAG_NEW_LISTENER: libc::c_ulong = 1 << 3;
+const SECCOMP_FILTER_FLAG_TSYNC_ESRCH: libc::c_ulong = 1 << 4;
+const SECCOMP_FILTER_FLAG_WAIT_KILLABLE_RECV: libc::c_ulong = 1 << 5;
+
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_KILL_THREAD: u32 = 0x0000_0000;
+const SECCOMP_RET_TRAP: u32 = 0x0003_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+const SECCOMP_RET_TRACE: u32 = 0x7ff0_0000;
+const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+const SECCOMP_RET_DATA: u32 = 0x0000_ffff;
+
+// offsets into struct seccomp_data
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+const ARGS_OFFSET: u32 = 16;
+const MAX_ARGS: usize = 6;
+
+// syscalls of the x32 ABI have this bit set on x86_64
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+#[repr(C)]
+struct SockFprog {
+    len: libc::c_ushort,
+    filter: *const SockFilter,
+}
+
+/// A seccomp filter compiled to a BPF program, which can be loaded into the
+/// kernel or exported for inspection.
+#[derive(Debug)]
+pub struct FilterContext {
+    program: Vec<SockFilter>,
+    flags: libc::c_ulong,
+    notify_fd: Cell<Option<RawFd>>,
+}
+
+impl FilterContext {
+    pub fn load(&self) -> Result<()> {
+        let prog = SockFprog {
+            len: self.program.len() as libc::c_ushort,
+            filter: self.program.as_ptr(),
+        };
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                SECCOMP_SET_MODE_FILTER,
+                self.flags,
+                &prog as *const SockFprog,
+            )
+        };
+        let res = Errno::result(res).context("Failed to load seccomp profile")?;
+
+        if self.flags & SECCOMP_FILTER_FLAG_NEW_LISTENER != 0 {
+            self.notify_fd.set(Some(res as RawFd));
+        }
+
+        Ok(())
+    }
+
+    pub fn notify_fd(&self) -> Result<Option<i32>> {
+        Ok(self.notify_fd.get())
+    }
+
+    /// Pseudo filter code is generated by libseccomp, so it can't be exported
+    /// by this backend.
+    pub fn export_pfc<F: AsRawFd>(&self, _dest: &F) -> Result<()> {
+        bail!("exporting pseudo filter code requires the libseccomp backend")
+    }
+
+    /// Writes the filter as the raw BPF program, which would be loaded into
+    /// the kernel, to dest.
+    pub fn export_bpf<F: AsRawFd>(&self, dest: &F) -> Result<()> {
+        let mut bytes = Vec::with_capacity(self.program.len() * 8);
+        for insn in &self.program {
+            bytes.extend_from_slice(&insn.code.to_ne_bytes());
+            bytes.push(insn.jt);
+            bytes.push(insn.jf);
+            bytes.extend_from_slice(&insn.k.to_ne_bytes());
+        }
+
+        // the file must not be closed, as the fd is owned by the caller
+        let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(dest.as_raw_fd()) });
+        file.write_all(&bytes)
+            .context("Failed to export seccomp profile as bpf")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Condition {
+    index: u32,
+    op: LinuxSeccompOperator,
+    value: u64,
+    value_two: u64,
+}
+
+#[derive(Debug)]
+struct Rule<'a> {
+    action: u32,
+    conditions: Vec<&'a Condition>,
+}
+
+fn translate_action(action: LinuxSeccompAction, errno: Option<u32>) -> u32 {
+    let errno = errno.unwrap_or(libc::EPERM as u32) & SECCOMP_RET_DATA;
+    match action {
+        LinuxSeccompAction::ScmpActKill => SECCOMP_RET_KILL_THREAD,
+        LinuxSeccompAction::ScmpActTrap => SECCOMP_RET_TRAP,
+        LinuxSeccompAction::ScmpActErrno => SECCOMP_RET_ERRNO | errno,
+        LinuxSeccompAction::ScmpActTrace => SECCOMP_RET_TRACE | errno,
+        LinuxSeccompAction::ScmpActAllow => SECCOMP_RET_ALLOW,
+        LinuxSeccompAction::ScmpActKillProcess => SECCOMP_RET_KILL_PROCESS,
+        LinuxSeccompAction::ScmpActNotify => SECCOMP_RET_USER_NOTIF,
+        LinuxSeccompAction::ScmpActLog => SECCOMP_RET_LOG,
+    }
+}
+
+fn translate_flag(flag: &str) -> Result<libc::c_ulong> {
+    match flag {
+        "SECCOMP_FILTER_FLAG_TSYNC" => Ok(SECCOMP_FILTER_FLAG_TSYNC),
+        "SECCOMP_FILTER_FLAG_LOG" => Ok(SECCOMP_FILTER_FLAG_LOG),
+        "SECCOMP_FILTER_FLAG_SPEC_ALLOW" => Ok(SECCOMP_FILTER_FLAG_SPEC_ALLOW),
+        "SECCOMP_FILTER_FLAG_WAIT_KILLABLE_RECV" => Ok(SECCOMP_FILTER_FLAG_WAIT_KILLABLE_RECV),
+        _ => bail!("unknown seccomp filter flag {}", flag),
+    }
+}
+
+/// Compiles the seccomp profile of the runtime spec into a filter without
+/// loading it.
+pub fn compile(seccomp: &LinuxSeccomp) -> Result<FilterContext> {
+    super::check_seccomp(seccomp)?;
+    build(seccomp)
+}
+
+// Compiles the profile without checking it first.
+pub(super) fn build(seccomp: &LinuxSeccomp) -> Result<FilterContext> {
+    for &arch in seccomp.architectures().iter().flatten() {
+        if arch != Arch::ScmpArchNative && arch != syscalls::ARCH {
+            log::warn!(
+                "{:?} is not supported by the native seccomp backend, its syscalls will be killed",
+                arch
+            );
+        }
+    }
+
+    let mut flags = 0;
+    for flag in seccomp.flags().iter().flatten() {
+        flags |= translate_flag(flag)?;
+    }
+    if super::is_notify(seccomp) {
+        flags |= SECCOMP_FILTER_FLAG_NEW_LISTENER;
+        // the kernel refuses to combine TSYNC with a listener, unless it may
+        // report failed synchronization with ESRCH
+        if flags & SECCOMP_FILTER_FLAG_TSYNC != 0 {
+            flags |= SECCOMP_FILTER_FLAG_TSYNC_ESRCH;
+        }
+    }
+
+    // TODO: fix default action error number. The spec repo doesn't have it yet.
+    let default_action = translate_action(seccomp.default_action(), None);
+
+    let mut entries = Vec::new();
+    for syscall in seccomp.syscalls().iter().flatten() {
+        let action = translate_action(syscall.action(), syscall.errno_ret());
+        if action == default_action {
+            // When the action is the same as the default action, the rule is redundent.
+            log::warn!(
+                "Detect a seccomp action that is the same as the default action: {:?}",
+                syscall
+            );
+            continue;
+        }
+
+        let mut conditions = Vec::new();
+        for arg in syscall.args().iter().flatten() {
+            if arg.index() >= MAX_ARGS {
+                bail!("invalid seccomp argument index {}", arg.index());
+            }
+            conditions.push(Condition {
+                index: arg.index() as u32,
+                op: arg.op(),
+                value: arg.value(),
+                value_two: arg.value_two().unwrap_or(0),
+            });
+        }
+
+        entries.push((syscall.names(), action, conditions));
+    }
+
+    let mut groups: Vec<(u32, Vec<Rule>)> = Vec::new();
+    for (names, action, conditions) in &entries {
+        for name in names.iter() {
+            let nr = match syscalls::resolve(name) {
+                Some(nr) => nr,
+                None if syscalls::is_foreign(name) => {
+                    log::debug!(
+                        "{:?} doesn't exist on the native architecture, ignoring its rule",
+                        name
+                    );
+                    continue;
+                }
+                // an allow rule which is silently dropped would make the
+                // container fail in obscure ways
+                None if *action == SECCOMP_RET_ALLOW => {
+                    bail!("unknown syscall {:?} in an allow rule", name)
+                }
+                None => {
+                    log::warn!("Failed to resolve syscall, likely the native seccomp backend doesn't support this. {:?}", name);
+                    continue;
+                }
+            };
+
+            let rules = build_rules(*action, conditions);
+            match groups.iter_mut().find(|(n, _)| *n == nr) {
+                Some((_, group)) => group.extend(rules),
+                None => groups.push((nr, rules)),
+            }
+        }
+    }
+
+    for (nr, rules) in groups.iter_mut() {
+        order_rules(rules).with_context(|| format!("invalid rules for syscall {}", nr))?;
+    }
+
+    let program = generate(default_action, &groups).context("failed to generate bpf program")?;
+
+    Ok(FilterContext {
+        program,
+        flags,
+        notify_fd: Cell::new(None),
+    })
+}
+
+// All conditions of a syscall entry have to match for the action to be taken,
+// so they are combined into a single rule. To match the libseccomp backend,
+// which follows runc here, several conditions on the same argument result in
+// one rule per condition, which means that the action is taken if any of them
+// matches.
+fn build_rules(action: u32, conditions: &[Condition]) -> Vec<Rule> {
+    let mut seen_args = HashSet::new();
+    let repeated_arg = conditions.iter().any(|c| !seen_args.insert(c.index));
+
+    if repeated_arg {
+        conditions
+            .iter()
+            .map(|c| Rule {
+                action,
+                conditions: vec![c],
+            })
+            .collect()
+    } else {
+        vec![Rule {
+            action,
+            conditions: conditions.iter().collect(),
+        }]
+    }
+}
+
+// libseccomp's order of the operators
+fn operator_rank(op: LinuxSeccompOperator) -> u8 {
+    match op {
+        LinuxSeccompOperator::ScmpCmpNe => 1,
+        LinuxSeccompOperator::ScmpCmpLt => 2,
+        LinuxSeccompOperator::ScmpCmpLe => 3,
+        LinuxSeccompOperator::ScmpCmpEq => 4,
+        LinuxSeccompOperator::ScmpCmpGe => 5,
+        LinuxSeccompOperator::ScmpCmpGt => 6,
+        LinuxSeccompOperator::ScmpCmpMaskedEq => 7,
+    }
+}
+
+fn condition_key(condition: &Condition) -> (u32, u8, u64, u64) {
+    (
+        condition.index,
+        operator_rank(condition.op),
+        condition.value,
+        condition.value_two,
+    )
+}
+
+// Orders the rules of a syscall the way libseccomp evaluates them, as the
+// generated program takes the action of the first matching rule. libseccomp
+// builds a tree of the conditions sorted by argument index, operator and
+// value, in which a rule replaces the more specific rules below it. Sorting
+// the condition chains lexicographically gives the same order, as a chain
+// sorts before all chains it is a prefix of. Rules with the same conditions
+// but different actions are rejected like libseccomp does.
+fn order_rules(rules: &mut Vec<Rule>) -> Result<()> {
+    for rule in rules.iter_mut() {
+        rule.conditions.sort_by_key(|c| condition_key(c));
+    }
+    rules.sort_by(|a, b| {
+        let a = a.conditions.iter().map(|c| condition_key(c));
+        let b = b.conditions.iter().map(|c| condition_key(c));
+        a.cmp(b)
+    });
+
+    let mut result: Vec<Rule> = Vec::with_capacity(rules.len());
+    for rule in rules.drain(..) {
+        match result.last() {
+            Some(last) if last.conditions == rule.conditions => {
+                if last.action != rule.action {
+                    bail!(
+                        "conflicting actions {:#x} and {:#x}",
+                        last.action,
+                        rule.action
+                    );
+                }
+            }
+            _ => result.push(rule),
+        }
+    }
+    *rules = result;
+
+    Ok(())
+}
+
+fn generate(default_action: u32, groups: &[(u32, Vec<Rule>)]) -> Result<Vec<SockFilter>> {
+    let mut asm = Assembler::new();
+
+    // syscalls of other architectures are killed
+    let arch_ok = asm.label();
+    asm.load(ARCH_OFFSET);
+    asm.jump(
+        Jump::Eq,
+        syscalls::AUDIT_ARCH,
+        Target::Label(arch_ok),
+        Target::Next,
+    );
+    asm.ret(SECCOMP_RET_KILL_THREAD);
+    asm.bind(arch_ok);
+
+    asm.load(NR_OFFSET);
+    #[cfg(target_arch = "x86_64")]
+    {
+        let native_abi = asm.label();
+        asm.jump(
+            Jump::Ge,
+            X32_SYSCALL_BIT,
+            Target::Next,
+            Target::Label(native_abi),
+        );
+        asm.ret(SECCOMP_RET_KILL_THREAD);
+        asm.bind(native_abi);
+    }
+
+    // Dispatch on the syscall number. The rules are only reached through
+    // unconditional jumps, as conditional jumps can't skip more than 255
+    // instructions.
+    let bodies: Vec<Label> = groups.iter().map(|_| asm.label()).collect();
+    for ((nr, _), body) in groups.iter().zip(&bodies) {
+        let next = asm.label();
+        asm.jump(Jump::Eq, *nr, Target::Next, Target::Label(next));
+        asm.ja(*body);
+        asm.bind(next);
+    }
+    asm.ret(default_action);
+
+    for ((_, rules), body) in groups.iter().zip(bodies) {
+        asm.bind(body);
+        for rule in rules {
+            let next_rule = asm.label();
+            for condition in &rule.conditions {
+                generate_condition(&mut asm, condition, next_rule);
+            }
+            asm.ret(rule.action);
+            asm.bind(next_rule);
+        }
+        asm.ret(default_action);
+    }
+
+    asm.assemble()
+}
+
+// Returns the offsets of the high and the low 32 bits of a syscall argument
+fn arg_offsets(index: u32) -> (u32, u32) {
+    let offset = ARGS_OFFSET + index * 8;
+    if cfg!(target_endian = "little") {
+        (offset + 4, offset)
+    } else {
+        (offset, offset + 4)
+    }
+}
+
+// Generates a 64 bit comparison of a syscall argument, which continues after
+// the comparison if the condition matches and jumps to fail otherwise.
+fn generate_condition(asm: &mut Assembler, condition: &Condition, fail: Label) {
+    let (hi_offset, lo_offset) = arg_offsets(condition.index);
+    let hi = (condition.value >> 32) as u32;
+    let lo = condition.value as u32;
+    let fail = Target::Label(fail);
+    let pass = asm.label();
+    let pass_target = Target::Label(pass);
+
+    match condition.op {
+        LinuxSeccompOperator::ScmpCmpEq => {
+            asm.load(hi_offset);
+            asm.jump(Jump::Eq, hi, Target::Next, fail);
+            asm.load(lo_offset);
+            asm.jump(Jump::Eq, lo, Target::Next, fail);
+        }
+        LinuxSeccompOperator::ScmpCmpNe => {
+            asm.load(hi_offset);
+            asm.jump(Jump::Eq, hi, Target::Next, pass_target);
+            asm.load(lo_offset);
+            asm.jump(Jump::Eq, lo, fail, Target::Next);
+        }
+        LinuxSeccompOperator::ScmpCmpGt | LinuxSeccompOperator::ScmpCmpGe => {
+            asm.load(hi_offset);
+            asm.jump(Jump::Gt, hi, pass_target, Target::Next);
+            asm.jump(Jump::Eq, hi, Target::Next, fail);
+            asm.load(lo_offset);
+            let jump = match condition.op {
+                LinuxSeccompOperator::ScmpCmpGt => Jump::Gt,
+                _ => Jump::Ge,
+            };
+            asm.jump(jump, lo, Target::Next, fail);
+        }
+        LinuxSeccompOperator::ScmpCmpLt | LinuxSeccompOperator::ScmpCmpLe => {
+            asm.load(hi_offset);
+            asm.jump(Jump::Gt, hi, fail, Target::Next);
+            asm.jump(Jump::Eq, hi, Target::Next, pass_target);
+            asm.load(lo_offset);
+            // a < b is !(a >= b) and a <= b is !(a > b)
+            let jump = match condition.op {
+                LinuxSeccompOperator::ScmpCmpLt => Jump::Ge,
+                _ => Jump::Gt,
+            };
+            asm.jump(jump, lo, fail, Target::Next);
+        }
+        LinuxSeccompOperator::ScmpCmpMaskedEq => {
+            // the value is the mask, which is applied before comparing with value_two
+            let datum_hi = (condition.value_two >> 32) as u32;
+            let datum_lo = condition.value_two as u32;
+            asm.load(hi_offset);
+            asm.and(hi);
+            asm.jump(Jump::Eq, datum_hi, Target::Next, fail);
+            asm.load(lo_offset);
+            asm.and(lo);
+            asm.jump(Jump::Eq, datum_lo, Target::Next, fail);
+        }
+    }
+
+    asm.bind(pass);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seccomp::test_utils::{arg, arg_eq, exported, profile, run_bpf};
+    use oci_spec::runtime::{LinuxSeccompArg, LinuxSeccompBuilder, LinuxSyscallBuilder};
+
+    fn run(filter: &FilterContext, nr: u32, args: [u64; 6]) -> Result<u32> {
+        let program = exported(|f| filter.export_bpf(f))?;
+        run_bpf(&program, syscalls::AUDIT_ARCH, nr as i32, args)
+    }
+
+    fn personality() -> u32 {
+        syscalls::resolve("personality").unwrap()
+    }
+
+    // Returns a profile which allows everything, except for the rules, which
+    // are (action, args) entries for the syscall
+    fn rules_profile(
+        syscall: &str,
+        rules: Vec<(LinuxSeccompAction, Vec<LinuxSeccompArg>)>,
+    ) -> Result<LinuxSeccomp> {
+        let mut syscalls = Vec::new();
+        for (action, args) in rules {
+            let mut builder = LinuxSyscallBuilder::default()
+                .names(vec![syscall.to_string()])
+                .action(action)
+                .errno_ret(libc::EAGAIN as u32);
+            if !args.is_empty() {
+                builder = builder.args(args);
+            }
+            syscalls.push(builder.build()?);
+        }
+
+        let seccomp = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .architectures(vec![Arch::ScmpArchNative])
+            .syscalls(syscalls)
+            .build()?;
+        Ok(seccomp)
+    }
+
+    const ERRNO: u32 = SECCOMP_RET_ERRNO | libc::EAGAIN as u32;
+
+    #[test]
+    fn test_unconditional_rule() -> Result<()> {
+        let filter = compile(&profile("personality", vec![])?)?;
+        assert_eq!(run(&filter, personality(), [0; 6])?, ERRNO);
+        assert_eq!(
+            run(&filter, syscalls::resolve("getcwd").unwrap(), [0; 6])?,
+            SECCOMP_RET_ALLOW
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_other_arch_is_killed() -> Result<()> {
+        let filter = compile(&profile("personality", vec![])?)?;
+        let program = exported(|f| filter.export_bpf(f))?;
+        assert_eq!(
+            run_bpf(&program, 0x4000_0003, personality() as i32, [0; 6])?,
+            SECCOMP_RET_KILL_THREAD
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_64bit_comparisons() -> Result<()> {
+        let value = 0x1_0000_0005;
+        let cases: Vec<(LinuxSeccompOperator, fn(u64, u64) -> bool)> = vec![
+            (LinuxSeccompOperator::ScmpCmpEq, |a, b| a == b),
+            (LinuxSeccompOperator::ScmpCmpNe, |a, b| a != b),
+            (LinuxSeccompOperator::ScmpCmpGt, |a, b| a > b),
+            (LinuxSeccompOperator::ScmpCmpGe, |a, b| a >= b),
+            (LinuxSeccompOperator::ScmpCmpLt, |a, b| a < b),
+            (LinuxSeccompOperator::ScmpCmpLe, |a, b| a <= b),
+        ];
+        let probes = [
+            0,
+            5,
+            6,
+            0x2_0000_0000,
+            value - 1,
+            value,
+            value + 1,
+            u64::MAX,
+        ];
+
+        for (op, matches) in cases {
+            let filter = compile(&profile("personality", vec![arg(1, op, value, None)?])?)?;
+            for &probe in &probes {
+                let expected = if matches(probe, value) {
+                    ERRNO
+                } else {
+                    SECCOMP_RET_ALLOW
+                };
+                assert_eq!(
+                    run(&filter, personality(), [0, probe, 0, 0, 0, 0])?,
+                    expected,
+                    "{:?} {:#x}",
+                    op,
+                    probe
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_masked_eq() -> Result<()> {
+        let arg = arg(
+            0,
+            LinuxSeccompOperator::ScmpCmpMaskedEq,
+            0xff00_0000_00ff,
+            Some(0x1200_0000_0034),
+        )?;
+        let filter = compile(&profile("personality", vec![arg])?)?;
+
+        assert_eq!(
+            run(&filter, personality(), [0x1234_0000_ff34, 0, 0, 0, 0, 0])?,
+            ERRNO
+        );
+        assert_eq!(
+            run(&filter, personality(), [0x1200_0000_0035, 0, 0, 0, 0, 0])?,
+            SECCOMP_RET_ALLOW
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_args() -> Result<()> {
+        let filter = compile(&profile("personality", vec![arg_eq(0, 1)?, arg_eq(1, 2)?])?)?;
+        assert_eq!(run(&filter, personality(), [1, 2, 0, 0, 0, 0])?, ERRNO);
+        assert_eq!(
+            run(&filter, personality(), [1, 0, 0, 0, 0, 0])?,
+            SECCOMP_RET_ALLOW
+        );
+
+        let filter = compile(&profile("personality", vec![arg_eq(0, 1)?, arg_eq(0, 2)?])?)?;
+        assert_eq!(run(&filter, personality(), [2, 0, 0, 0, 0, 0])?, ERRNO);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_order() -> Result<()> {
+        let trap = (LinuxSeccompAction::ScmpActTrap, vec![]);
+        let errno = (LinuxSeccompAction::ScmpActErrno, vec![arg_eq(0, 1)?]);
+        // the unconditional rule wins, wherever it is in the profile
+        for rules in [vec![trap.clone(), errno.clone()], vec![errno, trap]] {
+            let filter = compile(&rules_profile("personality", rules)?)?;
+            assert_eq!(
+                run(&filter, personality(), [1, 0, 0, 0, 0, 0])?,
+                SECCOMP_RET_TRAP
+            );
+        }
+
+        // the rules are ordered by operator, EQ is checked before GE
+        let ge = (
+            LinuxSeccompAction::ScmpActTrap,
+            vec![arg(0, LinuxSeccompOperator::ScmpCmpGe, 1, None)?],
+        );
+        let eq = (LinuxSeccompAction::ScmpActErrno, vec![arg_eq(0, 2)?]);
+        for rules in [vec![ge.clone(), eq.clone()], vec![eq, ge]] {
+            let filter = compile(&rules_profile("personality", rules)?)?;
+            assert_eq!(run(&filter, personality(), [2, 0, 0, 0, 0, 0])?, ERRNO);
+            assert_eq!(
+                run(&filter, personality(), [3, 0, 0, 0, 0, 0])?,
+                SECCOMP_RET_TRAP
+            );
+            assert_eq!(
+                run(&filter, personality(), [0, 0, 0, 0, 0, 0])?,
+                SECCOMP_RET_ALLOW
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflicting_rules() -> Result<()> {
+        let rules = vec![
+            (LinuxSeccompAction::ScmpActTrap, vec![arg_eq(0, 1)?]),
+            (LinuxSeccompAction::ScmpActErrno, vec![arg_eq(0, 1)?]),
+        ];
+        assert!(compile(&rules_profile("personality", rules)?).is_err());
+
+        // the same rule twice is no conflict
+        let rules = vec![
+            (LinuxSeccompAction::ScmpActTrap, vec![arg_eq(0, 1)?]),
+            (LinuxSeccompAction::ScmpActTrap, vec![arg_eq(0, 1)?]),
+        ];
+        assert!(compile(&rules_profile("personality", rules)?).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_syscalls() -> Result<()> {
+        let allow = |name: &str| -> Result<LinuxSeccomp> {
+            let seccomp = LinuxSeccompBuilder::default()
+                .default_action(LinuxSeccompAction::ScmpActErrno)
+                .syscalls(vec![LinuxSyscallBuilder::default()
+                    .names(vec![String::from(name)])
+                    .action(LinuxSeccompAction::ScmpActAllow)
+                    .build()?])
+                .build()?;
+            Ok(seccomp)
+        };
+        assert!(compile(&allow("unknown")?).is_err());
+        // syscalls of other architectures are ignored like libseccomp does
+        assert!(compile(&allow("chown32")?).is_ok());
+        assert!(compile(&allow("epoll_pwait2")?).is_ok());
+        // a denied syscall, which is unknown, can't be called anyway
+        assert!(compile(&profile("unknown", vec![])?).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_arg_index() -> Result<()> {
+        assert!(compile(&profile("personality", vec![arg_eq(6, 1)?])?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_flags() -> Result<()> {
+        let seccomp = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .flags(vec![
+                String::from("SECCOMP_FILTER_FLAG_TSYNC"),
+                String::from("SECCOMP_FILTER_FLAG_SPEC_ALLOW"),
+            ])
+            .build()?;
+        let filter = compile(&seccomp)?;
+        assert_eq!(
+            filter.flags,
+            SECCOMP_FILTER_FLAG_TSYNC | SECCOMP_FILTER_FLAG_SPEC_ALLOW
+        );
+
+        assert!(translate_flag("SECCOMP_FILTER_FLAG_UNKNOWN").is_err());
+        Ok(())
+    }
+
+    // The native backend has to produce filters which behave exactly like the
+    // ones generated by libseccomp.
+    #[cfg(feature = "libseccomp")]
+    mod equivalence {
+        use super::*;
+        use crate::seccomp::libseccomp_backend;
+        use std::ffi::CString;
+        use std::path::PathBuf;
+
+        // The default profile of Docker and containerd
+        fn moby() -> Result<LinuxSeccomp> {
+            let fixture_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/seccomp/fixture/config.json");
+            let spec = oci_spec::runtime::Spec::load(fixture_path)?;
+            let seccomp = spec.linux().as_ref().unwrap().seccomp().as_ref().unwrap();
+            Ok(seccomp.clone())
+        }
+
+        fn assert_equivalent(seccomp: &LinuxSeccomp, args: &[[u64; 6]]) -> Result<()> {
+            let native = exported(|f| compile(seccomp)?.export_bpf(f))?;
+            let libseccomp = exported(|f| libseccomp_backend::compile(seccomp)?.export_bpf(f))?;
+
+            for name in syscalls::names() {
+                let nr = syscalls::resolve(name).unwrap() as i32;
+                // libseccomp ignores rules for syscalls newer than itself
+                let c_name = CString::new(name)?;
+                if unsafe { libseccomp::seccomp_syscall_resolve_name(c_name.as_ptr()) } < 0 {
+                    continue;
+                }
+                for args in args {
+                    assert_eq!(
+                        run_bpf(&native, syscalls::AUDIT_ARCH, nr, *args)?,
+                        run_bpf(&libseccomp, syscalls::AUDIT_ARCH, nr, *args)?,
+                        "{} {:?}",
+                        name,
+                        args
+                    );
+                }
+            }
+
+            Ok(())
+        }
+
+        // Every syscall of the table and of the default profile has to be
+        // resolved like libseccomp resolves it on the native architecture.
+        #[test]
+        fn test_syscall_numbers() -> Result<()> {
+            let seccomp = moby()?;
+            let profile_names = seccomp
+                .syscalls()
+                .iter()
+                .flatten()
+                .flat_map(|syscall| syscall.names().iter().map(String::as_str));
+
+            for name in syscalls::names().chain(profile_names) {
+                assert!(
+                    syscalls::resolve(name).is_some() || syscalls::is_foreign(name),
+                    "{} is unknown",
+                    name
+                );
+
+                let c_name = CString::new(name)?;
+                let nr = unsafe { libseccomp::seccomp_syscall_resolve_name(c_name.as_ptr()) };
+                match nr {
+                    // __NR_SCMP_ERROR, the syscall is newer than libseccomp
+                    -1 => {}
+                    nr if nr >= 0 => {
+                        assert_eq!(syscalls::resolve(name), Some(nr as u32), "{}", name)
+                    }
+                    // pseudo syscall number of a syscall of other architectures
+                    _ => assert!(
+                        syscalls::resolve(name).is_none() && syscalls::is_foreign(name),
+                        "{}",
+                        name
+                    ),
+                }
+            }
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_overlapping_rules() -> Result<()> {
+            let probes = [0, 1, 2, 3, 0x1_0000_0002];
+            let args: Vec<[u64; 6]> = probes.iter().map(|&p| [p, p, 0, 0, 0, 0]).collect();
+
+            let trap = (LinuxSeccompAction::ScmpActTrap, vec![]);
+            let errno = (LinuxSeccompAction::ScmpActErrno, vec![arg_eq(0, 1)?]);
+            for rules in [vec![trap.clone(), errno.clone()], vec![errno, trap]] {
+                assert_equivalent(&rules_profile("personality", rules)?, &args)?;
+            }
+
+            let ge = (
+                LinuxSeccompAction::ScmpActTrap,
+                vec![arg(0, LinuxSeccompOperator::ScmpCmpGe, 1, None)?],
+            );
+            let eq = (LinuxSeccompAction::ScmpActErrno, vec![arg_eq(0, 2)?]);
+            for rules in [vec![ge.clone(), eq.clone()], vec![eq, ge]] {
+                assert_equivalent(&rules_profile("personality", rules)?, &args)?;
+            }
+
+            let short = (LinuxSeccompAction::ScmpActTrap, vec![arg_eq(0, 2)?]);
+            let long = (
+                LinuxSeccompAction::ScmpActErrno,
+                vec![arg_eq(0, 2)?, arg_eq(1, 2)?],
+            );
+            for rules in [vec![short.clone(), long.clone()], vec![long, short]] {
+                assert_equivalent(&rules_profile("personality", rules)?, &args)?;
+            }
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_comparisons() -> Result<()> {
+            let value = 0x1_0000_0005;
+            let probes = [0, 5, value - 1, value, value + 1, u64::MAX];
+            let args: Vec<[u64; 6]> = probes.iter().map(|&p| [p, p, 0, 0, 0, 0]).collect();
+
+            for op in &[
+                LinuxSeccompOperator::ScmpCmpEq,
+                LinuxSeccompOperator::ScmpCmpNe,
+                LinuxSeccompOperator::ScmpCmpGt,
+                LinuxSeccompOperator::ScmpCmpGe,
+                LinuxSeccompOperator::ScmpCmpLt,
+                LinuxSeccompOperator::ScmpCmpLe,
+            ] {
+                let seccomp = profile("personality", vec![arg(0, *op, value, None)?])?;
+                assert_equivalent(&seccomp, &args)?;
+            }
+
+            let masked = arg(
+                1,
+                LinuxSeccompOperator::ScmpCmpMaskedEq,
+                0xffff_0000_ffff,
+                Some(0x5),
+            )?;
+            assert_equivalent(&profile("personality", vec![masked])?, &args)?;
+
+            let combined = vec![arg_eq(0, 5)?, arg_eq(1, value)?];
+            assert_equivalent(&profile("personality", combined)?, &args)?;
+
+            let repeated = vec![arg_eq(0, 5)?, arg_eq(0, value)?];
+            assert_equivalent(&profile("personality", repeated)?, &args)?;
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_moby() -> Result<()> {
+            let seccomp = moby()?;
+            let args = [
+                [0; 6],
+                [8, 0, 0, 0, 0, 0],
+                [0xffff_ffff, 0, 0, 0, 0, 0],
+                [libc::CLONE_NEWNS as u64, 0, 0, 0, 0, 0],
+            ];
+            assert_equivalent(&seccomp, &args)
+        }
+    }
+}
@@ -0,0 +1,598 @@
+//! Syscall numbers of the native architecture
+use oci_spec::runtime::Arch;
+
+// The entries are the names of the syscall number constants of libc, the
+// syscall name is the constant name without the SYS_ prefix.
+macro_rules! syscall_table {
+    ($($sys:ident),* $(,)?) => {
+        &[$((stringify!($sys), libc::$sys)),*]
+    };
+}
+
+#[cfg(target_arch = "aarch64")]
+macro_rules! syscall_names {
+    ($($sys:ident),* $(,)?) => {
+        &[$(stringify!($sys)),*]
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+pub const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "x86_64")]
+pub const ARCH: Arch = Arch::ScmpArchX86_64;
+
+#[cfg(target_arch = "aarch64")]
+pub const AUDIT_ARCH: u32 = 0xc000_00b7;
+#[cfg(target_arch = "aarch64")]
+pub const ARCH: Arch = Arch::ScmpArchAarch64;
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+compile_error!("the native seccomp backend only supports x86_64 and aarch64");
+
+/// Syscalls which are available on all supported architectures
+const COMMON: &[(&str, libc::c_long)] = syscall_table![
+    SYS_read,
+    SYS_write,
+    SYS_close,
+    SYS_fstat,
+    SYS_lseek,
+    SYS_mmap,
+    SYS_mprotect,
+    SYS_munmap,
+    SYS_brk,
+    SYS_rt_sigaction,
+    SYS_rt_sigprocmask,
+    SYS_rt_sigreturn,
+    SYS_ioctl,
+    SYS_pread64,
+    SYS_pwrite64,
+    SYS_readv,
+    SYS_writev,
+    SYS_sched_yield,
+    SYS_mremap,
+    SYS_msync,
+    SYS_mincore,
+    SYS_madvise,
+    SYS_shmget,
+    SYS_shmat,
+    SYS_shmctl,
+    SYS_dup,
+    SYS_nanosleep,
+    SYS_getitimer,
+    SYS_setitimer,
+    SYS_getpid,
+    SYS_sendfile,
+    SYS_socket,
+    SYS_connect,
+    SYS_accept,
+    SYS_sendto,
+    SYS_recvfrom,
+    SYS_sendmsg,
+    SYS_recvmsg,
+    SYS_shutdown,
+    SYS_bind,
+    SYS_listen,
+    SYS_getsockname,
+    SYS_getpeername,
+    SYS_socketpair,
+    SYS_setsockopt,
+    SYS_getsockopt,
+    SYS_clone,
+    SYS_execve,
+    SYS_exit,
+    SYS_wait4,
+    SYS_kill,
+    SYS_uname,
+    SYS_semget,
+    SYS_semop,
+    SYS_semctl,
+    SYS_shmdt,
+    SYS_msgget,
+    SYS_msgsnd,
+    SYS_msgrcv,
+    SYS_msgctl,
+    SYS_fcntl,
+    SYS_flock,
+    SYS_fsync,
+    SYS_fdatasync,
+    SYS_truncate,
+    SYS_ftruncate,
+    SYS_getcwd,
+    SYS_chdir,
+    SYS_fchdir,
+    SYS_fchmod,
+    SYS_fchown,
+    SYS_umask,
+    SYS_gettimeofday,
+    SYS_getrlimit,
+    SYS_getrusage,
+    SYS_sysinfo,
+    SYS_times,
+    SYS_ptrace,
+    SYS_getuid,
+    SYS_syslog,
+    SYS_getgid,
+    SYS_setuid,
+    SYS_setgid,
+    SYS_geteuid,
+    SYS_getegid,
+    SYS_setpgid,
+    SYS_getppid,
+    SYS_setsid,
+    SYS_setreuid,
+    SYS_setregid,
+    SYS_getgroups,
+    SYS_setgroups,
+    SYS_setresuid,
+    SYS_getresuid,
+    SYS_setresgid,
+    SYS_getresgid,
+    SYS_getpgid,
+    SYS_setfsuid,
+    SYS_setfsgid,
+    SYS_getsid,
+    SYS_capget,
+    SYS_capset,
+    SYS_rt_sigpending,
+    SYS_rt_sigtimedwait,
+    SYS_rt_sigqueueinfo,
+    SYS_rt_sigsuspend,
+    SYS_sigaltstack,
+    SYS_personality,
+    SYS_statfs,
+    SYS_fstatfs,
+    SYS_getpriority,
+    SYS_setpriority,
+    SYS_sched_setparam,
+    SYS_sched_getparam,
+    SYS_sched_setscheduler,
+    SYS_sched_getscheduler,
+    SYS_sched_get_priority_max,
+    SYS_sched_get_priority_min,
+    SYS_sched_rr_get_interval,
+    SYS_mlock,
+    SYS_munlock,
+    SYS_mlockall,
+    SYS_munlockall,
+    SYS_vhangup,
+    SYS_pivot_root,
+    SYS_prctl,
+    SYS_adjtimex,
+    SYS_setrlimit,
+    SYS_chroot,
+    SYS_sync,
+    SYS_acct,
+    SYS_settimeofday,
+    SYS_mount,
+    SYS_umount2,
+    SYS_swapon,
+    SYS_swapoff,
+    SYS_reboot,
+    SYS_sethostname,
+    SYS_setdomainname,
+    SYS_init_module,
+    SYS_delete_module,
+    SYS_quotactl,
+    SYS_nfsservctl,
+    SYS_gettid,
+    SYS_readahead,
+    SYS_setxattr,
+    SYS_lsetxattr,
+    SYS_fsetxattr,
+    SYS_getxattr,
+    SYS_lgetxattr,
+    SYS_fgetxattr,
+    SYS_listxattr,
+    SYS_llistxattr,
+    SYS_flistxattr,
+    SYS_removexattr,
+    SYS_lremovexattr,
+    SYS_fremovexattr,
+    SYS_tkill,
+    SYS_futex,
+    SYS_sched_setaffinity,
+    SYS_sched_getaffinity,
+    SYS_io_setup,
+    SYS_io_destroy,
+    SYS_io_getevents,
+    SYS_io_submit,
+    SYS_io_cancel,
+    SYS_lookup_dcookie,
+    SYS_remap_file_pages,
+    SYS_getdents64,
+    SYS_set_tid_address,
+    SYS_restart_syscall,
+    SYS_semtimedop,
+    SYS_fadvise64,
+    SYS_timer_create,
+    SYS_timer_settime,
+    SYS_timer_gettime,
+    SYS_timer_getoverrun,
+    SYS_timer_delete,
+    SYS_clock_settime,
+    SYS_clock_gettime,
+    SYS_clock_getres,
+    SYS_clock_nanosleep,
+    SYS_exit_group,
+    SYS_epoll_ctl,
+    SYS_tgkill,
+    SYS_mbind,
+    SYS_set_mempolicy,
+    SYS_get_mempolicy,
+    SYS_mq_open,
+    SYS_mq_unlink,
+    SYS_mq_timedsend,
+    SYS_mq_timedreceive,
+    SYS_mq_notify,
+    SYS_mq_getsetattr,
+    SYS_kexec_load,
+    SYS_waitid,
+    SYS_add_key,
+    SYS_request_key,
+    SYS_keyctl,
+    SYS_ioprio_set,
+    SYS_ioprio_get,
+    SYS_inotify_add_watch,
+    SYS_inotify_rm_watch,
+    SYS_migrate_pages,
+    SYS_openat,
+    SYS_mkdirat,
+    SYS_mknodat,
+    SYS_fchownat,
+    SYS_newfstatat,
+    SYS_unlinkat,
+    SYS_renameat,
+    SYS_linkat,
+    SYS_symlinkat,
+    SYS_readlinkat,
+    SYS_fchmodat,
+    SYS_faccessat,
+    SYS_pselect6,
+    SYS_ppoll,
+    SYS_unshare,
+    SYS_set_robust_list,
+    SYS_get_robust_list,
+    SYS_splice,
+    SYS_tee,
+    SYS_sync_file_range,
+    SYS_vmsplice,
+    SYS_move_pages,
+    SYS_utimensat,
+    SYS_epoll_pwait,
+    SYS_timerfd_create,
+    SYS_fallocate,
+    SYS_timerfd_settime,
+    SYS_timerfd_gettime,
+    SYS_accept4,
+    SYS_signalfd4,
+    SYS_eventfd2,
+    SYS_epoll_create1,
+    SYS_dup3,
+    SYS_pipe2,
+    SYS_inotify_init1,
+    SYS_preadv,
+    SYS_pwritev,
+    SYS_rt_tgsigqueueinfo,
+    SYS_perf_event_open,
+    SYS_recvmmsg,
+    SYS_fanotify_init,
+    SYS_fanotify_mark,
+    SYS_prlimit64,
+    SYS_name_to_handle_at,
+    SYS_open_by_handle_at,
+    SYS_clock_adjtime,
+    SYS_syncfs,
+    SYS_sendmmsg,
+    SYS_setns,
+    SYS_getcpu,
+    SYS_process_vm_readv,
+    SYS_process_vm_writev,
+    SYS_kcmp,
+    SYS_finit_module,
+    SYS_sched_setattr,
+    SYS_sched_getattr,
+    SYS_renameat2,
+    SYS_seccomp,
+    SYS_getrandom,
+    SYS_memfd_create,
+    SYS_kexec_file_load,
+    SYS_bpf,
+    SYS_execveat,
+    SYS_userfaultfd,
+    SYS_membarrier,
+    SYS_mlock2,
+    SYS_copy_file_range,
+    SYS_preadv2,
+    SYS_pwritev2,
+    SYS_pkey_mprotect,
+    SYS_pkey_alloc,
+    SYS_pkey_free,
+    SYS_statx,
+    SYS_io_pgetevents,
+    SYS_rseq,
+    SYS_pidfd_send_signal,
+    SYS_io_uring_setup,
+    SYS_io_uring_enter,
+    SYS_io_uring_register,
+    SYS_open_tree,
+    SYS_move_mount,
+    SYS_fsopen,
+    SYS_fsconfig,
+    SYS_fsmount,
+    SYS_fspick,
+    SYS_pidfd_open,
+    SYS_clone3,
+    SYS_close_range,
+    SYS_openat2,
+    SYS_pidfd_getfd,
+    SYS_faccessat2,
+];
+
+/// Syscalls which are newer than the syscall numbers of libc. Since linux
+/// 5.1 syscalls have the same number on all architectures.
+const RECENT: &[(&str, libc::c_long)] = &[
+    ("SYS_process_madvise", 440),
+    ("SYS_epoll_pwait2", 441),
+    ("SYS_mount_setattr", 442),
+    ("SYS_quotactl_fd", 443),
+    ("SYS_landlock_create_ruleset", 444),
+    ("SYS_landlock_add_rule", 445),
+    ("SYS_landlock_restrict_self", 446),
+    ("SYS_memfd_secret", 447),
+    ("SYS_process_mrelease", 448),
+    ("SYS_futex_waitv", 449),
+    ("SYS_set_mempolicy_home_node", 450),
+    ("SYS_cachestat", 451),
+    ("SYS_fchmodat2", 452),
+    ("SYS_map_shadow_stack", 453),
+    ("SYS_futex_wake", 454),
+    ("SYS_futex_wait", 455),
+    ("SYS_futex_requeue", 456),
+    ("SYS_statmount", 457),
+    ("SYS_listmount", 458),
+    ("SYS_lsm_get_self_attr", 459),
+    ("SYS_lsm_set_self_attr", 460),
+    ("SYS_lsm_list_modules", 461),
+    ("SYS_mseal", 462),
+];
+
+/// Syscalls which only exist on other architectures, most of them on 32 bit
+/// ones. Rules for them are ignored like libseccomp does, instead of being
+/// rejected as unknown.
+const FOREIGN: &[&str] = &[
+    "_llseek",
+    "_newselect",
+    "arm_fadvise64_64",
+    "arm_sync_file_range",
+    "breakpoint",
+    "cacheflush",
+    "chown32",
+    "clock_adjtime64",
+    "clock_getres_time64",
+    "clock_gettime64",
+    "clock_nanosleep_time64",
+    "clock_settime64",
+    "fadvise64_64",
+    "fchown32",
+    "fcntl64",
+    "fstat64",
+    "fstatat64",
+    "fstatfs64",
+    "ftruncate64",
+    "futex_time64",
+    "getegid32",
+    "geteuid32",
+    "getgid32",
+    "getgroups32",
+    "getresgid32",
+    "getresuid32",
+    "getuid32",
+    "io_pgetevents_time64",
+    "ipc",
+    "lchown32",
+    "lstat64",
+    "mmap2",
+    "mq_timedreceive_time64",
+    "mq_timedsend_time64",
+    "multiplexer",
+    "nice",
+    "oldfstat",
+    "oldlstat",
+    "oldolduname",
+    "oldstat",
+    "olduname",
+    "pciconfig_iobase",
+    "pciconfig_read",
+    "pciconfig_write",
+    "ppoll_time64",
+    "pselect6_time64",
+    "readdir",
+    "recv",
+    "recvmmsg_time64",
+    "riscv_flush_icache",
+    "rt_sigtimedwait_time64",
+    "rtas",
+    "s390_guarded_storage",
+    "s390_pci_mmio_read",
+    "s390_pci_mmio_write",
+    "s390_runtime_instr",
+    "s390_sthyi",
+    "sched_rr_get_interval_time64",
+    "semtimedop_time64",
+    "send",
+    "sendfile64",
+    "set_tls",
+    "setfsgid32",
+    "setfsuid32",
+    "setgid32",
+    "setgroups32",
+    "setregid32",
+    "setresgid32",
+    "setresuid32",
+    "setreuid32",
+    "setuid32",
+    "sgetmask",
+    "sigaction",
+    "signal",
+    "sigpending",
+    "sigprocmask",
+    "sigreturn",
+    "sigsuspend",
+    "socketcall",
+    "spu_create",
+    "spu_run",
+    "ssetmask",
+    "stat64",
+    "statfs64",
+    "stime",
+    "subpage_prot",
+    "switch_endian",
+    "sync_file_range2",
+    "sys_debug_setcontext",
+    "timer_gettime64",
+    "timer_settime64",
+    "timerfd_gettime64",
+    "timerfd_settime64",
+    "truncate64",
+    "ugetrlimit",
+    "umount",
+    "usr26",
+    "usr32",
+    "utimensat_time64",
+    "vm86",
+    "vm86old",
+    "waitpid",
+];
+
+// Legacy syscalls of x86_64, which are not part of the generic syscall table
+macro_rules! x86_64_legacy {
+    ($table:ident) => {
+        $table![
+            SYS_open,
+            SYS_stat,
+            SYS_lstat,
+            SYS_poll,
+            SYS_access,
+            SYS_pipe,
+            SYS_select,
+            SYS_dup2,
+            SYS_pause,
+            SYS_alarm,
+            SYS_fork,
+            SYS_vfork,
+            SYS_getdents,
+            SYS_rename,
+            SYS_mkdir,
+            SYS_rmdir,
+            SYS_creat,
+            SYS_link,
+            SYS_unlink,
+            SYS_symlink,
+            SYS_readlink,
+            SYS_chmod,
+            SYS_chown,
+            SYS_lchown,
+            SYS_getpgrp,
+            SYS_utime,
+            SYS_mknod,
+            SYS_uselib,
+            SYS_ustat,
+            SYS_sysfs,
+            SYS_modify_ldt,
+            SYS__sysctl,
+            SYS_arch_prctl,
+            SYS_iopl,
+            SYS_ioperm,
+            SYS_create_module,
+            SYS_get_kernel_syms,
+            SYS_query_module,
+            SYS_getpmsg,
+            SYS_putpmsg,
+            SYS_afs_syscall,
+            SYS_tuxcall,
+            SYS_security,
+            SYS_time,
+            SYS_futimesat,
+            SYS_epoll_create,
+            SYS_epoll_wait,
+            SYS_utimes,
+            SYS_inotify_init,
+            SYS_signalfd,
+            SYS_eventfd,
+            SYS_set_thread_area,
+            SYS_get_thread_area,
+            SYS_epoll_ctl_old,
+            SYS_epoll_wait_old,
+            SYS_vserver,
+        ]
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+const ARCH_SPECIFIC: &[(&str, libc::c_long)] = x86_64_legacy!(syscall_table);
+#[cfg(target_arch = "x86_64")]
+const FOREIGN_ARCH: &[&str] = &[];
+
+#[cfg(target_arch = "aarch64")]
+const ARCH_SPECIFIC: &[(&str, libc::c_long)] = &[];
+#[cfg(target_arch = "aarch64")]
+const FOREIGN_ARCH: &[&str] = x86_64_legacy!(syscall_names);
+
+fn native() -> impl Iterator<Item = &'static (&'static str, libc::c_long)> {
+    COMMON.iter().chain(RECENT).chain(ARCH_SPECIFIC)
+}
+
+/// Returns the number of the syscall on the native architecture
+pub fn resolve(name: &str) -> Option<u32> {
+    native()
+        .find(|(sys, _)| sys.strip_prefix("SYS_") == Some(name))
+        .map(|&(_, nr)| nr as u32)
+}
+
+/// Returns whether the syscall exists, but not on the native architecture
+pub fn is_foreign(name: &str) -> bool {
+    FOREIGN.contains(&name)
+        || FOREIGN_ARCH
+            .iter()
+            .any(|sys| sys.strip_prefix("SYS_") == Some(name))
+}
+
+/// Returns the names of all syscalls of the native architecture
+#[cfg(test)]
+pub fn names() -> impl Iterator<Item = &'static str> {
+    native().map(|(sys, _)| sys.trim_start_matches("SYS_"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("getcwd"), Some(libc::SYS_getcwd as u32));
+        assert_eq!(resolve("personality"), Some(libc::SYS_personality as u32));
+        assert_eq!(resolve("SYS_getcwd"), None);
+        assert_eq!(resolve("unknown"), None);
+        assert_eq!(resolve("epoll_pwait2"), Some(441));
+        assert_eq!(resolve("mseal"), Some(462));
+    }
+
+    #[test]
+    fn test_is_foreign() {
+        assert!(is_foreign("chown32"));
+        assert!(is_foreign("sync_file_range2"));
+        assert!(!is_foreign("getcwd"));
+        assert!(!is_foreign("unknown"));
+        #[cfg(target_arch = "aarch64")]
+        assert!(is_foreign("arch_prctl"));
+    }
+
+    #[test]
+    fn test_no_duplicates() {
+        let mut names: Vec<_> = names().chain(FOREIGN.iter().copied()).collect();
+        let len = names.len();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), len);
+    }
+}
@@ -0,0 +1,168 @@
+//! Helpers to inspect and evaluate exported seccomp programs in tests
+use anyhow::{bail, Result};
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use oci_spec::runtime::{
+    Arch, LinuxSeccomp, LinuxSeccompAction, LinuxSeccompArg, LinuxSeccompArgBuilder,
+    LinuxSeccompBuilder, LinuxSeccompOperator, LinuxSyscallBuilder,
+};
+use std::convert::TryInto;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::io::FromRawFd;
+
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+
+/// Returns what the export function writes to the file it is passed
+pub fn exported<F: FnOnce(&File) -> Result<()>>(export: F) -> Result<Vec<u8>> {
+    let fd = memfd_create(&CString::new("seccomp")?, MemFdCreateFlag::empty())?;
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    export(&file)?;
+
+    let mut content = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// A minimal classic BPF interpreter that evaluates a seccomp program for a
+/// syscall and returns the seccomp action.
+pub fn run_bpf(program: &[u8], arch: u32, nr: i32, args: [u64; 6]) -> Result<u32> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&nr.to_ne_bytes());
+    data.extend_from_slice(&arch.to_ne_bytes());
+    data.extend_from_slice(&0u64.to_ne_bytes());
+    for arg in &args {
+        data.extend_from_slice(&arg.to_ne_bytes());
+    }
+
+    let insns: Vec<(u16, u8, u8, u32)> = program
+        .chunks_exact(8)
+        .map(|c| {
+            (
+                u16::from_ne_bytes([c[0], c[1]]),
+                c[2],
+                c[3],
+                u32::from_ne_bytes([c[4], c[5], c[6], c[7]]),
+            )
+        })
+        .collect();
+
+    let (mut a, mut x) = (0u32, 0u32);
+    let mut mem = [0u32; 16];
+    let mut pc = 0;
+    while let Some(&(code, jt, jf, k)) = insns.get(pc) {
+        pc += 1;
+        let src = if code & 0x08 != 0 { x } else { k };
+        match code & 0x07 {
+            BPF_LD | BPF_LDX => {
+                let value = match code & 0xe0 {
+                    // BPF_ABS
+                    0x20 => {
+                        let offset = k as usize;
+                        u32::from_ne_bytes(data[offset..offset + 4].try_into()?)
+                    }
+                    // BPF_IMM
+                    0x00 => k,
+                    // BPF_MEM
+                    0x60 => mem[k as usize],
+                    mode => bail!("unsupported load mode {:#x}", mode),
+                };
+                if code & 0x07 == BPF_LD {
+                    a = value;
+                } else {
+                    x = value;
+                }
+            }
+            BPF_ST => mem[k as usize] = a,
+            BPF_STX => mem[k as usize] = x,
+            BPF_ALU => {
+                a = match code & 0xf0 {
+                    0x00 => a.wrapping_add(src),
+                    0x10 => a.wrapping_sub(src),
+                    0x20 => a.wrapping_mul(src),
+                    0x40 => a | src,
+                    0x50 => a & src,
+                    0x60 => a << src,
+                    0x70 => a >> src,
+                    0x80 => a.wrapping_neg(),
+                    0xa0 => a ^ src,
+                    op => bail!("unsupported alu operation {:#x}", op),
+                }
+            }
+            BPF_JMP => {
+                let taken = match code & 0xf0 {
+                    // BPF_JA
+                    0x00 => {
+                        pc += k as usize;
+                        continue;
+                    }
+                    0x10 => a == src,
+                    0x20 => a > src,
+                    0x30 => a >= src,
+                    0x40 => a & src != 0,
+                    op => bail!("unsupported jump operation {:#x}", op),
+                };
+                pc += usize::from(if taken { jt } else { jf });
+            }
+            BPF_RET => return Ok(if code & 0x18 == 0x10 { a } else { k }),
+            BPF_MISC => {
+                if code & 0xf8 == 0x00 {
+                    x = a;
+                } else {
+                    a = x;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    bail!("seccomp program did not return an action")
+}
+
+/// Returns a profile which allows everything, except for the syscall if all
+/// args match. Matching calls fail with EAGAIN.
+pub fn profile(syscall: &str, args: Vec<LinuxSeccompArg>) -> Result<LinuxSeccomp> {
+    let mut builder = LinuxSyscallBuilder::default()
+        .names(vec![syscall.to_string()])
+        .action(LinuxSeccompAction::ScmpActErrno)
+        .errno_ret(libc::EAGAIN as u32);
+    if !args.is_empty() {
+        builder = builder.args(args);
+    }
+
+    let seccomp = LinuxSeccompBuilder::default()
+        .default_action(LinuxSeccompAction::ScmpActAllow)
+        .architectures(vec![Arch::ScmpArchNative])
+        .syscalls(vec![builder.build()?])
+        .build()?;
+    Ok(seccomp)
+}
+
+pub fn arg_eq(index: usize, value: u64) -> Result<LinuxSeccompArg> {
+    arg(index, LinuxSeccompOperator::ScmpCmpEq, value, None)
+}
+
+pub fn arg(
+    index: usize,
+    op: LinuxSeccompOperator,
+    value: u64,
+    value_two: Option<u64>,
+) -> Result<LinuxSeccompArg> {
+    let mut builder = LinuxSeccompArgBuilder::default()
+        .index(index)
+        .value(value)
+        .op(op);
+    if let Some(value_two) = value_two {
+        builder = builder.value_two(value_two);
+    }
+
+    Ok(builder.build()?)
+}
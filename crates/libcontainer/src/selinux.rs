@@ -0,0 +1,283 @@
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use std::{
+    ffi::CString,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::utils;
+
+const ENFORCE_PATH: &str = "/sys/fs/selinux/enforce";
+const ATTR_DIR: &str = "/proc/self/attr";
+const XATTR_NAME: &str = "security.selinux";
+
+/// Paths which must never be relabeled, as doing so would break the host
+const PROTECTED_PATHS: &[&str] = &[
+    "/",
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/etc/passwd",
+    "/etc/pki",
+    "/etc/shadow",
+    "/home",
+    "/lib",
+    "/lib64",
+    "/media",
+    "/opt",
+    "/proc",
+    "/root",
+    "/run",
+    "/sbin",
+    "/srv",
+    "/sys",
+    "/tmp",
+    "/usr",
+    "/var",
+    "/var/lib",
+    "/var/log",
+];
+
+/// Checks if SELinux has been enabled on the system.
+pub fn is_enabled() -> Result<bool> {
+    // selinuxfs is only mounted if SELinux is enabled
+    Ok(Path::new(ENFORCE_PATH).exists())
+}
+
+/// Sets the label the container process will be running with after the next exec.
+pub fn set_exec_label(label: &str) -> Result<()> {
+    AttrDir::proc().set_attr("exec", label)
+}
+
+/// Sets the label of the kernel keyrings that are created by this process.
+pub fn set_keycreate_label(label: &str) -> Result<()> {
+    AttrDir::proc().set_attr("keycreate", label)
+}
+
+/// Directory containing the LSM attributes of a process
+struct AttrDir {
+    path: PathBuf,
+    // Writing a label to a file which is not in procfs, e.g. because procfs has
+    // been overmounted, would silently leave the process unconfined.
+    verify_procfs: bool,
+}
+
+impl AttrDir {
+    fn proc() -> Self {
+        Self {
+            path: PathBuf::from(ATTR_DIR),
+            verify_procfs: true,
+        }
+    }
+
+    #[cfg(test)]
+    fn from_test_dir(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            verify_procfs: false,
+        }
+    }
+
+    fn set_attr(&self, attr: &str, label: &str) -> Result<()> {
+        if label.is_empty() {
+            return Ok(());
+        }
+
+        // Try the module specific subdirectory. This is the recommended way to configure
+        // LSMs since Linux 5.1.
+        if self
+            .write_attr(&self.path.join("selinux").join(attr), label)
+            .is_ok()
+        {
+            return Ok(());
+        }
+
+        // try the legacy interface
+        self.write_attr(&self.path.join(attr), label)
+    }
+
+    fn write_attr(&self, path: &Path, label: &str) -> Result<()> {
+        if self.verify_procfs {
+            utils::ensure_procfs(path)?;
+        }
+        utils::write_file(path, label)
+    }
+}
+
+/// Recursively relabels the content of path so that it can be accessed by a
+/// container running with the given mount label. A shared label (the `z` mount
+/// option) allows all containers to access the content, a private label (the
+/// `Z` mount option) only the container with this label.
+pub fn relabel(path: &Path, label: &str, shared: bool) -> Result<()> {
+    if label.is_empty() {
+        return Ok(());
+    }
+
+    let path = fs::canonicalize(path)
+        .with_context(|| format!("failed to canonicalize {}", path.display()))?;
+    if is_protected(&path) {
+        bail!("relabeling of {} is not allowed", path.display());
+    }
+
+    let label = if shared {
+        shared_label(label)
+    } else {
+        label.to_owned()
+    };
+
+    relabel_recursive(&path, &label)
+}
+
+fn relabel_recursive(path: &Path, label: &str) -> Result<()> {
+    set_file_label(path, label)?;
+
+    // symlinks are relabeled themselves, but never followed
+    let metadata = fs::symlink_metadata(path)
+        .with_context(|| format!("failed to get metadata of {}", path.display()))?;
+    if metadata.is_dir() {
+        for entry in
+            fs::read_dir(path).with_context(|| format!("failed to read {}", path.display()))?
+        {
+            relabel_recursive(&entry?.path(), label)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn set_file_label(path: &Path, label: &str) -> Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let c_name = CString::new(XATTR_NAME)?;
+    // SAFETY: all pointers are valid for the duration of the call
+    let res = unsafe {
+        libc::lsetxattr(
+            c_path.as_ptr(),
+            c_name.as_ptr(),
+            label.as_ptr() as *const libc::c_void,
+            label.len(),
+            0,
+        )
+    };
+    Errno::result(res)
+        .with_context(|| format!("failed to set label {} on {}", label, path.display()))?;
+    Ok(())
+}
+
+/// Returns the label with the MCS level replaced by s0, so that the label is
+/// not restricted to the categories of a single container.
+fn shared_label(label: &str) -> String {
+    let parts: Vec<&str> = label.splitn(4, ':').collect();
+    match parts.as_slice() {
+        [user, role, typ, _] => format!("{}:{}:{}:s0", user, role, typ),
+        _ => label.to_owned(),
+    }
+}
+
+fn is_protected(path: &Path) -> bool {
+    if PROTECTED_PATHS.iter().any(|p| Path::new(p) == path) {
+        return true;
+    }
+
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home) == path)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn test_set_attr_module_specific() -> Result<()> {
+        let tmp = create_temp_dir("test_set_attr_module_specific")?;
+        fs::create_dir(tmp.join("selinux"))?;
+        fs::write(tmp.join("selinux").join("exec"), "")?;
+        fs::write(tmp.join("exec"), "")?;
+
+        AttrDir::from_test_dir(&tmp).set_attr("exec", "system_u:system_r:container_t:s0:c1,c2")?;
+        assert_eq!(
+            fs::read_to_string(tmp.join("selinux").join("exec"))?,
+            "system_u:system_r:container_t:s0:c1,c2"
+        );
+        assert_eq!(fs::read_to_string(tmp.join("exec"))?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_attr_legacy() -> Result<()> {
+        let tmp = create_temp_dir("test_set_attr_legacy")?;
+        fs::write(tmp.join("keycreate"), "")?;
+
+        AttrDir::from_test_dir(&tmp).set_attr("keycreate", "system_u:system_r:container_t:s0")?;
+        assert_eq!(
+            fs::read_to_string(tmp.join("keycreate"))?,
+            "system_u:system_r:container_t:s0"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_attr_empty_label() -> Result<()> {
+        let tmp = create_temp_dir("test_set_attr_empty_label")?;
+        // nothing is written, so the missing attribute file is not an error
+        AttrDir::from_test_dir(&tmp).set_attr("exec", "")?;
+        assert!(!tmp.join("exec").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_proc_attr_dir() {
+        let attr_dir = AttrDir::proc();
+        assert_eq!(attr_dir.path, Path::new(ATTR_DIR));
+        assert!(attr_dir.verify_procfs);
+    }
+
+    #[test]
+    fn test_set_attr_not_procfs() -> Result<()> {
+        let tmp = create_temp_dir("test_set_attr_not_procfs")?;
+        fs::write(tmp.join("exec"), "")?;
+
+        let attr_dir = AttrDir {
+            path: tmp.to_path_buf(),
+            verify_procfs: true,
+        };
+        assert!(attr_dir
+            .set_attr("exec", "system_u:system_r:container_t:s0")
+            .is_err());
+        assert_eq!(fs::read_to_string(tmp.join("exec"))?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_label() {
+        assert_eq!(
+            shared_label("system_u:object_r:container_file_t:s0:c1,c2"),
+            "system_u:object_r:container_file_t:s0"
+        );
+        assert_eq!(
+            shared_label("system_u:object_r:container_file_t:s0"),
+            "system_u:object_r:container_file_t:s0"
+        );
+        assert_eq!(shared_label("invalid"), "invalid");
+    }
+
+    #[test]
+    fn test_relabel_protected_path() {
+        assert!(relabel(
+            Path::new("/usr"),
+            "system_u:object_r:container_file_t:s0",
+            true
+        )
+        .is_err());
+        assert!(relabel(
+            Path::new("/"),
+            "system_u:object_r:container_file_t:s0",
+            false
+        )
+        .is_err());
+    }
+}
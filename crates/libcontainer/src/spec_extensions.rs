@@ -0,0 +1,67 @@
+//! Parts of the runtime spec which are newer than the runtime spec types. They
+//! are read from the raw JSON of config.json, before it is parsed as [`Spec`].
+use anyhow::{Context, Result};
+use oci_spec::runtime::Spec;
+use serde_json::Value;
+use std::{fs, path::Path};
+
+use crate::{scheduling::Scheduling, time_namespace::TimeNamespace};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpecExtensions {
+    /// Time namespace, which is not part of the namespaces of the spec
+    pub time_namespace: Option<TimeNamespace>,
+    /// Scheduling attributes of the container process
+    pub scheduling: Scheduling,
+}
+
+/// Loads the runtime spec from path together with the extensions. Extensions
+/// which would make the spec invalid for the runtime spec types are removed
+/// from the spec.
+pub fn load_spec(path: &Path) -> Result<(Spec, SpecExtensions)> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut value: Value = serde_json::from_str(&content)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    let extensions = SpecExtensions {
+        time_namespace: TimeNamespace::take_from(&mut value)?,
+        scheduling: Scheduling::from_spec(&value)?,
+    };
+    let spec = serde_json::from_value(value)
+        .with_context(|| format!("failed to parse {} as runtime spec", path.display()))?;
+    Ok((spec, extensions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduling::SchedPolicy;
+    use serde_json::json;
+
+    #[test]
+    fn test_load_spec() -> Result<()> {
+        let tmp = crate::utils::create_temp_dir("test_load_spec_extensions")?;
+        let path = tmp.join("config.json");
+        let config = json!({
+            "ociVersion": "1.0.2-dev",
+            "root": { "path": "rootfs" },
+            "process": {
+                "cwd": "/",
+                "args": ["sh"],
+                "user": { "uid": 0, "gid": 0 },
+                "scheduler": { "policy": "SCHED_BATCH", "nice": 10 },
+            },
+            "linux": { "namespaces": [{ "type": "time" }] },
+        });
+        fs::write(&path, config.to_string())?;
+
+        let (spec, extensions) = load_spec(&path)?;
+        assert!(spec.process().is_some());
+        assert!(extensions.time_namespace.is_some());
+        let scheduler = extensions.scheduling.scheduler.unwrap();
+        assert_eq!(scheduler.policy, SchedPolicy::Batch);
+        assert_eq!(scheduler.nice, Some(10));
+        assert_eq!(extensions.scheduling.io_priority, None);
+        Ok(())
+    }
+}
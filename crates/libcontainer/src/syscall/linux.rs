@@ -1,12 +1,13 @@
 //! Implements Command trait for Linux systems
-use std::ffi::{CStr, OsStr};
+use std::ffi::{CStr, CString, OsStr};
 use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::symlink;
+use std::os::unix::io::RawFd;
 use std::sync::Arc;
 use std::{any::Any, mem, path::Path, ptr};
 
-use anyhow::{bail, Result};
-use caps::{errors::CapsError, CapSet, Capability, CapsHashSet};
+use anyhow::{bail, Context, Result};
+use caps::{errors::CapsError, CapSet, CapsHashSet};
 use libc::{c_char, uid_t};
 use nix::{
     errno::Errno,
@@ -18,10 +19,20 @@ use nix::{
     unistd::{chown, fchdir, pivot_root, setgroups, sethostname, Gid, Uid},
 };
 
-use oci_spec::runtime::LinuxRlimit;
+use oci_spec::runtime::{LinuxPersonality, LinuxPersonalityDomain, LinuxRlimit};
 
-use super::Syscall;
+use super::{
+    mount_api::{self, MountAttr},
+    Syscall,
+};
 use crate::capabilities;
+use crate::scheduling::{IoPriority, SchedAttr, Scheduler};
+
+// execution domains of personality(2), see include/uapi/linux/personality.h
+const PER_LINUX: libc::c_ulong = 0x0000;
+const PER_LINUX32: libc::c_ulong = 0x0008;
+// see include/uapi/linux/ioprio.h
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
 
 /// Empty structure to implement Command trait for
 #[derive(Clone)]
@@ -124,22 +135,14 @@ impl Syscall for LinuxSyscall {
             // caps::set cannot set capabilities in bounding set,
             // so we do it differently
             CapSet::Bounding => {
-                // get all capabilities
-                let all = caps::all();
+                // get all capabilities known to the kernel
+                let all = capabilities::supported();
                 // the difference will give capabilities
                 // which are to be unset
                 // for each such =, drop that capability
                 // after this, only those which are to be set will remain set
                 for c in all.difference(value) {
-                    match c {
-                        Capability::CAP_PERFMON
-                        | Capability::CAP_CHECKPOINT_RESTORE
-                        | Capability::CAP_BPF => {
-                            log::warn!("{:?} is not supported.", c);
-                            continue;
-                        }
-                        _ => caps::drop(None, CapSet::Bounding, *c)?,
-                    }
+                    caps::drop(None, CapSet::Bounding, *c)?;
                 }
                 Ok(())
             }
@@ -230,17 +233,12 @@ impl Syscall for LinuxSyscall {
     }
 
     fn mknod(&self, path: &Path, kind: SFlag, perm: Mode, dev: u64) -> Result<()> {
-        match mknod(path, kind, perm, dev) {
-            Ok(_) => Ok(()),
-            Err(e) => bail!("Failed to mknod {:?}", e),
-        }
+        // the errno is kept, so that callers can fall back to a bind mount on EPERM
+        mknod(path, kind, perm, dev).with_context(|| format!("failed to mknod {:?}", path))
     }
 
     fn chown(&self, path: &Path, owner: Option<Uid>, group: Option<Gid>) -> Result<()> {
-        match chown(path, owner, group) {
-            Ok(_) => Ok(()),
-            Err(e) => bail!("Failed to chown {:?}", e),
-        }
+        chown(path, owner, group).with_context(|| format!("failed to chown {:?}", path))
     }
 
     fn set_groups(&self, groups: &[Gid]) -> Result<()> {
@@ -249,4 +247,181 @@ impl Syscall for LinuxSyscall {
             Err(e) => bail!("Failed to setgroups {:?}", e),
         }
     }
+
+    fn personality(&self, personality: &LinuxPersonality) -> Result<()> {
+        let persona = match personality.domain() {
+            LinuxPersonalityDomain::PerLinux => PER_LINUX,
+            LinuxPersonalityDomain::PerLinux32 => PER_LINUX32,
+        };
+        // the flags of the runtime spec have no defined values yet
+        if personality.flags().iter().flatten().next().is_some() {
+            log::warn!("personality flags are not supported and will be ignored");
+        }
+
+        let res = unsafe { libc::personality(persona) };
+        if let Err(e) = Errno::result(res) {
+            bail!("Failed to set personality {:?}. {:?}", personality, e)
+        }
+        Ok(())
+    }
+
+    fn sched_setattr(&self, scheduler: &Scheduler) -> Result<()> {
+        let mut attr = SchedAttr::from(scheduler);
+        // pid 0 is the calling thread
+        let res = unsafe { libc::syscall(libc::SYS_sched_setattr, 0, &mut attr, 0) };
+        if let Err(e) = Errno::result(res) {
+            bail!("Failed to set scheduler {:?}. {:?}", scheduler, e)
+        }
+        Ok(())
+    }
+
+    fn ioprio_set(&self, io_priority: &IoPriority) -> Result<()> {
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                IOPRIO_WHO_PROCESS,
+                0,
+                io_priority.value(),
+            )
+        };
+        if let Err(e) = Errno::result(res) {
+            bail!("Failed to set I/O priority {:?}. {:?}", io_priority, e)
+        }
+        Ok(())
+    }
+
+    fn open_tree(&self, dirfd: RawFd, path: Option<&Path>, flags: u32) -> Result<RawFd> {
+        let path = path_to_cstring(path)?;
+        let res = unsafe { libc::syscall(libc::SYS_open_tree, dirfd, path.as_ptr(), flags) };
+        Ok(Errno::result(res)? as RawFd)
+    }
+
+    fn mount_setattr(
+        &self,
+        dirfd: RawFd,
+        path: Option<&Path>,
+        flags: u32,
+        attr: &MountAttr,
+    ) -> Result<()> {
+        let path = path_to_cstring(path)?;
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_mount_setattr,
+                dirfd,
+                path.as_ptr(),
+                flags,
+                attr as *const MountAttr,
+                mem::size_of::<MountAttr>(),
+            )
+        };
+        Errno::result(res)?;
+        Ok(())
+    }
+
+    fn move_mount(
+        &self,
+        from_dirfd: RawFd,
+        from_path: Option<&Path>,
+        to_dirfd: RawFd,
+        to_path: Option<&Path>,
+        flags: u32,
+    ) -> Result<()> {
+        let from_path = path_to_cstring(from_path)?;
+        let to_path = path_to_cstring(to_path)?;
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_move_mount,
+                from_dirfd,
+                from_path.as_ptr(),
+                to_dirfd,
+                to_path.as_ptr(),
+                flags,
+            )
+        };
+        Errno::result(res)?;
+        Ok(())
+    }
+
+    fn fsopen(&self, fstype: &str, flags: u32) -> Result<RawFd> {
+        let fstype = CString::new(fstype)?;
+        let res = unsafe { libc::syscall(libc::SYS_fsopen, fstype.as_ptr(), flags) };
+        Ok(Errno::result(res)? as RawFd)
+    }
+
+    fn fsconfig(&self, fd: RawFd, cmd: u32, key: Option<&str>, value: Option<&str>) -> Result<()> {
+        let key = key.map(CString::new).transpose()?;
+        let value = value.map(CString::new).transpose()?;
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_fsconfig,
+                fd,
+                cmd,
+                key.as_ref().map_or(ptr::null(), |k| k.as_ptr()),
+                value.as_ref().map_or(ptr::null(), |v| v.as_ptr()),
+                0,
+            )
+        };
+        if let Err(errno) = Errno::result(res) {
+            // the filesystem explains in the fs context log, why an option
+            // was rejected
+            let messages = mount_api::read_fs_log(fd);
+            if messages.is_empty() {
+                return Err(errno.into());
+            }
+            return Err(anyhow::Error::from(errno).context(messages.join("; ")));
+        }
+        Ok(())
+    }
+
+    fn fsmount(&self, fd: RawFd, flags: u32, attr_flags: u64) -> Result<RawFd> {
+        let res = unsafe { libc::syscall(libc::SYS_fsmount, fd, flags, attr_flags as u32) };
+        Ok(Errno::result(res)? as RawFd)
+    }
+}
+
+/// The path arguments of the mount API are empty, if the fd is used instead
+fn path_to_cstring(path: Option<&Path>) -> Result<CString> {
+    let path = path.map(|p| p.as_os_str().as_bytes()).unwrap_or_default();
+    Ok(CString::new(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils;
+    use oci_spec::runtime::LinuxPersonalityBuilder;
+    use serial_test::serial;
+
+    // passing 0xffffffff queries the persona without changing it
+    const PER_QUERY: libc::c_ulong = 0xffffffff;
+    const PER_MASK: libc::c_int = 0x00ff;
+
+    #[test]
+    #[serial]
+    fn test_personality() -> Result<()> {
+        test_utils::test_in_child_process(|| {
+            let syscall = LinuxSyscall;
+            let linux32 = LinuxPersonalityBuilder::default()
+                .domain(LinuxPersonalityDomain::PerLinux32)
+                .build()?;
+            syscall.personality(&linux32)?;
+            let persona = unsafe { libc::personality(PER_QUERY) };
+            if persona & PER_MASK != PER_LINUX32 as libc::c_int {
+                bail!("expected PER_LINUX32, got persona {:#x}", persona);
+            }
+
+            // flags are ignored, the domain is still set
+            let linux = LinuxPersonalityBuilder::default()
+                .domain(LinuxPersonalityDomain::PerLinux)
+                .flags(vec!["ADDR_NO_RANDOMIZE".to_string()])
+                .build()?;
+            syscall.personality(&linux)?;
+            let persona = unsafe { libc::personality(PER_QUERY) };
+            if persona & PER_MASK != PER_LINUX as libc::c_int {
+                bail!("expected PER_LINUX, got persona {:#x}", persona);
+            }
+
+            Ok(())
+        })
+    }
 }
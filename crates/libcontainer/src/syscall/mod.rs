@@ -3,6 +3,8 @@
 //! to call syscalls required for container management
 
 pub mod linux;
+pub mod mount_api;
+pub mod openat2;
 #[allow(clippy::module_inception)]
 pub mod syscall;
 pub mod test;
@@ -0,0 +1,203 @@
+//! Types and flags of the new mount API (fsopen, fsconfig, fsmount,
+//! open_tree, move_mount and mount_setattr), which are not defined by libc yet.
+//! See include/uapi/linux/mount.h
+use nix::{errno::Errno, mount::MsFlags, unistd};
+use std::{mem, os::unix::io::RawFd};
+
+pub const FSOPEN_CLOEXEC: u32 = 0x0000_0001;
+pub const FSMOUNT_CLOEXEC: u32 = 0x0000_0001;
+
+/// Commands of fsconfig(2)
+pub const FSCONFIG_SET_FLAG: u32 = 0;
+pub const FSCONFIG_SET_STRING: u32 = 1;
+pub const FSCONFIG_CMD_CREATE: u32 = 6;
+
+/// Clones the mount instead of opening it, see open_tree(2)
+pub const OPEN_TREE_CLONE: u32 = 1;
+pub const OPEN_TREE_CLOEXEC: u32 = libc::O_CLOEXEC as u32;
+/// Applies to the whole mount tree below the path
+pub const AT_RECURSIVE: u32 = 0x8000;
+pub const AT_EMPTY_PATH: u32 = libc::AT_EMPTY_PATH as u32;
+pub const AT_SYMLINK_NOFOLLOW: u32 = libc::AT_SYMLINK_NOFOLLOW as u32;
+/// The source of move_mount(2) is the mount referred to by the fd
+pub const MOVE_MOUNT_F_EMPTY_PATH: u32 = 0x0000_0004;
+/// The destination of move_mount(2) is the file referred to by the fd
+pub const MOVE_MOUNT_T_EMPTY_PATH: u32 = 0x0000_0040;
+
+pub const MOUNT_ATTR_RDONLY: u64 = 0x0000_0001;
+pub const MOUNT_ATTR_NOSUID: u64 = 0x0000_0002;
+pub const MOUNT_ATTR_NODEV: u64 = 0x0000_0004;
+pub const MOUNT_ATTR_NOEXEC: u64 = 0x0000_0008;
+/// The default atime mode, which is set by clearing MOUNT_ATTR__ATIME
+pub const MOUNT_ATTR_RELATIME: u64 = 0x0000_0000;
+pub const MOUNT_ATTR_NOATIME: u64 = 0x0000_0010;
+pub const MOUNT_ATTR_STRICTATIME: u64 = 0x0000_0020;
+pub const MOUNT_ATTR_NODIRATIME: u64 = 0x0000_0080;
+/// Mask of the atime attributes, of which only one can be set at a time
+pub const MOUNT_ATTR__ATIME: u64 = 0x0000_0070;
+
+/// Maps the uids and gids of the mount with the user namespace of userns_fd
+pub const MOUNT_ATTR_IDMAP: u64 = 0x0010_0000;
+
+/// Changes of the mount attributes, as expected by mount_setattr(2)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MountAttr {
+    pub attr_set: u64,
+    pub attr_clr: u64,
+    pub propagation: u64,
+    pub userns_fd: u64,
+}
+
+/// Returns true if the kernel supports mount_setattr(2), which is required
+/// for id mapped mounts. A change without any attributes is accepted by the
+/// kernel without looking at the fd.
+pub fn mount_setattr_supported() -> bool {
+    let attr = MountAttr::default();
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            -1,
+            b"\0".as_ptr(),
+            AT_EMPTY_PATH,
+            &attr as *const MountAttr,
+            mem::size_of::<MountAttr>(),
+        )
+    };
+    Errno::result(res).is_ok()
+}
+
+/// Returns true if the kernel supports creating mounts with fsopen(2). A
+/// filesystem without a name is rejected with ENODEV by supporting kernels.
+pub fn fsopen_supported() -> bool {
+    let res = unsafe { libc::syscall(libc::SYS_fsopen, b"\0".as_ptr(), FSOPEN_CLOEXEC) };
+    match Errno::result(res) {
+        Ok(fd) => {
+            let _ = unistd::close(fd as RawFd);
+            true
+        }
+        Err(errno) => errno == Errno::ENODEV,
+    }
+}
+
+/// Converts the mount flags to the MOUNT_ATTR_* flags of fsmount(2) and
+/// mount_setattr(2). Other flags don't have an equivalent mount attribute.
+pub fn attr_flags(flags: MsFlags) -> u64 {
+    [
+        (MsFlags::MS_RDONLY, MOUNT_ATTR_RDONLY),
+        (MsFlags::MS_NOSUID, MOUNT_ATTR_NOSUID),
+        (MsFlags::MS_NODEV, MOUNT_ATTR_NODEV),
+        (MsFlags::MS_NOEXEC, MOUNT_ATTR_NOEXEC),
+        (MsFlags::MS_NOATIME, MOUNT_ATTR_NOATIME),
+        (MsFlags::MS_STRICTATIME, MOUNT_ATTR_STRICTATIME),
+        (MsFlags::MS_NODIRATIME, MOUNT_ATTR_NODIRATIME),
+    ]
+    .iter()
+    .filter(|(flag, _)| flags.contains(*flag))
+    .fold(0, |attr, (_, attr_flag)| attr | attr_flag)
+}
+
+/// Converts the mount flags to the changes of mount_setattr(2). The atime
+/// attributes are a mode, the previous mode is cleared to change it.
+pub fn mount_attr(flags: MsFlags) -> MountAttr {
+    let attr_set = attr_flags(flags);
+    let attr_clr = if attr_set & MOUNT_ATTR__ATIME != 0 || flags.contains(MsFlags::MS_RELATIME) {
+        MOUNT_ATTR__ATIME
+    } else {
+        0
+    };
+    MountAttr {
+        attr_set,
+        attr_clr,
+        ..Default::default()
+    }
+}
+
+/// Returns the flags of the superblock as parameters of fsconfig(2). They
+/// are no mount attributes, therefore they can't be applied to bind mounts.
+pub fn sb_flag_params(flags: MsFlags) -> Vec<&'static str> {
+    [
+        (MsFlags::MS_SYNCHRONOUS, "sync"),
+        (MsFlags::MS_DIRSYNC, "dirsync"),
+        (MsFlags::MS_LAZYTIME, "lazytime"),
+    ]
+    .iter()
+    .filter(|(flag, _)| flags.contains(*flag))
+    .map(|(_, param)| *param)
+    .collect()
+}
+
+/// Reads the messages, which the filesystem logged to the fs context while
+/// the options were applied. Each message is prefixed with its severity, e.g.
+/// "e tmpfs: Bad value for 'size'".
+pub fn read_fs_log(fd: RawFd) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut buf = [0u8; 4096];
+    // every read returns a single message, until the log is empty
+    while let Ok(len) = unistd::read(fd, &mut buf) {
+        if len == 0 {
+            break;
+        }
+        messages.push(String::from_utf8_lossy(&buf[..len]).trim_end().to_owned());
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attr_flags() {
+        assert_eq!(attr_flags(MsFlags::empty()), 0);
+        assert_eq!(
+            attr_flags(
+                MsFlags::MS_RDONLY
+                    | MsFlags::MS_NOSUID
+                    | MsFlags::MS_NODEV
+                    | MsFlags::MS_NOEXEC
+                    | MsFlags::MS_BIND
+                    | MsFlags::MS_REC
+            ),
+            MOUNT_ATTR_RDONLY | MOUNT_ATTR_NOSUID | MOUNT_ATTR_NODEV | MOUNT_ATTR_NOEXEC
+        );
+        assert_eq!(
+            attr_flags(MsFlags::MS_NOATIME | MsFlags::MS_NODIRATIME),
+            MOUNT_ATTR_NOATIME | MOUNT_ATTR_NODIRATIME
+        );
+    }
+
+    #[test]
+    fn test_mount_attr() {
+        assert_eq!(mount_attr(MsFlags::MS_BIND), MountAttr::default());
+        assert_eq!(
+            mount_attr(MsFlags::MS_RDONLY | MsFlags::MS_NODIRATIME),
+            MountAttr {
+                attr_set: MOUNT_ATTR_RDONLY | MOUNT_ATTR_NODIRATIME,
+                ..Default::default()
+            }
+        );
+        for (flag, attr) in [
+            (MsFlags::MS_NOATIME, MOUNT_ATTR_NOATIME),
+            (MsFlags::MS_STRICTATIME, MOUNT_ATTR_STRICTATIME),
+            (MsFlags::MS_RELATIME, MOUNT_ATTR_RELATIME),
+        ] {
+            assert_eq!(
+                mount_attr(flag),
+                MountAttr {
+                    attr_set: attr,
+                    attr_clr: MOUNT_ATTR__ATIME,
+                    ..Default::default()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_sb_flag_params() {
+        assert!(sb_flag_params(MsFlags::MS_RDONLY | MsFlags::MS_BIND).is_empty());
+        assert_eq!(sb_flag_params(MsFlags::MS_SYNCHRONOUS), vec!["sync"]);
+        assert_eq!(sb_flag_params(MsFlags::MS_DIRSYNC), vec!["dirsync"]);
+        assert_eq!(sb_flag_params(MsFlags::MS_LAZYTIME), vec!["lazytime"]);
+    }
+}
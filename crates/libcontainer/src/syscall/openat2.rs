@@ -0,0 +1,191 @@
+//! Types and flags of openat2(2), which resolves paths in the scope of a
+//! directory, and is not defined by libc yet. See include/uapi/linux/openat2.h
+use anyhow::{bail, Context, Result};
+use nix::{errno::Errno, fcntl::OFlag, unistd, NixPath};
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fs, mem,
+    os::unix::io::RawFd,
+    path::{Component, Path, PathBuf},
+};
+
+/// Doesn't cross mount points
+pub const RESOLVE_NO_XDEV: u64 = 0x01;
+/// Doesn't follow magic links like /proc/self/fd/*
+pub const RESOLVE_NO_MAGICLINKS: u64 = 0x02;
+/// Fails with ELOOP on any symlink
+pub const RESOLVE_NO_SYMLINKS: u64 = 0x04;
+/// Fails if the path leaves the directory
+pub const RESOLVE_BENEATH: u64 = 0x08;
+/// Treats the directory as root of the resolution, so that neither ".." nor
+/// absolute symlinks leave it
+pub const RESOLVE_IN_ROOT: u64 = 0x10;
+
+/// How to open the path, as expected by openat2(2)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpenHow {
+    pub flags: u64,
+    pub mode: u64,
+    pub resolve: u64,
+}
+
+/// Opens path relative to dirfd and resolves it as restricted by resolve.
+/// Fails with ENOSYS on kernels before linux 5.6.
+pub fn openat2(dirfd: RawFd, path: &Path, flags: OFlag, resolve: u64) -> nix::Result<RawFd> {
+    let how = OpenHow {
+        flags: (flags | OFlag::O_CLOEXEC).bits() as u64,
+        mode: 0,
+        resolve,
+    };
+    let res = path.with_nix_path(|path| unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            dirfd,
+            path.as_ptr(),
+            &how as *const OpenHow,
+            mem::size_of::<OpenHow>(),
+        )
+    })?;
+    Errno::result(res).map(|fd| fd as RawFd)
+}
+
+/// Returns the path of the fd in procfs, which can be used in place of the
+/// path the fd was opened with, e.g. as target of mount(2).
+pub fn fd_path(fd: RawFd) -> PathBuf {
+    PathBuf::from(format!("/proc/self/fd/{}", fd))
+}
+
+/// Opens path below the root directory of the process as O_PATH fd without
+/// following any symlink, so that the path can't be redirected by a symlink
+/// the container created. Without openat2 the final component is opened with
+/// O_NOFOLLOW and the resolved path is compared with the requested one.
+pub fn open_no_symlinks(path: &Path) -> Result<RawFd> {
+    let root = nix::fcntl::open(
+        "/",
+        OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+        nix::sys::stat::Mode::empty(),
+    )?;
+    let relative = path.strip_prefix("/").unwrap_or(path);
+    let res = openat2(
+        root,
+        relative,
+        OFlag::O_PATH,
+        RESOLVE_IN_ROOT | RESOLVE_NO_SYMLINKS | RESOLVE_NO_MAGICLINKS,
+    );
+    let _ = unistd::close(root);
+
+    match res {
+        Ok(fd) => Ok(fd),
+        Err(Errno::ENOSYS) => {
+            let fd = nix::fcntl::open(
+                path,
+                OFlag::O_PATH | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC,
+                nix::sys::stat::Mode::empty(),
+            )?;
+            let is_symlink = nix::sys::stat::fstat(fd)
+                .map(|stat| stat.st_mode & libc::S_IFMT == libc::S_IFLNK)
+                .unwrap_or(false);
+            match fs::read_link(fd_path(fd)) {
+                Ok(resolved) if !is_symlink && resolved == Path::new("/").join(relative) => Ok(fd),
+                Ok(resolved) => {
+                    let _ = unistd::close(fd);
+                    bail!("{:?} resolves to {:?} through a symlink", path, resolved)
+                }
+                Err(err) => {
+                    let _ = unistd::close(fd);
+                    Err(err.into())
+                }
+            }
+        }
+        Err(errno) => Err(errno.into()),
+    }
+}
+
+/// Resolves unsafe_path component by component in the scope of the root fd,
+/// as if it was the root directory, and returns the resolved path relative to
+/// it. Components which don't exist are appended as they are.
+/// Fails with ENOSYS on kernels before linux 5.6.
+pub fn resolve_in_root(root_fd: RawFd, unsafe_path: &Path) -> Result<PathBuf> {
+    let root = fs::read_link(fd_path(root_fd))?;
+    let mut resolved = PathBuf::new();
+    let mut remaining: VecDeque<OsString> = VecDeque::new();
+    push_components(&mut remaining, unsafe_path);
+    let mut links = 0;
+
+    while let Some(part) = remaining.pop_front() {
+        if part == ".." {
+            resolved.pop();
+            continue;
+        }
+
+        let candidate = resolved.join(&part);
+        match openat2(root_fd, &candidate, OFlag::O_PATH, RESOLVE_IN_ROOT) {
+            Ok(fd) => {
+                let real = fs::read_link(fd_path(fd));
+                let _ = unistd::close(fd);
+                let real = real?;
+                resolved = real
+                    .strip_prefix(&root)
+                    .with_context(|| format!("{:?} resolved outside of {:?}", candidate, root))?
+                    .to_path_buf();
+            }
+            // the component doesn't exist or is a dangling symlink
+            Err(Errno::ENOENT) | Err(Errno::ENOTDIR) => {
+                let fd = match openat2(
+                    root_fd,
+                    &candidate,
+                    OFlag::O_PATH | OFlag::O_NOFOLLOW,
+                    RESOLVE_IN_ROOT,
+                ) {
+                    Ok(fd) => fd,
+                    Err(Errno::ENOENT) | Err(Errno::ENOTDIR) => {
+                        resolved = candidate;
+                        continue;
+                    }
+                    Err(errno) => return Err(errno.into()),
+                };
+                let target = nix::fcntl::readlinkat(fd, "");
+                let _ = unistd::close(fd);
+                let target = match target {
+                    Ok(target) => PathBuf::from(target),
+                    // not a symlink, it was created in the meantime
+                    Err(Errno::EINVAL) => {
+                        resolved = candidate;
+                        continue;
+                    }
+                    Err(errno) => return Err(errno.into()),
+                };
+
+                links += 1;
+                if links > 255 {
+                    bail!("dereference too many symlinks, may be infinite loop");
+                }
+                if target.is_absolute() {
+                    resolved = PathBuf::new();
+                }
+                let mut expanded = VecDeque::new();
+                push_components(&mut expanded, &target);
+                expanded.append(&mut remaining);
+                remaining = expanded;
+            }
+            Err(errno) => {
+                return Err(anyhow::Error::from(errno)
+                    .context(format!("failed to resolve {:?}", candidate)))
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+fn push_components(components: &mut VecDeque<OsString>, path: &Path) {
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => components.push_back(part.to_owned()),
+            Component::ParentDir => components.push_back(OsString::from("..")),
+            _ => {}
+        }
+    }
+}
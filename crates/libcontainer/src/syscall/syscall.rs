@@ -1,7 +1,7 @@
 //! An interface trait so that rest of Youki can call
 //! necessary functions without having to worry about their
 //! implementation details
-use std::{any::Any, ffi::OsStr, path::Path, sync::Arc};
+use std::{any::Any, ffi::OsStr, os::unix::io::RawFd, path::Path, sync::Arc};
 
 use anyhow::Result;
 use caps::{errors::CapsError, CapSet, CapsHashSet};
@@ -12,9 +12,10 @@ use nix::{
     unistd::{Gid, Uid},
 };
 
-use oci_spec::runtime::LinuxRlimit;
+use oci_spec::runtime::{LinuxPersonality, LinuxRlimit};
 
-use crate::syscall::{linux::LinuxSyscall, test::TestHelperSyscall};
+use crate::scheduling::{IoPriority, Scheduler};
+use crate::syscall::{linux::LinuxSyscall, mount_api::MountAttr, test::TestHelperSyscall};
 
 /// This specifies various kernel/other functionalities required for
 /// container management
@@ -41,6 +42,28 @@ pub trait Syscall {
     fn mknod(&self, path: &Path, kind: SFlag, perm: Mode, dev: u64) -> Result<()>;
     fn chown(&self, path: &Path, owner: Option<Uid>, group: Option<Gid>) -> Result<()>;
     fn set_groups(&self, groups: &[Gid]) -> Result<()>;
+    fn personality(&self, personality: &LinuxPersonality) -> Result<()>;
+    fn sched_setattr(&self, scheduler: &Scheduler) -> Result<()>;
+    fn ioprio_set(&self, io_priority: &IoPriority) -> Result<()>;
+    fn open_tree(&self, dirfd: RawFd, path: Option<&Path>, flags: u32) -> Result<RawFd>;
+    fn mount_setattr(
+        &self,
+        dirfd: RawFd,
+        path: Option<&Path>,
+        flags: u32,
+        attr: &MountAttr,
+    ) -> Result<()>;
+    fn move_mount(
+        &self,
+        from_dirfd: RawFd,
+        from_path: Option<&Path>,
+        to_dirfd: RawFd,
+        to_path: Option<&Path>,
+        flags: u32,
+    ) -> Result<()>;
+    fn fsopen(&self, fstype: &str, flags: u32) -> Result<RawFd>;
+    fn fsconfig(&self, fd: RawFd, cmd: u32, key: Option<&str>, value: Option<&str>) -> Result<()>;
+    fn fsmount(&self, fd: RawFd, flags: u32, attr_flags: u64) -> Result<RawFd>;
 }
 
 pub fn create_syscall() -> Box<dyn Syscall> {
@@ -2,21 +2,24 @@ use std::{
     any::Any,
     cell::RefCell,
     ffi::{OsStr, OsString},
+    os::unix::io::RawFd,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 use caps::{errors::CapsError, CapSet, CapsHashSet};
 use nix::{
+    errno::Errno,
     mount::MsFlags,
     sched::CloneFlags,
     sys::stat::{Mode, SFlag},
     unistd::{Gid, Uid},
 };
 
-use oci_spec::runtime::LinuxRlimit;
+use oci_spec::runtime::{LinuxPersonality, LinuxRlimit};
 
-use super::Syscall;
+use super::{mount_api::MountAttr, Syscall};
+use crate::scheduling::{IoPriority, Scheduler};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct MountArgs {
@@ -42,6 +45,48 @@ pub struct ChownArgs {
     pub group: Option<Gid>,
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OpenTreeArgs {
+    pub dirfd: RawFd,
+    pub path: Option<PathBuf>,
+    pub flags: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MountSetattrArgs {
+    pub dirfd: RawFd,
+    pub path: Option<PathBuf>,
+    pub flags: u32,
+    pub attr: MountAttr,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MoveMountArgs {
+    pub from_dirfd: RawFd,
+    pub from_path: Option<PathBuf>,
+    pub to_dirfd: RawFd,
+    pub to_path: Option<PathBuf>,
+    pub flags: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FsconfigArgs {
+    pub fd: RawFd,
+    pub cmd: u32,
+    pub key: Option<String>,
+    pub value: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FsmountArgs {
+    pub fd: RawFd,
+    pub flags: u32,
+    pub attr_flags: u64,
+}
+
+/// Fd returned by open_tree, fsopen and fsmount, so that tests can check it is passed on
+pub const TEST_MOUNT_FD: RawFd = 1000;
+
 #[derive(Clone)]
 pub struct TestHelperSyscall {
     set_ns_args: RefCell<Vec<(i32, CloneFlags)>>,
@@ -53,6 +98,16 @@ pub struct TestHelperSyscall {
     chown_args: RefCell<Vec<ChownArgs>>,
     hostname_args: RefCell<Vec<String>>,
     groups_args: RefCell<Vec<Vec<Gid>>>,
+    personality_args: RefCell<Vec<LinuxPersonality>>,
+    sched_setattr_args: RefCell<Vec<Scheduler>>,
+    ioprio_set_args: RefCell<Vec<IoPriority>>,
+    open_tree_args: RefCell<Vec<OpenTreeArgs>>,
+    mount_setattr_args: RefCell<Vec<MountSetattrArgs>>,
+    move_mount_args: RefCell<Vec<MoveMountArgs>>,
+    fsopen_args: RefCell<Vec<(String, u32)>>,
+    fsconfig_args: RefCell<Vec<FsconfigArgs>>,
+    fsmount_args: RefCell<Vec<FsmountArgs>>,
+    mknod_error: RefCell<Option<Errno>>,
 }
 
 impl Default for TestHelperSyscall {
@@ -67,6 +122,16 @@ impl Default for TestHelperSyscall {
             chown_args: RefCell::new(vec![]),
             hostname_args: RefCell::new(vec![]),
             groups_args: RefCell::new(vec![]),
+            personality_args: RefCell::new(vec![]),
+            sched_setattr_args: RefCell::new(vec![]),
+            ioprio_set_args: RefCell::new(vec![]),
+            open_tree_args: RefCell::new(vec![]),
+            mount_setattr_args: RefCell::new(vec![]),
+            move_mount_args: RefCell::new(vec![]),
+            fsopen_args: RefCell::new(vec![]),
+            fsconfig_args: RefCell::new(vec![]),
+            fsmount_args: RefCell::new(vec![]),
+            mknod_error: RefCell::new(None),
         }
     }
 }
@@ -150,7 +215,10 @@ impl Syscall for TestHelperSyscall {
             perm,
             dev,
         });
-        Ok(())
+        match *self.mknod_error.borrow() {
+            Some(errno) => Err(errno.into()),
+            None => Ok(()),
+        }
     }
     fn chown(&self, path: &Path, owner: Option<Uid>, group: Option<Gid>) -> anyhow::Result<()> {
         self.chown_args.borrow_mut().push(ChownArgs {
@@ -165,6 +233,96 @@ impl Syscall for TestHelperSyscall {
         self.groups_args.borrow_mut().push(groups.to_vec());
         Ok(())
     }
+
+    fn personality(&self, personality: &LinuxPersonality) -> anyhow::Result<()> {
+        self.personality_args.borrow_mut().push(personality.clone());
+        Ok(())
+    }
+
+    fn sched_setattr(&self, scheduler: &Scheduler) -> anyhow::Result<()> {
+        self.sched_setattr_args.borrow_mut().push(scheduler.clone());
+        Ok(())
+    }
+
+    fn ioprio_set(&self, io_priority: &IoPriority) -> anyhow::Result<()> {
+        self.ioprio_set_args.borrow_mut().push(io_priority.clone());
+        Ok(())
+    }
+
+    fn open_tree(&self, dirfd: RawFd, path: Option<&Path>, flags: u32) -> anyhow::Result<RawFd> {
+        self.open_tree_args.borrow_mut().push(OpenTreeArgs {
+            dirfd,
+            path: path.map(|p| p.to_path_buf()),
+            flags,
+        });
+        Ok(TEST_MOUNT_FD)
+    }
+
+    fn mount_setattr(
+        &self,
+        dirfd: RawFd,
+        path: Option<&Path>,
+        flags: u32,
+        attr: &MountAttr,
+    ) -> anyhow::Result<()> {
+        self.mount_setattr_args.borrow_mut().push(MountSetattrArgs {
+            dirfd,
+            path: path.map(|p| p.to_path_buf()),
+            flags,
+            attr: *attr,
+        });
+        Ok(())
+    }
+
+    fn move_mount(
+        &self,
+        from_dirfd: RawFd,
+        from_path: Option<&Path>,
+        to_dirfd: RawFd,
+        to_path: Option<&Path>,
+        flags: u32,
+    ) -> anyhow::Result<()> {
+        self.move_mount_args.borrow_mut().push(MoveMountArgs {
+            from_dirfd,
+            from_path: from_path.map(|p| p.to_path_buf()),
+            to_dirfd,
+            to_path: to_path.map(|p| p.to_path_buf()),
+            flags,
+        });
+        Ok(())
+    }
+
+    fn fsopen(&self, fstype: &str, flags: u32) -> anyhow::Result<RawFd> {
+        self.fsopen_args
+            .borrow_mut()
+            .push((fstype.to_owned(), flags));
+        Ok(TEST_MOUNT_FD)
+    }
+
+    fn fsconfig(
+        &self,
+        fd: RawFd,
+        cmd: u32,
+        key: Option<&str>,
+        value: Option<&str>,
+    ) -> anyhow::Result<()> {
+        self.fsconfig_args.borrow_mut().push(FsconfigArgs {
+            fd,
+            cmd,
+            key: key.map(|k| k.to_owned()),
+            value: value.map(|v| v.to_owned()),
+        });
+        Ok(())
+    }
+
+    fn fsmount(&self, fd: RawFd, flags: u32, attr_flags: u64) -> anyhow::Result<RawFd> {
+        self.fsmount_args.borrow_mut().push(FsmountArgs {
+            fd,
+            flags,
+            attr_flags,
+        });
+        Ok(TEST_MOUNT_FD)
+    }
 }
 
 impl TestHelperSyscall {
@@ -192,6 +350,11 @@ impl TestHelperSyscall {
         self.mknod_args.borrow_mut().clone()
     }
 
+    /// Makes mknod fail with errno, e.g. EPERM as in a user namespace
+    pub fn set_mknod_error(&self, errno: Errno) {
+        *self.mknod_error.borrow_mut() = Some(errno);
+    }
+
     pub fn get_chown_args(&self) -> Vec<ChownArgs> {
         self.chown_args.borrow_mut().clone()
     }
@@ -203,4 +366,40 @@ impl TestHelperSyscall {
     pub fn get_groups_args(&self) -> Vec<Vec<Gid>> {
         self.groups_args.borrow_mut().clone()
     }
+
+    pub fn get_personality_args(&self) -> Vec<LinuxPersonality> {
+        self.personality_args.borrow_mut().clone()
+    }
+
+    pub fn get_sched_setattr_args(&self) -> Vec<Scheduler> {
+        self.sched_setattr_args.borrow_mut().clone()
+    }
+
+    pub fn get_ioprio_set_args(&self) -> Vec<IoPriority> {
+        self.ioprio_set_args.borrow_mut().clone()
+    }
+
+    pub fn get_open_tree_args(&self) -> Vec<OpenTreeArgs> {
+        self.open_tree_args.borrow_mut().clone()
+    }
+
+    pub fn get_mount_setattr_args(&self) -> Vec<MountSetattrArgs> {
+        self.mount_setattr_args.borrow_mut().clone()
+    }
+
+    pub fn get_move_mount_args(&self) -> Vec<MoveMountArgs> {
+        self.move_mount_args.borrow_mut().clone()
+    }
+
+    pub fn get_fsopen_args(&self) -> Vec<(String, u32)> {
+        self.fsopen_args.borrow_mut().clone()
+    }
+
+    pub fn get_fsconfig_args(&self) -> Vec<FsconfigArgs> {
+        self.fsconfig_args.borrow_mut().clone()
+    }
+
+    pub fn get_fsmount_args(&self) -> Vec<FsmountArgs> {
+        self.fsmount_args.borrow_mut().clone()
+    }
 }
@@ -0,0 +1,238 @@
+//! Time namespaces virtualize the monotonic and boottime clocks, so that a
+//! container can be checkpointed and restored on another host, or clock
+//! sensitive software can be tested with deterministic clocks. The runtime spec
+//! types don't know the time namespace yet, therefore the `time` namespace and
+//! the `timeOffsets` of config.json are read by this module, see
+//! [`crate::spec_extensions`].
+use anyhow::{bail, Context, Result};
+use nix::{errno::Errno, fcntl, sys::stat, unistd};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::utils;
+
+/// Not defined by nix, as the time namespace is newer than the other namespaces.
+const CLONE_NEWTIME: libc::c_int = 0x80;
+const TIMENS_OFFSETS: &str = "/proc/self/timens_offsets";
+const CLOCKS: &[&str] = &["monotonic", "boottime"];
+
+/// Offset of a clock in the time namespace relative to the host
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeOffset {
+    pub secs: i64,
+    pub nanosecs: u32,
+}
+
+/// The time namespace the container process is created in
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimeNamespace {
+    /// Path of an existing time namespace to join. A new time namespace is
+    /// created otherwise.
+    pub path: Option<PathBuf>,
+    /// Offsets of the clocks of a new time namespace, keyed by clock name
+    pub offsets: BTreeMap<String, TimeOffset>,
+}
+
+impl TimeNamespace {
+    /// Removes the time namespace and the clock offsets from a runtime spec in
+    /// JSON representation.
+    pub fn take_from(spec: &mut Value) -> Result<Option<Self>> {
+        let linux = match spec.get_mut("linux").and_then(Value::as_object_mut) {
+            Some(linux) => linux,
+            None => return Ok(None),
+        };
+
+        let offsets: BTreeMap<String, TimeOffset> = match linux.remove("timeOffsets") {
+            Some(offsets) => {
+                serde_json::from_value(offsets).context("failed to parse timeOffsets")?
+            }
+            None => BTreeMap::new(),
+        };
+
+        let mut namespace = None;
+        if let Some(namespaces) = linux.get_mut("namespaces").and_then(Value::as_array_mut) {
+            let mut index = 0;
+            while index < namespaces.len() {
+                if namespaces[index].get("type").and_then(Value::as_str) != Some("time") {
+                    index += 1;
+                    continue;
+                }
+
+                let ns = namespaces.remove(index);
+                if namespace.is_some() {
+                    bail!("the time namespace is specified more than once");
+                }
+                namespace = Some(ns.get("path").and_then(Value::as_str).map(PathBuf::from));
+            }
+        }
+
+        let path = match namespace {
+            Some(path) => path,
+            None if offsets.is_empty() => return Ok(None),
+            None => bail!("timeOffsets require a time namespace"),
+        };
+
+        let time_namespace = TimeNamespace { path, offsets };
+        time_namespace.validate()?;
+        Ok(Some(time_namespace))
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.path.is_some() && !self.offsets.is_empty() {
+            bail!("timeOffsets can't be applied when joining an existing time namespace");
+        }
+
+        for (clock, offset) in &self.offsets {
+            if !CLOCKS.contains(&clock.as_str()) {
+                bail!("clock {} of timeOffsets is not supported", clock);
+            }
+
+            if offset.nanosecs >= 1_000_000_000 {
+                bail!("nanosecs of clock {} must be less than one second", clock);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates or joins the time namespace. Only children of the calling
+    /// process are created in the new namespace, so this has to be done
+    /// before the container init process is forked.
+    pub fn apply(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => {
+                let fd = fcntl::open(path, fcntl::OFlag::O_CLOEXEC, stat::Mode::empty())
+                    .with_context(|| format!("failed to open time namespace {:?}", path))?;
+                let res = Errno::result(unsafe { libc::setns(fd, CLONE_NEWTIME) });
+                let _ = unistd::close(fd);
+                res.with_context(|| format!("failed to join time namespace {:?}", path))?;
+            }
+            None => {
+                Errno::result(unsafe { libc::unshare(CLONE_NEWTIME) })
+                    .context("failed to create time namespace")?;
+                // the offsets can only be written until the first process has
+                // been created in the namespace
+                if !self.offsets.is_empty() {
+                    utils::write_file(TIMENS_OFFSETS, self.offsets_content())
+                        .context("failed to set clock offsets of the time namespace")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Formats the offsets as expected by /proc/<pid>/timens_offsets
+    fn offsets_content(&self) -> String {
+        self.offsets
+            .iter()
+            .map(|(clock, offset)| format!("{} {} {}\n", clock, offset.secs, offset.nanosecs))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::Spec;
+    use serde_json::json;
+
+    fn spec(linux: Value) -> Value {
+        json!({
+            "ociVersion": "1.0.2-dev",
+            "root": { "path": "rootfs" },
+            "linux": linux,
+        })
+    }
+
+    #[test]
+    fn test_take_new_namespace() -> Result<()> {
+        let mut value = spec(json!({
+            "namespaces": [{ "type": "pid" }, { "type": "time" }, { "type": "mount" }],
+            "timeOffsets": {
+                "monotonic": { "secs": 172800, "nanosecs": 0 },
+                "boottime": { "secs": -3600, "nanosecs": 500 },
+            },
+        }));
+        let time_namespace = TimeNamespace::take_from(&mut value)?.unwrap();
+        assert_eq!(time_namespace.path, None);
+        assert_eq!(
+            time_namespace.offsets_content(),
+            "boottime -3600 500\nmonotonic 172800 0\n"
+        );
+
+        // the remaining spec has to be understood by oci-spec
+        assert_eq!(
+            value["linux"],
+            json!({ "namespaces": [{ "type": "pid" }, { "type": "mount" }] })
+        );
+        let spec: Spec = serde_json::from_value(value)?;
+        assert_eq!(
+            spec.linux()
+                .as_ref()
+                .unwrap()
+                .namespaces()
+                .as_ref()
+                .unwrap()
+                .len(),
+            2
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_take_existing_namespace() -> Result<()> {
+        let mut value = spec(json!({
+            "namespaces": [{ "type": "time", "path": "/proc/1/ns/time" }],
+        }));
+        let time_namespace = TimeNamespace::take_from(&mut value)?.unwrap();
+        assert_eq!(time_namespace.path, Some(PathBuf::from("/proc/1/ns/time")));
+        assert!(time_namespace.offsets.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_take_no_namespace() -> Result<()> {
+        let mut value = spec(json!({ "namespaces": [{ "type": "pid" }] }));
+        assert_eq!(TimeNamespace::take_from(&mut value)?, None);
+
+        let mut value = json!({ "ociVersion": "1.0.2-dev" });
+        assert_eq!(TimeNamespace::take_from(&mut value)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_take_invalid() {
+        let invalid = vec![
+            // offsets without namespace
+            json!({ "timeOffsets": { "monotonic": { "secs": 1, "nanosecs": 0 } } }),
+            // offsets for an existing namespace
+            json!({
+                "namespaces": [{ "type": "time", "path": "/proc/1/ns/time" }],
+                "timeOffsets": { "monotonic": { "secs": 1, "nanosecs": 0 } },
+            }),
+            // unsupported clock
+            json!({
+                "namespaces": [{ "type": "time" }],
+                "timeOffsets": { "realtime": { "secs": 1, "nanosecs": 0 } },
+            }),
+            // nanosecs out of range
+            json!({
+                "namespaces": [{ "type": "time" }],
+                "timeOffsets": { "boottime": { "secs": 1, "nanosecs": 1_000_000_000 } },
+            }),
+            // duplicated namespace
+            json!({ "namespaces": [{ "type": "time" }, { "type": "time" }] }),
+        ];
+
+        for linux in invalid {
+            let mut value = spec(linux.clone());
+            assert!(
+                TimeNamespace::take_from(&mut value).is_err(),
+                "{} should be invalid",
+                linux
+            );
+        }
+    }
+}
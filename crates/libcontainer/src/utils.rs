@@ -1,7 +1,10 @@
 //! Utility functionality
 
+use crate::syscall::openat2;
 use anyhow::Context;
 use anyhow::{bail, Result};
+use nix::errno::Errno;
+use nix::fcntl::{self, OFlag};
 use nix::sys::stat::Mode;
 use nix::sys::statfs;
 use nix::unistd;
@@ -75,6 +78,15 @@ pub fn get_cgroup_path(cgroups_path: &Option<PathBuf>, container_id: &str) -> Pa
     }
 }
 
+/// Searches the directories of PATH for the binary
+pub fn lookup_binary(binary: &str) -> Result<Option<PathBuf>> {
+    let paths = std::env::var("PATH").context("could not find PATH")?;
+    Ok(paths
+        .split_terminator(':')
+        .map(|p| Path::new(p).join(binary))
+        .find(|p| p.exists()))
+}
+
 pub fn write_file<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()> {
     let path = path.as_ref();
     fs::write(path, contents).with_context(|| format!("failed to write to {:?}", path))?;
@@ -131,6 +143,33 @@ pub fn create_dir_all_with_mode<P: AsRef<Path>>(path: P, owner: u32, mode: Mode)
     }
 }
 
+/// Get a list of open fds for the calling process.
+pub fn get_open_fds() -> Result<Vec<i32>> {
+    const PROCFS_FD_PATH: &str = "/proc/self/fd";
+    ensure_procfs(Path::new(PROCFS_FD_PATH))
+        .with_context(|| format!("{} is not the actual procfs", PROCFS_FD_PATH))?;
+
+    let fds: Vec<i32> = fs::read_dir(PROCFS_FD_PATH)?
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry.path()),
+            Err(_) => None,
+        })
+        .filter_map(|path| path.file_name().map(|file_name| file_name.to_owned()))
+        .filter_map(|file_name| file_name.to_str().map(String::from))
+        .filter_map(|file_name| -> Option<i32> {
+            // Convert the file name from string into i32. Since we are looking
+            // at /proc/<pid>/fd, anything that's not a number (i32) can be
+            // ignored. We are only interested in opened fds.
+            match file_name.parse() {
+                Ok(fd) => Some(fd),
+                Err(_) => None,
+            }
+        })
+        .collect();
+
+    Ok(fds)
+}
+
 // Make sure a given path is on procfs. This is to avoid the security risk that
 // /proc path is mounted over. Ref: CVE-2019-16884
 pub fn ensure_procfs(path: &Path) -> Result<()> {
@@ -144,9 +183,42 @@ pub fn ensure_procfs(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Joins unsafe_path to rootfs, resolving symlinks and ".." as if rootfs was the
+/// root directory, so that the result never points outside of rootfs.
+/// Components which don't exist are appended as they are.
 pub fn secure_join<P: Into<PathBuf>>(rootfs: P, unsafe_path: P) -> Result<PathBuf> {
-    let mut rootfs = rootfs.into();
-    let mut path = unsafe_path.into();
+    let rootfs = rootfs.into();
+    let unsafe_path = unsafe_path.into();
+    match secure_join_openat2(&rootfs, &unsafe_path)? {
+        Some(path) => Ok(path),
+        None => secure_join_fallback(rootfs, unsafe_path),
+    }
+}
+
+/// Resolves the path with openat2(2), which resolves each component in the
+/// scope of the rootfs, so that symlinks swapped in concurrently can't
+/// redirect the resolution outside of the rootfs. Returns None if the kernel
+/// doesn't support openat2 or the rootfs doesn't exist.
+fn secure_join_openat2(rootfs: &Path, unsafe_path: &Path) -> Result<Option<PathBuf>> {
+    let root_fd = match fcntl::open(
+        rootfs,
+        OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    ) {
+        Ok(fd) => fd,
+        Err(_) => return Ok(None),
+    };
+    let res = openat2::resolve_in_root(root_fd, unsafe_path);
+    let _ = unistd::close(root_fd);
+
+    match res {
+        Ok(resolved) => Ok(Some(rootfs.join(resolved))),
+        Err(err) if matches!(err.downcast_ref(), Some(Errno::ENOSYS)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn secure_join_fallback(mut rootfs: PathBuf, mut path: PathBuf) -> Result<PathBuf> {
     let mut clean_path = PathBuf::new();
 
     let mut part = path.iter();
@@ -263,10 +335,12 @@ pub fn create_temp_dir(test_name: &str) -> Result<TempDir> {
 #[cfg(test)]
 pub(crate) mod test_utils {
     use crate::process::channel;
+    use crate::utils;
     use anyhow::Context;
     use anyhow::{bail, Result};
     use nix::sys::wait;
     use serde::{Deserialize, Serialize};
+    use std::path::{Path, PathBuf};
 
     #[derive(Debug, Serialize, Deserialize)]
     struct TestResult {
@@ -274,6 +348,16 @@ pub(crate) mod test_utils {
         message: String,
     }
 
+    /// Writes a fixture file below temp_dir, creating its parent directories
+    pub fn set_fixture(temp_dir: &Path, filename: &str, val: &str) -> Result<PathBuf> {
+        let full_path = temp_dir.join(filename);
+        if let Some(parent) = full_path.parent() {
+            utils::create_dir_all(parent)?;
+        }
+        utils::write_file(&full_path, val)?;
+        Ok(full_path)
+    }
+
     pub fn test_in_child_process<F: FnOnce() -> Result<()>>(cb: F) -> Result<()> {
         let (mut sender, mut receiver) = channel::channel::<TestResult>()?;
         match unsafe { nix::unistd::fork()? } {
@@ -310,6 +394,7 @@ pub(crate) mod test_utils {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serial_test::serial;
 
     #[test]
     fn test_get_cgroup_path() {
@@ -409,4 +494,49 @@ mod tests {
             PathBuf::from(&test_root_dir).join("somepath/passwd")
         );
     }
+
+    #[test]
+    fn test_secure_join_existing_symlink() {
+        use std::os::unix::fs::symlink;
+
+        let tmp = create_temp_dir("test_secure_join_existing_symlink").unwrap();
+        let test_root_dir = tmp.path();
+        fs::create_dir_all(test_root_dir.join("usr/lib")).unwrap();
+        symlink("/usr/lib", test_root_dir.join("lib")).unwrap();
+        symlink("../../../lib/../..", test_root_dir.join("usr/lib/up")).unwrap();
+
+        assert_eq!(
+            secure_join(test_root_dir, Path::new("/lib/modules")).unwrap(),
+            test_root_dir.join("usr/lib/modules")
+        );
+        assert_eq!(
+            secure_join(test_root_dir, Path::new("/lib/up/etc")).unwrap(),
+            test_root_dir.join("etc")
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_get_open_fds() -> Result<()> {
+        let file = fs::File::open("/dev/null")?;
+        let fd = file.as_raw_fd();
+        let open_fds = get_open_fds()?;
+
+        if !open_fds.iter().any(|&v| v == fd) {
+            bail!("Failed to find the opened dev null fds: {:?}", open_fds);
+        }
+
+        // explicitly close the file before the test case returns.
+        drop(file);
+
+        // The stdio fds should also be contained in the list of opened fds.
+        if !vec![0, 1, 2]
+            .iter()
+            .all(|&stdio_fd| open_fds.iter().any(|&open_fd| open_fd == stdio_fd))
+        {
+            bail!("Failed to find the stdio fds: {:?}", open_fds);
+        }
+
+        Ok(())
+    }
 }
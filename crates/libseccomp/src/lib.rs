@@ -77,6 +77,7 @@ pub enum scmp_filter_attr {
     SCMP_FLTATR_CTL_SSB,
     SCMP_FLTATR_CTL_OPTIMIZE,
     SCMP_FLTATR_API_SYSRAWRC,
+    SCMP_FLTATR_CTL_WAITKILL,
     _SCMP_FLTATR_MAX,
 }
 
@@ -186,6 +187,7 @@ extern "C" {
      *  4 : support for the SCMP_FLTATR_CTL_SSB filter attrbute
      *  5 : support for the SCMP_ACT_NOTIFY action and notify APIs
      *  6 : support the simultaneous use of SCMP_FLTATR_CTL_TSYNC and notify APIs
+     *  7 : support for the SCMP_FLTATR_CTL_WAITKILL filter attribute
      *
      */
     pub fn seccomp_api_get() -> ::std::os::raw::c_uint;
@@ -0,0 +1,608 @@
+//! Unpacks an image of a local OCI image layout into a bundle, which can be run
+//! by youki. See https://github.com/opencontainers/image-spec/blob/main/image-layout.md
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Clap;
+use flate2::read::GzDecoder;
+use libcontainer::utils;
+use nix::unistd::{self, FchownatFlags, Gid, Uid};
+use oci_spec::image::{Config, Descriptor, ImageConfiguration, ImageIndex, ImageManifest};
+use oci_spec::runtime::Spec;
+use serde_json::to_writer_pretty;
+use tar::{Archive, EntryType};
+
+use crate::commands::spec_json;
+
+/// Annotation of the index, which names an image of the layout
+const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+/// Marks a file of a lower layer as deleted
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// Hides all files of lower layers in the directory
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// Unpack an image of an OCI image layout into a bundle
+#[derive(Clap, Debug)]
+pub struct Bundle {
+    /// path to the OCI image layout directory
+    #[clap(required = true)]
+    pub image: PathBuf,
+    /// path to the bundle directory, which will contain config.json and the root filesystem
+    #[clap(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+    /// name of the image in the layout, required if the layout contains multiple images
+    #[clap(long = "ref")]
+    pub reference: Option<String>,
+    /// Generate a configuration for a rootless container
+    #[clap(long)]
+    pub rootless: bool,
+}
+
+impl Bundle {
+    pub fn exec(&self) -> Result<()> {
+        let rootfs = self.bundle.join("rootfs");
+        if rootfs.exists() {
+            bail!("{} already exists", rootfs.display());
+        }
+
+        let manifest = load_manifest(&self.image, self.reference.as_deref())?;
+        let config_path = blob_path(&self.image, manifest.config())?;
+        let config = ImageConfiguration::from_file(&config_path)
+            .with_context(|| format!("failed to load image config {}", config_path.display()))?;
+
+        utils::create_dir_all(&rootfs)?;
+        for layer in manifest.layers() {
+            unpack_layer(&self.image, layer, &rootfs)
+                .with_context(|| format!("failed to unpack layer {}", layer.digest()))?;
+        }
+
+        let spec = generate_spec(config.config().as_ref(), &rootfs, self.rootless)?;
+        let spec_path = self.bundle.join("config.json");
+        to_writer_pretty(&File::create(&spec_path)?, &spec)
+            .with_context(|| format!("failed to write {}", spec_path.display()))?;
+        Ok(())
+    }
+}
+
+/// Returns the path of the blob of the descriptor in the layout
+fn blob_path(layout: &Path, descriptor: &Descriptor) -> Result<PathBuf> {
+    let digest = descriptor.digest();
+    let (algorithm, encoded) = digest
+        .split_once(':')
+        .with_context(|| format!("invalid digest {}", digest))?;
+    let valid = |s: &str, extra: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || extra.contains(c))
+    };
+    // the digest becomes part of the path, so it must not contain a separator
+    if !valid(algorithm, "+._-") || !valid(encoded, "=_-") {
+        bail!("invalid digest {}", digest);
+    }
+
+    Ok(layout.join("blobs").join(algorithm).join(encoded))
+}
+
+/// Loads the manifest of the image from the index of the layout. An image is
+/// selected by its reference name or by the platform youki is running on.
+fn load_manifest(layout: &Path, reference: Option<&str>) -> Result<ImageManifest> {
+    if !layout.join("oci-layout").exists() {
+        bail!("{} is not an OCI image layout", layout.display());
+    }
+
+    let index_path = layout.join("index.json");
+    let mut index = ImageIndex::from_file(&index_path)
+        .with_context(|| format!("failed to load {}", index_path.display()))?;
+    // the reference names the images of the index of the layout, the nested
+    // indexes only select the platform
+    let mut reference = reference;
+    loop {
+        let descriptor = select_manifest(index.manifests(), reference)?;
+        let path = blob_path(layout, descriptor)?;
+        // an index may refer to the index of a multi platform image
+        if descriptor
+            .media_type()
+            .to_string()
+            .ends_with("image.index.v1+json")
+        {
+            let nested = ImageIndex::from_file(&path)
+                .with_context(|| format!("failed to load index {}", descriptor.digest()))?;
+            index = nested;
+            reference = None;
+            continue;
+        }
+
+        return ImageManifest::from_file(&path)
+            .with_context(|| format!("failed to load manifest {}", descriptor.digest()));
+    }
+}
+
+fn select_manifest<'a>(
+    manifests: &'a [Descriptor],
+    reference: Option<&str>,
+) -> Result<&'a Descriptor> {
+    let candidates: Vec<&Descriptor> = manifests
+        .iter()
+        .filter(|m| match reference {
+            Some(reference) => m
+                .annotations()
+                .as_ref()
+                .and_then(|a| a.get(REF_NAME_ANNOTATION))
+                .map_or(false, |name| name == reference),
+            None => true,
+        })
+        .filter(|m| match m.platform() {
+            Some(platform) => {
+                platform.os().to_string() == "linux"
+                    && platform.architecture().to_string() == go_arch()
+            }
+            None => true,
+        })
+        .collect();
+
+    match candidates.as_slice() {
+        [manifest] => Ok(manifest),
+        [] => bail!("the image layout contains no matching image"),
+        _ => bail!("the image layout contains multiple images, select one with --ref"),
+    }
+}
+
+/// Returns the architecture youki runs on, named as in the image spec
+fn go_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "x86" => "386",
+        "aarch64" => "arm64",
+        "powerpc64" if cfg!(target_endian = "big") => "ppc64",
+        "powerpc64" => "ppc64le",
+        arch => arch,
+    }
+}
+
+fn unpack_layer(layout: &Path, layer: &Descriptor, rootfs: &Path) -> Result<()> {
+    let path = blob_path(layout, layer)?;
+    let blob = BufReader::new(
+        File::open(&path).with_context(|| format!("failed to open {}", path.display()))?,
+    );
+
+    let media_type = layer.media_type().to_string();
+    if media_type.ends_with("gzip") {
+        apply_layer(GzDecoder::new(blob), rootfs)
+    } else if media_type.ends_with("zstd") {
+        apply_layer(zstd::Decoder::new(blob)?, rootfs)
+    } else if media_type.ends_with("tar") {
+        apply_layer(blob, rootfs)
+    } else {
+        bail!("unsupported layer media type {}", media_type)
+    }
+}
+
+/// Applies a layer to the rootfs. Whiteout files delete the files of the
+/// lower layers, instead of being unpacked.
+fn apply_layer<R: Read>(reader: R, rootfs: &Path) -> Result<()> {
+    let mut archive = Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.set_unpack_xattrs(true);
+    archive.set_overwrite(true);
+    let preserve_ownership = Uid::effective().is_root();
+
+    // files of this layer, which are not hidden by an opaque whiteout
+    let mut unpacked = HashSet::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = relative_path(&entry.path()?)?;
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+
+        if name == OPAQUE_WHITEOUT {
+            let dir = utils::secure_join(rootfs.to_path_buf(), parent.to_path_buf())?;
+            remove_children(&dir, parent, &unpacked)?;
+            continue;
+        }
+        if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            let dir = utils::secure_join(rootfs.to_path_buf(), parent.to_path_buf())?;
+            remove_path(&dir.join(hidden))?;
+            continue;
+        }
+
+        // the parent is resolved inside of the rootfs, so that symlinks of the
+        // lower layers can not redirect the entry outside of it
+        let target_dir = utils::secure_join(rootfs.to_path_buf(), parent.to_path_buf())?;
+        utils::create_dir_all(&target_dir)?;
+        let target = target_dir.join(&name);
+        let entry_type = entry.header().entry_type();
+        prepare_target(&target, entry_type)?;
+        if entry_type == EntryType::Link {
+            // hard links are unpacked relative to the current directory by tar
+            let link_name = entry.link_name()?.context("hard link without target")?;
+            let source = utils::secure_join(rootfs.to_path_buf(), relative_path(&link_name)?)?;
+            fs::hard_link(&source, &target)
+                .with_context(|| format!("failed to link {}", path.display()))?;
+        } else {
+            entry
+                .unpack(&target)
+                .with_context(|| format!("failed to unpack {}", path.display()))?;
+        }
+
+        if preserve_ownership {
+            let header = entry.header();
+            unistd::fchownat(
+                None,
+                &target,
+                Some(Uid::from_raw(header.uid()? as u32)),
+                Some(Gid::from_raw(header.gid()? as u32)),
+                FchownatFlags::NoFollowSymlink,
+            )
+            .with_context(|| format!("failed to chown {}", target.display()))?;
+            // chown clears the setuid and setgid bits
+            if entry_type != EntryType::Symlink {
+                fs::set_permissions(&target, fs::Permissions::from_mode(header.mode()?))?;
+            }
+        }
+        unpacked.insert(path);
+    }
+
+    Ok(())
+}
+
+/// Converts the path of a layer entry to a path relative to the rootfs
+fn relative_path(path: &Path) -> Result<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir | Component::RootDir => {}
+            _ => bail!("invalid path {} in layer", path.display()),
+        }
+    }
+    Ok(relative)
+}
+
+/// Removes a file or directory of a lower layer, which is replaced by an entry
+/// of another type. Directories are merged with the directories of the lower
+/// layers.
+fn prepare_target(target: &Path, entry_type: EntryType) -> Result<()> {
+    if let Ok(metadata) = target.symlink_metadata() {
+        if !(entry_type.is_dir() && metadata.is_dir()) {
+            remove_path(target)?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_path(path: &Path) -> Result<()> {
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(()),
+    };
+
+    if metadata.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .with_context(|| format!("failed to remove {}", path.display()))
+}
+
+/// Removes the children of dir, which were not unpacked from the current layer
+fn remove_children(dir: &Path, relative_dir: &Path, unpacked: &HashSet<PathBuf>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    for child in fs::read_dir(dir)? {
+        let child = child?;
+        if !unpacked.contains(&relative_dir.join(child.file_name())) {
+            remove_path(&child.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Generates the runtime spec of the bundle from the image config on top of
+/// the spec of `youki spec`.
+fn generate_spec(config: Option<&Config>, rootfs: &Path, rootless: bool) -> Result<Spec> {
+    let mut spec = if rootless {
+        spec_json::get_rootless()?
+    } else {
+        spec_json::get_default()?
+    };
+    let config = match config {
+        Some(config) => config,
+        None => return Ok(spec),
+    };
+
+    let mut process = spec.process().clone().context("no process in spec")?;
+    let args: Vec<String> = config
+        .entrypoint()
+        .iter()
+        .flatten()
+        .chain(config.cmd().iter().flatten())
+        .cloned()
+        .collect();
+    if !args.is_empty() {
+        process.set_args(Some(args));
+    }
+
+    if let Some(image_env) = config.env() {
+        // the environment of the image overrides the default environment
+        let keys: HashSet<&str> = image_env
+            .iter()
+            .map(|e| e.split('=').next().unwrap_or_default())
+            .collect();
+        let env: Vec<String> = process
+            .env()
+            .iter()
+            .flatten()
+            .filter(|e| !keys.contains(e.split('=').next().unwrap_or_default()))
+            .chain(image_env)
+            .cloned()
+            .collect();
+        process.set_env(Some(env));
+    }
+
+    if let Some(working_dir) = config.working_dir().as_ref().filter(|d| !d.is_empty()) {
+        process.set_cwd(PathBuf::from(working_dir));
+    }
+
+    if let Some(user) = config.user().as_ref().filter(|u| !u.is_empty()) {
+        let (uid, gid) = resolve_user(rootfs, user)?;
+        let mut process_user = process.user().clone();
+        process_user.set_uid(uid).set_gid(gid);
+        process.set_user(process_user);
+    }
+
+    spec.set_process(Some(process));
+    Ok(spec)
+}
+
+/// Resolves the user of the image config, which is given as user, uid,
+/// user:group or uid:gid, with the passwd and group files of the rootfs.
+fn resolve_user(rootfs: &Path, user: &str) -> Result<(u32, u32)> {
+    let (user, group) = match user.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (user, None),
+    };
+
+    let passwd = read_rootfs_file(rootfs, "/etc/passwd");
+    let entry = passwd
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 3 && (fields[0] == user || fields[2] == user));
+    let (uid, primary_gid) = match (entry, user.parse::<u32>()) {
+        (Some(fields), _) => (fields[2].parse()?, fields[3].parse()?),
+        (None, Ok(uid)) => (uid, 0),
+        (None, Err(_)) => bail!("user {} does not exist in the image", user),
+    };
+
+    let gid = match group {
+        Some(group) => match group.parse::<u32>() {
+            Ok(gid) => gid,
+            Err(_) => read_rootfs_file(rootfs, "/etc/group")
+                .lines()
+                .map(|line| line.split(':').collect::<Vec<_>>())
+                .find(|fields| fields.len() > 2 && fields[0] == group)
+                .with_context(|| format!("group {} does not exist in the image", group))?[2]
+                .parse()?,
+        },
+        None => primary_gid,
+    };
+
+    Ok((uid, gid))
+}
+
+/// Reads a file of the rootfs, resolving symlinks within the rootfs
+fn read_rootfs_file(rootfs: &Path, path: &str) -> String {
+    utils::secure_join(rootfs.to_path_buf(), PathBuf::from(path))
+        .and_then(|path| Ok(fs::read_to_string(path)?))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use flate2::{write::GzEncoder, Compression};
+    use serde_json::{json, Value};
+    use std::io::Write;
+
+    /// Creates a layer from (path, content) pairs, directories have no content
+    fn layer(entries: &[(&str, Option<&str>)]) -> Result<Vec<u8>> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            match content {
+                Some(content) => {
+                    header.set_entry_type(EntryType::Regular);
+                    header.set_mode(0o644);
+                    header.set_size(content.len() as u64);
+                    builder.append_data(&mut header, path, content.as_bytes())?;
+                }
+                None => {
+                    header.set_entry_type(EntryType::Directory);
+                    header.set_mode(0o755);
+                    header.set_size(0);
+                    builder.append_data(&mut header, path, std::io::empty())?;
+                }
+            }
+        }
+        Ok(builder.into_inner()?)
+    }
+
+    /// Writes the blob to the layout and returns its descriptor
+    fn write_blob(layout: &Path, index: usize, media_type: &str, blob: &[u8]) -> Result<Value> {
+        let digest = format!("sha256:{:064x}", index);
+        let dir = layout.join("blobs/sha256");
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(&digest[7..]), blob)?;
+        Ok(json!({ "mediaType": media_type, "digest": digest, "size": blob.len() }))
+    }
+
+    fn create_layout(layout: &Path) -> Result<()> {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&layer(&[
+            ("etc", None),
+            (
+                "etc/passwd",
+                Some("root:x:0:0::/root:/bin/sh\napp:x:1000:1001::/app:/bin/sh\n"),
+            ),
+            ("kept.txt", Some("kept")),
+            ("deleted.txt", Some("deleted")),
+            ("data", None),
+            ("data/old.txt", Some("old")),
+        ])?)?;
+        let lower = write_blob(
+            layout,
+            1,
+            "application/vnd.oci.image.layer.v1.tar+gzip",
+            &gzip.finish()?,
+        )?;
+        let upper = write_blob(
+            layout,
+            2,
+            "application/vnd.oci.image.layer.v1.tar",
+            &layer(&[
+                (".wh.deleted.txt", Some("")),
+                ("data", None),
+                ("data/new.txt", Some("new")),
+                ("data/.wh..wh..opq", Some("")),
+            ])?,
+        )?;
+
+        let config = json!({
+            "architecture": go_arch(),
+            "os": "linux",
+            "config": {
+                "User": "app",
+                "Env": ["PATH=/app/bin", "APP=1"],
+                "Entrypoint": ["/app/bin/server"],
+                "Cmd": ["--port", "8080"],
+                "WorkingDir": "/app",
+            },
+            "rootfs": { "type": "layers", "diff_ids": [] },
+            "history": [],
+        });
+        let config = write_blob(
+            layout,
+            3,
+            "application/vnd.oci.image.config.v1+json",
+            config.to_string().as_bytes(),
+        )?;
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": config,
+            "layers": [lower, upper],
+        });
+        let mut manifest = write_blob(
+            layout,
+            4,
+            "application/vnd.oci.image.manifest.v1+json",
+            manifest.to_string().as_bytes(),
+        )?;
+        manifest["annotations"] = json!({ REF_NAME_ANNOTATION: "latest" });
+
+        let index = json!({ "schemaVersion": 2, "manifests": [manifest] });
+        fs::write(layout.join("index.json"), index.to_string())?;
+        fs::write(
+            layout.join("oci-layout"),
+            json!({ "imageLayoutVersion": "1.0.0" }).to_string(),
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle() -> Result<()> {
+        let tmp = create_temp_dir("test_youki_bundle")?;
+        let layout = tmp.join("image");
+        create_layout(&layout)?;
+
+        let bundle = Bundle {
+            image: layout,
+            bundle: tmp.join("bundle"),
+            reference: Some("latest".to_string()),
+            rootless: false,
+        };
+        bundle.exec()?;
+
+        let rootfs = tmp.join("bundle/rootfs");
+        assert_eq!(fs::read_to_string(rootfs.join("kept.txt"))?, "kept");
+        assert!(!rootfs.join("deleted.txt").exists());
+        assert!(!rootfs.join("data/old.txt").exists());
+        assert_eq!(fs::read_to_string(rootfs.join("data/new.txt"))?, "new");
+        assert!(!rootfs.join("data/.wh..wh..opq").exists());
+
+        let spec = Spec::load(tmp.join("bundle/config.json"))?;
+        let process = spec.process().as_ref().unwrap();
+        assert_eq!(
+            process.args().as_ref().unwrap(),
+            &vec!["/app/bin/server", "--port", "8080"]
+        );
+        assert_eq!(process.cwd(), Path::new("/app"));
+        assert_eq!(process.user().uid(), 1000);
+        assert_eq!(process.user().gid(), 1001);
+        let env = process.env().as_ref().unwrap();
+        assert!(env.contains(&"PATH=/app/bin".to_string()));
+        assert!(env.contains(&"APP=1".to_string()));
+        assert_eq!(env.iter().filter(|e| e.starts_with("PATH=")).count(), 1);
+
+        // an existing rootfs is not overwritten
+        assert!(bundle.exec().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_manifest() -> Result<()> {
+        let tmp = create_temp_dir("test_youki_bundle_load_manifest")?;
+        let layout = tmp.join("image");
+        create_layout(&layout)?;
+
+        assert!(load_manifest(&layout, None).is_ok());
+        assert!(load_manifest(&layout, Some("latest")).is_ok());
+        assert!(load_manifest(&layout, Some("missing")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_user() -> Result<()> {
+        let tmp = create_temp_dir("test_youki_bundle_resolve_user")?;
+        fs::create_dir_all(tmp.join("etc"))?;
+        fs::write(tmp.join("etc/passwd"), "app:x:1000:1001::/app:/bin/sh\n")?;
+        fs::write(tmp.join("etc/group"), "staff:x:50:app\n")?;
+
+        assert_eq!(resolve_user(&tmp, "app")?, (1000, 1001));
+        assert_eq!(resolve_user(&tmp, "app:staff")?, (1000, 50));
+        assert_eq!(resolve_user(&tmp, "1000:7")?, (1000, 7));
+        assert_eq!(resolve_user(&tmp, "2000")?, (2000, 0));
+        assert!(resolve_user(&tmp, "nobody").is_err());
+        assert!(resolve_user(&tmp, "app:nogroup").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_blob_path() {
+        let descriptor = |digest: &str| -> Descriptor {
+            serde_json::from_value(json!({
+                "mediaType": "application/vnd.oci.image.layer.v1.tar",
+                "digest": digest,
+                "size": 0,
+            }))
+            .unwrap()
+        };
+        let layout = Path::new("/image");
+        assert_eq!(
+            blob_path(layout, &descriptor("sha256:abc123")).unwrap(),
+            PathBuf::from("/image/blobs/sha256/abc123")
+        );
+        assert!(blob_path(layout, &descriptor("sha256:../../etc/passwd")).is_err());
+        assert!(blob_path(layout, &descriptor("abc123")).is_err());
+    }
+}
@@ -21,6 +21,10 @@ pub struct Create {
     /// Pass N additional file descriptors to the container (stdio + $LISTEN_FDS + N in total)
     #[clap(long, default_value = "0")]
     preserve_fds: i32,
+    /// Pin the BPF program restricting the devices of the container to this directory in a
+    /// bpf filesystem (cgroup v2 only)
+    #[clap(long)]
+    bpf_pin_dir: Option<PathBuf>,
     /// name of the container instance to be started
     #[clap(required = true)]
     pub container_id: String,
@@ -41,6 +45,7 @@ impl Create {
             .with_preserved_fds(self.preserve_fds)
             .as_init(&self.bundle)
             .with_systemd(systemd_cgroup)
+            .with_bpf_pin_dir(self.bpf_pin_dir.as_ref())
             .build()?;
 
         Ok(())
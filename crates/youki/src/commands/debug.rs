@@ -0,0 +1,181 @@
+//! Contains functionality to inspect the runtime configuration of a container
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Clap;
+use libcgroups::common::{CgroupSetup, DEFAULT_CGROUP_ROOT, HYBRID_UNIFIED_ROOT};
+use libcgroups::v2::devices::{inspect, Devices as DevicesController};
+use libcontainer::utils::{self, PathBufExt};
+use oci_spec::runtime::LinuxDeviceType;
+
+use crate::commands::load_container;
+
+/// Inspect the configuration that is applied to a container
+#[derive(Clap, Debug)]
+pub struct DebugCommand {
+    #[clap(subcommand)]
+    subcmd: DebugSubCommand,
+}
+
+#[derive(Clap, Debug)]
+enum DebugSubCommand {
+    Devices(Devices),
+}
+
+impl DebugCommand {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        match &self.subcmd {
+            DebugSubCommand::Devices(devices) => devices.exec(root_path),
+        }
+    }
+}
+
+/// Show the effective device policy of a container
+#[derive(Clap, Debug)]
+pub struct Devices {
+    #[clap(required = true)]
+    pub container_id: String,
+    /// Disassemble the device programs that are attached to the cgroup of the container
+    #[clap(long)]
+    pub disassemble: bool,
+    /// Check if an access to a device would be allowed, e.g. "c 10:200 rwm"
+    #[clap(long)]
+    pub check: Option<String>,
+}
+
+impl Devices {
+    pub fn exec(&self, root_path: PathBuf) -> Result<()> {
+        let container = load_container(root_path, &self.container_id)?;
+        if container.systemd().unwrap_or(false) {
+            bail!("inspecting devices is not supported for the systemd cgroup manager");
+        }
+
+        let spec = container.spec()?;
+        let linux = spec.linux().as_ref().context("no linux in spec")?;
+
+        let no_devices = None;
+        let devices = linux
+            .resources()
+            .as_ref()
+            .map(|r| r.devices())
+            .unwrap_or(&no_devices);
+        let emulator = DevicesController::effective_rules(devices)?;
+
+        let default = if emulator.default_allow {
+            "allow"
+        } else {
+            "deny"
+        };
+        println!("{:<18}{}", "Default", default);
+        println!("Rules");
+        for rule in &emulator.rules {
+            let action = if rule.allow() { "allow" } else { "deny" };
+            println!("  {:<16}{}", action, rule.to_string().trim());
+        }
+
+        let cgroup_root = match libcgroups::common::get_cgroup_setup()? {
+            CgroupSetup::Unified => Path::new(DEFAULT_CGROUP_ROOT),
+            CgroupSetup::Hybrid => Path::new(HYBRID_UNIFIED_ROOT),
+            CgroupSetup::Legacy => {
+                bail!("device programs are only available with the unified hierarchy")
+            }
+        };
+        let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), container.id());
+        let cgroup_path = cgroup_root.join_safely(&cgroups_path)?;
+
+        let programs = inspect::attached_programs(&cgroup_path)?;
+        println!("Attached programs");
+        for program in &programs {
+            println!(
+                "  {:<16}{} instructions",
+                program.id,
+                program.bytecodes.len() / 8
+            );
+            if self.disassemble {
+                for line in program.disassemble() {
+                    println!("    {}", line);
+                }
+            }
+        }
+
+        if let Some(check) = &self.check {
+            let (typ, major, minor, access) = parse_access_check(check)?;
+            if programs.is_empty() {
+                bail!("no device program is attached to {}", cgroup_path.display());
+            }
+
+            // the kernel only allows the access if all attached programs allow it
+            let mut allowed = true;
+            for program in &programs {
+                allowed &= program.is_allowed(typ, major, minor, &access)?;
+            }
+
+            println!(
+                "{:<18}{}",
+                "Access",
+                if allowed { "allowed" } else { "denied" }
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a device access in the format of a device cgroup rule, e.g. "c 10:200 rwm"
+fn parse_access_check(check: &str) -> Result<(LinuxDeviceType, u32, u32, String)> {
+    let fields: Vec<&str> = check.split_whitespace().collect();
+    if fields.len() != 3 {
+        bail!(
+            "access check {:?} is not of the form 'type major:minor access'",
+            check
+        );
+    }
+
+    let typ = match fields[0] {
+        "c" => LinuxDeviceType::C,
+        "b" => LinuxDeviceType::B,
+        t => bail!("invalid device type {}, expected c or b", t),
+    };
+
+    let (major, minor) = fields[1]
+        .split_once(':')
+        .with_context(|| format!("invalid device number {}", fields[1]))?;
+    let major = major
+        .parse()
+        .with_context(|| format!("invalid major number {}", major))?;
+    let minor = minor
+        .parse()
+        .with_context(|| format!("invalid minor number {}", minor))?;
+
+    let access = fields[2];
+    if access.is_empty() || !access.chars().all(|c| matches!(c, 'r' | 'w' | 'm')) {
+        bail!(
+            "invalid access {}, expected a combination of r, w and m",
+            access
+        );
+    }
+
+    Ok((typ, major, minor, access.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_access_check() {
+        let (typ, major, minor, access) = parse_access_check("c 10:200 rwm").unwrap();
+        assert_eq!(typ, LinuxDeviceType::C);
+        assert_eq!((major, minor), (10, 200));
+        assert_eq!(access, "rwm");
+    }
+
+    #[test]
+    fn test_parse_invalid_access_check() {
+        assert!(parse_access_check("c 10:200").is_err());
+        assert!(parse_access_check("a 10:200 rwm").is_err());
+        assert!(parse_access_check("c 10 rwm").is_err());
+        assert!(parse_access_check("c 10:*").is_err());
+        assert!(parse_access_check("b 8:0 rx").is_err());
+    }
+}
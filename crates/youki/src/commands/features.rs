@@ -0,0 +1,53 @@
+//! Contains functionality to list the features youki supports on this host
+use anyhow::Result;
+use clap::Clap;
+use libcontainer::capabilities;
+use serde::Serialize;
+
+/// Show the features youki supports on this host in JSON
+#[derive(Clap, Debug)]
+pub struct Features {}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FeaturesInfo {
+    linux: LinuxFeatures,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LinuxFeatures {
+    /// Capabilities known to both youki and the running kernel
+    capabilities: Vec<String>,
+}
+
+impl Features {
+    pub fn exec(&self) -> Result<()> {
+        let features = FeaturesInfo {
+            linux: LinuxFeatures {
+                capabilities: supported_capabilities(),
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&features)?);
+
+        Ok(())
+    }
+}
+
+fn supported_capabilities() -> Vec<String> {
+    let mut caps: Vec<_> = capabilities::supported().into_iter().collect();
+    caps.sort_by_key(|c| c.index());
+    caps.iter().map(|c| c.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supported_capabilities() {
+        let caps = supported_capabilities();
+        assert_eq!(caps.first().map(|c| c.as_str()), Some("CAP_CHOWN"));
+        assert!(caps.contains(&"CAP_SYS_ADMIN".to_string()));
+    }
+}
@@ -126,6 +126,10 @@ pub fn print_cgroups() {
 
     if let Ok(cgroup_setup) = cgroup_setup {
         if let Ok(unified) = &unified {
+            if matches!(cgroup_setup, CgroupSetup::Hybrid) {
+                print_hybrid_features(unified);
+            }
+
             if matches!(cgroup_setup, CgroupSetup::Hybrid | CgroupSetup::Unified) {
                 if let Ok(controllers) = libcgroups::v2::util::get_available_controllers(unified) {
                     println!("CGroup v2 controllers");
@@ -151,6 +155,35 @@ pub fn print_cgroups() {
     }
 }
 
+/// Print which hierarchy provides a feature on a hybrid system. Resource control is
+/// done by the v1 controllers, everything that does not depend on a controller is
+/// taken from the unified hierarchy.
+fn print_hybrid_features(unified: &Path) {
+    println!("Hybrid features");
+    println!("  {:<16}v1", "resources");
+    println!("  {:<16}v1", "freezer");
+
+    let psi = if unified.join("cgroup.pressure").exists() || Path::new("/proc/pressure").exists() {
+        "unified"
+    } else {
+        "unavailable"
+    };
+    println!("  {:<16}{}", "pressure", psi);
+
+    // the device controller of the unified hierarchy is only used by youki
+    // if it is built with the cgroupsv2_devices feature
+    let devices = match read_kernel_config() {
+        Some(config)
+            if cfg!(feature = "cgroupsv2_devices")
+                && find_parameter(&config, "CONFIG_CGROUP_BPF") == Some("y") =>
+        {
+            "v1, unified"
+        }
+        _ => "v1",
+    };
+    println!("  {:<16}{}", "devices", devices);
+}
+
 fn read_kernel_config() -> Option<String> {
     let uname = nix::sys::utsname::uname();
     let kernel_config = Path::new("/boot").join(format!("config-{}", uname.release()));
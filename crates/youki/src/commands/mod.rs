@@ -3,10 +3,14 @@ use std::{fs, path::Path};
 
 use libcontainer::container::Container;
 
+pub mod bundle;
 pub mod create;
+#[cfg(feature = "cgroupsv2_devices")]
+pub mod debug;
 pub mod delete;
 pub mod events;
 pub mod exec;
+pub mod features;
 pub mod info;
 pub mod kill;
 pub mod list;
@@ -14,6 +18,7 @@ pub mod pause;
 pub mod ps;
 pub mod resume;
 pub mod run;
+pub mod seccomp;
 pub mod spec_json;
 pub mod start;
 pub mod state;
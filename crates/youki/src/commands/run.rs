@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use clap::Clap;
@@ -20,6 +20,15 @@ pub struct Run {
     /// Pass N additional file descriptors to the container (stdio + $LISTEN_FDS + N in total)
     #[clap(long, default_value = "0")]
     preserve_fds: i32,
+    /// Pin the BPF program restricting the devices of the container to this directory in a
+    /// bpf filesystem (cgroup v2 only)
+    #[clap(long)]
+    bpf_pin_dir: Option<PathBuf>,
+    /// Allow and record the syscalls denied by the seccomp profile, and write a suggested
+    /// profile to this file once the container has exited. youki waits for the container to exit.
+    #[cfg(feature = "libseccomp")]
+    #[clap(long)]
+    seccomp_audit: Option<PathBuf>,
     /// name of the container instance to be started
     #[clap(required = true)]
     pub container_id: String,
@@ -27,6 +36,22 @@ pub struct Run {
 
 impl Run {
     pub fn exec(&self, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
+        #[cfg(feature = "libseccomp")]
+        {
+            if let Some(output) = &self.seccomp_audit {
+                return self.run_seccomp_audit(root_path, systemd_cgroup, output);
+            }
+        }
+
+        self.run(root_path, systemd_cgroup, None)
+    }
+
+    fn run(
+        &self,
+        root_path: PathBuf,
+        systemd_cgroup: bool,
+        seccomp_audit: Option<&Path>,
+    ) -> Result<()> {
         let syscall = create_syscall();
         let mut container = ContainerBuilder::new(self.container_id.clone(), syscall.as_ref())
             .with_pid_file(self.pid_file.as_ref())
@@ -35,10 +60,69 @@ impl Run {
             .with_preserved_fds(self.preserve_fds)
             .as_init(&self.bundle)
             .with_systemd(systemd_cgroup)
+            .with_seccomp_audit(seccomp_audit)
+            .with_bpf_pin_dir(self.bpf_pin_dir.as_ref())
             .build()?;
 
         container
             .start()
             .with_context(|| format!("failed to start container {}", self.container_id))
     }
+
+    #[cfg(feature = "libseccomp")]
+    fn run_seccomp_audit(
+        &self,
+        root_path: PathBuf,
+        systemd_cgroup: bool,
+        output: &Path,
+    ) -> Result<()> {
+        use libcontainer::seccomp::{agent, audit};
+        use nix::sys::socket::{self, Shutdown};
+        use oci_spec::runtime::Spec;
+        use std::{fs, fs::File, thread};
+
+        let spec_path = self.bundle.join("config.json");
+        let spec = Spec::load(&spec_path)
+            .with_context(|| format!("failed to load spec from {}", spec_path.display()))?;
+        let profile = spec
+            .linux()
+            .as_ref()
+            .and_then(|l| l.seccomp().clone())
+            .with_context(|| format!("{} contains no seccomp profile", spec_path.display()))?;
+
+        let listener_path = root_path.join(format!("{}-seccomp-audit.sock", self.container_id));
+        if listener_path.exists() {
+            fs::remove_file(&listener_path)
+                .with_context(|| format!("failed to remove stale {}", listener_path.display()))?;
+        }
+        // The runtime connects to the listener while creating the container,
+        // so it has to exist before the recording thread is running.
+        let listener = agent::listen(&listener_path)?;
+        let recorder = thread::spawn(move || audit::record_violations(listener));
+
+        let res = self.run(root_path, systemd_cgroup, Some(&listener_path));
+        if res.is_err() {
+            // unblock the recorder, if the container never connected
+            let _ = socket::shutdown(listener, Shutdown::Both);
+        }
+        let violations = recorder.join();
+        let _ = nix::unistd::close(listener);
+        let _ = fs::remove_file(&listener_path);
+        res?;
+        let violations = match violations {
+            Ok(violations) => violations.context("failed to record seccomp violations")?,
+            Err(err) => anyhow::bail!("seccomp audit thread panicked: {:?}", err),
+        };
+
+        for syscall in &violations {
+            log::info!("seccomp profile denied syscall {}", syscall);
+        }
+        let suggested = audit::suggest_profile(&profile, &violations)?;
+        let file = File::create(output)
+            .with_context(|| format!("failed to create {}", output.display()))?;
+        serde_json::to_writer_pretty(file, &suggested)
+            .with_context(|| format!("failed to write {}", output.display()))?;
+
+        Ok(())
+    }
 }
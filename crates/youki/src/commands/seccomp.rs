@@ -0,0 +1,73 @@
+//! Contains functionality to inspect the seccomp filter youki would install
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::Clap;
+use libcontainer::seccomp;
+use oci_spec::runtime::Spec;
+
+/// Inspect the seccomp filter of a bundle
+#[derive(Clap, Debug)]
+pub struct SeccompCommand {
+    #[clap(subcommand)]
+    subcmd: SeccompSubCommand,
+}
+
+#[derive(Clap, Debug)]
+enum SeccompSubCommand {
+    Export(Export),
+}
+
+impl SeccompCommand {
+    pub fn exec(&self) -> Result<()> {
+        match &self.subcmd {
+            SeccompSubCommand::Export(export) => export.exec(),
+        }
+    }
+}
+
+/// Compile the seccomp profile of a bundle and export the resulting filter
+#[derive(Clap, Debug)]
+pub struct Export {
+    /// path to the bundle directory, containing config.json
+    #[clap(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+    /// Format of the filter, either pseudo filter code (pfc) or the raw bpf program (bpf)
+    #[clap(long, default_value = "pfc", possible_values = &["pfc", "bpf"])]
+    pub format: String,
+    /// Write the filter to a file instead of stdout
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+impl Export {
+    pub fn exec(&self) -> Result<()> {
+        let spec_path = self.bundle.join("config.json");
+        let spec = Spec::load(&spec_path)
+            .with_context(|| format!("failed to load spec from {}", spec_path.display()))?;
+        let profile = match spec.linux().as_ref().and_then(|l| l.seccomp().as_ref()) {
+            Some(profile) => profile,
+            None => bail!("{} contains no seccomp profile", spec_path.display()),
+        };
+
+        let filter = seccomp::compile(profile).context("failed to compile seccomp profile")?;
+        match &self.output {
+            Some(output) => {
+                let file = File::create(output)
+                    .with_context(|| format!("failed to create {}", output.display()))?;
+                self.export(&filter, &file)
+            }
+            None => self.export(&filter, &io::stdout()),
+        }
+    }
+
+    fn export<F: AsRawFd>(&self, filter: &seccomp::FilterContext, dest: &F) -> Result<()> {
+        match self.format.as_str() {
+            "bpf" => filter.export_bpf(dest),
+            _ => filter.export_pfc(dest),
+        }
+    }
+}
@@ -12,10 +12,14 @@ use anyhow::Context;
 use anyhow::Result;
 use clap::{crate_version, Clap};
 
+use crate::commands::bundle;
 use crate::commands::create;
+#[cfg(feature = "cgroupsv2_devices")]
+use crate::commands::debug;
 use crate::commands::delete;
 use crate::commands::events;
 use crate::commands::exec;
+use crate::commands::features;
 use crate::commands::info;
 use crate::commands::kill;
 use crate::commands::list;
@@ -23,6 +27,7 @@ use crate::commands::pause;
 use crate::commands::ps;
 use crate::commands::resume;
 use crate::commands::run;
+use crate::commands::seccomp;
 use crate::commands::spec_json;
 use crate::commands::start;
 use crate::commands::state;
@@ -74,6 +79,8 @@ enum SubCommand {
     #[clap(version = crate_version!(), author = "youki team")]
     Info(info::Info),
     #[clap(version = crate_version!(), author = "youki team")]
+    Features(features::Features),
+    #[clap(version = crate_version!(), author = "youki team")]
     Spec(spec_json::SpecJson),
     #[clap(version = crate_version!(), author = "youki team")]
     List(list::List),
@@ -85,6 +92,13 @@ enum SubCommand {
     Events(events::Events),
     #[clap(version = crate_version!(), author = "youki team", setting=clap::AppSettings::AllowLeadingHyphen)]
     Ps(ps::Ps),
+    #[clap(version = crate_version!(), author = "youki team")]
+    Seccomp(seccomp::SeccompCommand),
+    #[clap(version = crate_version!(), author = "youki team")]
+    Bundle(bundle::Bundle),
+    #[cfg(feature = "cgroupsv2_devices")]
+    #[clap(version = crate_version!(), author = "youki team")]
+    Debug(debug::DebugCommand),
 }
 
 /// This is the entry point in the container runtime. The binary is run by a high-level container runtime,
@@ -120,12 +134,17 @@ fn main() -> Result<()> {
         SubCommand::Delete(delete) => delete.exec(root_path),
         SubCommand::State(state) => state.exec(root_path),
         SubCommand::Info(info) => info.exec(),
+        SubCommand::Features(features) => features.exec(),
         SubCommand::List(list) => list.exec(root_path),
         SubCommand::Spec(spec) => spec.exec(),
         SubCommand::Pause(pause) => pause.exec(root_path),
         SubCommand::Resume(resume) => resume.exec(root_path),
         SubCommand::Events(events) => events.exec(root_path),
         SubCommand::Ps(ps) => ps.exec(root_path),
+        SubCommand::Seccomp(seccomp) => seccomp.exec(),
+        SubCommand::Bundle(bundle) => bundle.exec(),
+        #[cfg(feature = "cgroupsv2_devices")]
+        SubCommand::Debug(debug) => debug.exec(root_path),
     }
 }
 